@@ -0,0 +1,49 @@
+//! Measures the cost of starting a new segment file.
+//!
+//! Run with `cargo bench --bench segment_rotation`. Creating a writer from a prepared
+//! builder should cost a small fraction of opening the file it writes to.
+
+use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriterBuilder};
+use std::fs::File;
+use std::hint::black_box;
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 2_000;
+
+fn measure<F: FnMut(u32)>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        f(i);
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let dir = std::path::Path::new("target/bench_output");
+    std::fs::create_dir_all(dir).unwrap();
+    let builder = MjpegWriterBuilder::new(1920, 1080, 30);
+    let frame = [0xFF, 0xD8, 0xFF, 0xD9];
+
+    let open = measure(|i| {
+        black_box(File::create(dir.join(format!("open_{}.avi", i % 8))).unwrap());
+    });
+
+    let header = measure(|_| {
+        let mut writer = builder.build(Cursor::new(Vec::with_capacity(512))).unwrap();
+        writer.add_frame(&frame).unwrap();
+        black_box(writer.finish().unwrap());
+    });
+
+    let rotation = measure(|i| {
+        let file = File::create(dir.join(format!("rotate_{}.avi", i % 8))).unwrap();
+        let mut writer = builder.build(file).unwrap();
+        writer.add_frame(&frame).unwrap();
+        black_box(writer.finish().unwrap());
+    });
+
+    println!("file open:              {:>10.2?}", open);
+    println!("header + finish (mem):  {:>10.2?}", header);
+    println!("full segment rotation:  {:>10.2?}", rotation);
+    println!("in-memory share of rotation: {:.1}%", header.as_secs_f64() / rotation.as_secs_f64() * 100.0);
+}
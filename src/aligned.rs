@@ -0,0 +1,332 @@
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const DEFAULT_SECTOR_SIZE: usize = 4096;
+const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Options for opening an [`AlignedFile`].
+///
+/// On Windows the file is opened with `FILE_FLAG_NO_BUFFERING`, which bypasses the
+/// system cache and avoids the latency spikes of large buffered writes. Every write is
+/// then issued as whole, sector-aligned blocks from a sector-aligned buffer.
+/// On other platforms the file is opened normally but the same aligned write pattern is used.
+#[derive(Debug, Clone)]
+pub struct AlignedFileOptions {
+    sector_size: usize,
+    buffer_size: usize,
+    preallocate: u64,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    sparse: bool,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    valid_data: bool,
+}
+
+impl Default for AlignedFileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AlignedFileOptions {
+    /// Creates options with a 4096-byte sector size and a 1MB write buffer.
+    pub fn new() -> Self {
+        AlignedFileOptions {
+            sector_size: DEFAULT_SECTOR_SIZE,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            preallocate: 0,
+            sparse: false,
+            valid_data: false,
+        }
+    }
+
+    /// Sets the sector size. Must be a power of two.
+    pub fn sector_size(&mut self, size: usize) -> &mut Self {
+        self.sector_size = size;
+        self
+    }
+
+    /// Sets the size of the aligned write buffer. It is rounded up to a multiple of the sector size.
+    pub fn buffer_size(&mut self, size: usize) -> &mut Self {
+        self.buffer_size = size;
+        self
+    }
+
+    /// Preallocates `bytes` on creation. The file is truncated to its real length by
+    /// [`AlignedFile::finalize`].
+    pub fn preallocate(&mut self, bytes: u64) -> &mut Self {
+        self.preallocate = bytes;
+        self
+    }
+
+    /// Marks the file as sparse before preallocation (Windows only, ignored elsewhere).
+    pub fn sparse(&mut self, sparse: bool) -> &mut Self {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Calls `SetFileValidData` after preallocation so the file system skips zero-filling
+    /// (Windows only, ignored elsewhere).
+    ///
+    /// This requires the `SE_MANAGE_VOLUME_NAME` privilege; creation fails if it is not held.
+    pub fn valid_data(&mut self, valid_data: bool) -> &mut Self {
+        self.valid_data = valid_data;
+        self
+    }
+
+    /// Creates (or truncates) the file at `path` with these options.
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> io::Result<AlignedFile> {
+        if !self.sector_size.is_power_of_two() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "sector size must be a power of two"));
+        }
+        let buffer_size = self.buffer_size.max(1).div_ceil(self.sector_size) * self.sector_size;
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            options.custom_flags(sys::FILE_FLAG_NO_BUFFERING);
+        }
+        let file = options.open(path)?;
+
+        #[cfg(windows)]
+        if self.sparse {
+            sys::set_sparse(&file)?;
+        }
+        if self.preallocate > 0 {
+            file.set_len(self.preallocate)?;
+            #[cfg(windows)]
+            if self.valid_data {
+                sys::set_valid_data(&file, self.preallocate)?;
+            }
+        }
+
+        Ok(AlignedFile {
+            file,
+            sector_size: self.sector_size,
+            buf: AlignedBuf::new(buffer_size, self.sector_size),
+            window: 0,
+            filled: 0,
+            modified: false,
+            pos: 0,
+            len: 0,
+        })
+    }
+}
+
+/// A file target that only issues sector-aligned writes.
+///
+/// Writes are collected in a sector-aligned buffer and written out in whole sectors.
+/// Seeking back to patch earlier data (as `finish()` does for the AVI header) reads the
+/// affected sectors back first. Because the last sector is zero-padded on disk,
+/// [`finalize`](AlignedFile::finalize) must be called after the AVI writer is finished
+/// to truncate the file to its real length.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{AlignedFileOptions, MjpegAviWriter, MjpegWriter};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let file = AlignedFileOptions::new().preallocate(64 << 20).create("output.avi")?;
+///     let mut writer = MjpegWriter::new(file, 320, 240, 30)?;
+///     writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///     writer.finish()?.finalize()?;
+///     Ok(())
+/// }
+/// ```
+#[must_use = "The file must be finalized using .finalize() to truncate the sector padding"]
+pub struct AlignedFile {
+    file: File,
+    sector_size: usize,
+    buf: AlignedBuf,
+    window: u64,
+    filled: usize,
+    modified: bool,
+    pos: u64,
+    len: u64,
+}
+
+impl AlignedFile {
+    /// Creates a file at `path` with the default options.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        AlignedFileOptions::new().create(path)
+    }
+
+    /// Writes out the buffered sectors, truncates the file to its logical length
+    /// and returns the underlying file.
+    pub fn finalize(mut self) -> io::Result<File> {
+        self.flush_window()?;
+        self.file.set_len(self.len)?;
+        Ok(self.file)
+    }
+
+    fn flush_window(&mut self) -> io::Result<()> {
+        if !self.modified {
+            return Ok(());
+        }
+        let n = self.filled.div_ceil(self.sector_size) * self.sector_size;
+        self.file.seek(SeekFrom::Start(self.window))?;
+        self.file.write_all(&self.buf.as_slice()[..n])?;
+        self.modified = false;
+        Ok(())
+    }
+
+    fn move_window(&mut self, pos: u64) -> io::Result<()> {
+        self.flush_window()?;
+
+        self.window = pos & !(self.sector_size as u64 - 1);
+        self.filled = 0;
+        let buf = self.buf.as_mut_slice();
+        buf.fill(0);
+
+        if self.window < self.len {
+            let valid = (self.len - self.window).min(buf.len() as u64) as usize;
+            let n = valid.div_ceil(self.sector_size) * self.sector_size;
+            self.file.seek(SeekFrom::Start(self.window))?;
+            let mut read = 0;
+            while read < n {
+                match self.file.read(&mut buf[read..n]) {
+                    Ok(0) => break,
+                    Ok(r) => read += r,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.filled = valid;
+        }
+        Ok(())
+    }
+}
+
+impl Write for AlignedFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let capacity = self.buf.len() as u64;
+        if self.pos < self.window || self.pos >= self.window + capacity {
+            self.move_window(self.pos)?;
+        }
+
+        let offset = (self.pos - self.window) as usize;
+        let n = data.len().min(self.buf.len() - offset);
+        self.buf.as_mut_slice()[offset..offset + n].copy_from_slice(&data[..n]);
+        self.filled = self.filled.max(offset + n);
+        self.modified = true;
+        self.pos += n as u64;
+        self.len = self.len.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_window()?;
+        self.file.flush()
+    }
+}
+
+impl Seek for AlignedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// A zero-initialized heap buffer aligned to the sector size.
+struct AlignedBuf {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// The buffer is uniquely owned, like a `Vec<u8>`.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    fn new(size: usize, align: usize) -> Self {
+        let layout = Layout::from_size_align(size, align).expect("invalid aligned buffer layout");
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        AlignedBuf { ptr, layout }
+    }
+
+    fn len(&self) -> usize {
+        self.layout.size()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.layout.size()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+
+    pub(super) const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+    const FSCTL_SET_SPARSE: u32 = 0x0009_00C4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            control_code: u32,
+            in_buffer: *const c_void,
+            in_size: u32,
+            out_buffer: *mut c_void,
+            out_size: u32,
+            bytes_returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+        fn SetFileValidData(file: *mut c_void, valid_data_length: i64) -> i32;
+    }
+
+    pub(super) fn set_sparse(file: &File) -> io::Result<()> {
+        let mut returned = 0u32;
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle() as *mut c_void,
+                FSCTL_SET_SPARSE,
+                std::ptr::null(),
+                0,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_valid_data(file: &File, len: u64) -> io::Result<()> {
+        let ok = unsafe { SetFileValidData(file.as_raw_handle() as *mut c_void, len as i64) };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+use std::io::{Read, Seek};
+use std::time::Duration;
+use crate::Result;
+use crate::reader::MjpegReader;
+
+/// The difference of a frame from the frames before it, see [`DiffAnalyzer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameDiff {
+    /// The frame number.
+    pub frame: usize,
+    /// The presentation time of the frame.
+    pub timestamp: Duration,
+    /// The size of the JPEG data, 0 for a dropped frame.
+    pub size: u32,
+    /// The size difference from the previous frame with data, 0 for the first and dropped frames.
+    pub size_delta: i64,
+    /// The size difference relative to the average size of the recent frames since the last
+    /// scene change, e.g. 0.5 for a frame 50% larger.
+    pub size_change: f64,
+    /// The structural similarity (SSIM) of the luma to the previous sampled frame, from 0 to 1,
+    /// for sampled frames. Requires the `preview` feature.
+    pub ssim: Option<f64>,
+}
+
+/// A frame where the picture changes, see [`DiffAnalyzer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneChange {
+    /// The frame number.
+    pub frame: usize,
+    /// The presentation time of the frame.
+    pub timestamp: Duration,
+    /// How much the frame differs, from 0 upwards: the relative size change, or 1 - SSIM if
+    /// frames are sampled for SSIM.
+    pub score: f64,
+}
+
+/// The result of [`DiffAnalyzer::analyze`], in frame order for plotting.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DiffReport {
+    /// The difference metrics of every frame.
+    pub frames: Vec<FrameDiff>,
+    /// The frames where the picture changes.
+    pub scene_changes: Vec<SceneChange>,
+}
+
+/// Computes per-frame difference metrics across a recording and finds scene changes, to find
+/// events in long recordings.
+///
+/// The size of a JPEG frame grows with its detail, so a frame whose size departs from the
+/// average of the recent frames by more than the [size threshold](Self::size_threshold)
+/// usually shows something new. This reads only the index and costs no decoding. With the
+/// `preview` feature, every n-th frame can be decoded with [`ssim_every`](Self::ssim_every)
+/// to compare its luma with SSIM, which is slower but also catches changes that keep the size;
+/// scene changes are then found by SSIM alone. Dropped frames are skipped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{DiffAnalyzer, MjpegReader};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = MjpegReader::new(File::open("recording.avi")?)?;
+///     let report = DiffAnalyzer::new().size_threshold(0.4).analyze(&mut reader)?;
+///     for change in &report.scene_changes {
+///         println!("{:?}: {:.2}", change.timestamp, change.score);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DiffAnalyzer {
+    size_threshold: f64,
+    window: usize,
+    ssim_every: usize,
+    ssim_threshold: f64,
+}
+
+impl Default for DiffAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiffAnalyzer {
+    /// Creates an analyzer reporting size changes of 30% over the last 8 frames.
+    pub fn new() -> Self {
+        DiffAnalyzer { size_threshold: 0.3, window: 8, ssim_every: 0, ssim_threshold: 0.7 }
+    }
+
+    /// Sets the relative size change that makes a scene change. Default: 0.3.
+    pub fn size_threshold(&mut self, threshold: f64) -> &mut Self {
+        self.size_threshold = threshold;
+        self
+    }
+
+    /// Sets the number of recent frames whose average size a frame is compared with. Default: 8.
+    pub fn window(&mut self, frames: usize) -> &mut Self {
+        self.window = frames.max(1);
+        self
+    }
+
+    /// Decodes every `n`-th frame to compute its SSIM to the previous sampled frame, 0 to turn
+    /// it off. Default: 0.
+    #[cfg(feature = "preview")]
+    pub fn ssim_every(&mut self, n: usize) -> &mut Self {
+        self.ssim_every = n;
+        self
+    }
+
+    /// Sets the SSIM below which a sampled frame makes a scene change. Default: 0.7.
+    #[cfg(feature = "preview")]
+    pub fn ssim_threshold(&mut self, threshold: f64) -> &mut Self {
+        self.ssim_threshold = threshold;
+        self
+    }
+
+    /// Analyzes all frames of `reader`.
+    pub fn analyze<R: Read + Seek>(&self, reader: &mut MjpegReader<R>) -> Result<DiffReport> {
+        let mut report = DiffReport::default();
+        let mut recent = VecDeque::with_capacity(self.window);
+        let mut sampler = Sampler::default();
+        for (frame, entry) in reader.frame_map().into_iter().enumerate() {
+            let size = entry.len;
+            let previous = recent.back().copied();
+            let average = recent.iter().map(|&len| len as f64).sum::<f64>() / recent.len().max(1) as f64;
+            let size_change = if size == 0 || average == 0.0 { 0.0 } else { size as f64 / average - 1.0 };
+            let ssim = if size > 0 && self.ssim_every > 0 && frame % self.ssim_every == 0 {
+                sampler.sample(&reader.read_frame(frame)?)
+            } else {
+                None
+            };
+
+            let score = if self.ssim_every > 0 {
+                ssim.filter(|&ssim| ssim < self.ssim_threshold).map(|ssim| 1.0 - ssim)
+            } else {
+                (previous.is_some() && size_change.abs() > self.size_threshold).then_some(size_change.abs())
+            };
+            if let Some(score) = score {
+                report.scene_changes.push(SceneChange { frame, timestamp: entry.timestamp, score });
+                // The new scene sets the sizes to compare with
+                recent.clear();
+            }
+            report.frames.push(FrameDiff {
+                frame,
+                timestamp: entry.timestamp,
+                size,
+                size_delta: previous.filter(|_| size > 0).map_or(0, |previous| size as i64 - previous as i64),
+                size_change,
+                ssim,
+            });
+            if size > 0 {
+                if recent.len() == self.window {
+                    recent.pop_front();
+                }
+                recent.push_back(size);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The luma of the last sampled frame.
+#[derive(Default)]
+struct Sampler {
+    #[cfg(feature = "preview")]
+    previous: Option<(u32, u32, Vec<u8>)>,
+}
+
+impl Sampler {
+    /// The SSIM of `jpeg` to the previous sample, `None` for the first one or an undecodable one.
+    #[cfg(feature = "preview")]
+    fn sample(&mut self, jpeg: &[u8]) -> Option<f64> {
+        let Ok((width, height, luma)) = crate::preview::decode_luma(jpeg) else {
+            return None;
+        };
+        let ssim = match &self.previous {
+            Some((w, h, previous)) if (*w, *h) == (width, height) => Some(ssim(previous, &luma, width as usize)),
+            Some(_) => Some(0.0),
+            None => None,
+        };
+        self.previous = Some((width, height, luma));
+        ssim
+    }
+
+    #[cfg(not(feature = "preview"))]
+    fn sample(&mut self, _jpeg: &[u8]) -> Option<f64> {
+        None
+    }
+}
+
+/// The mean SSIM of two luma images of the same size over 8x8 windows.
+#[cfg(feature = "preview")]
+fn ssim(a: &[u8], b: &[u8], width: usize) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let height = a.len() / width.max(1);
+    let (mut total, mut windows) = (0.0, 0);
+    for y in (0..height.saturating_sub(7)).step_by(8) {
+        for x in (0..width.saturating_sub(7)).step_by(8) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for row in y..y + 8 {
+                for i in row * width + x..row * width + x + 8 {
+                    let (pa, pb) = (a[i] as f64, b[i] as f64);
+                    sum_a += pa;
+                    sum_b += pb;
+                    sum_aa += pa * pa;
+                    sum_bb += pb * pb;
+                    sum_ab += pa * pb;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / 64.0, sum_b / 64.0);
+            let var_a = sum_aa / 64.0 - mean_a * mean_a;
+            let var_b = sum_bb / 64.0 - mean_b * mean_b;
+            let covar = sum_ab / 64.0 - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 { 1.0 } else { total / windows as f64 }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Write};
+use serde_json::Value;
+use crate::observer::FrameObserver;
+
+/// An observer that writes per-frame JSON annotations to an NDJSON sidecar file.
+///
+/// Call [`annotate`](Self::annotate) before adding a frame to attach a value to it, such as
+/// the detections of a CV pipeline. Each annotated frame becomes one line
+/// `{"frame":<n>,"annotation":<value>}`; frames without an annotation get no line.
+///
+/// Lines are buffered until [`flush`](Self::flush), and only frames the writer has accepted
+/// are annotated, so flushing the sidecar right after flushing the AVI output keeps both files
+/// consistent: the sidecar never refers to a frame that isn't in the AVI file. The sidecar is
+/// also flushed when the writer is finished. Enabled by the `annotations` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{AnnotationSink, MjpegAviWriter, MjpegWriter, ObservedWriter};
+/// use serde_json::json;
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut annotations = AnnotationSink::new(File::create("output.ndjson")?);
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 30)?;
+///     let mut writer = ObservedWriter::new(writer, &mut annotations);
+///     writer.observer_mut().annotate(json!({"label": "person", "box": [10, 20, 64, 128]}));
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.finish()?;
+///     annotations.into_inner()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AnnotationSink<S: Write> {
+    sidecar: S,
+    buf: Vec<u8>,
+    frames: u64,
+    next: Option<Value>,
+    /// An error of a flush by `on_finish()`, reported by the next call that returns errors
+    error: Option<io::Error>,
+}
+
+impl<S: Write> AnnotationSink<S> {
+    /// Creates a sink writing to `sidecar`.
+    pub fn new(sidecar: S) -> Self {
+        AnnotationSink { sidecar, buf: Vec::new(), frames: 0, next: None, error: None }
+    }
+
+    /// Sets the annotation of the next frame, replacing one set before.
+    ///
+    /// If the writer rejects the frame, the annotation stays pending for the frame after it.
+    pub fn annotate(&mut self, annotation: Value) {
+        self.next = Some(annotation);
+    }
+
+    /// The number of frames observed so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Writes the buffered lines and flushes the sidecar.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.sidecar.write_all(&self.buf)?;
+        self.buf.clear();
+        self.sidecar.flush()
+    }
+
+    /// Flushes the sidecar and returns it.
+    pub fn into_inner(mut self) -> io::Result<S> {
+        self.flush()?;
+        Ok(self.sidecar)
+    }
+}
+
+impl<S: Write> FrameObserver for AnnotationSink<S> {
+    fn on_frame(&mut self, _bufs: &[&[u8]]) {
+        if let Some(annotation) = self.next.take() {
+            self.buf.extend_from_slice(format!(r#"{{"frame":{},"annotation":"#, self.frames).as_bytes());
+            // Serializing a Value to a Vec can't fail
+            serde_json::to_writer(&mut self.buf, &annotation).expect("JSON serialization");
+            self.buf.extend_from_slice(b"}\n");
+        }
+        self.frames += 1;
+    }
+
+    fn on_finish(&mut self) {
+        if let Err(err) = self.flush() {
+            self.error = Some(err);
+        }
+    }
+}
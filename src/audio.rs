@@ -0,0 +1,202 @@
+use std::time::Duration;
+use crate::common::{add_stream, create_chunk_header, super_index_chunk};
+use crate::index::IndexEntry;
+use crate::riff::le_u32;
+
+/// `AVIF_ISINTERLEAVED`: the streams are interleaved in the `movi` list.
+const AVIF_ISINTERLEAVED: u32 = 0x100;
+/// `WAVE_FORMAT_PCM`
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+/// `WAVE_FORMAT_MPEGLAYER3`
+const WAVE_FORMAT_MPEGLAYER3: u16 = 0x0055;
+/// The sample rates of MPEG-1 Layer III; MPEG-2 halves and MPEG-2.5 quarters them
+const MP3_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+/// The bitrates of MPEG-1 Layer III frames in kbit/s, by bitrate index
+const MP3_BITRATES_V1: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+/// The bitrates of MPEG-2 and MPEG-2.5 Layer III frames in kbit/s, by bitrate index
+const MP3_BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// The encoding of an audio stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// Uncompressed little-endian PCM samples, 8-bit samples unsigned.
+    Pcm,
+    /// Pre-encoded MPEG Layer III frames, muxed as they are.
+    Mp3 {
+        /// The (average) bitrate in bits per second, e.g. 128000.
+        bitrate: u32,
+    },
+}
+
+/// When the writer puts audio chunks between video frames, see
+/// [`MjpegWriterBuilder::interleave`](crate::MjpegWriterBuilder::interleave).
+///
+/// Fewer, larger audio chunks keep the index small; smaller ones keep the audio of a frame
+/// close to it in the file, which players reading over slow media prefer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterleavePolicy {
+    /// Writes the samples of each `add_audio_samples` call as they arrive.
+    #[default]
+    Immediate,
+    /// Buffers the samples and writes them once `n` video frames were written since the first
+    /// of them.
+    EveryFrames(u32),
+    /// Buffers the samples and writes them once they, or the video frames written since the
+    /// first of them, span the duration.
+    MaxSkew(Duration),
+}
+
+/// The format of the audio stream of a recording, see
+/// [`MjpegWriterBuilder::audio`](crate::MjpegWriterBuilder::audio).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    /// The encoding of the samples.
+    pub codec: AudioCodec,
+    /// Samples per second, e.g. 48000.
+    pub sample_rate: u32,
+    /// The number of channels, 1 for mono, 2 for stereo.
+    pub channels: u16,
+    /// Bits per sample of one channel for PCM, e.g. 16; 0 for MP3.
+    pub bits_per_sample: u16,
+}
+
+impl AudioFormat {
+    /// Creates a PCM format.
+    pub fn new(sample_rate: u32, channels: u16, bits_per_sample: u16) -> Self {
+        AudioFormat { codec: AudioCodec::Pcm, sample_rate, channels, bits_per_sample }
+    }
+
+    /// Creates an MP3 format for frames at `sample_rate` with an (average) `bitrate` in bits
+    /// per second. Variable bitrates are fine, since each frame is a chunk of its own.
+    pub fn mp3(sample_rate: u32, channels: u16, bitrate: u32) -> Self {
+        AudioFormat { codec: AudioCodec::Mp3 { bitrate }, sample_rate, channels, bits_per_sample: 0 }
+    }
+
+    /// The `nBlockAlign` of the format: the size of one sample of all channels in bytes for
+    /// PCM, the samples per frame for MP3.
+    pub fn block_align(&self) -> u16 {
+        match self.codec {
+            AudioCodec::Pcm => self.channels * self.bits_per_sample.div_ceil(8),
+            // MPEG-1 frames hold 1152 samples, MPEG-2 and MPEG-2.5 frames 576
+            AudioCodec::Mp3 { .. } if self.sample_rate >= 32000 => 1152,
+            AudioCodec::Mp3 { .. } => 576,
+        }
+    }
+
+    /// The bytes of audio per second.
+    pub fn bytes_per_second(&self) -> u32 {
+        match self.codec {
+            AudioCodec::Pcm => self.sample_rate * self.block_align() as u32,
+            AudioCodec::Mp3 { bitrate } => bitrate / 8,
+        }
+    }
+
+    /// Returns true if the stream header can describe the format.
+    pub(crate) fn is_valid(&self) -> bool {
+        match self.codec {
+            AudioCodec::Pcm => self.sample_rate > 0 && self.block_align() > 0,
+            AudioCodec::Mp3 { .. } => self.channels > 0 && [1, 2, 4].iter().any(|div| MP3_SAMPLE_RATES.iter().any(|rate| rate / div == self.sample_rate)),
+        }
+    }
+}
+
+/// Appends the `strl` list of an audio stream to the `hdrl` list, with an empty `indx` super
+/// index for OpenDML if `super_index` is set.
+///
+/// PCM streams count bytes in samples of `nBlockAlign` bytes. MP3 streams are written as
+/// players expect variable bitrates: one frame per chunk, each a sample of `dwScale` audio
+/// samples (`dwSampleSize` 0).
+pub(crate) fn add_audio_stream(header: &[u8], format: &AudioFormat, super_index: bool) -> Vec<u8> {
+    let stream = le_u32(header, 56) as u8;
+    let block_align = format.block_align() as u32;
+    let (rate, sample_size) = match format.codec {
+        AudioCodec::Pcm => (format.bytes_per_second(), block_align),
+        AudioCodec::Mp3 { .. } => (format.sample_rate, 0),
+    };
+    let mut strl = create_chunk_header(*b"LIST", 0).to_vec();
+    strl.extend_from_slice(b"strl");
+
+    strl.extend_from_slice(&create_chunk_header(*b"strh", 56));
+    strl.extend_from_slice(b"auds");
+    strl.extend_from_slice(&[0; 4]); // fccHandler
+    strl.extend_from_slice(&[0; 12]); // dwFlags, wPriority, wLanguage, dwInitialFrames
+    strl.extend_from_slice(&block_align.to_le_bytes()); // dwScale
+    strl.extend_from_slice(&rate.to_le_bytes()); // dwRate
+    strl.extend_from_slice(&[0; 4]); // dwStart
+    strl.extend_from_slice(&[0; 4]); // dwLength, set at finish
+    strl.extend_from_slice(&format.bytes_per_second().to_le_bytes()); // dwSuggestedBufferSize
+    strl.extend_from_slice(&u32::MAX.to_le_bytes()); // dwQuality: default
+    strl.extend_from_slice(&sample_size.to_le_bytes()); // dwSampleSize
+    strl.extend_from_slice(&[0; 8]); // rcFrame
+
+    let format_tag = match format.codec {
+        AudioCodec::Pcm => WAVE_FORMAT_PCM,
+        AudioCodec::Mp3 { .. } => WAVE_FORMAT_MPEGLAYER3,
+    };
+    let extra_size: u16 = if format_tag == WAVE_FORMAT_MPEGLAYER3 { 12 } else { 0 };
+    strl.extend_from_slice(&create_chunk_header(*b"strf", 18 + extra_size as u32));
+    strl.extend_from_slice(&format_tag.to_le_bytes()); // wFormatTag
+    strl.extend_from_slice(&format.channels.to_le_bytes());
+    strl.extend_from_slice(&format.sample_rate.to_le_bytes());
+    strl.extend_from_slice(&format.bytes_per_second().to_le_bytes()); // nAvgBytesPerSec
+    strl.extend_from_slice(&format.block_align().to_le_bytes());
+    strl.extend_from_slice(&format.bits_per_sample.to_le_bytes());
+    strl.extend_from_slice(&extra_size.to_le_bytes()); // cbSize
+    if format_tag == WAVE_FORMAT_MPEGLAYER3 {
+        // The rest of MPEGLAYER3WAVEFORMAT
+        strl.extend_from_slice(&1u16.to_le_bytes()); // wID: MPEGLAYER3_ID_MPEG
+        strl.extend_from_slice(&2u32.to_le_bytes()); // fdwFlags: MPEGLAYER3_FLAG_PADDING_OFF
+        strl.extend_from_slice(&format.block_align().to_le_bytes()); // nBlockSize
+        strl.extend_from_slice(&1u16.to_le_bytes()); // nFramesPerBlock
+        strl.extend_from_slice(&0u16.to_le_bytes()); // nCodecDelay
+    }
+
+    if super_index {
+        strl.extend_from_slice(&super_index_chunk(IndexEntry::chunk_id(stream, IndexEntry::AUDIO)));
+    }
+    let size = strl.len() as u32 - 8;
+    strl[4..8].copy_from_slice(&size.to_le_bytes());
+
+    let mut header = add_stream(header, &strl);
+    let flags = le_u32(&header, 44) | AVIF_ISINTERLEAVED;
+    header[44..48].copy_from_slice(&flags.to_le_bytes());
+    header
+}
+
+/// Splits `data` into MP3 frames. Returns `None` unless it is a sequence of whole Layer III
+/// frames.
+pub(crate) fn mp3_frames(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut frames = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let len = mp3_frame_len(rest)?;
+        if len > rest.len() {
+            return None;
+        }
+        let (frame, next) = rest.split_at(len);
+        frames.push(frame);
+        rest = next;
+    }
+    Some(frames)
+}
+
+/// The length of the MP3 frame starting with the 4-byte frame header of `data`.
+fn mp3_frame_len(data: &[u8]) -> Option<usize> {
+    let &[0xFF, b1, b2, _, ..] = data else {
+        return None;
+    };
+    // Sync bits, and layer bits 01 for Layer III
+    if b1 & 0xE0 != 0xE0 || (b1 >> 1) & 0x3 != 1 {
+        return None;
+    }
+    let (bitrates, divisor, coefficient) = match (b1 >> 3) & 0x3 {
+        3 => (&MP3_BITRATES_V1, 1, 144), // MPEG-1
+        2 => (&MP3_BITRATES_V2, 2, 72),  // MPEG-2
+        0 => (&MP3_BITRATES_V2, 4, 72),  // MPEG-2.5
+        _ => return None,
+    };
+    let bitrate = *bitrates.get((b2 >> 4) as usize).filter(|&&kbps| kbps > 0)? * 1000;
+    let sample_rate = MP3_SAMPLE_RATES.get(((b2 >> 2) & 0x3) as usize)? / divisor;
+    let padding = ((b2 >> 1) & 0x1) as u32;
+    Some((coefficient * bitrate / sample_rate + padding) as usize)
+}
@@ -0,0 +1,148 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use crate::{MjpegError, Result};
+use crate::catalog::write_json_string;
+use crate::clock::{Clock, SystemClock};
+use crate::observer::FrameObserver;
+use crate::timing::rfc3339;
+
+/// An append-only audit log of recorder operations, for deployments that must keep a record
+/// of who recorded what and when.
+///
+/// Each operation becomes one NDJSON line with a sequence number, the wall-clock time, the
+/// actor given at creation, the event, the number of frames written so far and a detail text:
+///
+/// ```text
+/// {"seq":0,"time":"2024-05-01T12:00:00.000000Z","actor":"cam1","event":"session_start","frame":0,"detail":"lobby"}
+/// ```
+///
+/// The events are `session_start`, `segment_rotated`, `bookmark`, `error` and `finish`; other
+/// events can be added with [`record`](Self::record). Lines are written and flushed one at a
+/// time, so the log is complete up to the last operation even if the recorder crashes. As a
+/// [`FrameObserver`] it counts frames and records `finish` when the writer is finished.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{AuditLog, MjpegAviWriter, MjpegWriter, ObservedWriter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut audit = AuditLog::open("audit.ndjson", "operator@cam1")?;
+///     audit.session_start("lobby camera")?;
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 30)?;
+///     let mut writer = ObservedWriter::new(writer, &mut audit);
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.inner_mut().add_bookmark("door")?;
+///     writer.observer_mut().bookmark("door")?;
+///     writer.finish()?;
+///     audit.check()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AuditLog<S: Write, C: Clock = SystemClock> {
+    sink: S,
+    actor: String,
+    clock: C,
+    seq: u64,
+    frames: u64,
+    /// An error of the write by `on_finish()`, reported by the next call that returns errors
+    error: Option<io::Error>,
+}
+
+impl AuditLog<File> {
+    /// Opens the log file at `path` for appending, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P, actor: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file, actor))
+    }
+}
+
+impl<S: Write> AuditLog<S> {
+    /// Creates a log writing to `sink` on behalf of `actor`, such as a user or device name.
+    pub fn new(sink: S, actor: &str) -> Self {
+        Self::with_clock(sink, actor, SystemClock)
+    }
+}
+
+impl<S: Write, C: Clock> AuditLog<S, C> {
+    /// Creates a log taking the time from `clock`.
+    pub fn with_clock(sink: S, actor: &str, clock: C) -> Self {
+        AuditLog { sink, actor: actor.to_string(), clock, seq: 0, frames: 0, error: None }
+    }
+
+    /// Records the start of a recording session.
+    pub fn session_start(&mut self, detail: &str) -> Result<()> {
+        self.record("session_start", detail)
+    }
+
+    /// Records that recording continues in a new segment file at `path`.
+    pub fn segment_rotated<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.record("segment_rotated", &path.as_ref().to_string_lossy())
+    }
+
+    /// Records a bookmark with `label`.
+    pub fn bookmark(&mut self, label: &str) -> Result<()> {
+        self.record("bookmark", label)
+    }
+
+    /// Records an error of the recorder.
+    pub fn error(&mut self, err: &MjpegError) -> Result<()> {
+        self.record("error", &err.to_string())
+    }
+
+    /// Records the end of the recording. Called by `on_finish()` when used as an observer.
+    pub fn finish(&mut self) -> Result<()> {
+        self.record("finish", "")
+    }
+
+    /// Appends a line for `event` and flushes the sink.
+    pub fn record(&mut self, event: &str, detail: &str) -> Result<()> {
+        self.check()?;
+        let mut line = format!(r#"{{"seq":{},"time":"{}","actor":"#, self.seq, rfc3339(self.clock.system_time()));
+        write_json_string(&self.actor, &mut line)?;
+        line.push_str(r#","event":"#);
+        write_json_string(event, &mut line)?;
+        write!(line, r#","frame":{},"detail":"#, self.frames)?;
+        write_json_string(detail, &mut line)?;
+        line.push_str("}\n");
+
+        self.sink.write_all(line.as_bytes())?;
+        self.sink.flush()?;
+        self.seq += 1;
+        Ok(())
+    }
+
+    /// The number of frames observed so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the error of a `finish` record written by `on_finish()`, if any.
+    pub fn check(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: Write, C: Clock> FrameObserver for AuditLog<S, C> {
+    fn on_frame(&mut self, _bufs: &[&[u8]]) {
+        self.frames += 1;
+    }
+
+    fn on_finish(&mut self) {
+        if let Err(err) = self.finish() {
+            self.error = Some(io::Error::other(err.to_string()));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::{MjpegError, Result};
+use crate::target::{StorageTarget, StoredSegment};
+
+/// Statistics of the finalize queue of a [`BackgroundTarget`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// The segments waiting or being finalized.
+    pub depth: usize,
+    /// The largest depth so far.
+    pub max_depth: usize,
+    /// The segments finalized by the target.
+    pub finalized: u64,
+    /// The segments the target failed to finalize.
+    pub failed: u64,
+    /// The segments rejected because the queue was full.
+    pub rejected: u64,
+    /// The total time the target spent finalizing.
+    pub finalize_time: Duration,
+    /// The longest time a `finalize()` call took to queue a segment, which is all the
+    /// recording loop waits for.
+    pub max_enqueue_time: Duration,
+    /// The error of the last segment that failed.
+    pub last_error: Option<MjpegError>,
+}
+
+/// Finalizes the segments of a slow target, such as an [`S3Target`](crate::S3Target) upload,
+/// on a background thread, so an archival copy never holds up the live recording.
+///
+/// A recorder that writes each segment both to local storage and to an archive finalizes the
+/// archive copy through this target: [`finalize`](StorageTarget::finalize) only queues the
+/// segment and returns, and a worker thread with its own clone of the target makes it durable
+/// or uploads it, one segment at a time. The queue holds at most `capacity` segments; when the
+/// archive falls that far behind, `finalize()` rejects the segment with an error instead of
+/// waiting, and the live path carries on. [`stats`](Self::stats) reports the queue depth,
+/// the failures and how long `finalize()` calls took, to check that archival backpressure
+/// stays off the live path. The other methods go to the target directly.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{BackgroundTarget, FileTarget, MemoryTarget, MjpegAviWriter, MjpegWriterBuilder, StorageTarget};
+///
+/// # fn camera_frame() -> Vec<u8> { Vec::new() }
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut live = FileTarget::new("/var/recordings/cam1")?;
+///     // An S3Target in production
+///     let mut archive = BackgroundTarget::new(MemoryTarget::new(), 4);
+///     let builder = MjpegWriterBuilder::new(640, 480, 10);
+///     for segment in 0..24 {
+///         let name = format!("{:04}.avi", segment);
+///         let mut live_writer = builder.build(live.open_segment(&name)?)?;
+///         let mut archive_writer = builder.build(archive.open_segment(&name)?)?;
+///         for _ in 0..36000 {
+///             let frame = camera_frame();
+///             live_writer.add_frame(&frame)?;
+///             archive_writer.add_frame(&frame)?;
+///         }
+///         live.finalize(&name, live_writer.finish()?)?;
+///         if let Err(err) = archive.finalize(&name, archive_writer.finish()?) {
+///             eprintln!("archive copy of {} skipped: {}", name, err);
+///         }
+///     }
+///     let stats = archive.finish();
+///     println!("{} archived, {} failed", stats.finalized, stats.failed);
+///     Ok(())
+/// }
+/// ```
+pub struct BackgroundTarget<T: StorageTarget> {
+    target: T,
+    queue: Option<SyncSender<(String, T::Segment)>>,
+    worker: Option<JoinHandle<()>>,
+    stats: Arc<Mutex<QueueStats>>,
+}
+
+impl<T> BackgroundTarget<T>
+where
+    T: StorageTarget + Clone + Send + 'static,
+    T::Segment: Send + 'static,
+{
+    /// Wraps `target`, queuing at most `capacity` segments for finalizing.
+    pub fn new(target: T, capacity: usize) -> Self {
+        let (queue, segments) = sync_channel::<(String, T::Segment)>(capacity.max(1));
+        let stats = Arc::new(Mutex::new(QueueStats::default()));
+        let mut worker_target = target.clone();
+        let worker_stats = stats.clone();
+        let worker = std::thread::spawn(move || {
+            for (name, segment) in segments {
+                let start = Instant::now();
+                let result = worker_target.finalize(&name, segment);
+                let mut stats = lock(&worker_stats);
+                stats.depth -= 1;
+                stats.finalize_time += start.elapsed();
+                match result {
+                    Ok(()) => stats.finalized += 1,
+                    Err(err) => {
+                        stats.failed += 1;
+                        stats.last_error = Some(err);
+                    }
+                }
+            }
+        });
+        BackgroundTarget { target, queue: Some(queue), worker: Some(worker), stats }
+    }
+}
+
+impl<T: StorageTarget> BackgroundTarget<T> {
+    /// The statistics so far.
+    pub fn stats(&self) -> QueueStats {
+        lock(&self.stats).clone()
+    }
+
+    /// The wrapped target.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    /// Waits until the queued segments are finalized and returns the final statistics.
+    pub fn finish(mut self) -> QueueStats {
+        self.shutdown();
+        self.stats()
+    }
+
+    fn shutdown(&mut self) {
+        self.queue = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<T: StorageTarget> StorageTarget for BackgroundTarget<T> {
+    type Segment = T::Segment;
+    type Reader = T::Reader;
+
+    fn open_segment(&mut self, name: &str) -> Result<T::Segment> {
+        self.target.open_segment(name)
+    }
+
+    /// Queues the segment for the worker thread without waiting, or fails if the queue is full.
+    fn finalize(&mut self, name: &str, segment: T::Segment) -> Result<()> {
+        let start = Instant::now();
+        let queue = self.queue.as_ref().ok_or_else(|| MjpegError::Io("background target is shut down".to_string()))?;
+        // Count the segment first, as the worker may finish it before `try_send` returns
+        lock(&self.stats).depth += 1;
+        let result = queue.try_send((name.to_string(), segment));
+        let mut stats = lock(&self.stats);
+        stats.max_enqueue_time = stats.max_enqueue_time.max(start.elapsed());
+        match result {
+            Ok(()) => {
+                stats.max_depth = stats.max_depth.max(stats.depth);
+                Ok(())
+            }
+            Err(err) => {
+                stats.depth -= 1;
+                stats.rejected += 1;
+                Err(match err {
+                    TrySendError::Full(_) => MjpegError::Io(format!("finalize queue is full, segment {} rejected", name)),
+                    TrySendError::Disconnected(_) => MjpegError::Io("background target worker stopped".to_string()),
+                })
+            }
+        }
+    }
+
+    fn delete(&mut self, name: &str) -> Result<()> {
+        self.target.delete(name)
+    }
+
+    fn list(&mut self) -> Result<Vec<StoredSegment>> {
+        self.target.list()
+    }
+
+    fn open_read(&mut self, name: &str) -> Result<T::Reader> {
+        self.target.open_read(name)
+    }
+}
+
+impl<T: StorageTarget> Drop for BackgroundTarget<T> {
+    /// Waits for the queued segments, so none is lost when the recorder stops.
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn lock(stats: &Mutex<QueueStats>) -> MutexGuard<'_, QueueStats> {
+    stats.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -0,0 +1,208 @@
+use std::io::{IoSlice, SeekFrom};
+use std::time::{Duration, Instant};
+use crate::Result;
+use crate::clock::{Clock, SystemClock};
+use crate::writer::Writer;
+
+/// A hook called after buffered data has been written to the underlying writer.
+type FlushHook<W> = Box<dyn FnMut(&mut W) -> Result<()> + Send>;
+
+/// How long a [`BatchWriter`] keeps data in RAM before writing it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerPolicy {
+    /// The longest time data stays buffered. Data is flushed with the first write after it.
+    pub flush_interval: Duration,
+    /// The most bytes buffered; a write that fills the buffer flushes it early.
+    pub max_buffered: usize,
+}
+
+impl PowerPolicy {
+    /// A policy flushing every `flush_interval` or `max_buffered` bytes, whichever comes first.
+    pub fn new(flush_interval: Duration, max_buffered: usize) -> Self {
+        PowerPolicy { flush_interval, max_buffered }
+    }
+
+    /// For battery-powered recorders: flushes every 30 seconds or 8MB, so the storage sleeps
+    /// between bursts.
+    pub fn battery() -> Self {
+        Self::new(Duration::from_secs(30), 8 << 20)
+    }
+}
+
+impl Default for PowerPolicy {
+    /// Flushes every second or 1MB.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), 1 << 20)
+    }
+}
+
+/// Statistics collected by [`BatchWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchStats {
+    /// The number of flushes.
+    pub flushes: u64,
+    /// The number of flushes because the buffer was full.
+    pub full_flushes: u64,
+    /// The most bytes buffered at once.
+    pub max_buffered: usize,
+    /// The number of writes into data flushed before, like the header rewrite at finish.
+    pub rewrites: u64,
+}
+
+/// A writer for battery-operated recorders, such as wildlife cameras and dashcams, that
+/// batches writes and syncs into long intervals so the storage and the CPU can idle.
+///
+/// Appended data is kept in RAM and written in one go when the [`PowerPolicy`]'s interval has
+/// passed or its buffer limit is reached, followed by the hook set with
+/// [`on_flush`](Self::on_flush), such as `File::sync_data`. Updates of buffered data, like list
+/// sizes, are applied in RAM. Data still buffered is lost on power failure, so the interval
+/// bounds how much of a recording a crash can cost; [`flush`](Self::flush) writes it out
+/// early, e.g. when an event is detected, and [`into_inner`](Self::into_inner) after
+/// `finish()` writes the rest. Time is read through a [`Clock`], the system clock by default.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{BatchWriter, MjpegAviWriter, MjpegWriter, PowerPolicy};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut batch = BatchWriter::new(File::create("/sdcard/trail.avi")?, PowerPolicy::battery());
+///     batch.on_flush(|file: &mut File| Ok(file.sync_data()?));
+///     let mut writer = MjpegWriter::new(batch, 1280, 720, 5)?;
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     let batch = writer.finish()?;
+///     println!("{:?}", batch.stats());
+///     batch.into_inner()?;
+///     Ok(())
+/// }
+/// ```
+pub struct BatchWriter<W: Writer, C: Clock = SystemClock> {
+    inner: W,
+    policy: PowerPolicy,
+    clock: C,
+    buf: Vec<u8>,
+    /// File offset of `buf[0]`; the buffer always holds the end of the data
+    buf_start: u64,
+    /// Current position
+    pos: u64,
+    /// When the buffer was last flushed
+    flushed: Instant,
+    hook: Option<FlushHook<W>>,
+    stats: BatchStats,
+}
+
+impl<W: Writer> BatchWriter<W> {
+    /// Wraps `inner`, which must be empty and positioned at its start.
+    pub fn new(inner: W, policy: PowerPolicy) -> Self {
+        Self::with_clock(inner, policy, SystemClock)
+    }
+}
+
+impl<W: Writer, C: Clock> BatchWriter<W, C> {
+    /// Wraps `inner`, reading the time from `clock`.
+    pub fn with_clock(inner: W, policy: PowerPolicy, clock: C) -> Self {
+        let flushed = clock.now();
+        BatchWriter { inner, policy, clock, buf: Vec::new(), buf_start: 0, pos: 0, flushed, hook: None, stats: BatchStats::default() }
+    }
+
+    /// Sets the hook called after every flush, such as `File::sync_data`.
+    pub fn on_flush<F: FnMut(&mut W) -> Result<()> + Send + 'static>(&mut self, hook: F) -> &mut Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Changes the policy, e.g. to flush more often while on external power.
+    pub fn set_policy(&mut self, policy: PowerPolicy) {
+        self.policy = policy;
+    }
+
+    /// The number of bytes buffered.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The statistics of the writes so far.
+    pub fn stats(&self) -> &BatchStats {
+        &self.stats
+    }
+
+    /// Writes the buffered data and calls the flush hook.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flushed = self.clock.now();
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.inner.seek(SeekFrom::Start(self.buf_start))?;
+        self.inner.write_all(&self.buf)?;
+        self.buf_start += self.buf.len() as u64;
+        self.buf.clear();
+        self.stats.flushes += 1;
+        if let Some(hook) = &mut self.hook {
+            hook(&mut self.inner)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the buffered data and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    /// The end of the data written so far, including the buffer.
+    fn len(&self) -> u64 {
+        self.buf_start + self.buf.len() as u64
+    }
+}
+
+impl<W: Writer, C: Clock> Writer for BatchWriter<W, C> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        let end = self.pos + buf.len() as u64;
+        if self.pos > self.len() {
+            // Writing past the end: fill the gap as a file would
+            self.buf.resize((self.pos - self.buf_start) as usize, 0);
+        }
+        if self.pos >= self.buf_start {
+            let at = (self.pos - self.buf_start) as usize;
+            let overlap = (self.buf.len() - at).min(buf.len());
+            self.buf[at..at + overlap].copy_from_slice(&buf[..overlap]);
+            self.buf.extend_from_slice(&buf[overlap..]);
+            self.pos = end;
+            self.stats.max_buffered = self.stats.max_buffered.max(self.buf.len());
+            if self.buf.len() >= self.policy.max_buffered {
+                self.stats.full_flushes += 1;
+                return self.flush();
+            }
+            if self.clock.now().saturating_duration_since(self.flushed) >= self.policy.flush_interval {
+                return self.flush();
+            }
+            return Ok(());
+        }
+
+        // Writes into the flushed data, like the header rewrite, go straight through
+        if end > self.buf_start {
+            self.flush()?;
+        }
+        self.stats.rewrites += 1;
+        self.inner.seek(SeekFrom::Start(self.pos))?;
+        self.inner.write_all(buf)?;
+        self.pos = end;
+        self.buf_start = self.buf_start.max(end);
+        Ok(())
+    }
+
+    fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        bufs.iter().try_for_each(|buf| self.write_all(buf))
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = new_pos.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
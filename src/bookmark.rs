@@ -0,0 +1,38 @@
+use std::time::Duration;
+use crate::frame_map::frame_timestamp;
+
+/// The chunk ID of bookmark chunks in the `movi` list.
+pub(crate) const BOOKMARK_CHUNK_ID: [u8; 4] = *b"bkmk";
+
+/// A labeled position in a recording, added with `add_bookmark()`.
+///
+/// Bookmarks are stored as `bkmk` chunks in the `movi` list with the `AVIIF_NO_TIME` index
+/// flag, so players ignore them. The payload is the frame number (`u32`, little-endian)
+/// followed by the UTF-8 label.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Bookmark {
+    /// The number of the first frame after the bookmark.
+    pub frame: u32,
+    /// The timestamp of that frame.
+    pub timestamp: Duration,
+    /// The label.
+    pub label: String,
+}
+
+pub(crate) fn bookmark_payload(frame: u32, label: &str) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(4 + label.len());
+    payload.extend_from_slice(&frame.to_le_bytes());
+    payload.extend_from_slice(label.as_bytes());
+    payload
+}
+
+/// Parses a bookmark chunk payload, ignoring the padding byte.
+pub(crate) fn parse_bookmark(payload: &[u8], scale: u32, rate: u32) -> Option<Bookmark> {
+    let frame = u32::from_le_bytes(payload.get(..4)?.try_into().unwrap());
+    let label = payload[4..].strip_suffix(&[0]).unwrap_or(&payload[4..]);
+    Some(Bookmark {
+        frame,
+        timestamp: frame_timestamp(frame as u64, scale, rate),
+        label: String::from_utf8_lossy(label).into_owned(),
+    })
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// Statistics of a [`FramePool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FramePoolStats {
+    /// The number of buffers allocated.
+    pub allocated: u64,
+    /// The number of buffers handed out again after they were returned.
+    pub reused: u64,
+    /// The number of requests that had to wait for a buffer to be returned.
+    pub waited: u64,
+    /// The number of buffers currently handed out.
+    pub in_use: usize,
+}
+
+/// A bounded pool of reusable frame buffers shared by the stages of a recording pipeline.
+///
+/// At high frame rates, allocating a buffer for every frame in the camera thread and freeing it
+/// in the writer thread costs more than the muxing itself. A pool hands out at most `buffers`
+/// [`PooledFrame`]s, which return their allocation to the pool when dropped, from any thread
+/// or task, so a steady-state recording allocates nothing per frame. A stage that runs ahead
+/// waits in [`get`](Self::get) or [`get_async`](Self::get_async) until a buffer is returned,
+/// which also bounds the frames queued between the stages.
+///
+/// Pooled frames go through a channel to the writer thread: `Receiver<PooledFrame>` is a
+/// [`FrameSource`](crate::FrameSource) whose [`next_frame_into`](crate::FrameSource::next_frame_into)
+/// swaps buffers instead of copying. They are also the output buffer of
+/// [`FrameEncoder::encode_into`](crate::FrameEncoder::encode_into) with the `encode` feature.
+/// Clones share the pool.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{pull_at_fps, FramePool, MissedTickPolicy, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+/// use std::io::Read;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let pool = FramePool::new(8, 256 * 1024);
+///     let (sender, receiver) = std::sync::mpsc::channel();
+///     std::thread::spawn(move || -> mjpeg_avi_rs::Result<()> {
+///         let mut camera = File::open("/dev/camera")?;
+///         loop {
+///             let mut frame = pool.get();
+///             // Read one JPEG from the camera here
+///             camera.read_to_end(&mut frame)?;
+///             if sender.send(frame).is_err() {
+///                 return Ok(());
+///             }
+///         }
+///     });
+///
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 30)?;
+///     pull_at_fps(&mut writer, 30, MissedTickPolicy::Skip, receiver)?;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FramePool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    returned: Condvar,
+    #[cfg(feature = "tokio")]
+    returned_async: tokio::sync::Notify,
+    buffers: usize,
+    frame_capacity: usize,
+}
+
+struct State {
+    free: Vec<Vec<u8>>,
+    /// The number of buffers in circulation, free or handed out
+    created: usize,
+    stats: FramePoolStats,
+}
+
+impl FramePool {
+    /// Creates a pool of at most `buffers` buffers (at least 1), each allocated with room for
+    /// `frame_capacity` bytes when first needed.
+    pub fn new(buffers: usize, frame_capacity: usize) -> Self {
+        FramePool {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State { free: Vec::new(), created: 0, stats: FramePoolStats::default() }),
+                returned: Condvar::new(),
+                #[cfg(feature = "tokio")]
+                returned_async: tokio::sync::Notify::new(),
+                buffers: buffers.max(1),
+                frame_capacity,
+            }),
+        }
+    }
+
+    /// Returns an empty buffer, blocking the thread until one is returned if all are in use.
+    pub fn get(&self) -> PooledFrame {
+        let mut state = self.shared.lock();
+        let mut waited = false;
+        loop {
+            if let Some(buf) = self.shared.take(&mut state) {
+                state.stats.waited += waited as u64;
+                return self.frame(buf);
+            }
+            waited = true;
+            state = self.shared.returned.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// Returns an empty buffer, or `None` if all are in use.
+    pub fn try_get(&self) -> Option<PooledFrame> {
+        let buf = self.shared.take(&mut self.shared.lock())?;
+        Some(self.frame(buf))
+    }
+
+    /// Asynchronous version of [`get`](Self::get), waiting without blocking the runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn get_async(&self) -> PooledFrame {
+        let mut waited = false;
+        loop {
+            // Registered before checking, so a buffer returned in between wakes this task
+            let mut returned = std::pin::pin!(self.shared.returned_async.notified());
+            returned.as_mut().enable();
+            {
+                let mut state = self.shared.lock();
+                if let Some(buf) = self.shared.take(&mut state) {
+                    state.stats.waited += waited as u64;
+                    return self.frame(buf);
+                }
+            }
+            waited = true;
+            returned.await;
+        }
+    }
+
+    /// The statistics of the pool.
+    pub fn stats(&self) -> FramePoolStats {
+        self.shared.lock().stats
+    }
+
+    fn frame(&self, buf: Vec<u8>) -> PooledFrame {
+        PooledFrame { buf: Some(buf), shared: self.shared.clone() }
+    }
+}
+
+impl fmt::Debug for FramePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FramePool")
+            .field("buffers", &self.shared.buffers)
+            .field("frame_capacity", &self.shared.frame_capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The free list stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Takes a free buffer, or allocates one if fewer than `buffers` exist.
+    fn take(&self, state: &mut State) -> Option<Vec<u8>> {
+        let buf = if let Some(buf) = state.free.pop() {
+            state.stats.reused += 1;
+            buf
+        } else if state.created < self.buffers {
+            state.created += 1;
+            state.stats.allocated += 1;
+            Vec::with_capacity(self.frame_capacity)
+        } else {
+            return None;
+        };
+        state.stats.in_use += 1;
+        Some(buf)
+    }
+
+    fn release(&self, buf: Option<Vec<u8>>) {
+        let mut state = self.lock();
+        state.stats.in_use -= 1;
+        match buf {
+            Some(mut buf) => {
+                buf.clear();
+                state.free.push(buf);
+            }
+            // Detached, so another buffer may be allocated in its place
+            None => state.created -= 1,
+        }
+        drop(state);
+        self.returned.notify_one();
+        #[cfg(feature = "tokio")]
+        self.returned_async.notify_one();
+    }
+}
+
+/// A buffer of a [`FramePool`], returned to the pool when dropped.
+///
+/// Dereferences to `Vec<u8>`, so it is filled like any vector and written with
+/// `writer.add_frame(&frame)`.
+pub struct PooledFrame {
+    /// Always `Some` until dropped or detached
+    buf: Option<Vec<u8>>,
+    shared: Arc<Shared>,
+}
+
+impl PooledFrame {
+    /// Takes the buffer out of the pool, e.g. to keep the frame. The pool may allocate a new
+    /// buffer in its place.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.buf.take().unwrap_or_default()
+    }
+}
+
+impl Deref for PooledFrame {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        self.buf.as_ref().expect("pooled frame is only empty when dropped")
+    }
+}
+
+impl DerefMut for PooledFrame {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        self.buf.as_mut().expect("pooled frame is only empty when dropped")
+    }
+}
+
+impl Drop for PooledFrame {
+    fn drop(&mut self) {
+        self.shared.release(self.buf.take());
+    }
+}
+
+impl fmt::Debug for PooledFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledFrame").field("len", &self.len()).field("capacity", &self.capacity()).finish()
+    }
+}
@@ -0,0 +1,597 @@
+use crate::{MjpegError, Result};
+use crate::common::*;
+use crate::audio::{add_audio_stream, AudioCodec, AudioFormat, InterleavePolicy};
+use crate::data::add_data_stream;
+use crate::metadata::{add_info_list, AviMetadata};
+use crate::overrides::{check_header, HeaderOverrides};
+use crate::timecode::{with_start_timecode, TimecodeSource, TIMECODE_FOURCC};
+use crate::producer::{add_producer_chunk, ProducerInfo};
+use crate::mjpeg_sync::MjpegWriter;
+use crate::riff::le_u32;
+use crate::writer::Writer;
+use crate::estimate::{estimate_with_state, SizeEstimate};
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+use crate::mjpeg_async::MjpegAsyncWriter;
+#[cfg(any(feature = "async", feature = "tokio"))]
+use crate::writer::AsyncWriter;
+#[cfg(any(feature = "async", feature = "tokio", feature = "embedded-io"))]
+use crate::mjpeg_local::MjpegLocalWriter;
+#[cfg(any(feature = "async", feature = "tokio", feature = "embedded-io"))]
+use crate::writer::LocalAsyncWriter;
+
+/// `AVIF_HASINDEX`: the file has an `idx1` index.
+const AVIF_HASINDEX: u32 = 0x10;
+/// The largest width and height of a JPEG image
+const MAX_DIMENSION: u32 = u16::MAX as u32;
+
+/// How [`MjpegWriterBuilder`] handles frame sizes that aren't a multiple of a block size, such
+/// as odd widths or heights that don't fill whole 8x8 or 16x16 JPEG blocks (MCUs).
+///
+/// JPEG itself allows any size, but some hardware decoders and players refuse files whose
+/// declared size isn't even or MCU-aligned, without saying why. The JPEG frames are always
+/// written as they are; the policy only concerns the sizes declared in the header. `multiple`
+/// must be a power of two of at least 2, such as 2, 8 or 16, or `build()` fails with
+/// [`MjpegError::InvalidFrameSize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DimensionPolicy {
+    /// Declares the sizes as given.
+    #[default]
+    Keep,
+    /// Fails to build with [`MjpegError::UnalignedDimensions`] if a video stream's width or
+    /// height isn't a multiple of `multiple`, to catch such sources before recording.
+    Reject { multiple: u32 },
+    /// Rounds the declared sizes up to a multiple of `multiple`. Decoders then get an aligned
+    /// size; players that size the picture from the header show the extra rows and columns,
+    /// usually as the edge pixels JPEG encoders repeat to fill the last block.
+    Pad { multiple: u32 },
+}
+
+impl DimensionPolicy {
+    /// The size declared for frames of `width` x `height`.
+    fn declared(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            DimensionPolicy::Pad { multiple } if multiple.is_power_of_two() => (
+                width.checked_next_multiple_of(multiple).unwrap_or(width),
+                height.checked_next_multiple_of(multiple).unwrap_or(height),
+            ),
+            _ => (width, height),
+        }
+    }
+
+    /// Checks the policy and the frame size of a video stream.
+    fn check(self, width: u32, height: u32) -> Result<()> {
+        match self {
+            DimensionPolicy::Keep => Ok(()),
+            DimensionPolicy::Reject { multiple } | DimensionPolicy::Pad { multiple } if !multiple.is_power_of_two() || multiple < 2 => {
+                Err(MjpegError::InvalidFrameSize)
+            }
+            DimensionPolicy::Reject { multiple } if !width.is_multiple_of(multiple) || !height.is_multiple_of(multiple) => {
+                Err(MjpegError::UnalignedDimensions(width, height))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A builder for [`MjpegWriter`] (and `MjpegAsyncWriter`).
+///
+/// The AVI header only depends on the configuration, so the builder prepares it once and
+/// copies it into every writer it creates. Keep one builder per configuration when
+/// rotating files frequently; creating each new file then costs little more than opening it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriterBuilder};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let builder = MjpegWriterBuilder::new(320, 240, 30);
+///     for segment in 0..24 {
+///         let file = File::create(format!("segment_{:02}.avi", segment))?;
+///         let mut writer = builder.build(file)?;
+///         writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///         writer.finish()?;
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MjpegWriterBuilder {
+    width: u32,
+    height: u32,
+    fps: u32,
+    header: Vec<u8>,
+    reserved: u32,
+    riff_limit: Option<u64>,
+    video_streams: Vec<(u32, u32)>,
+    audio: Option<AudioFormat>,
+    interleave: InterleavePolicy,
+    data_streams: Vec<[u8; 4]>,
+    timecode: Option<TimecodeSource>,
+    metadata: AviMetadata,
+    aspect_ratio: Option<(u16, u16)>,
+    frame_alignment: u32,
+    padding_granularity: u32,
+    write_index: bool,
+    header_overrides: HeaderOverrides,
+    stream_quality: Option<u32>,
+    dimension_policy: DimensionPolicy,
+    allow_4gb: bool,
+    unchecked_dimensions: bool,
+}
+
+impl MjpegWriterBuilder {
+    /// Creates a builder for frames of `width` x `height` played at `fps`.
+    ///
+    /// `build()` fails with [`MjpegError::ZeroDimensions`] if `width` or `height` is 0, and with
+    /// [`MjpegError::DimensionsTooLarge`] if either is larger than the 65535 pixels a JPEG image
+    /// can have, unless [`unchecked_dimensions`](Self::unchecked_dimensions) is set. Other
+    /// sizes are declared as given; see [`dimension_policy`](Self::dimension_policy) for sizes
+    /// that are odd or not a multiple of the JPEG block size.
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        let mut builder = MjpegWriterBuilder {
+            width,
+            height,
+            fps,
+            header: Vec::new(),
+            reserved: 0,
+            riff_limit: None,
+            video_streams: Vec::new(),
+            audio: None,
+            interleave: InterleavePolicy::Immediate,
+            data_streams: Vec::new(),
+            timecode: None,
+            metadata: AviMetadata::default(),
+            aspect_ratio: None,
+            frame_alignment: 0,
+            padding_granularity: 0,
+            write_index: true,
+            header_overrides: HeaderOverrides::default(),
+            stream_quality: None,
+            dimension_policy: DimensionPolicy::Keep,
+            allow_4gb: false,
+            unchecked_dimensions: false,
+        };
+        builder.prepare_header();
+        builder
+    }
+
+    /// The width of the video frames.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the video frames.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The frames per second of the video.
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Sets the display aspect ratio of the video, `width`:`height`, for sources with
+    /// non-square pixels, e.g. 4:3 for 720x480 DV-sized frames, which would otherwise be shown
+    /// stretched to 3:2.
+    ///
+    /// The ratio is written in an OpenDML `vprp` video properties chunk in the header of video
+    /// stream 0, which players such as FFmpeg and VLC follow. Default: none, so players
+    /// assume square pixels.
+    pub fn aspect_ratio(&mut self, width: u16, height: u16) -> &mut Self {
+        self.aspect_ratio = Some((width, height));
+        self.prepare_header();
+        self
+    }
+
+    /// Reserves `bytes` of `JUNK` space after the standard header, rounded up to an even size.
+    ///
+    /// Data that is only known later, such as metadata gathered during the recording, can
+    /// then be stored in the header with
+    /// [`MjpegWriter::write_header_chunk`](crate::MjpegWriter::write_header_chunk) and is
+    /// written with the header at `finish()`, without moving the frames. Players skip `JUNK`
+    /// chunks. Calling it again replaces the reservation. Default: 0.
+    pub fn reserve_header(&mut self, bytes: u32) -> &mut Self {
+        self.reserved = bytes;
+        self.prepare_header();
+        self
+    }
+
+    /// Aligns every video frame chunk to a file offset that is a multiple of `bytes`, such as
+    /// 512 or 4096, by writing a `JUNK` chunk before it where needed.
+    ///
+    /// Frames that start on sector boundaries write faster to SD cards and other flash storage,
+    /// and let an [`AlignedFile`](crate::AlignedFile) or another `O_DIRECT` writer issue each
+    /// frame without a read-modify-write of the sector before it. The padding costs up to
+    /// `bytes + 6` bytes per frame; players and [`MjpegReader`](crate::MjpegReader) skip `JUNK`
+    /// chunks. Alignments that are not a power of two of at least 8 make `build()` fail with
+    /// [`MjpegError::InvalidFrameSize`]. Default: 0, not aligned.
+    pub fn align_frames(&mut self, bytes: u32) -> &mut Self {
+        self.frame_alignment = bytes;
+        self.prepare_header();
+        self
+    }
+
+    /// Pads the `movi` list so that every chunk, frames as well as audio, data and custom
+    /// chunks, starts at a file offset that is a multiple of `bytes`, and stores `bytes` as the
+    /// `dwPaddingGranularity` of the `avih` header.
+    ///
+    /// Some hardware players and capture-card tools read the chunks in blocks of the padding
+    /// granularity and only play files that keep to it. The padding is written as `JUNK`
+    /// chunks, as with [`align_frames`](Self::align_frames), which can be combined with it for a
+    /// larger alignment of the frames. The same alignment rules apply. Default: 0, no padding.
+    pub fn padding_granularity(&mut self, bytes: u32) -> &mut Self {
+        self.padding_granularity = bytes;
+        self.prepare_header();
+        self
+    }
+
+    /// Sets whether the `idx1` index is written at the end of the first RIFF.
+    ///
+    /// Pipelines that post-process the files, or index them with another tool, can turn it off
+    /// to save the index writes at `finish()`; the `AVIF_HASINDEX` flag of the `avih` header is
+    /// then cleared, so players scan the `movi` list instead, as
+    /// [`MjpegReader`](crate::MjpegReader) does. The OpenDML indexes are still written with
+    /// [`open_dml`](Self::open_dml). Default: true.
+    pub fn write_index(&mut self, write_index: bool) -> &mut Self {
+        self.write_index = write_index;
+        self.prepare_header();
+        self
+    }
+
+    /// Turns off the checks of the frame sizes of the video streams for 0 and for more than
+    /// 65535 pixels, for exotic uses such as files that only carry data streams, or frames in
+    /// another codec. The header then declares the sizes as given. Default: false.
+    pub fn unchecked_dimensions(&mut self, unchecked: bool) -> &mut Self {
+        self.unchecked_dimensions = unchecked;
+        self
+    }
+
+    /// Sets how frame sizes that are odd or not a multiple of the JPEG block size are declared
+    /// in the header, see [`DimensionPolicy`]. It applies to every video stream.
+    /// Default: [`DimensionPolicy::Keep`].
+    pub fn dimension_policy(&mut self, policy: DimensionPolicy) -> &mut Self {
+        self.dimension_policy = policy;
+        self.prepare_header();
+        self
+    }
+
+    /// Records the quality of the JPEG frames as the `dwQuality` of video stream 0, from 0 to
+    /// 10000, e.g. 7500 for frames encoded at JPEG quality 75.
+    ///
+    /// Some editing tools read it to pick the quality when re-compressing. Encoders that only
+    /// learn their quality while recording can set it on the writer with `set_stream_quality()`
+    /// instead, and [`estimate_quality`](crate::estimate_quality) estimates it from a frame.
+    /// Values above 10000 make `build()` fail with [`MjpegError::InvalidStreamQuality`]. Default: 0.
+    pub fn stream_quality(&mut self, quality: u32) -> &mut Self {
+        self.stream_quality = Some(quality);
+        self.prepare_header();
+        self
+    }
+
+    /// Writes the fields set in `overrides` over the values the builder computes for the `avih`
+    /// header and the `strh` and `strf` headers of video stream 0, see [`HeaderOverrides`].
+    ///
+    /// This is an escape hatch for legacy decoders that need unusual values; the writer keeps
+    /// writing the file as configured, so overriding e.g. `avih_flags` doesn't change what is
+    /// written. Default: no overrides.
+    pub fn header_overrides(&mut self, overrides: HeaderOverrides) -> &mut Self {
+        self.header_overrides = overrides;
+        self.prepare_header();
+        self
+    }
+
+    /// Raises the size limit of a RIFF from 2GB to 4GB, for players that read the RIFF sizes as
+    /// unsigned, as most modern players do.
+    ///
+    /// Without [`open_dml`](Self::open_dml), files can then grow to 4GB before
+    /// [`MjpegError::FileSizeExceeded`]; with it, `max_riff_size` is capped at 4GB instead. Older
+    /// players and tools that read the sizes as signed fail on files beyond 2GB, so only use it
+    /// where the playback environment is known. Default: false.
+    pub fn allow_4gb(&mut self, allow: bool) -> &mut Self {
+        self.allow_4gb = allow;
+        self.prepare_header();
+        self
+    }
+
+    /// Enables OpenDML (AVI 2.0) extension segments, so a file can grow beyond 2GB.
+    ///
+    /// When a chunk doesn't fit in a RIFF of `max_riff_size` bytes (capped at 2GB, or 4GB with
+    /// [`allow_4gb`](Self::allow_4gb)), the writer
+    /// continues in a new `RIFF AVIX` segment instead of returning
+    /// [`MjpegError::FileSizeExceeded`]. The first RIFF keeps the `idx1` index of its frames for
+    /// players without OpenDML support, which only play that part; the frame count in the
+    /// `dmlh` header and the stream length cover all frames. Lists started with `begin_list()`
+    /// can't span segments. 1GB is a common choice. Default: disabled.
+    ///
+    /// Every RIFF also gets an `ix00` standard index of its frames, listed in an `indx` super
+    /// index in the header, so OpenDML players seek without reading `idx1`. The super index
+    /// adds about 4KB to the header and has room for 256 RIFFs.
+    pub fn open_dml(&mut self, max_riff_size: u64) -> &mut Self {
+        self.riff_limit = Some(max_riff_size);
+        self.prepare_header();
+        self
+    }
+
+    /// Adds another video stream of frames of `width` x `height`, e.g. for the second camera of
+    /// a stereo rig, so both views are recorded in one synchronized file.
+    ///
+    /// The streams are numbered in the order they are added, starting at 1; stream 0 is the one
+    /// given to [`new`](Self::new). Frames are written with `add_frame_for_stream()` as `01dc`,
+    /// `02dc`, ... chunks and play at the same frame rate, so add one frame to each stream per
+    /// frame interval. The `avih` header keeps the dimensions of stream 0, and
+    /// [`MjpegReader`](crate::MjpegReader) reads the frames of stream 0; the others are listed
+    /// in its [`index`](crate::MjpegReader::index). Many players only show the first video
+    /// stream.
+    pub fn video_stream(&mut self, width: u32, height: u32) -> &mut Self {
+        self.video_streams.push((width, height));
+        self.prepare_header();
+        self
+    }
+
+    /// Adds an audio stream of the given format after the video streams: PCM samples, or MP3
+    /// frames from devices that already deliver them, muxed without transcoding.
+    ///
+    /// Samples are written with `add_audio_samples()` between the frames, as `01wb` chunks
+    /// (`02wb` after one more video stream, and so on) interleaved with the `00dc` frames, and
+    /// players keep them in sync by the stream rates. Write about one frame interval of
+    /// samples after each frame. Formats that can't be
+    /// described, such as MP3 at a sample rate MP3 doesn't have, make `build()` fail with
+    /// [`MjpegError::InvalidFrameSize`]. Default: no audio.
+    pub fn audio(&mut self, format: AudioFormat) -> &mut Self {
+        self.audio = Some(format);
+        self.prepare_header();
+        self
+    }
+
+    /// Sets when audio samples are written between the frames. Buffered samples are written
+    /// at the latest by `finish()`. Default: [`InterleavePolicy::Immediate`].
+    pub fn interleave(&mut self, policy: InterleavePolicy) -> &mut Self {
+        self.interleave = policy;
+        self.prepare_header();
+        self
+    }
+
+    /// Adds a data stream identified by `fourcc`, e.g. `*b"GPS "`, for binary samples such as
+    /// GPS fixes or IMU readings that are recorded along with the frames.
+    ///
+    /// Samples are written with `add_data()` as `NNdt` chunks between the frames, where `NN` is
+    /// the stream number after the video and audio streams, and indexed like frames, so
+    /// [`MjpegReader::data_samples`](crate::MjpegReader::data_samples) recovers each with the
+    /// frame it belongs to. The payload format is up to the application. Players skip the
+    /// `dats` stream. Adding the same fourcc twice makes `build()` fail with
+    /// [`MjpegError::InvalidFrameSize`]. Default: none.
+    pub fn data_stream(&mut self, fourcc: [u8; 4]) -> &mut Self {
+        self.data_streams.push(fourcc);
+        self.prepare_header();
+        self
+    }
+
+    /// Adds a timecode track, so each frame carries a timecode that editors such as Premiere
+    /// or Resolve can line up clips by.
+    ///
+    /// The timecodes are written with every frame of stream 0 as `HH:MM:SS:FF` strings in a
+    /// `tmcd` data stream (see [`data_stream`](Self::data_stream)), read back by
+    /// [`MjpegReader::timecodes`](crate::MjpegReader::timecodes). With
+    /// [`TimecodeSource::Smpte`] they count up from the given start, which is also stored as the
+    /// `ISMP` tag of the `INFO` list in the header; with [`TimecodeSource::WallClock`] they are
+    /// the UTC time of day when each frame is written. Timecodes are non-drop-frame at the
+    /// integer frame rate. Default: none.
+    pub fn timecode(&mut self, source: TimecodeSource) -> &mut Self {
+        self.timecode = Some(source);
+        self.prepare_header();
+        self
+    }
+
+    /// Sets the tags of the `INFO` list written into the header of every file, such as the
+    /// title, the creation date (`ICRD`) or the producing software (`ISFT`), see
+    /// [`AviMetadata`].
+    ///
+    /// The list comes before the space reserved with [`reserve_header`](Self::reserve_header),
+    /// so `set_metadata()` on the writer can replace it until `finish()` with a list that fits
+    /// in both, e.g. to add a comment known only at the end of the recording. Default: none.
+    pub fn metadata(&mut self, metadata: AviMetadata) -> &mut Self {
+        self.metadata = metadata;
+        self.prepare_header();
+        self
+    }
+
+    /// The crate version and settings written into the header, see [`ProducerInfo`].
+    pub fn producer_info(&self) -> ProducerInfo {
+        let mut settings = vec![
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("fps", self.fps.to_string()),
+            ("open_dml", self.riff_limit.map_or("off".to_string(), |limit| limit.to_string())),
+        ];
+        if self.allow_4gb {
+            settings.push(("allow_4gb", "on".to_string()));
+        }
+        if self.frame_alignment != 0 {
+            settings.push(("frame_alignment", self.frame_alignment.to_string()));
+        }
+        if self.padding_granularity != 0 {
+            settings.push(("padding_granularity", self.padding_granularity.to_string()));
+        }
+        if !self.write_index {
+            settings.push(("write_index", "off".to_string()));
+        }
+        match self.dimension_policy {
+            DimensionPolicy::Keep => {}
+            DimensionPolicy::Reject { multiple } => settings.push(("dimension_policy", format!("reject {}", multiple))),
+            DimensionPolicy::Pad { multiple } => settings.push(("dimension_policy", format!("pad {}", multiple))),
+        }
+        if let Some(quality) = self.stream_quality {
+            settings.push(("stream_quality", quality.to_string()));
+        }
+        if !self.header_overrides.is_empty() {
+            settings.push(("header_overrides", self.header_overrides.names().join(" ")));
+        }
+        if let Some((width, height)) = self.aspect_ratio {
+            settings.push(("aspect_ratio", format!("{}:{}", width, height)));
+        }
+        let video_streams = self.video_streams.iter().map(|(width, height)| format!("{}x{}", width, height)).collect::<Vec<_>>();
+        if !video_streams.is_empty() {
+            settings.push(("video_streams", video_streams.join(" ")));
+        }
+        if let Some(format) = &self.audio {
+            let audio = match format.codec {
+                AudioCodec::Pcm => format!("pcm {} {} {}", format.sample_rate, format.channels, format.bits_per_sample),
+                AudioCodec::Mp3 { bitrate } => format!("mp3 {} {} {}", format.sample_rate, format.channels, bitrate),
+            };
+            let interleave = match self.interleave {
+                InterleavePolicy::Immediate => "immediate".to_string(),
+                InterleavePolicy::EveryFrames(n) => format!("every_frames {}", n),
+                InterleavePolicy::MaxSkew(skew) => format!("max_skew_ms {}", skew.as_millis()),
+            };
+            settings.extend([("audio", audio), ("interleave", interleave)]);
+        }
+        let data_streams = self.data_streams.iter().map(|fourcc| String::from_utf8_lossy(fourcc).into_owned()).collect::<Vec<_>>();
+        if !data_streams.is_empty() {
+            settings.push(("data_streams", data_streams.join(",")));
+        }
+        match self.timecode {
+            Some(TimecodeSource::Smpte(start)) => settings.push(("timecode", format!("smpte {}", start))),
+            Some(TimecodeSource::WallClock) => settings.push(("timecode", "wall_clock".to_string())),
+            None => {}
+        }
+        ProducerInfo::current(settings.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// The largest size of a RIFF, see [`allow_4gb`](Self::allow_4gb).
+    pub(crate) fn max_riff_size(&self) -> u64 {
+        if self.allow_4gb { MAX_AVI_4GB_FILE_SIZE } else { MAX_AVI_FILE_SIZE }
+    }
+
+    /// Returns true if OpenDML extension segments are enabled, see [`open_dml`](Self::open_dml).
+    pub(crate) fn is_open_dml(&self) -> bool {
+        self.riff_limit.is_some()
+    }
+
+    /// The size of the header written at the start of each file.
+    pub fn header_len(&self) -> usize {
+        self.header.len()
+    }
+
+    /// Creates a new `MjpegWriter` and writes the prepared header to `writer`.
+    pub fn build<W: Writer>(&self, writer: W) -> Result<MjpegWriter<W>> {
+        self.validate()?;
+        MjpegWriter::with_state(writer, self.state())
+    }
+
+    /// Creates a new `MjpegAsyncWriter` and asynchronously writes the prepared header to `writer`.
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    pub async fn build_async<W: AsyncWriter>(&self, writer: W) -> Result<MjpegAsyncWriter<W>> {
+        self.validate()?;
+        MjpegAsyncWriter::with_state(writer, self.state()).await
+    }
+
+    /// Creates a new `MjpegLocalWriter` for a `!Send` writer and asynchronously writes the prepared header.
+    #[cfg(any(feature = "async", feature = "tokio", feature = "embedded-io"))]
+    pub async fn build_local<W: LocalAsyncWriter>(&self, writer: W) -> Result<MjpegLocalWriter<W>> {
+        self.validate()?;
+        MjpegLocalWriter::with_state(writer, self.state()).await
+    }
+
+    /// Predicts the file size and duration for frames of the given sizes, see [`estimate`](crate::estimate).
+    pub fn estimate<I: IntoIterator<Item = usize>>(&self, frame_sizes: I) -> Result<SizeEstimate> {
+        self.validate()?;
+        Ok(estimate_with_state(self.state(), frame_sizes))
+    }
+
+    fn prepare_header(&mut self) {
+        let (width, height) = self.dimension_policy.declared(self.width, self.height);
+        let mut header = create_header_template(self.fps, width, height).to_vec();
+        // avih dwPaddingGranularity
+        header[40..44].copy_from_slice(&self.padding_granularity.to_le_bytes());
+        if let Some(quality) = self.stream_quality {
+            // strh dwQuality
+            header[148..152].copy_from_slice(&quality.to_le_bytes());
+        }
+        if !self.write_index {
+            // avih dwFlags
+            let flags = le_u32(&header, 44) & !AVIF_HASINDEX;
+            header[44..48].copy_from_slice(&flags.to_le_bytes());
+        }
+        if self.riff_limit.is_some() {
+            header = add_super_index(&header);
+        }
+        if let Some(aspect) = self.aspect_ratio {
+            header = add_video_properties(&header, aspect);
+        }
+        for (stream, &(width, height)) in (1..=u8::MAX).zip(&self.video_streams) {
+            let (width, height) = self.dimension_policy.declared(width, height);
+            header = add_video_stream(&header, stream, width, height, self.riff_limit.is_some());
+        }
+        if let Some(format) = &self.audio {
+            header = add_audio_stream(&header, format, self.riff_limit.is_some());
+        }
+        for &fourcc in &self.data_streams {
+            header = add_data_stream(&header, fourcc, self.riff_limit.is_some());
+        }
+        if self.timecode.is_some() {
+            header = add_data_stream(&header, TIMECODE_FOURCC, self.riff_limit.is_some());
+        }
+        header = add_producer_chunk(&header, &self.producer_info());
+        let metadata = with_start_timecode(&self.metadata, self.timecode);
+        if !metadata.is_empty() {
+            header = add_info_list(&header, &metadata);
+        }
+        self.header = reserve_header_space(&header, self.reserved);
+        self.header_overrides.apply(&mut self.header);
+    }
+
+    fn state(&self) -> MoviState {
+        let mut state = MoviState::new(&self.header, self.fps, self.riff_limit);
+        state.set_interleave(self.interleave);
+        state.set_alignment(self.frame_alignment, self.padding_granularity);
+        state.set_write_index(self.write_index);
+        state.set_max_riff_size(self.max_riff_size());
+        state.set_header_overrides(self.header_overrides.clone());
+        state.set_timecode(self.timecode);
+        state
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fps == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        if self.aspect_ratio.is_some_and(|(width, height)| width == 0 || height == 0) {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        if [self.frame_alignment, self.padding_granularity].iter().any(|&bytes| bytes != 0 && (!bytes.is_power_of_two() || bytes < 8)) {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        for &(width, height) in [(self.width, self.height)].iter().chain(&self.video_streams) {
+            if !self.unchecked_dimensions && (width == 0 || height == 0) {
+                return Err(MjpegError::ZeroDimensions(width, height));
+            }
+            if !self.unchecked_dimensions && (width > MAX_DIMENSION || height > MAX_DIMENSION) {
+                return Err(MjpegError::DimensionsTooLarge(width, height));
+            }
+            self.dimension_policy.check(width, height)?;
+        }
+        if let Some(quality) = self.stream_quality.filter(|&quality| quality > MAX_STREAM_QUALITY) {
+            return Err(MjpegError::InvalidStreamQuality(quality));
+        }
+        if !self.header_overrides.is_empty() {
+            check_header(&self.header)?;
+        }
+        if self.audio.is_some_and(|format| !format.is_valid()) {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        // Stream numbers are two hexadecimal digits
+        if 1 + self.video_streams.len() + self.audio.is_some() as usize + self.data_streams.len() + self.timecode.is_some() as usize > 256 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        if self.data_streams.iter().enumerate().any(|(i, fourcc)| self.data_streams[..i].contains(fourcc)) {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        if self.timecode.is_some() && self.data_streams.contains(&TIMECODE_FOURCC) {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        Ok(())
+    }
+}
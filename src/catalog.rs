@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::Result;
+use crate::bookmark::Bookmark;
+use crate::frame_map::frame_timestamp;
+use crate::reader::MjpegReader;
+use crate::target::StorageTarget;
+use crate::timing::rfc3339;
+
+/// One recording in a catalog built by [`catalog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// When the recording started, estimated as the modification time minus the duration.
+    pub start_time: Option<SystemTime>,
+    /// The duration of the recording.
+    pub duration: Duration,
+    /// The width of the video frames.
+    pub width: u32,
+    /// The height of the video frames.
+    pub height: u32,
+    /// The file size in bytes.
+    pub size: u64,
+    /// The bookmarks in the recording.
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// Probes every AVI file in a directory tree.
+///
+/// Files with the `.avi` extension (in any case) are read with [`MjpegReader`], so unfinished
+/// recordings are included. Files that aren't valid AVI files are skipped. The entries are
+/// sorted by path; write them with [`write_catalog_json`] to feed a review UI.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{catalog, write_catalog_json};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut json = String::new();
+///     write_catalog_json(&catalog("/var/recordings")?, &mut json)?;
+///     std::fs::write("/var/recordings/catalog.json", json)?;
+///     Ok(())
+/// }
+/// ```
+pub fn catalog<P: AsRef<Path>>(dir: P) -> Result<Vec<CatalogEntry>> {
+    let mut paths = Vec::new();
+    collect_avi_files(dir.as_ref(), &mut paths)?;
+    paths.sort();
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        entries.extend(probe(file, metadata.len(), metadata.modified().ok(), path)?);
+    }
+    Ok(entries)
+}
+
+/// Probes every segment of a [`StorageTarget`], like [`catalog`] does for a directory.
+///
+/// The entries are sorted by segment name, which becomes their path. Segments that aren't
+/// valid AVI files are skipped.
+pub fn catalog_target<T: StorageTarget>(target: &mut T) -> Result<Vec<CatalogEntry>> {
+    let mut entries = Vec::new();
+    for segment in target.list()? {
+        let reader = target.open_read(&segment.name)?;
+        entries.extend(probe(reader, segment.size, segment.modified, PathBuf::from(segment.name))?);
+    }
+    Ok(entries)
+}
+
+/// Reads the catalog entry of an opened file, `None` if it isn't a valid AVI file.
+fn probe<R: Read + Seek>(file: R, size: u64, modified: Option<SystemTime>, path: PathBuf) -> Result<Option<CatalogEntry>> {
+    let Ok(mut reader) = MjpegReader::new(file) else { return Ok(None) };
+    let info = reader.info();
+    let duration = frame_timestamp(reader.frame_count() as u64, info.scale, info.rate);
+    Ok(Some(CatalogEntry {
+        start_time: modified.and_then(|modified| modified.checked_sub(duration)),
+        duration,
+        width: info.width,
+        height: info.height,
+        size,
+        bookmarks: reader.bookmarks()?,
+        path,
+    }))
+}
+
+/// The result of a [`CatalogIndex::update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CatalogUpdate {
+    /// The number of new or changed files that were probed.
+    pub probed: usize,
+    /// The number of files taken from the previous state.
+    pub unchanged: usize,
+    /// The number of files that were deleted since the previous update.
+    pub removed: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexedFile {
+    size: u64,
+    modified: Option<SystemTime>,
+    /// `None` for files that aren't valid AVI files
+    entry: Option<CatalogEntry>,
+}
+
+const INDEX_HEADER: &str = "mjpeg-avi-rs catalog 1";
+
+/// A catalog kept up to date incrementally, for archives too large to probe on every run.
+///
+/// [`update`](Self::update) only probes files that are new or whose size or modification time
+/// changed since the previous update, and forgets deleted files, so the cost of keeping the
+/// catalog current grows with the new recordings rather than with the archive. Persist the
+/// state between runs with [`save`](Self::save) and [`load`](Self::load); keep one state per
+/// directory.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{write_catalog_json, CatalogIndex};
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut index = match File::open("/var/recordings/.catalog") {
+///         Ok(file) => CatalogIndex::load(BufReader::new(file))?,
+///         Err(_) => CatalogIndex::new(),
+///     };
+///     let update = index.update("/var/recordings")?;
+///     println!("{} files probed", update.probed);
+///     index.save(File::create("/var/recordings/.catalog")?)?;
+///
+///     let mut json = String::new();
+///     write_catalog_json(&index.entries(), &mut json)?;
+///     std::fs::write("/var/recordings/catalog.json", json)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CatalogIndex {
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+impl CatalogIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Brings the index up to date with the AVI files in a directory tree.
+    pub fn update<P: AsRef<Path>>(&mut self, dir: P) -> Result<CatalogUpdate> {
+        let mut paths = Vec::new();
+        collect_avi_files(dir.as_ref(), &mut paths)?;
+
+        let mut update = CatalogUpdate::default();
+        let mut files = BTreeMap::new();
+        for path in paths {
+            let metadata = fs::metadata(&path)?;
+            let (size, modified) = (metadata.len(), metadata.modified().ok());
+            match self.files.remove(&path) {
+                Some(indexed) if indexed.size == size && modified.is_some() && indexed.modified == modified => {
+                    update.unchanged += 1;
+                    files.insert(path, indexed);
+                }
+                _ => {
+                    update.probed += 1;
+                    let file = File::open(&path)?;
+                    let metadata = file.metadata()?;
+                    let (size, modified) = (metadata.len(), metadata.modified().ok());
+                    let entry = probe(file, size, modified, path.clone())?;
+                    files.insert(path, IndexedFile { size, modified, entry });
+                }
+            }
+        }
+        update.removed = self.files.len();
+        self.files = files;
+        Ok(update)
+    }
+
+    /// The entries of the valid AVI files, sorted by path as by [`catalog`].
+    pub fn entries(&self) -> Vec<CatalogEntry> {
+        self.files.values().filter_map(|file| file.entry.clone()).collect()
+    }
+
+    /// Writes the state of the index.
+    ///
+    /// The format is a line-based text format private to this crate; it may change between
+    /// versions, in which case [`load`](Self::load) fails and the archive is probed again.
+    pub fn save<W: Write>(&self, mut out: W) -> Result<()> {
+        writeln!(out, "{}", INDEX_HEADER)?;
+        for (path, file) in &self.files {
+            let modified = file
+                .modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or("-".to_string(), |modified| format!("{}.{:09}", modified.as_secs(), modified.subsec_nanos()));
+            writeln!(out, "F\t{}\t{}\t{}", file.size, modified, escape(&path.to_string_lossy()))?;
+            if let Some(entry) = &file.entry {
+                writeln!(out, "E\t{}\t{}\t{}", entry.duration.as_nanos(), entry.width, entry.height)?;
+                for bookmark in &entry.bookmarks {
+                    writeln!(out, "B\t{}\t{}\t{}", bookmark.frame, bookmark.timestamp.as_nanos(), escape(&bookmark.label))?;
+                }
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Reads a state written by [`save`](Self::save).
+    pub fn load<R: BufRead>(input: R) -> Result<Self> {
+        let mut lines = input.lines();
+        if lines.next().transpose()?.as_deref() != Some(INDEX_HEADER) {
+            return Err(invalid_state());
+        }
+
+        let mut index = CatalogIndex::new();
+        let mut current: Option<(PathBuf, IndexedFile)> = None;
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            match (fields.as_slice(), &mut current) {
+                (["F", size, modified, path], _) => {
+                    let modified = match *modified {
+                        "-" => None,
+                        modified => {
+                            let (secs, nanos) = modified.split_once('.').ok_or_else(invalid_state)?;
+                            Some(UNIX_EPOCH + Duration::new(parse(secs)?, parse(nanos)?))
+                        }
+                    };
+                    let file = IndexedFile { size: parse(size)?, modified, entry: None };
+                    if let Some((path, file)) = current.replace((PathBuf::from(unescape(path)), file)) {
+                        index.files.insert(path, file);
+                    }
+                }
+                (["E", duration, width, height], Some((path, file))) => {
+                    let duration = Duration::from_nanos(parse(duration)?);
+                    file.entry = Some(CatalogEntry {
+                        path: path.clone(),
+                        start_time: file.modified.and_then(|modified| modified.checked_sub(duration)),
+                        duration,
+                        width: parse(width)?,
+                        height: parse(height)?,
+                        size: file.size,
+                        bookmarks: Vec::new(),
+                    });
+                }
+                (["B", frame, timestamp, label], Some((_, IndexedFile { entry: Some(entry), .. }))) => {
+                    entry.bookmarks.push(Bookmark {
+                        frame: parse(frame)?,
+                        timestamp: Duration::from_nanos(parse(timestamp)?),
+                        label: unescape(label),
+                    });
+                }
+                _ => return Err(invalid_state()),
+            }
+        }
+        if let Some((path, file)) = current {
+            index.files.insert(path, file);
+        }
+        Ok(index)
+    }
+}
+
+fn parse<T: std::str::FromStr>(field: &str) -> Result<T> {
+    field.parse().map_err(|_| invalid_state())
+}
+
+fn invalid_state() -> crate::MjpegError {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid catalog index state").into()
+}
+
+/// Escapes the characters that separate fields and lines.
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+pub(crate) fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+fn collect_avi_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_avi_files(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("avi")) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Writes a catalog as JSON.
+///
+/// The output is an array with one object per recording: `path`, `start` (RFC 3339 UTC, or
+/// `null` if unknown), `duration` in seconds, `width`, `height`, `size` in bytes and
+/// `bookmarks`, an array of objects with the frame number, the timestamp `t` in seconds and
+/// the label.
+///
+/// ```text
+/// [{"path":"cam/0001.avi","start":"2024-05-01T12:00:00.000000Z","duration":60.000000,"width":640,"height":480,"size":31457280,"bookmarks":[{"frame":450,"t":15.000000,"label":"door"}]}]
+/// ```
+pub fn write_catalog_json<O: fmt::Write>(catalog: &[CatalogEntry], out: &mut O) -> Result<()> {
+    out.write_char('[')?;
+    for (i, entry) in catalog.iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        out.write_str(r#"{"path":"#)?;
+        write_json_string(&entry.path.to_string_lossy(), out)?;
+        match entry.start_time {
+            Some(start) => write!(out, r#","start":"{}""#, rfc3339(start))?,
+            None => out.write_str(r#","start":null"#)?,
+        }
+        write!(
+            out,
+            r#","duration":{:.6},"width":{},"height":{},"size":{},"bookmarks":["#,
+            entry.duration.as_secs_f64(),
+            entry.width,
+            entry.height,
+            entry.size
+        )?;
+        for (j, bookmark) in entry.bookmarks.iter().enumerate() {
+            if j > 0 {
+                out.write_char(',')?;
+            }
+            write!(out, r#"{{"frame":{},"t":{:.6},"label":"#, bookmark.frame, bookmark.timestamp.as_secs_f64())?;
+            write_json_string(&bookmark.label, out)?;
+            out.write_char('}')?;
+        }
+        out.write_str("]}")?;
+    }
+    out.write_char(']')?;
+    Ok(())
+}
+
+pub(crate) fn write_json_string<O: fmt::Write>(s: &str, out: &mut O) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
@@ -1,37 +1,53 @@
+use std::io::IoSlice;
 use std::mem::MaybeUninit;
+use std::time::SystemTime;
 use crate::{MjpegError, Result};
+use crate::index::{FrameLocation, IndexEntry};
+use crate::frame_map::{frame_map, frame_timestamp, FrameMapEntry};
+use crate::riff::{fourcc, le_u32};
+use crate::audio::{mp3_frames, InterleavePolicy};
+use crate::metadata::AviMetadata;
+use crate::overrides::HeaderOverrides;
+use crate::timecode::{with_start_timecode, Timecode, TimecodeSource};
+use crate::custom::{check_custom_chunk_id, FRAME_META_CHUNK_ID};
 
 pub(crate) const MAX_AVI_FILE_SIZE: u64 = 2_147_483_648 - 1; // 2GB - 1 (AVI RIFF limit)
+pub(crate) const MAX_AVI_4GB_FILE_SIZE: u64 = u32::MAX as u64; // 4GB - 1 (unsigned RIFF sizes)
 pub(crate) const MAX_FRAME_COUNT: u32 = 1_000_000; // 実用的な上限
 
-/// File size calculation results
-#[derive(Debug)]
-pub(crate) struct FileSizes {
-    pub(crate) total_file_size: u32,
-    pub(crate) movi_size: u32,
-    pub(crate) index_size: u32,
-}
+/// Offset of the first chunk in the movi list, relative to the 'movi' list type
+const MOVI_FIRST_CHUNK_OFFSET: u64 = 4;
+/// The offset of the `odml` list in the standard header, where the super index goes
+const SUPER_INDEX_OFFSET: usize = 220;
+/// The number of standard indexes, one per RIFF, the super index has room for
+const SUPER_INDEX_ENTRIES: usize = 256;
+/// The size of an `ix00` standard index chunk without entries
+const STANDARD_INDEX_HEADER_SIZE: u64 = 32;
+/// The size of the `RIFF AVIX` and `LIST movi` headers starting an OpenDML extension segment
+const AVIX_HEADER_SIZE: u64 = 24;
+/// The highest `strh` `dwQuality`
+pub(crate) const MAX_STREAM_QUALITY: u32 = 10_000;
 
-/// Creates a frame chunk header (8 bytes: "00dc" + size)
-pub(crate) fn create_frame_chunk_header(size: u32) -> [u8; 8] {
+/// Creates a chunk header (8 bytes: fourcc + size)
+pub(crate) fn create_chunk_header(chunk_id: [u8; 4], size: u32) -> [u8; 8] {
     let mut chunk = MaybeUninit::<[u8; 8]>::uninit();
     let chunk_ptr = chunk.as_mut_ptr() as *mut u8;
     
     unsafe {
-        chunk_ptr.copy_from_nonoverlapping(b"00dc".as_ptr(), 4);
+        chunk_ptr.copy_from_nonoverlapping(chunk_id.as_ptr(), 4);
         chunk_ptr.add(4).copy_from_nonoverlapping(size.to_le_bytes().as_ptr(), 4);
         chunk.assume_init()
     }
 }
 
 /// Creates an index entry (16 bytes: fourcc + flags + offset + size)
-pub(crate) fn create_index_entry(offset: u32, size: u32) -> [u8; 16] {
+pub(crate) fn create_index_entry(chunk_id: [u8; 4], flags: u32, offset: u32, size: u32) -> [u8; 16] {
     let mut entry = MaybeUninit::<[u8; 16]>::uninit();
     let entry_ptr = entry.as_mut_ptr() as *mut u8;
     
     unsafe {
-        entry_ptr.copy_from_nonoverlapping(b"00dc".as_ptr(), 4);
-        entry_ptr.add(4).copy_from_nonoverlapping(0x10u32.to_le_bytes().as_ptr(), 4);
+        entry_ptr.copy_from_nonoverlapping(chunk_id.as_ptr(), 4);
+        entry_ptr.add(4).copy_from_nonoverlapping(flags.to_le_bytes().as_ptr(), 4);
         entry_ptr.add(8).copy_from_nonoverlapping(offset.to_le_bytes().as_ptr(), 4);
         entry_ptr.add(12).copy_from_nonoverlapping(size.to_le_bytes().as_ptr(), 4);
         entry.assume_init()
@@ -40,55 +56,917 @@ pub(crate) fn create_index_entry(offset: u32, size: u32) -> [u8; 16] {
 
 /// Creates idx1 chunk header (8 bytes: "idx1" + size)
 pub(crate) fn create_idx_header(index_size: u32) -> [u8; 8] {
-    let mut header = MaybeUninit::<[u8; 8]>::uninit();
-    let header_ptr = header.as_mut_ptr() as *mut u8;
-    
-    unsafe {
-        header_ptr.copy_from_nonoverlapping(b"idx1".as_ptr(), 4);
-        header_ptr.add(4).copy_from_nonoverlapping(index_size.to_le_bytes().as_ptr(), 4);
-        header.assume_init()
+    create_chunk_header(*b"idx1", index_size)
+}
+
+/// A chunk that has been checked against the limits and is ready to be written
+pub(crate) struct PreparedChunk {
+    pub(crate) header: [u8; 8],
+    /// A `JUNK` chunk written before the chunk to align it, empty if not needed
+    pub(crate) junk: Vec<u8>,
+    /// The payload has an odd size and needs one padding byte
+    pub(crate) pad: bool,
+    /// The RIFF is full and the chunk starts a new OpenDML extension segment
+    pub(crate) segment_break: Option<SegmentBreak>,
+    entry: IndexEntry,
+    indexed: bool,
+}
+
+/// The end of a full RIFF and the start of a `RIFF AVIX` segment, written before a chunk
+pub(crate) struct SegmentBreak {
+    /// The `idx1` chunk of the first RIFF and the headers of the new segment
+    pub(crate) data: Vec<u8>,
+    /// The sizes of the full RIFF and its `movi` list: (file offset, value)
+    pub(crate) patches: [(u64, [u8; 4]); 2],
+    /// File offset to continue writing at
+    pub(crate) resume: u64,
+}
+
+impl PreparedChunk {
+    /// The buffers to write: the alignment `JUNK` chunk, the chunk header, the payload and the
+    /// padding byte.
+    pub(crate) fn io_slices<'s, 'a: 's, I: ExactSizeIterator<Item = IoSlice<'a>>>(&'s self, payload: I) -> Vec<IoSlice<'s>> {
+        let mut bufs = Vec::with_capacity(payload.len() + 3);
+        if !self.junk.is_empty() {
+            bufs.push(IoSlice::new(&self.junk));
+        }
+        bufs.push(IoSlice::new(&self.header));
+        for buf in payload {
+            bufs.push(buf);
+        }
+        if self.pad {
+            bufs.push(IoSlice::new(&[0u8]));
+        }
+        bufs
     }
 }
 
-/// Calculates final file sizes for AVI format
-pub(crate) fn calculate_file_sizes(frame_sizes: &[u32], jpeg_total_size: u64) -> Result<FileSizes> {
-    let frame_count = frame_sizes.len();
-    
-    // フレーム数がu32に収まることを確認
-    if frame_count > u32::MAX as usize {
-        return Err(MjpegError::FrameCountExceeded);
+/// A LIST header that has been checked against the limits and is ready to be written
+pub(crate) struct PreparedList {
+    pub(crate) header: [u8; 12],
+    index_flags: Option<u32>,
+    list_type: [u8; 4],
+}
+
+/// The size of a finished LIST, to be patched into its header
+pub(crate) struct ListPatch {
+    /// File offset of the size field
+    pub(crate) pos: u64,
+    pub(crate) size: [u8; 4],
+    /// File offset to continue writing at
+    pub(crate) resume: u64,
+}
+
+/// The audio stream of the header and the samples waiting to be interleaved
+struct AudioState {
+    /// The header offset of the `strh` chunk
+    strh: usize,
+    /// The ID of the audio chunks, such as `01wb`
+    chunk_id: [u8; 4],
+    /// `dwSampleSize`: the bytes per sample for PCM, 0 for MP3, which is written one frame per
+    /// chunk
+    sample_size: u32,
+    /// `dwScale` and `dwRate`: a sample (PCM) or frame (MP3) lasts `scale / rate` seconds
+    scale: u32,
+    rate: u32,
+    /// The number of samples (PCM) or frames (MP3) written
+    written: u32,
+    /// Samples buffered by the interleave policy
+    pending: Vec<u8>,
+    /// The number of samples (PCM) or frames (MP3) in `pending`
+    pending_samples: u64,
+    /// The video frame count when the first pending sample was buffered
+    pending_since: u32,
+}
+
+/// A video stream after the first, see `MjpegWriterBuilder::video_stream`
+struct VideoStream {
+    stream: u8,
+    /// The header offset of the `strh` chunk
+    strh: usize,
+    /// The number of frames written
+    frames: u32,
+    /// The largest frame chunk, its `dwSuggestedBufferSize`
+    max_frame: u32,
+}
+
+/// A data stream, see `MjpegWriterBuilder::data_stream`
+struct DataStream {
+    /// The fourcc the stream is identified by, its `fccHandler`
+    fourcc: [u8; 4],
+    chunk_id: [u8; 4],
+    /// The header offset of the `strh` chunk
+    strh: usize,
+    /// The number of samples written
+    samples: u32,
+    /// The largest sample chunk, its `dwSuggestedBufferSize`
+    max_sample: u32,
+}
+
+/// The OpenDML indexes of a stream
+struct StreamIndex {
+    /// The header offset of the stream's `indx` super index
+    super_index: usize,
+    /// The ID of the indexed chunks, such as `00dc`
+    chunk_id: [u8; 4],
+    /// The `dwSampleSize` of the stream, 0 if each chunk is one sample, as video frames and
+    /// MP3 frames are
+    sample_size: u32,
+    /// The entries of the current RIFF: data offset relative to the 'movi' list type and
+    /// size, with bit 31 set for non-keyframes
+    entries: Vec<(u32, u32)>,
+    /// The duration of the entries of the current RIFF in frames or samples
+    duration: u32,
+    /// The file offsets, sizes and durations of the standard index chunks of the full RIFFs
+    finished: Vec<(u64, u32, u32)>,
+}
+
+impl StreamIndex {
+    /// The size of the standard index chunk of the current RIFF
+    fn size(&self) -> u64 {
+        STANDARD_INDEX_HEADER_SIZE + self.entries.len() as u64 * 8
     }
-    let frame_count_u32 = frame_count as u32;
-    
-    // インデックスサイズがオーバーフローしないかチェック
-    let index_size = frame_count_u32.checked_mul(16)
-        .ok_or(MjpegError::FileSizeExceeded)?;
-
-    // ファイルサイズ計算（Python版と同じ計算方法）
-    let header_size = 256u64;
-    let total_file_size = header_size
-        .checked_add(jpeg_total_size)
-        .and_then(|s| s.checked_add(frame_count as u64 * (8 + 16))) // frame chunks + index entries
-        .ok_or(MjpegError::FileSizeExceeded)?;
-        
-    if total_file_size > u32::MAX as u64 {
-        return Err(MjpegError::FileSizeExceeded);
+
+    /// Creates the standard index chunk of the current RIFF, `ix00` for stream 0.
+    fn chunk(&self, movi_base: u64) -> Vec<u8> {
+        let mut chunk = Vec::with_capacity(self.size() as usize);
+        let id = [b'i', b'x', self.chunk_id[0], self.chunk_id[1]];
+        chunk.extend_from_slice(&create_chunk_header(id, self.size() as u32 - 8));
+        chunk.extend_from_slice(&2u16.to_le_bytes()); // wLongsPerEntry
+        chunk.push(0); // bIndexSubType
+        chunk.push(1); // bIndexType: AVI_INDEX_OF_CHUNKS
+        chunk.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(&self.chunk_id);
+        chunk.extend_from_slice(&movi_base.to_le_bytes()); // qwBaseOffset
+        chunk.extend_from_slice(&[0; 4]);
+        for &(offset, size) in &self.entries {
+            chunk.extend_from_slice(&offset.to_le_bytes());
+            chunk.extend_from_slice(&size.to_le_bytes());
+        }
+        chunk
+    }
+}
+
+struct OpenList {
+    /// Offset of the LIST header relative to the 'movi' list type
+    start: u64,
+    /// Position of the list's entry in the index
+    index_pos: Option<usize>,
+}
+
+/// Bookkeeping of the movi list and the idx1 index, shared by the sync and async writers.
+///
+/// The writers only do the I/O: they ask for a chunk to be prepared, write it,
+/// commit it, and at the end write the index and header patches produced here.
+///
+/// With OpenDML, a chunk that doesn't fit in the current RIFF starts a `RIFF AVIX` segment
+/// with its own `movi` list. `idx1` only covers the first RIFF; the chunks of each stream in
+/// every RIFF are also listed in a standard index (`ix00`, `ix01`) at the end of its `movi`
+/// list, which the `indx` super index of the stream in the header points to.
+pub(crate) struct MoviState {
+    /// The idx1 entries of the first RIFF
+    index: Vec<IndexEntry>,
+    /// The header as written when the file was created
+    header: Vec<u8>,
+    header_size: u64,
+    /// Size of the movi list payload, including the 'movi' list type
+    movi_size: u64,
+    frame_count: u32,
+    /// The largest frame chunk of video stream 0
+    max_frame: u32,
+    /// The largest chunk of any stream, the `avih` `dwSuggestedBufferSize`
+    max_chunk: u32,
+    open_lists: Vec<OpenList>,
+    last_frame: Option<FrameLocation>,
+    fps: u32,
+    /// The header offset of the `JUNK` chunk holding the free reserved space
+    reserved: Option<usize>,
+    /// The header offset of the `INFO` list
+    info: Option<usize>,
+    /// The video streams after the first
+    video_streams: Vec<VideoStream>,
+    /// The header offset of the `dmlh` total frame count
+    odml_frames: usize,
+    /// The audio stream, if any
+    audio: Option<AudioState>,
+    data_streams: Vec<DataStream>,
+    /// The source of the frame timecodes written to the `tmcd` data stream, if any
+    timecode: Option<TimecodeSource>,
+    /// When buffered audio samples are written
+    interleave: InterleavePolicy,
+    /// The file offset alignment of video chunks, 0 if not aligned
+    frame_alignment: u64,
+    /// The file offset alignment of all chunks, the padding granularity, 0 if not aligned
+    chunk_alignment: u64,
+    /// Whether the `idx1` index is written at the end of the first RIFF
+    write_index: bool,
+    /// The header fields written over the computed ones at finish
+    header_overrides: HeaderOverrides,
+    /// The `dwQuality` of video stream 0 set while recording, patched at finish
+    stream_quality: Option<u32>,
+    /// The largest RIFF with OpenDML, `None` for a single RIFF
+    riff_limit: Option<u64>,
+    /// The largest RIFF any player must handle, 2GB, or 4GB for players reading the sizes as unsigned
+    max_riff_size: u64,
+    /// File offset of the current RIFF
+    riff_start: u64,
+    /// File offset of the 'movi' list type of the current RIFF
+    movi_base: u64,
+    /// The RIFF size, `movi` list size and frame count of the first RIFF once it is full
+    first_riff: Option<(u32, u32, u32)>,
+    /// The file offsets and sizes of the frame chunks after the first RIFF
+    extended_frames: Vec<(u64, u32)>,
+    /// The OpenDML indexes of the streams, empty without OpenDML
+    stream_indexes: Vec<StreamIndex>,
+}
+
+impl MoviState {
+    pub(crate) fn new(header: &[u8], fps: u32, riff_limit: Option<u64>) -> Self {
+        // Super indexes, more video streams, the audio and data streams and the reserved space
+        // are optional chunks of the standard header
+        let hdrl_end = 20 + le_u32(header, 16) as usize;
+        let mut odml_frames = 0;
+        let mut video_streams = Vec::new();
+        let mut audio = None;
+        let mut data_streams = Vec::new();
+        let mut stream_indexes = Vec::new();
+        let strls = header_chunks(header, 24, hdrl_end).filter(|&(pos, id)| &id == b"LIST" && &header[pos + 8..pos + 12] == b"strl");
+        for (stream, (strl, _)) in strls.enumerate() {
+            let strh = strl + 12;
+            match &header[strh + 8..strh + 12] {
+                b"vids" if stream > 0 => video_streams.push(VideoStream { stream: stream as u8, strh, frames: 0, max_frame: 0 }),
+                b"auds" if audio.is_none() => {
+                    audio = Some(AudioState {
+                        strh,
+                        chunk_id: IndexEntry::chunk_id(stream as u8, IndexEntry::AUDIO),
+                        sample_size: le_u32(header, strh + 52),
+                        scale: le_u32(header, strh + 28),
+                        rate: le_u32(header, strh + 32),
+                        written: 0,
+                        pending: Vec::new(),
+                        pending_samples: 0,
+                        pending_since: 0,
+                    })
+                }
+                b"dats" => data_streams.push(DataStream {
+                    fourcc: fourcc(header, strh + 12),
+                    chunk_id: IndexEntry::chunk_id(stream as u8, IndexEntry::DATA),
+                    strh,
+                    samples: 0,
+                    max_sample: 0,
+                }),
+                _ => {}
+            }
+            for (pos, id) in header_chunks(header, strh, strl + 8 + le_u32(header, strl + 4) as usize) {
+                if &id == b"LIST" && &header[pos + 8..pos + 12] == b"odml" {
+                    odml_frames = pos + 20;
+                } else if &id == b"indx" {
+                    let kind = match &header[strh + 8..strh + 12] {
+                        b"auds" => IndexEntry::AUDIO,
+                        b"dats" => IndexEntry::DATA,
+                        _ => IndexEntry::COMPRESSED_VIDEO,
+                    };
+                    stream_indexes.push(StreamIndex {
+                        super_index: pos,
+                        chunk_id: IndexEntry::chunk_id(stream as u8, kind),
+                        sample_size: le_u32(header, strh + 52), // dwSampleSize
+                        entries: Vec::new(),
+                        duration: 0,
+                        finished: Vec::new(),
+                    });
+                }
+            }
+        }
+        let reserved = header_chunks(header, hdrl_end, header.len()).find(|(_, id)| id == b"JUNK").map(|(pos, _)| pos);
+        let info = header_chunks(header, hdrl_end, header.len())
+            .find(|&(pos, id)| &id == b"LIST" && &header[pos + 8..pos + 12] == b"INFO")
+            .map(|(pos, _)| pos);
+        MoviState {
+            header: header.to_vec(),
+            index: Vec::new(),
+            header_size: header.len() as u64,
+            movi_size: MOVI_FIRST_CHUNK_OFFSET,
+            frame_count: 0,
+            max_frame: 0,
+            max_chunk: 0,
+            open_lists: Vec::new(),
+            last_frame: None,
+            fps,
+            reserved,
+            info,
+            video_streams,
+            odml_frames,
+            audio,
+            data_streams,
+            timecode: None,
+            interleave: InterleavePolicy::Immediate,
+            frame_alignment: 0,
+            chunk_alignment: 0,
+            write_index: true,
+            header_overrides: HeaderOverrides::default(),
+            stream_quality: None,
+            riff_limit,
+            max_riff_size: MAX_AVI_FILE_SIZE,
+            riff_start: 0,
+            // header ends with the 'movi' list type
+            movi_base: header.len() as u64 - MOVI_FIRST_CHUNK_OFFSET,
+            first_riff: None,
+            extended_frames: Vec::new(),
+            stream_indexes,
+        }
+    }
+
+    /// The header as written when the file was created.
+    pub(crate) fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// Converts an offset relative to the 'movi' list type to a file offset
+    fn file_offset(&self, movi_offset: u64) -> u64 {
+        self.movi_base + movi_offset
+    }
+
+    pub(crate) fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    pub(crate) fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    pub(crate) fn last_frame(&self) -> Option<FrameLocation> {
+        self.last_frame
+    }
+
+    /// The locations of the frames written so far.
+    pub(crate) fn frame_map(&self) -> Vec<FrameMapEntry> {
+        let frames = self.index.iter().filter(|entry| entry.is_video() && entry.stream() == Some(0));
+        let mut map = frame_map((0..).zip(frames), self.header_size - MOVI_FIRST_CHUNK_OFFSET, 1, self.fps);
+        let first = map.len() as u64;
+        map.extend(self.extended_frames.iter().zip(first..).map(|(&(offset, len), frame)| FrameMapEntry {
+            timestamp: frame_timestamp(frame, 1, self.fps),
+            offset: offset + 8,
+            len,
+        }));
+        map
+    }
+
+    pub(crate) fn has_open_list(&self) -> bool {
+        !self.open_lists.is_empty()
+    }
+
+    /// The size of the file if it were finished now
+    pub(crate) fn file_size(&self) -> u64 {
+        self.riff_start + self.riff_size()
+    }
+
+    /// The size of the current RIFF, including its header, if it were finished now
+    fn riff_size(&self) -> u64 {
+        let movi_end = self.file_offset(self.movi_size) + self.standard_index_size() - self.riff_start;
+        match self.first_riff {
+            None if self.write_index => movi_end + 8 + self.index.len() as u64 * 16,
+            _ => movi_end,
+        }
+    }
+
+    /// The size of the standard index chunks of the current RIFF, 0 without OpenDML
+    fn standard_index_size(&self) -> u64 {
+        self.stream_indexes.iter().map(StreamIndex::size).sum()
+    }
+
+    /// The size a chunk adds to the current RIFF, with its idx1 and standard index entries
+    fn riff_growth(&self, size: u64, indexed: bool, chunk_id: [u8; 4]) -> u64 {
+        let idx1 = if indexed && self.write_index && self.first_riff.is_none() { 16 } else { 0 };
+        let standard = if self.stream_indexes.iter().any(|index| index.chunk_id == chunk_id) { 8 } else { 0 };
+        size + idx1 + standard
+    }
+
+    /// Checks the limits for a video frame of `frame_size` bytes and prepares its chunk header.
+    pub(crate) fn prepare_frame(&self, frame_size: usize) -> Result<PreparedChunk> {
+        if frame_size == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        if self.frame_count >= self.max_frame_count() {
+            return Err(MjpegError::FrameCountExceeded);
+        }
+        self.prepare_chunk(*b"00dc", Some(IndexEntry::KEYFRAME), frame_size)
+    }
+
+    /// Checks the limits for a video frame of `frame_size` bytes of `stream` and prepares its
+    /// chunk header.
+    pub(crate) fn prepare_stream_frame(&self, stream: usize, frame_size: usize) -> Result<PreparedChunk> {
+        if stream == 0 {
+            return self.prepare_frame(frame_size);
+        }
+        let video = self.video_streams.iter().find(|video| video.stream as usize == stream).ok_or(MjpegError::NoVideoStream(stream))?;
+        if frame_size == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        if video.frames >= self.max_frame_count() {
+            return Err(MjpegError::FrameCountExceeded);
+        }
+        self.prepare_chunk(IndexEntry::chunk_id(video.stream, IndexEntry::COMPRESSED_VIDEO), Some(IndexEntry::KEYFRAME), frame_size)
+    }
+
+    /// Checks the limits for a dropped frame and prepares its empty chunk header.
+    pub(crate) fn prepare_dropped_frame(&self) -> Result<PreparedChunk> {
+        if self.frame_count >= self.max_frame_count() {
+            return Err(MjpegError::FrameCountExceeded);
+        }
+        self.prepare_chunk(*b"00dc", Some(0), 0)
+    }
+
+    /// The largest size of the current RIFF.
+    fn riff_size_limit(&self) -> u64 {
+        self.riff_limit.map_or(self.max_riff_size, |limit| limit.min(self.max_riff_size))
+    }
+
+    /// Only the idx1 index of a single RIFF limits the frame count for practical reasons.
+    fn max_frame_count(&self) -> u32 {
+        if self.riff_limit.is_some() { u32::MAX } else { MAX_FRAME_COUNT }
+    }
+
+    /// Checks the limits for a chunk of `size` bytes and prepares its chunk header.
+    ///
+    /// The chunk gets an index entry with `index_flags`, or none if it is `None`.
+    pub(crate) fn prepare_chunk(&self, chunk_id: [u8; 4], index_flags: Option<u32>, size: usize) -> Result<PreparedChunk> {
+        // Check if chunk size fits in u32
+        if size > u32::MAX as usize - 1 {
+            return Err(MjpegError::FrameSizeExceeded);
+        }
+
+        let pad = size % 2 == 1;
+        let padded_size = size as u64 + pad as u64;
+        // junk + chunk header + data + index entries
+        let mut segment_break = None;
+        let mut junk = self.alignment_junk(chunk_id, self.file_offset(self.movi_size));
+        if self.riff_size() + self.riff_growth(junk + 8 + padded_size, index_flags.is_some(), chunk_id) > self.riff_size_limit() {
+            junk = self.alignment_junk(chunk_id, self.riff_start + self.riff_size() + AVIX_HEADER_SIZE);
+            segment_break = Some(self.prepare_segment_break(self.riff_growth(junk + 8 + padded_size, false, chunk_id))?);
+        }
+
+        // The padding is part of the declared size, as in the original implementation, except
+        // for audio, whose players would play it, and data, whose readers would get it
+        let declared_size = if chunk_id[2..] == IndexEntry::AUDIO || chunk_id[2..] == IndexEntry::DATA { size as u32 } else { padded_size as u32 };
+        let movi_offset = if segment_break.is_some() { MOVI_FIRST_CHUNK_OFFSET } else { self.movi_size } + junk;
+        Ok(PreparedChunk {
+            header: create_chunk_header(chunk_id, declared_size),
+            junk: junk_chunk(junk),
+            pad,
+            segment_break,
+            entry: IndexEntry::new(chunk_id, index_flags.unwrap_or(0), movi_offset as u32, declared_size),
+            indexed: index_flags.is_some(),
+        })
+    }
+
+    /// The size of the `JUNK` chunk that moves a chunk at file offset `pos` to its alignment,
+    /// 0 if it is already aligned or isn't aligned at all.
+    fn alignment_junk(&self, chunk_id: [u8; 4], pos: u64) -> u64 {
+        // Both are powers of two, so the larger one satisfies both
+        let alignment = if IndexEntry::new(chunk_id, 0, 0, 0).is_video() { self.frame_alignment.max(self.chunk_alignment) } else { self.chunk_alignment };
+        if alignment == 0 {
+            return 0;
+        }
+        match (alignment - pos % alignment) % alignment {
+            // A JUNK chunk needs room for its header
+            junk @ 1..=7 => junk + alignment,
+            junk => junk,
+        }
+    }
+
+    pub(crate) fn set_interleave(&mut self, policy: InterleavePolicy) {
+        self.interleave = policy;
+    }
+
+    pub(crate) fn set_alignment(&mut self, frame_alignment: u32, chunk_alignment: u32) {
+        self.frame_alignment = frame_alignment as u64;
+        self.chunk_alignment = chunk_alignment as u64;
+    }
+
+    pub(crate) fn set_max_riff_size(&mut self, max_riff_size: u64) {
+        self.max_riff_size = max_riff_size;
+    }
+
+    pub(crate) fn set_write_index(&mut self, write_index: bool) {
+        self.write_index = write_index;
+    }
+
+    pub(crate) fn set_header_overrides(&mut self, overrides: HeaderOverrides) {
+        self.header_overrides = overrides;
+    }
+
+    /// Sets the `dwQuality` of video stream 0 written at finish.
+    pub(crate) fn set_stream_quality(&mut self, quality: u32) -> Result<()> {
+        if quality > MAX_STREAM_QUALITY {
+            return Err(MjpegError::InvalidStreamQuality(quality));
+        }
+        self.stream_quality = Some(quality);
+        Ok(())
+    }
+
+    pub(crate) fn set_timecode(&mut self, source: Option<TimecodeSource>) {
+        self.timecode = source;
+    }
+
+    /// The payload of the timecode chunk of the next frame, `None` without a timecode stream.
+    pub(crate) fn timecode_payload(&self) -> Option<Vec<u8>> {
+        let timecode = match self.timecode? {
+            TimecodeSource::Smpte(start) => Timecode::from_frames(start.to_frames(self.fps) + self.frame_count as u64, self.fps),
+            TimecodeSource::WallClock => Timecode::from_time(SystemTime::now(), self.fps),
+        };
+        // NUL-terminated like the INFO strings, 12 bytes
+        let mut payload = timecode.to_string().into_bytes();
+        payload.push(0);
+        Some(payload)
+    }
+
+    /// Splits audio data into the payloads of its chunks: PCM samples go into one chunk, MP3
+    /// data into one chunk per frame.
+    pub(crate) fn audio_chunks<'a>(&self, samples: &'a [u8]) -> Result<Vec<&'a [u8]>> {
+        let audio = self.audio.as_ref().ok_or(MjpegError::NoAudioStream)?;
+        let chunks = match audio.sample_size {
+            0 => mp3_frames(samples).ok_or(MjpegError::InvalidFrameSize)?,
+            sample_size if samples.len().is_multiple_of(sample_size as usize) => vec![samples],
+            _ => return Err(MjpegError::InvalidFrameSize),
+        };
+        if chunks.is_empty() || samples.is_empty() {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        Ok(chunks)
+    }
+
+    /// Buffers audio samples for the interleave policy. Returns false without buffering them if
+    /// they are to be written right away.
+    pub(crate) fn buffer_audio(&mut self, samples: &[u8]) -> Result<bool> {
+        let chunks = self.audio_chunks(samples)?.len();
+        let frame_count = self.frame_count;
+        let Some(audio) = self.audio.as_mut().filter(|_| self.interleave != InterleavePolicy::Immediate) else {
+            return Ok(false);
+        };
+        if audio.pending.is_empty() {
+            audio.pending_since = frame_count;
+        }
+        audio.pending.extend_from_slice(samples);
+        // MP3 streams count frames, one per chunk
+        audio.pending_samples += samples.len().checked_div(audio.sample_size as usize).unwrap_or(chunks) as u64;
+        Ok(true)
+    }
+
+    /// Returns true if the buffered audio samples are due to be written.
+    pub(crate) fn audio_due(&self) -> bool {
+        let Some(audio) = self.audio.as_ref().filter(|audio| !audio.pending.is_empty()) else {
+            return false;
+        };
+        let frames = self.frame_count - audio.pending_since;
+        match self.interleave {
+            InterleavePolicy::Immediate => true,
+            InterleavePolicy::EveryFrames(n) => frames >= n.max(1),
+            InterleavePolicy::MaxSkew(max_skew) => {
+                frame_timestamp(audio.pending_samples, audio.scale, audio.rate) >= max_skew
+                    || frame_timestamp(frames as u64, 1, self.fps) >= max_skew
+            }
+        }
+    }
+
+    /// Takes the buffered audio samples to write them.
+    pub(crate) fn take_audio(&mut self) -> Vec<u8> {
+        let Some(audio) = &mut self.audio else { return Vec::new() };
+        audio.pending_samples = 0;
+        std::mem::take(&mut audio.pending)
+    }
+
+    /// Checks the limits for `size` bytes of audio samples, or an MP3 frame, and prepares their
+    /// chunk header.
+    pub(crate) fn prepare_audio(&self, size: usize) -> Result<PreparedChunk> {
+        let audio = self.audio.as_ref().ok_or(MjpegError::NoAudioStream)?;
+        let sample_size = audio.sample_size as usize;
+        if size == 0 || (sample_size > 0 && !size.is_multiple_of(sample_size)) {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        self.prepare_chunk(audio.chunk_id, Some(IndexEntry::KEYFRAME), size)
+    }
+
+    /// Checks the limits for a sample of `size` bytes of the data stream `fourcc` and prepares
+    /// its chunk header.
+    pub(crate) fn prepare_data(&self, fourcc: [u8; 4], size: usize) -> Result<PreparedChunk> {
+        let data = self.data_streams.iter().find(|data| data.fourcc == fourcc).ok_or(MjpegError::NoDataStream(fourcc))?;
+        if size == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        self.prepare_chunk(data.chunk_id, Some(IndexEntry::KEYFRAME), size)
+    }
+
+    /// Checks a custom chunk for the `movi` list against the reserved chunk IDs and the limits.
+    pub(crate) fn prepare_custom_chunk(&self, chunk_id: [u8; 4], size: usize) -> Result<PreparedChunk> {
+        check_custom_chunk_id(chunk_id)?;
+        self.prepare_untimed_chunk(chunk_id, size)
+    }
+
+    /// Checks the metadata chunk of the frame just written against the limits.
+    pub(crate) fn prepare_frame_meta(&self, size: usize) -> Result<PreparedChunk> {
+        self.prepare_untimed_chunk(FRAME_META_CHUNK_ID, size)
+    }
+
+    /// Prepares an indexed chunk outside of the stream timelines.
+    fn prepare_untimed_chunk(&self, chunk_id: [u8; 4], size: usize) -> Result<PreparedChunk> {
+        let mut chunk = self.prepare_chunk(chunk_id, Some(IndexEntry::NO_TIME), size)?;
+        // Declare the size as written, so readers get the payload without the padding byte
+        chunk.header = create_chunk_header(chunk_id, size as u32);
+        chunk.entry.size = size as u32;
+        Ok(chunk)
+    }
+
+    /// Stores a custom chunk in the reserved header space, see `write_header_chunk`.
+    pub(crate) fn write_custom_header_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8]) -> Result<()> {
+        check_custom_chunk_id(chunk_id)?;
+        self.write_header_chunk(chunk_id, payload)
+    }
+
+    /// Prepares the end of the full RIFF and a new OpenDML segment for a chunk that adds `size`
+    /// bytes to it.
+    fn prepare_segment_break(&self, size: u64) -> Result<SegmentBreak> {
+        if self.riff_limit.is_none() {
+            return Err(MjpegError::FileSizeExceeded);
+        }
+        let limit = self.riff_size_limit();
+        // Lists can't span RIFFs, and a chunk that doesn't fit in an empty segment never fits
+        let index_headers = self.stream_indexes.len() as u64 * STANDARD_INDEX_HEADER_SIZE;
+        if self.has_open_list() || AVIX_HEADER_SIZE + index_headers + size > limit {
+            return Err(MjpegError::FileSizeExceeded);
+        }
+        // The super indexes need an entry for the full RIFF and the new one
+        if self.stream_indexes.first().is_some_and(|index| index.finished.len() + 2 > SUPER_INDEX_ENTRIES) {
+            return Err(MjpegError::FileSizeExceeded);
+        }
+        let riff_size = u32::try_from(self.riff_size() - 8).map_err(|_| MjpegError::FileSizeExceeded)?;
+        let movi_size = u32::try_from(self.movi_size + self.standard_index_size()).map_err(|_| MjpegError::FileSizeExceeded)?;
+
+        let mut data = self.index_chunk()?;
+        data.extend_from_slice(&create_chunk_header(*b"RIFF", 0));
+        data.extend_from_slice(b"AVIX");
+        data.extend_from_slice(&create_chunk_header(*b"LIST", 0));
+        data.extend_from_slice(b"movi");
+        let movi_list = self.file_offset(0) - 8;
+        Ok(SegmentBreak {
+            patches: [(self.riff_start + 4, riff_size.to_le_bytes()), (movi_list + 4, movi_size.to_le_bytes())],
+            resume: self.riff_start + self.riff_size() + AVIX_HEADER_SIZE,
+            data,
+        })
+    }
+
+    /// Records a prepared chunk after it has been written.
+    pub(crate) fn commit(&mut self, chunk: PreparedChunk) {
+        if let Some(segment_break) = &chunk.segment_break {
+            if self.first_riff.is_none() {
+                let [(_, riff_size), (_, movi_size)] = segment_break.patches;
+                self.first_riff = Some((u32::from_le_bytes(riff_size), u32::from_le_bytes(movi_size), self.frame_count));
+            }
+            let mut offset = self.file_offset(self.movi_size);
+            for index in &mut self.stream_indexes {
+                index.finished.push((offset, index.size() as u32, index.duration));
+                offset += index.size();
+                index.entries.clear();
+                index.duration = 0;
+            }
+            self.riff_start = segment_break.resume - AVIX_HEADER_SIZE;
+            self.movi_base = segment_break.resume - MOVI_FIRST_CHUNK_OFFSET;
+            self.movi_size = MOVI_FIRST_CHUNK_OFFSET;
+        }
+        self.movi_size += chunk.junk.len() as u64;
+        if chunk.entry.is_video() && chunk.entry.stream() == Some(0) {
+            let chunk_offset = self.file_offset(self.movi_size);
+            self.last_frame = Some(FrameLocation {
+                frame: self.frame_count,
+                chunk_offset,
+                data_offset: chunk_offset + 8,
+                len: chunk.entry.size - chunk.pad as u32,
+            });
+            self.frame_count += 1;
+            self.max_frame = self.max_frame.max(chunk.entry.size);
+            if self.first_riff.is_some() {
+                self.extended_frames.push((chunk_offset, chunk.entry.size));
+            }
+        }
+        if let Some(audio) = self.audio.as_mut().filter(|audio| audio.chunk_id == chunk.entry.chunk_id) {
+            audio.written += chunk.entry.size.checked_div(audio.sample_size).unwrap_or(1);
+        }
+        if let Some(data) = self.data_streams.iter_mut().find(|data| data.chunk_id == chunk.entry.chunk_id) {
+            data.samples += 1;
+            data.max_sample = data.max_sample.max(chunk.entry.size);
+        }
+        if chunk.entry.is_video() {
+            if let Some(video) = self.video_streams.iter_mut().find(|video| chunk.entry.stream() == Some(video.stream)) {
+                video.frames += 1;
+                video.max_frame = video.max_frame.max(chunk.entry.size);
+            }
+        }
+        if chunk.entry.stream().is_some() {
+            self.max_chunk = self.max_chunk.max(chunk.entry.size);
+        }
+        let data_offset = self.movi_size as u32 + 8;
+        if let Some(index) = self.stream_indexes.iter_mut().find(|index| index.chunk_id == chunk.entry.chunk_id) {
+            let keyframe = if chunk.entry.is_keyframe() { 0 } else { 1 << 31 };
+            index.entries.push((data_offset, chunk.entry.size | keyframe));
+            index.duration += chunk.entry.size.checked_div(index.sample_size).unwrap_or(1);
+        }
+        self.movi_size += 8 + chunk.entry.size as u64 + (chunk.entry.size & 1) as u64;
+        if chunk.indexed && self.first_riff.is_none() {
+            self.index.push(chunk.entry);
+        }
+    }
+
+    /// Checks the limits for a new LIST and prepares its header. The size is patched by `end_list()`.
+    pub(crate) fn prepare_list(&self, list_type: [u8; 4], index_flags: Option<u32>) -> Result<PreparedList> {
+        if self.riff_size() + self.riff_growth(12, index_flags.is_some(), *b"LIST") > self.riff_size_limit() {
+            return Err(MjpegError::FileSizeExceeded);
+        }
+
+        let mut header = [0u8; 12];
+        header[0..8].copy_from_slice(&create_chunk_header(*b"LIST", 0));
+        header[8..12].copy_from_slice(&list_type);
+        Ok(PreparedList { header, index_flags, list_type })
+    }
+
+    /// Records a prepared LIST header after it has been written.
+    pub(crate) fn commit_list(&mut self, list: PreparedList) {
+        let index_flags = list.index_flags.filter(|_| self.first_riff.is_none());
+        let index_pos = index_flags.map(|flags| {
+            self.index.push(IndexEntry::new(list.list_type, flags | IndexEntry::LIST, self.movi_size as u32, 0));
+            self.index.len() - 1
+        });
+        self.open_lists.push(OpenList { start: self.movi_size, index_pos });
+        self.movi_size += 12;
+    }
+
+    /// Closes the innermost open LIST and returns the size to patch into its header.
+    pub(crate) fn end_list(&mut self) -> Result<ListPatch> {
+        let list = self.open_lists.pop().ok_or(MjpegError::NoOpenList)?;
+        let size = (self.movi_size - list.start - 8) as u32;
+        if let Some(pos) = list.index_pos {
+            self.index[pos].size = size;
+        }
+        Ok(ListPatch {
+            pos: self.file_offset(list.start + 4),
+            size: size.to_le_bytes(),
+            resume: self.file_offset(self.movi_size),
+        })
+    }
+
+    /// Stores a chunk in the reserved header space, written with the header at finish.
+    pub(crate) fn write_header_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8]) -> Result<()> {
+        let pos = self.reserved.ok_or(MjpegError::HeaderSpaceExceeded)?;
+        let free = 8 + u32::from_le_bytes(self.header[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let size = 8 + payload.len() + (payload.len() & 1);
+        // The rest of the space must be empty or hold at least a chunk header
+        if size != free && size + 8 > free {
+            return Err(MjpegError::HeaderSpaceExceeded);
+        }
+        let chunk = &mut self.header[pos..pos + size];
+        chunk[..8].copy_from_slice(&create_chunk_header(chunk_id, payload.len() as u32));
+        chunk[8..8 + payload.len()].copy_from_slice(payload);
+        chunk[8 + payload.len()..].fill(0);
+        self.reserved = (size < free).then_some(pos + size);
+        if let Some(pos) = self.reserved {
+            self.header[pos..pos + 8].copy_from_slice(&create_chunk_header(*b"JUNK", (free - size - 8) as u32));
+        }
+        Ok(())
+    }
+
+    /// Replaces the `INFO` list in the header, written with the header at finish.
+    ///
+    /// The new list takes the space of the current one and the free reserved space right after
+    /// it, or goes into the reserved space if the header has no `INFO` list yet.
+    pub(crate) fn set_metadata(&mut self, metadata: &AviMetadata) -> Result<()> {
+        let metadata = with_start_timecode(metadata, self.timecode);
+        let Some(pos) = self.info else {
+            if metadata.is_empty() {
+                return Ok(());
+            }
+            let pos = self.reserved.ok_or(MjpegError::HeaderSpaceExceeded)?;
+            self.write_header_chunk(*b"LIST", &metadata.info_payload())?;
+            self.info = Some(pos);
+            return Ok(());
+        };
+        let mut end = pos + 8 + le_u32(&self.header, pos + 4) as usize;
+        let merged = self.reserved == Some(end);
+        if merged {
+            end += 8 + le_u32(&self.header, end + 4) as usize;
+        }
+        let mut info = Vec::new();
+        if !metadata.is_empty() {
+            let payload = metadata.info_payload();
+            info.extend_from_slice(&create_chunk_header(*b"LIST", payload.len() as u32));
+            info.extend_from_slice(&payload);
+        }
+        let free = end - pos;
+        // The rest of the space must be empty or hold at least a chunk header
+        if info.len() != free && info.len() + 8 > free {
+            return Err(MjpegError::HeaderSpaceExceeded);
+        }
+        let rest = pos + info.len();
+        self.header[pos..rest].copy_from_slice(&info);
+        if rest < end {
+            self.header[rest..rest + 8].copy_from_slice(&create_chunk_header(*b"JUNK", (end - rest - 8) as u32));
+            self.header[rest + 8..end].fill(0);
+        }
+        self.info = (!info.is_empty()).then_some(pos);
+        if merged {
+            self.reserved = (rest < end).then_some(rest);
+        }
+        Ok(())
+    }
+
+    /// Creates the indexes written at the end of the current RIFF: the standard index chunks
+    /// closing the `movi` list with OpenDML, and the idx1 chunk unless the first RIFF is full
+    /// and already has it, or it is turned off.
+    pub(crate) fn index_chunk(&self) -> Result<Vec<u8>> {
+        let mut chunk: Vec<u8> = self.stream_indexes.iter().flat_map(|index| index.chunk(self.file_offset(0))).collect();
+        if self.first_riff.is_some() || !self.write_index {
+            return Ok(chunk);
+        }
+        let index_size = u32::try_from(self.index.len() * 16).map_err(|_| MjpegError::FileSizeExceeded)?;
+
+        chunk.reserve(8 + index_size as usize);
+        chunk.extend_from_slice(&create_idx_header(index_size));
+        for entry in &self.index {
+            chunk.extend_from_slice(&entry.to_bytes());
+        }
+        Ok(chunk)
+    }
+
+    /// Creates the header with the final sizes and frame counts, to be written over the
+    /// original header at finish in a single write.
+    pub(crate) fn finished_header(&self) -> Result<Vec<u8>> {
+        let mut header = self.header.clone();
+        for (pos, bytes) in self.header_patches()? {
+            header[pos as usize..pos as usize + 4].copy_from_slice(&bytes);
+        }
+        // The standard index chunks of the full RIFFs and the ones written at finish
+        let mut offset = self.file_offset(self.movi_size);
+        for index in &self.stream_indexes {
+            let pos = index.super_index;
+            let last = (offset, index.size() as u32, index.duration);
+            offset += index.size();
+            header[pos + 12..pos + 16].copy_from_slice(&(index.finished.len() as u32 + 1).to_le_bytes());
+            let entries = index.finished.iter().copied().chain([last]);
+            for ((offset, size, duration), entry) in entries.zip(header[pos + 32..].chunks_exact_mut(16)) {
+                entry[..8].copy_from_slice(&offset.to_le_bytes());
+                entry[8..12].copy_from_slice(&size.to_le_bytes());
+                entry[12..].copy_from_slice(&duration.to_le_bytes());
+            }
+        }
+        self.header_overrides.apply(&mut header);
+        Ok(header)
+    }
+
+    /// Creates the sizes patched into the current `RIFF AVIX` segment at finish: (file offset,
+    /// value). Empty while the first RIFF isn't full.
+    pub(crate) fn segment_patches(&self) -> Result<Vec<(u64, [u8; 4])>> {
+        if self.first_riff.is_none() {
+            return Ok(Vec::new());
+        }
+        let riff_size = u32::try_from(self.riff_size() - 8).map_err(|_| MjpegError::FileSizeExceeded)?;
+        let movi_size = u32::try_from(self.movi_size + self.standard_index_size()).map_err(|_| MjpegError::FileSizeExceeded)?;
+        Ok(vec![(self.riff_start + 4, riff_size.to_le_bytes()), (self.file_offset(0) - 4, movi_size.to_le_bytes())])
+    }
+
+    /// Creates the values patched into the header at finish: (file offset, value).
+    fn header_patches(&self) -> Result<Vec<(u64, [u8; 4])>> {
+        let (riff_size, movi_size, first_frames) = match self.first_riff {
+            Some(first) => first,
+            None => (
+                u32::try_from(self.file_size() - 8).map_err(|_| MjpegError::FileSizeExceeded)?,
+                u32::try_from(self.movi_size + self.standard_index_size()).map_err(|_| MjpegError::FileSizeExceeded)?,
+                self.frame_count,
+            ),
+        };
+        let frame_count = self.frame_count.to_le_bytes();
+
+        let mut patches = vec![
+            (4, riff_size.to_le_bytes()),     // RIFF file size
+            (48, first_frames.to_le_bytes()), // totalframes of the first RIFF
+            (60, self.max_chunk.to_le_bytes()), // suggestedBufferSize
+            (140, frame_count),               // length
+            (144, self.max_frame.to_le_bytes()), // suggestedBufferSize
+            (self.odml_frames as u64, frame_count), // odml totalframes
+            (self.header_size - 8, movi_size.to_le_bytes()),  // movi size
+        ];
+        if let Some(quality) = self.stream_quality {
+            patches.push((148, quality.to_le_bytes())); // quality
+        }
+        for video in &self.video_streams {
+            patches.push((video.strh as u64 + 40, video.frames.to_le_bytes())); // length
+            patches.push((video.strh as u64 + 44, video.max_frame.to_le_bytes())); // suggestedBufferSize
+        }
+        if let Some(audio) = &self.audio {
+            patches.push((audio.strh as u64 + 40, audio.written.to_le_bytes())); // audio length
+        }
+        for data in &self.data_streams {
+            patches.push((data.strh as u64 + 40, data.samples.to_le_bytes())); // data length
+            patches.push((data.strh as u64 + 44, data.max_sample.to_le_bytes())); // suggestedBufferSize
+        }
+        Ok(patches)
     }
-    
-    let movi_size = 4u64
-        .checked_add(jpeg_total_size)
-        .and_then(|s| s.checked_add(frame_count as u64 * 8))
-        .ok_or(MjpegError::FileSizeExceeded)?;
-        
-    if movi_size > u32::MAX as u64 {
-        return Err(MjpegError::FileSizeExceeded);
-    }
-
-    Ok(FileSizes {
-        total_file_size: total_file_size as u32,
-        movi_size: movi_size as u32,
-        index_size,
-    })
 }
 
 const AVI_HEADER_TEMPLATE: [u8; 256] = [
@@ -112,7 +990,7 @@ const AVI_HEADER_TEMPLATE: [u8; 256] = [
     0, 0, 0, 0,    // totalframes placeholder (48-51)
     0, 0, 0, 0,    // initialframes
     1, 0, 0, 0,    // streams
-    0, 0, 0, 0,    // suggestedBufferSize
+    0, 0, 0, 0,    // suggestedBufferSize placeholder (60-63)
     0, 0, 0, 0,    // width placeholder (64-67)
     0, 0, 0, 0,    // height placeholder (68-71)
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,  // reserved
@@ -131,12 +1009,12 @@ const AVI_HEADER_TEMPLATE: [u8; 256] = [
     0, 0,          // priority
     0, 0,          // language
     0, 0, 0, 0,    // initialframes
-    1, 0, 0, 0,    // scale
-    0, 0, 0, 0,    // rate placeholder (128-131)
+    1, 0, 0, 0,    // scale (128-131)
+    0, 0, 0, 0,    // rate placeholder (132-135)
     0, 0, 0, 0,    // start
     0, 0, 0, 0,    // length placeholder (140-143)
-    0, 0, 0, 0,    // suggestedBufferSize
-    0, 0, 0, 0,    // quality
+    0, 0, 0, 0,    // suggestedBufferSize placeholder (144-147)
+    0, 0, 0, 0,    // quality (148-151)
     0, 0, 0, 0,    // sampleSize
     0, 0, 0, 0,    // left
     0, 0, 0, 0,    // top
@@ -172,10 +1050,153 @@ const AVI_HEADER_TEMPLATE: [u8; 256] = [
     b'm', b'o', b'v', b'i',
 ];
 
+/// A `JUNK` chunk of `size` bytes in total, including its header, or nothing if `size` is 0.
+fn junk_chunk(size: u64) -> Vec<u8> {
+    if size == 0 {
+        return Vec::new();
+    }
+    let mut junk = vec![0u8; size as usize];
+    junk[..8].copy_from_slice(&create_chunk_header(*b"JUNK", size as u32 - 8));
+    junk
+}
+
+/// Inserts a `JUNK` chunk with `bytes` of free space, rounded up to an even size, before the
+/// `movi` list header.
+pub(crate) fn reserve_header_space(header: &[u8], bytes: u32) -> Vec<u8> {
+    let size = bytes.saturating_add(bytes & 1);
+    let movi_list = header.len() - 12;
+    let mut reserved = Vec::with_capacity(header.len() + 8 + size as usize);
+    reserved.extend_from_slice(&header[..movi_list]);
+    if size > 0 {
+        reserved.extend_from_slice(&create_chunk_header(*b"JUNK", size));
+        reserved.resize(reserved.len() + size as usize, 0);
+    }
+    reserved.extend_from_slice(&header[movi_list..]);
+    reserved
+}
+
+/// Inserts an empty `indx` super index with room for the `ix00` chunks of all RIFFs at the
+/// end of the video `strl` list, for OpenDML.
+pub(crate) fn add_super_index(header: &[u8]) -> Vec<u8> {
+    let index = super_index_chunk(*b"00dc");
+    let mut indexed = Vec::with_capacity(header.len() + index.len());
+    indexed.extend_from_slice(&header[..SUPER_INDEX_OFFSET]);
+    indexed.extend_from_slice(&index);
+    indexed.extend_from_slice(&header[SUPER_INDEX_OFFSET..]);
+    // The hdrl and strl lists grow with it
+    for pos in [16, 92] {
+        let list_size = le_u32(&indexed, pos) + index.len() as u32;
+        indexed[pos..pos + 4].copy_from_slice(&list_size.to_le_bytes());
+    }
+    indexed
+}
+
+/// Inserts a `vprp` video properties chunk into the video `strl` list, before the `odml` list,
+/// so players show the frames at the display aspect ratio `aspect` (width:height) even if the
+/// pixels aren't square.
+pub(crate) fn add_video_properties(header: &[u8], aspect: (u16, u16)) -> Vec<u8> {
+    let (width, height) = (le_u32(header, 64), le_u32(header, 68));
+    let (scale, rate) = (le_u32(header, 128), le_u32(header, 132));
+    let mut vprp = create_chunk_header(*b"vprp", 68).to_vec();
+    vprp.extend_from_slice(&[0; 8]); // VideoFormatToken, VideoStandard: unknown
+    vprp.extend_from_slice(&rate.div_ceil(scale.max(1)).to_le_bytes()); // dwVerticalRefreshRate
+    vprp.extend_from_slice(&width.to_le_bytes()); // dwHTotalInT
+    vprp.extend_from_slice(&height.to_le_bytes()); // dwVTotalInLines
+    vprp.extend_from_slice(&aspect.1.to_le_bytes()); // dwFrameAspectRatio, height in the low word
+    vprp.extend_from_slice(&aspect.0.to_le_bytes());
+    vprp.extend_from_slice(&width.to_le_bytes()); // dwFrameWidthInPixels
+    vprp.extend_from_slice(&height.to_le_bytes()); // dwFrameHeightInLines
+    vprp.extend_from_slice(&1u32.to_le_bytes()); // nbFieldPerFrame: progressive
+    for value in [height, width, height, width, 0, 0, 0, 0] {
+        // CompressedBMHeight/Width, ValidBMHeight/Width, ValidBMX/YOffset, VideoX/YOffset
+        vprp.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let strl_end = 96 + le_u32(header, 92) as usize;
+    let pos = header_chunks(header, 100, strl_end)
+        .find(|&(pos, id)| &id == b"LIST" && &header[pos + 8..pos + 12] == b"odml")
+        .map_or(strl_end, |(pos, _)| pos);
+    let mut extended = Vec::with_capacity(header.len() + vprp.len());
+    extended.extend_from_slice(&header[..pos]);
+    extended.extend_from_slice(&vprp);
+    extended.extend_from_slice(&header[pos..]);
+    // The hdrl and strl lists grow with it
+    for pos in [16, 92] {
+        let list_size = le_u32(&extended, pos) + vprp.len() as u32;
+        extended[pos..pos + 4].copy_from_slice(&list_size.to_le_bytes());
+    }
+    extended
+}
+
+/// Appends the `strl` list of video stream `stream` with frames of `width` x `height` at the
+/// frame rate of the first, with an empty `indx` super index for OpenDML if `super_index` is
+/// set.
+pub(crate) fn add_video_stream(header: &[u8], stream: u8, width: u32, height: u32, super_index: bool) -> Vec<u8> {
+    let template = create_header_template(le_u32(header, 132), width, height);
+    // The strl list of the template without the odml list, which only the first stream has
+    let mut strl = template[88..SUPER_INDEX_OFFSET].to_vec();
+    if super_index {
+        strl.extend_from_slice(&super_index_chunk(IndexEntry::chunk_id(stream, IndexEntry::COMPRESSED_VIDEO)));
+    }
+    let size = strl.len() as u32 - 8;
+    strl[4..8].copy_from_slice(&size.to_le_bytes());
+    add_stream(header, &strl)
+}
+
+/// Creates an empty `indx` super index of the chunks with `chunk_id`, with room for the
+/// standard indexes of all RIFFs.
+pub(crate) fn super_index_chunk(chunk_id: [u8; 4]) -> Vec<u8> {
+    let size = 24 + SUPER_INDEX_ENTRIES * 16;
+    let mut chunk = Vec::with_capacity(8 + size);
+    chunk.extend_from_slice(&create_chunk_header(*b"indx", size as u32));
+    chunk.extend_from_slice(&4u16.to_le_bytes()); // wLongsPerEntry
+    chunk.push(0); // bIndexSubType
+    chunk.push(0); // bIndexType: AVI_INDEX_OF_INDEXES
+    chunk.extend_from_slice(&0u32.to_le_bytes()); // nEntriesInUse, set at finish
+    chunk.extend_from_slice(&chunk_id);
+    chunk.resize(8 + size, 0);
+    chunk
+}
+
+/// Appends a stream list at the end of the `hdrl` list and counts the stream in `avih`.
+pub(crate) fn add_stream(header: &[u8], strl: &[u8]) -> Vec<u8> {
+    let mut extended = append_to_hdrl(header, strl);
+    let streams = le_u32(&extended, 56) + 1;
+    extended[56..60].copy_from_slice(&streams.to_le_bytes());
+    extended
+}
+
+/// Appends a chunk at the end of the `hdrl` list.
+pub(crate) fn append_to_hdrl(header: &[u8], chunk: &[u8]) -> Vec<u8> {
+    let hdrl_end = 20 + le_u32(header, 16) as usize;
+    let mut extended = Vec::with_capacity(header.len() + chunk.len());
+    extended.extend_from_slice(&header[..hdrl_end]);
+    extended.extend_from_slice(chunk);
+    extended.extend_from_slice(&header[hdrl_end..]);
+    let hdrl_size = le_u32(&extended, 16) + chunk.len() as u32;
+    extended[16..20].copy_from_slice(&hdrl_size.to_le_bytes());
+    extended
+}
+
+/// Iterates over the chunks in `header[start..end]`: (offset, chunk ID).
+fn header_chunks(header: &[u8], start: usize, end: usize) -> impl Iterator<Item = (usize, [u8; 4])> + '_ {
+    let mut pos = start;
+    std::iter::from_fn(move || {
+        if pos + 8 > end {
+            return None;
+        }
+        let chunk = (pos, fourcc(header, pos));
+        let size = le_u32(header, pos + 4) as usize;
+        pos += 8 + size + (size & 1);
+        Some(chunk)
+    })
+}
+
 /// Creates AVI header with dynamic values filled in
 pub(crate) fn create_header_template(fps: u32, width: u32, height: u32) -> [u8; 256] {
-    let microsec = 1_000_000 / fps;
-    let bi_size_image = ((width * 24 / 8 + 3) & 0xFFFFFFFC) * height;
+    let microsec = 1_000_000u32.checked_div(fps).unwrap_or(0); // fps 0 is rejected when building the writer
+    // Saturates for the sizes `unchecked_dimensions()` lets through
+    let bi_size_image = u32::try_from(((width as u64 * 24 / 8 + 3) & !3) * height as u64).unwrap_or(u32::MAX);
     
     let mut header = AVI_HEADER_TEMPLATE;
     
@@ -183,7 +1204,7 @@ pub(crate) fn create_header_template(fps: u32, width: u32, height: u32) -> [u8;
     header[32..36].copy_from_slice(&microsec.to_le_bytes());
     header[64..68].copy_from_slice(&width.to_le_bytes());
     header[68..72].copy_from_slice(&height.to_le_bytes());
-    header[128..132].copy_from_slice(&fps.to_le_bytes());
+    header[132..136].copy_from_slice(&fps.to_le_bytes());
     header[164..168].copy_from_slice(&width.to_le_bytes());
     header[168..172].copy_from_slice(&height.to_le_bytes());
     header[184..188].copy_from_slice(&width.to_le_bytes());
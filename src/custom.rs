@@ -0,0 +1,60 @@
+use crate::{MjpegError, Result};
+use crate::bookmark::BOOKMARK_CHUNK_ID;
+use crate::index::IndexEntry;
+use crate::producer::PRODUCER_CHUNK_ID;
+
+/// The ID of the chunk holding the metadata of the frame before it, see `add_frame_with_meta()`.
+pub(crate) const FRAME_META_CHUNK_ID: [u8; 4] = *b"fmta";
+
+/// Chunk IDs with a meaning to this crate or to players, besides the stream chunks.
+const RESERVED_CHUNK_IDS: [[u8; 4]; 15] = [
+    *b"RIFF", *b"LIST", *b"JUNK", *b"idx1", *b"indx", *b"avih", *b"strh", *b"strf", *b"strd", *b"strn", *b"vprp", *b"dmlh",
+    BOOKMARK_CHUNK_ID, PRODUCER_CHUNK_ID, FRAME_META_CHUNK_ID,
+];
+
+/// Where `write_custom_chunk()` stores a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkPlacement {
+    /// In the `movi` list, between the frames written before and after it. The chunk gets an
+    /// `idx1` entry with the `AVIIF_NO_TIME` flag, so players skip it.
+    Movi,
+    /// At the top level of the RIFF file, in the header space reserved with
+    /// [`MjpegWriterBuilder::reserve_header`](crate::MjpegWriterBuilder::reserve_header), like
+    /// `write_header_chunk()`.
+    Header,
+}
+
+/// A chunk written with `write_custom_chunk()`, read back by
+/// [`MjpegReader::custom_chunks`](crate::MjpegReader::custom_chunks).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CustomChunk {
+    /// Where the chunk is stored.
+    pub placement: ChunkPlacement,
+    /// For a chunk in the `movi` list, the number of the first frame after it.
+    pub frame: Option<u32>,
+    /// The payload as written.
+    pub data: Vec<u8>,
+}
+
+/// The chunk kinds of stream chunks, which follow the stream number.
+const STREAM_KINDS: [[u8; 2]; 6] = [
+    IndexEntry::COMPRESSED_VIDEO,
+    IndexEntry::UNCOMPRESSED_VIDEO,
+    IndexEntry::AUDIO,
+    IndexEntry::PALETTE_CHANGE,
+    IndexEntry::TEXT,
+    IndexEntry::DATA,
+];
+
+/// Checks that `chunk_id` is printable ASCII and can't be mistaken for a chunk that this crate
+/// or players interpret: stream chunks such as `00dc` or anything else starting with two
+/// digits, OpenDML `ix00` indexes and the header chunks.
+pub(crate) fn check_custom_chunk_id(chunk_id: [u8; 4]) -> Result<()> {
+    let entry = IndexEntry::new(chunk_id, 0, 0, 0);
+    let stream = chunk_id[..2].iter().all(u8::is_ascii_digit) || (entry.stream().is_some() && STREAM_KINDS.contains(&entry.kind()));
+    let index = chunk_id.starts_with(b"ix") && chunk_id[2..].iter().all(u8::is_ascii_hexdigit);
+    if !chunk_id.iter().all(|c| (0x20..0x7f).contains(c)) || stream || index || RESERVED_CHUNK_IDS.contains(&chunk_id) {
+        return Err(MjpegError::ReservedChunkId(chunk_id));
+    }
+    Ok(())
+}
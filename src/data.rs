@@ -0,0 +1,49 @@
+use std::time::Duration;
+use crate::common::{add_stream, create_chunk_header, super_index_chunk};
+use crate::index::IndexEntry;
+use crate::riff::le_u32;
+
+/// A sample of a data stream added with `add_data()`, read back by
+/// [`MjpegReader::data_samples`](crate::MjpegReader::data_samples).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DataSample {
+    /// The number of the first frame after the sample.
+    pub frame: u32,
+    /// The timestamp of that frame.
+    pub timestamp: Duration,
+    /// The payload as written.
+    pub data: Vec<u8>,
+}
+
+/// Appends the `strl` list of a data stream identified by `fourcc` to the `hdrl` list, with
+/// an empty `indx` super index for OpenDML if `super_index` is set.
+///
+/// The stream has the `dats` type, which players skip, the frame rate of the video and one
+/// chunk per sample (`dwSampleSize` 0). Its `strf` is empty: the payload format is up to the
+/// application.
+pub(crate) fn add_data_stream(header: &[u8], fourcc: [u8; 4], super_index: bool) -> Vec<u8> {
+    let stream = le_u32(header, 56) as u8;
+    let mut strl = create_chunk_header(*b"LIST", 0).to_vec();
+    strl.extend_from_slice(b"strl");
+
+    strl.extend_from_slice(&create_chunk_header(*b"strh", 56));
+    strl.extend_from_slice(b"dats");
+    strl.extend_from_slice(&fourcc); // fccHandler
+    strl.extend_from_slice(&[0; 12]); // dwFlags, wPriority, wLanguage, dwInitialFrames
+    strl.extend_from_slice(&1u32.to_le_bytes()); // dwScale
+    strl.extend_from_slice(&header[132..136]); // dwRate: the frame rate
+    strl.extend_from_slice(&[0; 4]); // dwStart
+    strl.extend_from_slice(&[0; 4]); // dwLength, set at finish
+    strl.extend_from_slice(&[0; 4]); // dwSuggestedBufferSize, set at finish
+    strl.extend_from_slice(&u32::MAX.to_le_bytes()); // dwQuality: default
+    strl.extend_from_slice(&[0; 4]); // dwSampleSize
+    strl.extend_from_slice(&[0; 8]); // rcFrame
+    strl.extend_from_slice(&create_chunk_header(*b"strf", 0));
+
+    if super_index {
+        strl.extend_from_slice(&super_index_chunk(IndexEntry::chunk_id(stream, IndexEntry::DATA)));
+    }
+    let size = strl.len() as u32 - 8;
+    strl[4..8].copy_from_slice(&size.to_le_bytes());
+    add_stream(header, &strl)
+}
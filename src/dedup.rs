@@ -0,0 +1,204 @@
+use crate::Result;
+use crate::mjpeg_sync::{MjpegAviWriter, MjpegWriter};
+use crate::writer::Writer;
+
+/// Computes the hashes that [`DedupWriter`] and `HashObserver` compare frames by.
+///
+/// The default comparison is equality, for exact hashes such as `Xxh3Hasher` with the `xxhash`
+/// feature. Implement it with a perceptual hash (pHash, dHash) of the decoded image and
+/// override [`matches`](Self::matches) with a Hamming distance threshold to treat frames that
+/// only differ by sensor noise or JPEG artifacts as duplicates.
+///
+/// # Examples
+///
+/// ```
+/// use mjpeg_avi_rs::FrameHasher;
+///
+/// /// Matches frames whose 64-bit perceptual hashes differ in at most 5 bits.
+/// struct PerceptualHasher;
+///
+/// impl FrameHasher for PerceptualHasher {
+///     fn hash(&mut self, bufs: &[&[u8]]) -> u64 {
+///         let jpeg = bufs.concat();
+///         // Decode `jpeg`, shrink it to 9x8 gray pixels and compare neighbors here
+///         # jpeg.len() as u64
+///     }
+///
+///     fn matches(&self, a: u64, b: u64) -> bool {
+///         (a ^ b).count_ones() <= 5
+///     }
+/// }
+/// ```
+pub trait FrameHasher {
+    /// The hash of a frame, given as the buffers passed to the writer.
+    fn hash(&mut self, bufs: &[&[u8]]) -> u64;
+
+    /// Returns true if frames with the hashes `a` and `b` are duplicates. Default: `a == b`.
+    fn matches(&self, a: u64, b: u64) -> bool {
+        a == b
+    }
+}
+
+impl<H: FrameHasher + ?Sized> FrameHasher for &mut H {
+    fn hash(&mut self, bufs: &[&[u8]]) -> u64 {
+        (**self).hash(bufs)
+    }
+
+    fn matches(&self, a: u64, b: u64) -> bool {
+        (**self).matches(a, b)
+    }
+}
+
+/// Hashes the JPEG data with XXH3, so only byte-identical frames match. Enabled by the
+/// `xxhash` feature.
+#[cfg(feature = "xxhash")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xxh3Hasher;
+
+#[cfg(feature = "xxhash")]
+impl FrameHasher for Xxh3Hasher {
+    fn hash(&mut self, bufs: &[&[u8]]) -> u64 {
+        let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+        for buf in bufs {
+            hasher.update(buf);
+        }
+        hasher.digest()
+    }
+}
+
+/// Statistics on runs of duplicate frames, collected by [`DedupWriter`] and `HashObserver`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DuplicateStats {
+    /// The number of frames seen.
+    pub frames: u64,
+    /// The number of frames that duplicate the frame starting their run.
+    pub duplicate_frames: u64,
+    /// The number of bytes in duplicate frames.
+    pub duplicate_bytes: u64,
+    /// The number of runs of two or more duplicate frames.
+    pub runs: u64,
+    /// The length of the longest run of duplicate frames, in frames.
+    pub longest_run: u64,
+}
+
+impl DuplicateStats {
+    /// The fraction of frames that repeat the previous frame.
+    pub fn duplicate_ratio(&self) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.duplicate_frames as f64 / self.frames as f64
+    }
+}
+
+/// Finds runs of duplicate frames. Frames are compared with the first frame of the current
+/// run, so a scene that drifts slowly still starts a new run once it differs enough.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DuplicateTracker<H> {
+    pub(crate) hasher: H,
+    stats: DuplicateStats,
+    run_hash: Option<u64>,
+    run: u64,
+}
+
+impl<H: FrameHasher> DuplicateTracker<H> {
+    pub(crate) fn new(hasher: H) -> Self {
+        DuplicateTracker { hasher, stats: DuplicateStats::default(), run_hash: None, run: 0 }
+    }
+
+    /// Counts a frame and returns true if it duplicates the first frame of the current run.
+    pub(crate) fn check(&mut self, bufs: &[&[u8]]) -> bool {
+        let hash = self.hasher.hash(bufs);
+        self.stats.frames += 1;
+        let duplicate = self.run_hash.is_some_and(|run_hash| self.hasher.matches(run_hash, hash));
+        if duplicate {
+            self.stats.duplicate_frames += 1;
+            self.stats.duplicate_bytes += bufs.iter().map(|b| b.len() as u64).sum::<u64>();
+            self.run += 1;
+            if self.run == 2 {
+                self.stats.runs += 1;
+            }
+        } else {
+            self.run = 1;
+            self.run_hash = Some(hash);
+        }
+        self.stats.longest_run = self.stats.longest_run.max(self.run);
+        duplicate
+    }
+
+    pub(crate) fn stats(&self) -> DuplicateStats {
+        self.stats
+    }
+}
+
+/// Wraps a writer and stores duplicate frames as dropped frames, so a noisy camera watching a
+/// static scene doesn't fill the disk with the same image.
+///
+/// Each frame is hashed with a [`FrameHasher`]; one that matches the first frame of the
+/// current run is written with
+/// [`add_dropped_frame`](MjpegWriter::add_dropped_frame), which keeps the timeline while
+/// players show the previous frame. With a perceptual hasher, frames that only differ by noise
+/// are suppressed too.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{DedupWriter, FrameHasher, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+///
+/// # fn camera_frame() -> Vec<u8> { Vec::new() }
+/// fn record<H: FrameHasher>(hasher: H) -> mjpeg_avi_rs::Result<()> {
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 10)?;
+///     let mut writer = DedupWriter::new(writer, hasher);
+///     for _ in 0..600 {
+///         writer.add_frame(&camera_frame())?;
+///     }
+///     println!("{:.0}% of the frames were duplicates", writer.stats().duplicate_ratio() * 100.0);
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+pub struct DedupWriter<W: Writer, H: FrameHasher> {
+    inner: MjpegWriter<W>,
+    tracker: DuplicateTracker<H>,
+}
+
+impl<W: Writer, H: FrameHasher> DedupWriter<W, H> {
+    /// Wraps `inner`, comparing frames by the hashes of `hasher`.
+    pub fn new(inner: MjpegWriter<W>, hasher: H) -> Self {
+        DedupWriter { inner, tracker: DuplicateTracker::new(hasher) }
+    }
+
+    /// The wrapped writer.
+    pub fn inner_mut(&mut self) -> &mut MjpegWriter<W> {
+        &mut self.inner
+    }
+
+    /// The hasher.
+    pub fn hasher(&self) -> &H {
+        &self.tracker.hasher
+    }
+
+    /// The statistics collected so far. The duplicate bytes are the JPEG data not stored.
+    pub fn stats(&self) -> DuplicateStats {
+        self.tracker.stats()
+    }
+}
+
+impl<W: Writer, H: FrameHasher> MjpegAviWriter<W> for DedupWriter<W, H> {
+    fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary])
+    }
+
+    fn add_frame_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        if self.tracker.check(bufs) {
+            self.inner.add_dropped_frame()
+        } else {
+            self.inner.add_frame_vectored(bufs)
+        }
+    }
+
+    fn finish(self) -> Result<W> {
+        self.inner.finish()
+    }
+}
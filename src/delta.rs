@@ -0,0 +1,115 @@
+use std::io::{Read, Seek, Write};
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::reader::MjpegReader;
+use crate::writer::Writer;
+
+const DELTA_MAGIC: [u8; 4] = *b"MJPD";
+const DELTA_VERSION: u8 = 1;
+
+/// Statistics of a [`pack_delta`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeltaStats {
+    /// The number of frames packed.
+    pub frames: u64,
+    /// The total size of the frames.
+    pub frame_bytes: u64,
+    /// The number of frame bytes stored; the rest is referenced from the previous frame.
+    pub stored_bytes: u64,
+}
+
+impl DeltaStats {
+    /// The fraction of frame bytes that didn't have to be stored.
+    pub fn saving_ratio(&self) -> f64 {
+        if self.frame_bytes == 0 {
+            return 0.0;
+        }
+        1.0 - self.stored_bytes as f64 / self.frame_bytes as f64
+    }
+}
+
+/// Packs the frames of `reader` into the experimental differential format.
+///
+/// Every frame is stored as the number of bytes it shares with the start and with the end of
+/// the previous frame, followed by the bytes in between. A JPEG encoder emits the blocks of an
+/// image in order, so in a static scene the headers and the blocks before the first change
+/// produce identical bytes, and with restart markers the blocks after the last change do too;
+/// nothing is decoded to find them. [`unpack_delta`] restores a standard AVI file equal to
+/// [`remux`](crate::remux) of the original.
+///
+/// The format is little-endian: the magic `MJPD`, a version byte (1), the width, height, frame
+/// rate and frame count as `u32`, then per frame the shared prefix length, the shared suffix
+/// length and the stored length as `u32`, followed by the stored bytes.
+pub fn pack_delta<R: Read + Seek, O: Write>(reader: &mut MjpegReader<R>, mut out: O) -> Result<DeltaStats> {
+    let info = reader.info();
+    let fps = if info.scale == 1 { info.rate } else { info.fps().round() as u32 };
+    let frame_count = reader.frame_count();
+
+    out.write_all(&DELTA_MAGIC)?;
+    out.write_all(&[DELTA_VERSION])?;
+    for value in [info.width, info.height, fps, frame_count as u32] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+
+    let mut stats = DeltaStats::default();
+    let mut previous = Vec::new();
+    for i in 0..frame_count {
+        let frame = reader.read_frame(i)?;
+        let prefix = frame.iter().zip(&previous).take_while(|(a, b)| a == b).count();
+        let max_suffix = frame.len().min(previous.len()) - prefix;
+        let suffix = frame.iter().rev().zip(previous.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+        let stored = &frame[prefix..frame.len() - suffix];
+
+        for value in [prefix, suffix, stored.len()] {
+            out.write_all(&(value as u32).to_le_bytes())?;
+        }
+        out.write_all(stored)?;
+
+        stats.frames += 1;
+        stats.frame_bytes += frame.len() as u64;
+        stats.stored_bytes += stored.len() as u64;
+        previous = frame;
+    }
+    out.flush()?;
+    Ok(stats)
+}
+
+/// Restores a standard AVI file from the output of [`pack_delta`].
+pub fn unpack_delta<I: Read, W: Writer>(mut input: I, writer: W) -> Result<W> {
+    let mut magic = [0u8; 5];
+    input.read_exact(&mut magic)?;
+    if magic[..4] != DELTA_MAGIC || magic[4] != DELTA_VERSION {
+        return Err(MjpegError::InvalidAvi("not a delta file of a supported version".to_string()));
+    }
+    let width = read_u32(&mut input)?;
+    let height = read_u32(&mut input)?;
+    let fps = read_u32(&mut input)?;
+    let frame_count = read_u32(&mut input)?;
+
+    let mut writer = MjpegWriterBuilder::new(width, height, fps).build(writer)?;
+    let mut previous: Vec<u8> = Vec::new();
+    for _ in 0..frame_count {
+        let prefix = read_u32(&mut input)? as usize;
+        let suffix = read_u32(&mut input)? as usize;
+        let stored = read_u32(&mut input)? as usize;
+        if prefix.checked_add(suffix).is_none_or(|shared| shared > previous.len()) {
+            return Err(MjpegError::InvalidAvi("delta frame references missing data".to_string()));
+        }
+
+        let mut frame = Vec::with_capacity(prefix + stored + suffix);
+        frame.extend_from_slice(&previous[..prefix]);
+        frame.resize(prefix + stored, 0);
+        input.read_exact(&mut frame[prefix..])?;
+        frame.extend_from_slice(&previous[previous.len() - suffix..]);
+        writer.add_frame(&frame)?;
+        previous = frame;
+    }
+    writer.finish()
+}
+
+fn read_u32<I: Read>(input: &mut I) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use crate::Result;
+use crate::riff::*;
+use crate::producer::PRODUCER_CHUNK_ID;
+
+/// The number of chunks printed at the start of a `movi` list before it is summarized.
+const MOVI_HEAD_CHUNKS: usize = 3;
+
+/// Prints the RIFF structure of an AVI file in a human-readable form.
+///
+/// The output lists every chunk with its offset and size, decodes the `avih`, `strh` and
+/// `strf` header fields, summarizes the `movi` list by chunk type and shows the first and
+/// last `idx1` entries. Unlike [`MjpegReader`](crate::MjpegReader) it doesn't reject
+/// malformed files, which makes it useful for finding out why a player refuses a file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut report = String::new();
+///     mjpeg_avi_rs::dump(&mut File::open("output.avi")?, &mut report)?;
+///     println!("{}", report);
+///     Ok(())
+/// }
+/// ```
+pub fn dump<R: Read + Seek, O: fmt::Write>(reader: &mut R, out: &mut O) -> Result<()> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    writeln!(out, "file size: {}", file_len)?;
+
+    let mut offset = 0;
+    while let Some(chunk) = read_chunk(reader, offset, file_len)? {
+        dump_chunk(reader, out, &chunk, file_len, 0)?;
+        offset = chunk.end_within(file_len);
+    }
+    if offset < file_len {
+        // Only reachable when the last chunk header is cut off
+        writeln!(out, "{} trailing bytes at offset {}", file_len - offset, offset)?;
+    }
+    Ok(())
+}
+
+fn dump_chunk<R: Read + Seek, O: fmt::Write>(reader: &mut R, out: &mut O, chunk: &Chunk, end: u64, depth: usize) -> Result<()> {
+    let indent = "  ".repeat(depth);
+    let truncated = if chunk.data_offset() + chunk.size as u64 > end { " (truncated)" } else { "" };
+
+    if !chunk.is_list() {
+        writeln!(out, "{}{} offset={} size={}{}", indent, fourcc_str(&chunk.id), chunk.offset, chunk.size, truncated)?;
+        if truncated.is_empty() {
+            dump_fields(reader, out, chunk, depth + 1)?;
+        }
+        return Ok(());
+    }
+
+    let list_type = read_list_type(reader, chunk)?;
+    writeln!(out, "{}{} '{}' offset={} size={}{}", indent, fourcc_str(&chunk.id), fourcc_str(&list_type), chunk.offset, chunk.size, truncated)?;
+
+    // Unfinished files have zero sizes, so their contents run to the end of the file
+    let list_end = chunk.end_within(end);
+    if &list_type == b"movi" {
+        return dump_movi(reader, out, chunk, list_end, depth + 1);
+    }
+
+    let mut offset = chunk.data_offset() + 4;
+    while let Some(child) = read_chunk(reader, offset, list_end)? {
+        dump_chunk(reader, out, &child, list_end, depth + 1)?;
+        offset = child.end_within(list_end);
+    }
+    Ok(())
+}
+
+fn dump_movi<R: Read + Seek, O: fmt::Write>(reader: &mut R, out: &mut O, movi: &Chunk, end: u64, depth: usize) -> Result<()> {
+    let indent = "  ".repeat(depth);
+    let mut counts: Vec<([u8; 4], usize, u64)> = Vec::new();
+    let mut count = 0;
+    let mut last = None;
+
+    let mut offset = movi.data_offset() + 4;
+    while let Some(chunk) = read_chunk(reader, offset, end)? {
+        if count < MOVI_HEAD_CHUNKS {
+            dump_chunk(reader, out, &chunk, end, depth)?;
+        }
+        match counts.iter_mut().find(|(id, _, _)| *id == chunk.id) {
+            Some((_, n, bytes)) => {
+                *n += 1;
+                *bytes += chunk.size as u64;
+            }
+            None => counts.push((chunk.id, 1, chunk.size as u64)),
+        }
+        count += 1;
+        last = Some(chunk);
+        offset = chunk.end();
+    }
+
+    if count > MOVI_HEAD_CHUNKS {
+        if count > MOVI_HEAD_CHUNKS + 1 {
+            writeln!(out, "{}... {} more chunks", indent, count - MOVI_HEAD_CHUNKS - 1)?;
+        }
+        if let Some(last) = last {
+            dump_chunk(reader, out, &last, end, depth)?;
+        }
+    }
+    for (id, n, bytes) in counts {
+        writeln!(out, "{}{}: {} chunks, {} bytes", indent, fourcc_str(&id), n, bytes)?;
+    }
+    Ok(())
+}
+
+fn dump_fields<R: Read + Seek, O: fmt::Write>(reader: &mut R, out: &mut O, chunk: &Chunk, depth: usize) -> Result<()> {
+    let indent = "  ".repeat(depth);
+    let fields: &[(&str, usize, Field)] = match &chunk.id {
+        b"avih" => &[
+            ("dwMicroSecPerFrame", 0, Field::U32),
+            ("dwMaxBytesPerSec", 4, Field::U32),
+            ("dwPaddingGranularity", 8, Field::U32),
+            ("dwFlags", 12, Field::Hex),
+            ("dwTotalFrames", 16, Field::U32),
+            ("dwInitialFrames", 20, Field::U32),
+            ("dwStreams", 24, Field::U32),
+            ("dwSuggestedBufferSize", 28, Field::U32),
+            ("dwWidth", 32, Field::U32),
+            ("dwHeight", 36, Field::U32),
+        ],
+        b"strh" => &[
+            ("fccType", 0, Field::FourCC),
+            ("fccHandler", 4, Field::FourCC),
+            ("dwFlags", 8, Field::Hex),
+            ("dwInitialFrames", 16, Field::U32),
+            ("dwScale", 20, Field::U32),
+            ("dwRate", 24, Field::U32),
+            ("dwStart", 28, Field::U32),
+            ("dwLength", 32, Field::U32),
+            ("dwSuggestedBufferSize", 36, Field::U32),
+            ("dwQuality", 40, Field::U32),
+            ("dwSampleSize", 44, Field::U32),
+        ],
+        b"strf" => &[
+            ("biSize", 0, Field::U32),
+            ("biWidth", 4, Field::U32),
+            ("biHeight", 8, Field::U32),
+            ("biPlanes", 12, Field::U16),
+            ("biBitCount", 14, Field::U16),
+            ("biCompression", 16, Field::FourCC),
+            ("biSizeImage", 20, Field::U32),
+        ],
+        b"dmlh" => &[("dwTotalFrames", 0, Field::U32)],
+        b"idx1" => return dump_idx1(reader, out, chunk, depth),
+        &PRODUCER_CHUNK_ID => {
+            for line in String::from_utf8_lossy(&read_payload(reader, chunk)?).lines() {
+                writeln!(out, "{}{}", indent, line.replacen('=', ": ", 1))?;
+            }
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+
+    let data = read_payload(reader, chunk)?;
+    for &(name, at, field) in fields {
+        if at + field.len() > data.len() {
+            break;
+        }
+        match field {
+            Field::U16 => writeln!(out, "{}{}: {}", indent, name, le_u16(&data, at))?,
+            Field::U32 => writeln!(out, "{}{}: {}", indent, name, le_u32(&data, at))?,
+            Field::Hex => writeln!(out, "{}{}: 0x{:08x}", indent, name, le_u32(&data, at))?,
+            Field::FourCC => writeln!(out, "{}{}: {}", indent, name, fourcc_str(&fourcc(&data, at)))?,
+        }
+    }
+    Ok(())
+}
+
+fn dump_idx1<R: Read + Seek, O: fmt::Write>(reader: &mut R, out: &mut O, chunk: &Chunk, depth: usize) -> Result<()> {
+    let indent = "  ".repeat(depth);
+    let entries = chunk.size as u64 / 16;
+    writeln!(out, "{}entries: {}", indent, entries)?;
+
+    let mut entry = [0u8; 16];
+    let positions: &[(&str, u64)] = &[("first", 0), ("last", entries.saturating_sub(1))];
+    for &(name, i) in positions.iter().take(entries.min(2) as usize) {
+        reader.seek(SeekFrom::Start(chunk.data_offset() + i * 16))?;
+        reader.read_exact(&mut entry)?;
+        writeln!(
+            out,
+            "{}{}: {} flags=0x{:08x} offset={} size={}",
+            indent,
+            name,
+            fourcc_str(&fourcc(&entry, 0)),
+            le_u32(&entry, 4),
+            le_u32(&entry, 8),
+            le_u32(&entry, 12),
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    U16,
+    U32,
+    Hex,
+    FourCC,
+}
+
+impl Field {
+    fn len(self) -> usize {
+        match self {
+            Field::U16 => 2,
+            _ => 4,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::time::Duration;
+use image::{ImageFormat, RgbImage};
+use image::codecs::jpeg::JpegEncoder;
+use crate::{MjpegError, Result};
+use crate::common::MAX_AVI_FILE_SIZE;
+use crate::filter::FrameFilter;
+use crate::builder::MjpegWriterBuilder;
+
+/// Bytes added to the file for every frame besides the JPEG data:
+/// chunk header, index entry and at most one padding byte.
+const FRAME_OVERHEAD: u64 = 8 + 16 + 1;
+/// Header, with about 100 bytes of `mjpv` producer chunk, and `idx1` chunk header.
+const FILE_OVERHEAD: u64 = 256 + 100 + 8;
+
+/// A JPEG frame produced by [`FrameEncoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedFrame {
+    /// The JPEG data, ready for `add_frame()`.
+    pub jpeg: Vec<u8>,
+    /// The JPEG quality the frame was encoded with.
+    pub quality: u8,
+}
+
+/// Statistics collected by [`FrameEncoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodeStats {
+    /// The number of frames encoded.
+    pub frames: u64,
+    /// The total size of the encoded frames.
+    pub bytes: u64,
+    /// The quality of the last frame.
+    pub last_quality: u8,
+    /// The lowest quality used so far.
+    pub min_quality: u8,
+    /// The highest quality used so far.
+    pub max_quality: u8,
+}
+
+/// A feedback controller that adjusts the JPEG quality to hold a target frame size.
+///
+/// After each frame, the quality is moved in proportion to how far the frame missed the
+/// target, so a scene that gets busier is encoded at a lower quality instead of making the
+/// file grow. Use [`QualityController::for_segment`] to keep a recording of a given duration
+/// below the AVI size limit.
+#[derive(Debug, Clone)]
+pub struct QualityController {
+    target_frame_bytes: f64,
+    quality: f64,
+    min_quality: u8,
+    max_quality: u8,
+    gain: f64,
+}
+
+impl QualityController {
+    /// Creates a controller holding `bitrate` bits per second at `fps` frames per second.
+    pub fn new(bitrate: u64, fps: u32) -> Self {
+        QualityController {
+            target_frame_bytes: bitrate as f64 / 8.0 / fps.max(1) as f64,
+            quality: 75.0,
+            min_quality: 10,
+            max_quality: 95,
+            gain: 20.0,
+        }
+    }
+
+    /// Creates a controller for segments of `duration` at `fps` that must fit in `max_bytes`.
+    ///
+    /// `max_bytes` is capped at the 2GB AVI limit, and the header and index are accounted for.
+    pub fn for_segment(duration: Duration, fps: u32, max_bytes: u64) -> Self {
+        let frames = (duration.as_secs_f64() * fps as f64).ceil().max(1.0);
+        let budget = max_bytes.min(MAX_AVI_FILE_SIZE).saturating_sub(FILE_OVERHEAD) as f64;
+        let frame_bytes = (budget / frames - FRAME_OVERHEAD as f64).max(1.0);
+        let mut controller = Self::new(0, fps);
+        controller.target_frame_bytes = frame_bytes;
+        controller
+    }
+
+    /// Sets the range the quality is kept in (1 to 100). Default: 10 to 95.
+    pub fn quality_range(&mut self, min: u8, max: u8) -> &mut Self {
+        self.min_quality = min.clamp(1, 100);
+        self.max_quality = max.clamp(self.min_quality, 100);
+        self.quality = self.quality.clamp(self.min_quality as f64, self.max_quality as f64);
+        self
+    }
+
+    /// Sets the quality of the first frame. Default: 75.
+    pub fn initial_quality(&mut self, quality: u8) -> &mut Self {
+        self.quality = (quality as f64).clamp(self.min_quality as f64, self.max_quality as f64);
+        self
+    }
+
+    /// Sets how strongly the quality reacts to a missed target. Default: 20.
+    ///
+    /// The quality moves by `gain * ln(target / actual)` per frame, at most 10 steps.
+    pub fn gain(&mut self, gain: f64) -> &mut Self {
+        self.gain = gain;
+        self
+    }
+
+    /// The target size of a frame in bytes.
+    pub fn target_frame_bytes(&self) -> f64 {
+        self.target_frame_bytes
+    }
+
+    /// The quality to encode the next frame with.
+    pub fn quality(&self) -> u8 {
+        self.quality.round() as u8
+    }
+
+    /// Feeds back the size of a frame encoded at `quality()`.
+    pub fn update(&mut self, frame_bytes: usize) {
+        if frame_bytes == 0 {
+            return;
+        }
+        let step = (self.gain * (self.target_frame_bytes / frame_bytes as f64).ln()).clamp(-10.0, 10.0);
+        self.quality = (self.quality + step).clamp(self.min_quality as f64, self.max_quality as f64);
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Quality {
+    Fixed(u8),
+    Adaptive(QualityController),
+}
+
+/// Encodes raw RGB frames to JPEG for the writers.
+///
+/// Enabled by the `encode` feature. The quality is either fixed or chosen per frame by a
+/// [`QualityController`]; the quality of each frame is returned with it and summarized in
+/// [`stats()`](Self::stats). [`FrameFilter`]s added with [`add_filter()`](Self::add_filter)
+/// are applied in order before encoding.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameEncoder, MjpegAviWriter, MjpegWriter, QualityController};
+/// use std::fs::File;
+/// use std::time::Duration;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     // One hour at 30fps in at most 1GB
+///     let controller = QualityController::for_segment(Duration::from_secs(3600), 30, 1 << 30);
+///     let mut encoder = FrameEncoder::adaptive(controller);
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 30)?;
+///
+///     let image = image::RgbImage::new(640, 480);
+///     let frame = encoder.encode(&image)?;
+///     writer.add_frame(&frame.jpeg)?;
+///     writer.finish()?;
+///     println!("quality: {}", frame.quality);
+///     Ok(())
+/// }
+/// ```
+pub struct FrameEncoder {
+    quality: Quality,
+    stats: EncodeStats,
+    filters: Vec<Box<dyn FrameFilter + Send>>,
+}
+
+impl fmt::Debug for FrameEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameEncoder")
+            .field("quality", &self.quality)
+            .field("stats", &self.stats)
+            .field("filters", &self.filters.len())
+            .finish()
+    }
+}
+
+impl FrameEncoder {
+    /// Creates an encoder with a fixed JPEG quality (1 to 100).
+    pub fn new(quality: u8) -> Self {
+        FrameEncoder {
+            quality: Quality::Fixed(quality.clamp(1, 100)),
+            stats: EncodeStats::default(),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Creates an encoder whose quality is adjusted by `controller`.
+    pub fn adaptive(controller: QualityController) -> Self {
+        FrameEncoder {
+            quality: Quality::Adaptive(controller),
+            stats: EncodeStats::default(),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Adds a filter applied to every frame after the filters added before it.
+    pub fn add_filter<F: FrameFilter + Send + 'static>(&mut self, filter: F) -> &mut Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// The size of the encoded frames for `width` x `height` input frames, after all filters.
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        self.filters.iter().fold((width, height), |(w, h), filter| filter.output_size(w, h))
+    }
+
+    /// Creates a writer builder for the frames this encoder produces from `width` x `height` input frames.
+    pub fn writer_builder(&self, width: u32, height: u32, fps: u32) -> MjpegWriterBuilder {
+        let (width, height) = self.output_size(width, height);
+        MjpegWriterBuilder::new(width, height, fps)
+    }
+
+    /// The quality controller, if the encoder is adaptive.
+    pub fn controller(&self) -> Option<&QualityController> {
+        match &self.quality {
+            Quality::Adaptive(controller) => Some(controller),
+            Quality::Fixed(_) => None,
+        }
+    }
+
+    /// The statistics of the frames encoded so far.
+    pub fn stats(&self) -> &EncodeStats {
+        &self.stats
+    }
+
+    /// Applies the filters to an RGB frame and encodes it.
+    pub fn encode(&mut self, image: &RgbImage) -> Result<EncodedFrame> {
+        if self.filters.is_empty() {
+            return self.encode_filtered(image);
+        }
+        let image = self.filter(image.clone());
+        self.encode_filtered(&image)
+    }
+
+    /// Applies the filters to an RGB frame and encodes it into `jpeg`, replacing its contents,
+    /// e.g. a [`PooledFrame`](crate::PooledFrame). Returns the quality used.
+    pub fn encode_into(&mut self, image: &RgbImage, jpeg: &mut Vec<u8>) -> Result<u8> {
+        if self.filters.is_empty() {
+            return self.encode_filtered_into(image, jpeg);
+        }
+        let image = self.filter(image.clone());
+        self.encode_filtered_into(&image, jpeg)
+    }
+
+    /// Decodes a JPEG frame, applies the filters and encodes it again.
+    pub fn reencode(&mut self, jpeg: &[u8]) -> Result<EncodedFrame> {
+        let image = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg).map_err(codec_error)?.to_rgb8();
+        let image = self.filter(image);
+        self.encode_filtered(&image)
+    }
+
+    fn filter(&mut self, image: RgbImage) -> RgbImage {
+        self.filters.iter_mut().fold(image, |image, filter| filter.apply(image))
+    }
+
+    fn encode_filtered(&mut self, image: &RgbImage) -> Result<EncodedFrame> {
+        let mut jpeg = Vec::new();
+        let quality = self.encode_filtered_into(image, &mut jpeg)?;
+        Ok(EncodedFrame { jpeg, quality })
+    }
+
+    fn encode_filtered_into(&mut self, image: &RgbImage, jpeg: &mut Vec<u8>) -> Result<u8> {
+        let quality = match &self.quality {
+            Quality::Fixed(quality) => *quality,
+            Quality::Adaptive(controller) => controller.quality(),
+        };
+
+        jpeg.clear();
+        JpegEncoder::new_with_quality(&mut *jpeg, quality).encode_image(image).map_err(codec_error)?;
+
+        if let Quality::Adaptive(controller) = &mut self.quality {
+            controller.update(jpeg.len());
+        }
+        self.record(quality, jpeg.len());
+        Ok(quality)
+    }
+
+    fn record(&mut self, quality: u8, size: usize) {
+        let stats = &mut self.stats;
+        if stats.frames == 0 {
+            stats.min_quality = quality;
+            stats.max_quality = quality;
+        }
+        stats.frames += 1;
+        stats.bytes += size as u64;
+        stats.last_quality = quality;
+        stats.min_quality = stats.min_quality.min(quality);
+        stats.max_quality = stats.max_quality.max(quality);
+    }
+}
+
+pub(crate) fn codec_error(err: image::ImageError) -> MjpegError {
+    MjpegError::Codec(err.to_string())
+}
@@ -0,0 +1,91 @@
+use std::time::Instant;
+use crate::Result;
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::pacing::{MissedTickPolicy, Ticker};
+use crate::writer::Writer;
+
+/// Statistics returned by [`capture_loop`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CaptureStats {
+    /// The number of frames written.
+    pub frames: u64,
+    /// The number of ticks dropped because the camera or the card was too slow.
+    pub missed_ticks: u64,
+    /// The number of frames dropped because they were not a complete JPEG.
+    pub corrupt_frames: u64,
+}
+
+/// Grabs up to `max_frames` camera frames at `fps` and writes them, as in an ESP32-CAM recording loop.
+///
+/// `grab` returns the camera frame buffer, typically a guard around `esp_camera_fb_get()` that
+/// calls `esp_camera_fb_return()` when dropped. The frame is written straight from the buffer,
+/// which is returned to the driver before the loop sleeps until the next tick, so the driver
+/// can run with only one or two frame buffers. The loop also stops when `grab` returns `Ok(None)`.
+///
+/// The camera DMA hands out buffers that may be truncated or padded after the end of the
+/// image: frames without the JPEG start and end markers are dropped and counted in
+/// [`CaptureStats::corrupt_frames`], and data after the last end marker is cut off.
+/// Missed ticks are skipped as with [`MissedTickPolicy::Skip`]. The writer is not finished.
+///
+/// Enabled by the `esp` feature. The crate builds for the ESP-IDF std targets
+/// (`xtensa-esp32-espidf`, `riscv32imc-esp-espidf`) with default features; the `tokio`
+/// feature is not supported there.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{capture_loop, FlashWriter, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+///
+/// # fn camera_frame() -> Option<Vec<u8>> { None }
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let flash = FlashWriter::new(File::create("/sdcard/video.avi")?, 4096);
+///     let mut writer = MjpegWriter::new(flash, 800, 600, 10)?;
+///     // Replace camera_frame() with a guard around esp_camera_fb_get()
+///     let stats = capture_loop(&mut writer, 10, 600, || Ok(camera_frame()))?;
+///     writer.finish()?.into_inner()?;
+///     println!("{:?}", stats);
+///     Ok(())
+/// }
+/// ```
+pub fn capture_loop<W, M, F, B>(writer: &mut M, fps: u32, max_frames: u64, mut grab: F) -> Result<CaptureStats>
+where
+    W: Writer,
+    M: MjpegAviWriter<W>,
+    F: FnMut() -> Result<Option<B>>,
+    B: AsRef<[u8]>,
+{
+    let mut ticker = Ticker::new(fps, MissedTickPolicy::Skip)?;
+    let mut stats = CaptureStats::default();
+
+    while stats.frames < max_frames {
+        let now = Instant::now();
+        if now < ticker.next {
+            std::thread::sleep(ticker.next - now);
+        }
+
+        let Some(frame_buffer) = grab()? else { break };
+        match trim_jpeg(frame_buffer.as_ref()) {
+            Some(jpeg) => {
+                writer.add_frame(jpeg)?;
+                stats.frames += 1;
+            }
+            None => stats.corrupt_frames += 1,
+        }
+        // Return the buffer to the driver before sleeping
+        drop(frame_buffer);
+        stats.missed_ticks += ticker.advance(Instant::now());
+    }
+
+    Ok(stats)
+}
+
+/// Returns the frame up to its last EOI marker, or `None` if it doesn't start with SOI.
+fn trim_jpeg(frame: &[u8]) -> Option<&[u8]> {
+    if !frame.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let end = frame.windows(2).rposition(|w| w == [0xFF, 0xD9])?;
+    // The SOI marker can't double as the EOI marker
+    (end >= 2).then(|| &frame[..end + 2])
+}
@@ -0,0 +1,124 @@
+use std::io::{self, IoSlice, Seek, SeekFrom, Write};
+use std::time::Duration;
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::common::MoviState;
+
+/// A writer that discards everything written to it and only keeps track of the size.
+///
+/// Use it to dry-run a conversion with the real writer: the file size limits are checked and
+/// `finish()` succeeds as usual, but no I/O happens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NullWriter {
+    pos: u64,
+    len: u64,
+}
+
+impl NullWriter {
+    /// Creates an empty writer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The size of the data written so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Write for NullWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let n = bufs.iter().map(|b| b.len()).sum();
+        self.pos += n as u64;
+        self.len = self.len.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for NullWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = new_pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
+
+/// The predicted result of writing a sequence of frames, see [`estimate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// The number of frames that fit in the file.
+    pub frames: u32,
+    /// The size of the finished file.
+    pub file_size: u64,
+    /// The playback duration of the frames that fit.
+    pub duration: Duration,
+    /// The error the writer would return for the first frame that doesn't fit,
+    /// such as [`MjpegError::FileSizeExceeded`].
+    pub error: Option<MjpegError>,
+}
+
+impl SizeEstimate {
+    /// Returns true if all frames fit in the file.
+    pub fn fits(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Predicts the file size and duration of a recording from the sizes of its frames,
+/// without any I/O.
+///
+/// The prediction stops at the first frame the writer would reject, which is reported in
+/// [`SizeEstimate::error`]. Returns an error only for an invalid `fps`.
+///
+/// # Examples
+///
+/// ```
+/// // Two hours of 30fps frames of about 40kB
+/// let estimate = mjpeg_avi_rs::estimate(std::iter::repeat(40_000).take(2 * 3600 * 30), 30)?;
+/// assert!(!estimate.fits());
+/// println!("only {:?} fit in one file", estimate.duration);
+/// # Ok::<(), mjpeg_avi_rs::MjpegError>(())
+/// ```
+pub fn estimate<I: IntoIterator<Item = usize>>(frame_sizes: I, fps: u32) -> Result<SizeEstimate> {
+    MjpegWriterBuilder::new(0, 0, fps).unchecked_dimensions(true).estimate(frame_sizes)
+}
+
+pub(crate) fn estimate_with_state<I: IntoIterator<Item = usize>>(mut state: MoviState, frame_sizes: I) -> SizeEstimate {
+    let mut error = None;
+    for size in frame_sizes {
+        match state.prepare_frame(size) {
+            Ok(chunk) => state.commit(chunk),
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+
+    let frames = state.frame_count();
+    SizeEstimate {
+        frames,
+        file_size: state.file_size(),
+        duration: Duration::from_secs(frames as u64) / state.fps(),
+        error,
+    }
+}
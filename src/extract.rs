@@ -0,0 +1,172 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::Result;
+use crate::frame_map::FrameMapEntry;
+use crate::reader::MjpegReader;
+
+/// Which frames [`Extractor`] exports.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sampling {
+    /// Every frame.
+    #[default]
+    All,
+    /// Every nth frame, starting with the first.
+    EveryNth(usize),
+    /// This many frames, evenly spread over the file.
+    Uniform(usize),
+    /// The first frame and every frame whose size differs from the previous frame's by more
+    /// than this fraction (e.g. `0.2` for 20%).
+    ///
+    /// A cheap scene-change heuristic: JPEG sizes jump when the content of the image changes,
+    /// and no frame has to be decoded.
+    SceneChange(f64),
+}
+
+/// Exports the frames of an AVI file as JPEG files.
+///
+/// The extractor reads the index once and then copies every frame with positioned reads, so
+/// frames can be read from one file handle by several threads. With the `rayon` feature,
+/// `extract_parallel` spreads the work across the rayon thread pool.
+/// A [`Sampling`] strategy selects representative frames from long recordings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::Extractor;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let extractor = Extractor::open("archive.avi")?;
+///     std::fs::create_dir_all("frames")?;
+///     let written = extractor.extract("frames")?;
+///     println!("{} frames written", written);
+///     Ok(())
+/// }
+/// ```
+pub struct Extractor {
+    file: File,
+    frames: Vec<FrameMapEntry>,
+    prefix: String,
+    sampling: Sampling,
+}
+
+impl Extractor {
+    /// Opens an AVI file and reads its index.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let reader = MjpegReader::new(File::open(path)?)?;
+        let frames = reader.frame_map();
+        Ok(Extractor { file: reader.into_inner(), frames, prefix: "frame_".to_string(), sampling: Sampling::All })
+    }
+
+    /// Sets the prefix of the file names. Default: `frame_`.
+    ///
+    /// Frame `n` is written to `{prefix}{n:06}.jpg`.
+    pub fn prefix<S: Into<String>>(&mut self, prefix: S) -> &mut Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets which frames are exported. Default: [`Sampling::All`].
+    pub fn sampling(&mut self, sampling: Sampling) -> &mut Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// The numbers of the frames selected by the sampling strategy.
+    pub fn selected_frames(&self) -> Vec<usize> {
+        let n = self.frames.len();
+        match self.sampling {
+            Sampling::All => (0..n).collect(),
+            Sampling::EveryNth(step) => (0..n).step_by(step.max(1)).collect(),
+            Sampling::Uniform(count) => {
+                let count = count.min(n);
+                (0..count).map(|i| i * n / count).collect()
+            }
+            Sampling::SceneChange(threshold) => (0..n)
+                .filter(|&i| {
+                    i == 0 || {
+                        let (prev, size) = (self.frames[i - 1].len as f64, self.frames[i].len as f64);
+                        (size - prev).abs() > prev * threshold
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// The number of frames in the file.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The path frame `frame` is written to in `dir`.
+    pub fn frame_path<P: AsRef<Path>>(&self, dir: P, frame: usize) -> PathBuf {
+        dir.as_ref().join(format!("{}{:06}.jpg", self.prefix, frame))
+    }
+
+    /// Reads the JPEG data of frame `frame`.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`](crate::MjpegError::FrameIndexOutOfRange) if it doesn't exist.
+    pub fn read_frame(&self, frame: usize) -> Result<Vec<u8>> {
+        let entry = self.frames.get(frame).ok_or(crate::MjpegError::FrameIndexOutOfRange)?;
+        let mut data = vec![0u8; entry.len as usize];
+        read_exact_at(&self.file, &mut data, entry.offset)?;
+        Ok(data)
+    }
+
+    /// Writes the selected frames to `dir`, which must exist, and returns the number of frames written.
+    pub fn extract<P: AsRef<Path>>(&self, dir: P) -> Result<usize> {
+        let frames = self.selected_frames();
+        for &frame in &frames {
+            self.extract_frame(dir.as_ref(), frame)?;
+        }
+        Ok(frames.len())
+    }
+
+    /// Like [`extract`](Self::extract), but reads and writes frames on the rayon thread pool.
+    ///
+    /// Enabled by the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn extract_parallel<P: AsRef<Path> + Sync>(&self, dir: P) -> Result<usize> {
+        use rayon::prelude::*;
+
+        let frames = self.selected_frames();
+        frames.par_iter().try_for_each(|&frame| self.extract_frame(dir.as_ref(), frame))?;
+        Ok(frames.len())
+    }
+
+    fn extract_frame(&self, dir: &Path, frame: usize) -> Result<()> {
+        let data = self.read_frame(frame)?;
+        std::fs::write(self.frame_path(dir, frame), data)?;
+        Ok(())
+    }
+}
+
+/// Reads at `offset` without moving a shared file cursor, so threads can share the handle.
+#[cfg(unix)]
+pub(crate) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+pub(crate) fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    // seek_read moves the file cursor, but every call passes its own offset
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = file.try_clone()?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
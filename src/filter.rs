@@ -0,0 +1,206 @@
+use image::{Rgb, RgbImage};
+use image::imageops::{self, FilterType};
+
+/// A transformation applied to every frame by [`FrameEncoder`](crate::FrameEncoder) before encoding.
+pub trait FrameFilter {
+    /// The size of the frames produced from `width` x `height` input frames.
+    ///
+    /// Use it to configure the writer when a filter changes the frame size.
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (width, height)
+    }
+
+    /// Transforms a frame.
+    fn apply(&mut self, image: RgbImage) -> RgbImage;
+}
+
+/// A rectangle in frame coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// The left edge.
+    pub x: u32,
+    /// The top edge.
+    pub y: u32,
+    /// The width.
+    pub width: u32,
+    /// The height.
+    pub height: u32,
+}
+
+impl Rect {
+    /// Creates a rectangle.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect { x, y, width, height }
+    }
+}
+
+/// A filter that fills privacy zones with a solid color.
+///
+/// Zones are given in frame coordinates and clipped to the frame. Frames that are already JPEG
+/// are decoded, masked and re-encoded with [`FrameEncoder::reencode`](crate::FrameEncoder::reencode),
+/// so the masked pixels can't be recovered from the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameEncoder, MaskFilter, MjpegAviWriter, MjpegWriter, Rect};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut mask = MaskFilter::new();
+///     mask.add_zone(Rect::new(0, 0, 200, 120)).add_zone(Rect::new(500, 300, 140, 180));
+///     let mut encoder = FrameEncoder::new(85);
+///     encoder.add_filter(mask);
+///
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 30)?;
+///     let frame = encoder.reencode(&std::fs::read("frame.jpg")?)?;
+///     writer.add_frame(&frame.jpeg)?;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MaskFilter {
+    zones: Vec<Rect>,
+    color: [u8; 3],
+}
+
+impl MaskFilter {
+    /// Creates a filter without zones that masks in black.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a zone to mask.
+    pub fn add_zone(&mut self, zone: Rect) -> &mut Self {
+        self.zones.push(zone);
+        self
+    }
+
+    /// Sets the RGB fill color. Default: black.
+    pub fn color(&mut self, color: [u8; 3]) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    /// The zones to mask.
+    pub fn zones(&self) -> &[Rect] {
+        &self.zones
+    }
+}
+
+impl FrameFilter for MaskFilter {
+    fn apply(&mut self, mut image: RgbImage) -> RgbImage {
+        let (width, height) = image.dimensions();
+        for zone in &self.zones {
+            let right = zone.x.saturating_add(zone.width).min(width);
+            let bottom = zone.y.saturating_add(zone.height).min(height);
+            for y in zone.y..bottom {
+                for x in zone.x..right {
+                    image.put_pixel(x, y, Rgb(self.color));
+                }
+            }
+        }
+        image
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ScaleMode {
+    Exact,
+    Fit,
+}
+
+/// A filter that scales frames to a target resolution, such as 4K camera frames to a 1080p archive.
+///
+/// Use [`FrameEncoder::writer_builder`](crate::FrameEncoder::writer_builder) to create a writer
+/// whose header has the scaled dimensions.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameEncoder, MjpegAviWriter, ScaleFilter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut encoder = FrameEncoder::new(80);
+///     encoder.add_filter(ScaleFilter::fit(1920, 1080));
+///     let mut writer = encoder.writer_builder(3840, 2160, 30).build(File::create("archive.avi")?)?;
+///     let frame = encoder.reencode(&std::fs::read("frame_4k.jpg")?)?;
+///     writer.add_frame(&frame.jpeg)?;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScaleFilter {
+    width: u32,
+    height: u32,
+    mode: ScaleMode,
+    filter: FilterType,
+}
+
+impl ScaleFilter {
+    /// Creates a filter that scales frames to exactly `width` x `height`.
+    pub fn new(width: u32, height: u32) -> Self {
+        ScaleFilter { width: width.max(1), height: height.max(1), mode: ScaleMode::Exact, filter: FilterType::Triangle }
+    }
+
+    /// Creates a filter that shrinks frames to fit in `width` x `height`, keeping the aspect ratio.
+    ///
+    /// Frames that already fit are not scaled.
+    pub fn fit(width: u32, height: u32) -> Self {
+        ScaleFilter { mode: ScaleMode::Fit, ..Self::new(width, height) }
+    }
+
+    /// Sets the resampling filter. Default: [`FilterType::Triangle`].
+    pub fn resampling(&mut self, filter: FilterType) -> &mut Self {
+        self.filter = filter;
+        self
+    }
+}
+
+impl FrameFilter for ScaleFilter {
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.mode {
+            ScaleMode::Exact => (self.width, self.height),
+            ScaleMode::Fit if width <= self.width && height <= self.height => (width, height),
+            ScaleMode::Fit => {
+                // Scale by the tighter of the two ratios, rounding to the nearest pixel
+                let (w, h) = (width as u64, height as u64);
+                if w * self.height as u64 >= h * self.width as u64 {
+                    (self.width, ((h * self.width as u64 + w / 2) / w).max(1) as u32)
+                } else {
+                    (((w * self.height as u64 + h / 2) / h).max(1) as u32, self.height)
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, image: RgbImage) -> RgbImage {
+        let (width, height) = self.output_size(image.width(), image.height());
+        if (width, height) == image.dimensions() {
+            return image;
+        }
+        imageops::resize(&image, width, height, self.filter)
+    }
+}
+
+/// A filter that blurs whole frames with a Gaussian blur, e.g. to redact them on export.
+#[derive(Debug, Clone, Copy)]
+pub struct BlurFilter {
+    sigma: f32,
+}
+
+impl BlurFilter {
+    /// Creates a filter blurring with the standard deviation `sigma`, in pixels.
+    pub fn new(sigma: f32) -> Self {
+        BlurFilter { sigma }
+    }
+}
+
+impl FrameFilter for BlurFilter {
+    fn apply(&mut self, image: RgbImage) -> RgbImage {
+        imageops::blur(&image, self.sigma)
+    }
+}
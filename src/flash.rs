@@ -0,0 +1,178 @@
+use std::io::{IoSlice, SeekFrom};
+use crate::Result;
+use crate::writer::Writer;
+
+/// A hook called after data has been committed to the underlying writer.
+type SyncHook<W> = Box<dyn FnMut(&mut W) -> Result<()> + Send>;
+
+/// Statistics collected by [`FlashWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FlashStats {
+    /// The number of full erase blocks written.
+    pub blocks: u64,
+    /// The number of partial blocks written, when the writer seeked or was finished.
+    pub partial_blocks: u64,
+    /// The number of writes outside the appended data, like the header rewrite at finish.
+    pub rewrites: u64,
+}
+
+/// A writer for raw flash and flash file systems like littlefs that commits appended data in
+/// whole erase blocks.
+///
+/// Frames are collected in a buffer of one erase block, which is written and synced when it
+/// is full, so every block of the file is programmed once. The writers defer all header
+/// updates to `finish()`, where the header is rewritten in a single write, so apart from the
+/// last partial block only the first block is programmed twice per file.
+///
+/// The sync hook set with [`on_commit`](Self::on_commit) runs after every commit; use it to
+/// call the file system's sync primitive.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FlashWriter, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut flash = FlashWriter::new(File::create("/sdcard/video.avi")?, 4096);
+///     flash.on_commit(|file: &mut File| Ok(file.sync_data()?));
+///     let mut writer = MjpegWriter::new(flash, 320, 240, 10)?;
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     let flash = writer.finish()?;
+///     println!("{:?}", flash.stats());
+///     Ok(())
+/// }
+/// ```
+pub struct FlashWriter<W: Writer> {
+    inner: W,
+    block_size: u64,
+    buf: Vec<u8>,
+    /// File offset of `buf[0]`
+    buf_start: u64,
+    /// Current position
+    pos: u64,
+    /// The end of the data written so far, including `buf`
+    len: u64,
+    sync: Option<SyncHook<W>>,
+    stats: FlashStats,
+}
+
+impl<W: Writer> FlashWriter<W> {
+    /// Wraps `inner`, which must be empty and positioned at its start, committing in blocks of `block_size` bytes.
+    pub fn new(inner: W, block_size: usize) -> Self {
+        let block_size = block_size.max(1);
+        FlashWriter {
+            inner,
+            block_size: block_size as u64,
+            buf: Vec::with_capacity(block_size),
+            buf_start: 0,
+            pos: 0,
+            len: 0,
+            sync: None,
+            stats: FlashStats::default(),
+        }
+    }
+
+    /// Sets the hook called after every commit, such as `File::sync_data`.
+    pub fn on_commit<F: FnMut(&mut W) -> Result<()> + Send + 'static>(&mut self, hook: F) -> &mut Self {
+        self.sync = Some(Box::new(hook));
+        self
+    }
+
+    /// The statistics of the writes so far.
+    pub fn stats(&self) -> &FlashStats {
+        &self.stats
+    }
+
+    /// Commits the buffered data and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.commit_partial()?;
+        Ok(self.inner)
+    }
+
+    /// Writes the buffer, which ends at a block boundary or at the end of the data.
+    fn commit(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.inner.seek(SeekFrom::Start(self.buf_start))?;
+        self.inner.write_all(&self.buf)?;
+        self.buf_start += self.buf.len() as u64;
+        self.buf.clear();
+        if let Some(sync) = &mut self.sync {
+            sync(&mut self.inner)?;
+        }
+        Ok(())
+    }
+
+    fn commit_partial(&mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            self.stats.partial_blocks += 1;
+        }
+        self.commit()
+    }
+
+    fn append(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            // Fill up to the next block boundary
+            let block_end = (self.buf_start / self.block_size + 1) * self.block_size;
+            let room = (block_end - self.buf_start) as usize - self.buf.len();
+            let n = room.min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            self.pos += n as u64;
+            self.len = self.pos;
+
+            if n == room {
+                if self.buf_start.is_multiple_of(self.block_size) {
+                    self.stats.blocks += 1;
+                } else {
+                    // The start of the block was committed before
+                    self.stats.partial_blocks += 1;
+                }
+                self.commit()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Writer> Writer for FlashWriter<W> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        if self.pos == self.buf_start + self.buf.len() as u64 && self.pos == self.len {
+            return self.append(buf);
+        }
+
+        // Writes into the committed data, like the header rewrite, go straight through
+        self.commit_partial()?;
+        self.stats.rewrites += 1;
+        self.inner.seek(SeekFrom::Start(self.pos))?;
+        self.inner.write_all(buf)?;
+        if let Some(sync) = &mut self.sync {
+            sync(&mut self.inner)?;
+        }
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        self.buf_start = self.len;
+        Ok(())
+    }
+
+    fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        bufs.iter().try_for_each(|buf| self.write_all(buf))
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        let new_pos = new_pos.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        if new_pos != self.pos {
+            // Leaving the end of the data: commit it, so the header rewrite at finish leaves nothing buffered
+            self.commit_partial()?;
+            self.pos = new_pos;
+        }
+        Ok(self.pos)
+    }
+}
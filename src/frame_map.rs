@@ -0,0 +1,67 @@
+use std::fmt;
+use std::time::Duration;
+use crate::Result;
+use crate::index::IndexEntry;
+
+/// The timestamp and byte range of one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameMapEntry {
+    /// The presentation time of the frame.
+    pub timestamp: Duration,
+    /// File offset of the JPEG data.
+    pub offset: u64,
+    /// The length of the chunk payload. It may include one padding byte after the JPEG data,
+    /// which decoders ignore.
+    pub len: u32,
+}
+
+impl FrameMapEntry {
+    /// The HTTP `Range` header value that fetches this frame, e.g. `bytes=348-9347`.
+    pub fn range_header(&self) -> String {
+        format!("bytes={}-{}", self.offset, self.offset + self.len.max(1) as u64 - 1)
+    }
+}
+
+/// Builds the frame map from the frame numbers and index entries of the frames. A frame lasts `scale / rate` seconds.
+pub(crate) fn frame_map<'a, I: IntoIterator<Item = (u64, &'a IndexEntry)>>(frames: I, movi_base: u64, scale: u32, rate: u32) -> Vec<FrameMapEntry> {
+    frames
+        .into_iter()
+        .map(|(frame, entry)| FrameMapEntry {
+            timestamp: frame_timestamp(frame, scale, rate),
+            offset: movi_base + entry.offset as u64 + 8,
+            len: entry.size,
+        })
+        .collect()
+}
+
+pub(crate) fn frame_timestamp(frame: u64, scale: u32, rate: u32) -> Duration {
+    if rate == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = frame as u128 * scale as u128 * 1_000_000_000 / rate as u128;
+    Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+}
+
+/// Writes a frame map as JSON.
+///
+/// Every frame of an MJPEG AVI file is a complete JPEG image stored contiguously in the `movi`
+/// list, so a web player that has the frame map can show any frame by fetching its byte range
+/// from object storage, without downloading the whole file.
+///
+/// The output is an array with one object per frame: `t` is the timestamp in seconds,
+/// `offset` and `len` the byte range of the JPEG data.
+///
+/// ```text
+/// [{"t":0.000000,"offset":348,"len":9000},{"t":0.033333,"offset":9356,"len":9012}]
+/// ```
+pub fn write_frame_map_json<O: fmt::Write>(map: &[FrameMapEntry], out: &mut O) -> Result<()> {
+    out.write_char('[')?;
+    for (i, entry) in map.iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        write!(out, r#"{{"t":{:.6},"offset":{},"len":{}}}"#, entry.timestamp.as_secs_f64(), entry.offset, entry.len)?;
+    }
+    out.write_char(']')?;
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::lock::create_locked;
+use crate::mjpeg_sync::{MjpegAviWriter, MjpegWriter};
+use crate::segmenter::SegmentPlanner;
+use crate::timestamp::{PathDigits, TimestampStrategy};
+use crate::timing::rfc3339;
+
+/// How much of a recording [`JpegImporter`] puts into one AVI file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SegmentLength {
+    /// One file per hour, named `YYYY-MM-DDTHH.avi`.
+    #[default]
+    Hour,
+    /// One file per day, named `YYYY-MM-DD.avi`.
+    Day,
+}
+
+impl SegmentLength {
+    fn secs(self) -> u64 {
+        match self {
+            SegmentLength::Hour => 3600,
+            SegmentLength::Day => 86400,
+        }
+    }
+}
+
+/// Statistics of a [`JpegImporter::import`] run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportStats {
+    /// The number of JPEG files found.
+    pub files: u64,
+    /// The number of frames written.
+    pub frames: u64,
+    /// The number of dropped frames written for gaps between images.
+    pub dropped_frames: u64,
+    /// The number of images left out because an earlier image took their frame slot.
+    pub duplicates: u64,
+    /// The number of images left out because no strategy found their timestamp.
+    pub untimed: u64,
+    /// The AVI files written, in time order.
+    pub segments: Vec<PathBuf>,
+}
+
+/// Backfills AVI files from a directory tree of timestamped JPEG images.
+///
+/// The capture time of each image is found by the [`TimestampStrategy`]s added with
+/// [`timestamp_strategy`](Self::timestamp_strategy), trying them in order; by default it is
+/// read from the digits of the path with [`PathDigits`](crate::PathDigits), so
+/// `2024/05/01/12/34/56.123.jpg` is 12:34:56.123 on 2024-05-01.
+///
+/// Each image is placed on the frame grid of the target rate starting at the first image of its
+/// segment. Gaps are filled with dropped frames, so the files play in real time and timestamps
+/// can be recovered from frame numbers; images falling into an already used slot are left out.
+/// Timestamps are taken as UTC; segment boundaries and names use the same clock, so a tree in
+/// local time produces segments in local time. The frame size of a segment is that of its
+/// first image.
+///
+/// A [`SegmentPlanner`](crate::SegmentPlanner) watches the image sizes, so a segment that would
+/// exceed the 2GB AVI limit, or the [`max_segment_size`](Self::max_segment_size), is split
+/// evenly into parts named `YYYY-MM-DD.2.avi`, `YYYY-MM-DD.3.avi`, ... after the first, each
+/// starting with the image that didn't fit.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{ExifTimestamp, JpegImporter, ModifiedTime, PathDigits, SegmentLength};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let stats = JpegImporter::new(1)
+///         .segment_length(SegmentLength::Day)
+///         .timestamp_strategy(ExifTimestamp)
+///         .timestamp_strategy(PathDigits)
+///         .timestamp_strategy(ModifiedTime)
+///         .import("snapshots", "archive")?;
+///     println!("{} frames, {} gaps filled in {} files", stats.frames, stats.dropped_frames, stats.segments.len());
+///     Ok(())
+/// }
+/// ```
+pub struct JpegImporter {
+    fps: u32,
+    segment_length: SegmentLength,
+    max_segment_size: Option<u64>,
+    strategies: Vec<Box<dyn TimestampStrategy + Send>>,
+}
+
+impl fmt::Debug for JpegImporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JpegImporter")
+            .field("fps", &self.fps)
+            .field("segment_length", &self.segment_length)
+            .field("max_segment_size", &self.max_segment_size)
+            .field("strategies", &self.strategies.len())
+            .finish()
+    }
+}
+
+impl JpegImporter {
+    /// Creates an importer writing `fps` frames per second into hourly files.
+    pub fn new(fps: u32) -> Self {
+        JpegImporter { fps, segment_length: SegmentLength::default(), max_segment_size: None, strategies: Vec::new() }
+    }
+
+    /// Sets how much of the recording goes into one file. Default: [`SegmentLength::Hour`].
+    pub fn segment_length(&mut self, segment_length: SegmentLength) -> &mut Self {
+        self.segment_length = segment_length;
+        self
+    }
+
+    /// Sets the largest file size, e.g. for storage with smaller files. Segments that would
+    /// be larger are split into parts. Default: the 2GB AVI limit.
+    pub fn max_segment_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_segment_size = Some(bytes);
+        self
+    }
+
+    /// Adds a way to find the capture time, tried if the strategies added before it find none.
+    pub fn timestamp_strategy<S: TimestampStrategy + Send + 'static>(&mut self, strategy: S) -> &mut Self {
+        self.strategies.push(Box::new(strategy));
+        self
+    }
+
+    /// Imports the JPEG files below `input` into AVI files in `output_dir`, creating it if needed.
+    pub fn import<P: AsRef<Path>, Q: AsRef<Path>>(&self, input: P, output_dir: Q) -> Result<ImportStats> {
+        if self.fps == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        let (input, output_dir) = (input.as_ref(), output_dir.as_ref());
+        let mut stats = ImportStats::default();
+
+        let mut files = Vec::new();
+        collect_jpegs(input, &mut files)?;
+        stats.files = files.len() as u64;
+        let mut images: Vec<(Duration, PathBuf)> = Vec::with_capacity(files.len());
+        for path in files {
+            match self.timestamp(&path, path.strip_prefix(input).unwrap_or(&path))? {
+                Some(time) => images.push((time, path)),
+                None => stats.untimed += 1,
+            }
+        }
+        images.sort();
+
+        fs::create_dir_all(output_dir)?;
+        let segment_secs = self.segment_length.secs();
+        let mut start = 0;
+        while start < images.len() {
+            let segment = images[start].0.as_secs() / segment_secs;
+            let end = images[start..]
+                .iter()
+                .position(|(time, _)| time.as_secs() / segment_secs != segment)
+                .map_or(images.len(), |len| start + len);
+            let path = output_dir.join(self.segment_name(segment * segment_secs));
+            self.write_segment(&images[start..end], &path, &mut stats)?;
+            start = end;
+        }
+        Ok(stats)
+    }
+
+    /// The time since the epoch of the image at `path` from the first strategy that finds one.
+    fn timestamp(&self, path: &Path, relative: &Path) -> Result<Option<Duration>> {
+        let default: [Box<dyn TimestampStrategy + Send>; 1] = [Box::new(PathDigits)];
+        let strategies = if self.strategies.is_empty() { &default[..] } else { &self.strategies[..] };
+        for strategy in strategies {
+            if let Some(time) = strategy.timestamp(path, relative)? {
+                return Ok(time.duration_since(UNIX_EPOCH).ok());
+            }
+        }
+        Ok(None)
+    }
+
+    fn segment_name(&self, start_secs: u64) -> String {
+        let start = rfc3339(UNIX_EPOCH + Duration::from_secs(start_secs));
+        match self.segment_length {
+            SegmentLength::Hour => format!("{}.avi", &start[..13]),
+            SegmentLength::Day => format!("{}.avi", &start[..10]),
+        }
+    }
+
+    /// Writes the images of a segment to `path`, and to further parts if they don't fit.
+    fn write_segment(&self, images: &[(Duration, PathBuf)], path: &Path, stats: &mut ImportStats) -> Result<()> {
+        let mut first = fs::read(&images[0].1)?;
+        let (width, height) = jpeg_size(&first)
+            .ok_or_else(|| MjpegError::Codec(format!("no frame size in {}", images[0].1.display())))?;
+        let builder = MjpegWriterBuilder::new(width, height, self.fps);
+        let mut planner = SegmentPlanner::new(&builder, Duration::from_secs(self.segment_length.secs()));
+        if let Some(bytes) = self.max_segment_size {
+            planner.max_bytes(bytes);
+        }
+        let mut writer: MjpegWriter<File> = builder.build(create_locked(path)?)?;
+        let mut part_path = path.to_path_buf();
+        let mut part = 1;
+        planner.begin_segment();
+
+        let mut origin = images[0].0;
+        let mut next_slot = 0u128;
+        for (i, (time, image)) in images.iter().enumerate() {
+            let mut slot = ((*time - origin).as_nanos() * self.fps as u128 + 500_000_000) / 1_000_000_000;
+            if slot < next_slot {
+                stats.duplicates += 1;
+                continue;
+            }
+            if planner.should_rotate() {
+                writer.finish()?.sync_all()?;
+                stats.segments.push(part_path);
+                part += 1;
+                part_path = path.with_extension(format!("{}.avi", part));
+                writer = builder.build(create_locked(&part_path)?)?;
+                planner.begin_segment();
+                (origin, slot, next_slot) = (*time, 0, 0);
+            }
+            for _ in next_slot..slot {
+                writer.add_dropped_frame()?;
+                planner.add_frame(0);
+                stats.dropped_frames += 1;
+            }
+            let frame = if i == 0 { std::mem::take(&mut first) } else { fs::read(image)? };
+            writer.add_frame(&frame)?;
+            planner.add_frame(frame.len());
+            stats.frames += 1;
+            next_slot = slot + 1;
+        }
+        writer.finish()?.sync_all()?;
+        stats.segments.push(part_path);
+        Ok(())
+    }
+}
+
+/// Collects the `.jpg` and `.jpeg` files below `dir`.
+fn collect_jpegs(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_jpegs(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with the marker and the payload of each segment of a JPEG image before the scan
+/// data, until it returns a value.
+pub(crate) fn find_segment<'a, T>(jpeg: &'a [u8], mut f: impl FnMut(u8, &'a [u8]) -> Option<T>) -> Option<T> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    loop {
+        while *jpeg.get(pos)? != 0xFF {
+            pos += 1;
+        }
+        while *jpeg.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = jpeg[pos];
+        pos += 1;
+        match marker {
+            // Markers without a length
+            0x01 | 0xD0..=0xD7 => continue,
+            0xD9 | 0xDA => return None,
+            _ => {
+                let len = jpeg.get(pos..pos + 2)?;
+                let end = pos + u16::from_be_bytes([len[0], len[1]]) as usize;
+                if let Some(value) = f(marker, jpeg.get(pos + 2..end)?) {
+                    return Some(value);
+                }
+                pos = end;
+            }
+        }
+    }
+}
+
+/// Reads the width and height from the SOF segment of a JPEG image.
+pub(crate) fn jpeg_size(jpeg: &[u8]) -> Option<(u32, u32)> {
+    find_segment(jpeg, |marker, payload| {
+        // SOF0-SOF15 except DHT, JPG and DAC
+        if !matches!(marker, 0xC0..=0xCF) || matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return None;
+        }
+        let sof = payload.get(..5)?;
+        let height = u16::from_be_bytes([sof[1], sof[2]]);
+        let width = u16::from_be_bytes([sof[3], sof[4]]);
+        Some((width as u32, height as u32))
+    })
+}
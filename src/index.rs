@@ -0,0 +1,107 @@
+use crate::common::create_index_entry;
+
+/// An entry of the AVI `idx1` index.
+///
+/// Every chunk in the `movi` list that should be found by players has an index entry.
+/// The chunk ID names the stream and the kind of data (`00dc` is compressed video of
+/// stream 0, `01wb` audio of stream 1, ...), see [`IndexEntry::chunk_id`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexEntry {
+    /// The chunk ID (fourcc) of the indexed chunk.
+    pub chunk_id: [u8; 4],
+    /// `AVIIF_*` flags, see the associated constants.
+    pub flags: u32,
+    /// Offset of the chunk header, relative to the `movi` list type.
+    pub offset: u32,
+    /// Size of the chunk payload.
+    pub size: u32,
+}
+
+impl IndexEntry {
+    /// `AVIIF_LIST`: the entry points to a `LIST` chunk.
+    pub const LIST: u32 = 0x01;
+    /// `AVIIF_KEYFRAME`: the chunk can be decoded on its own. Every MJPEG frame and audio chunk is a keyframe.
+    pub const KEYFRAME: u32 = 0x10;
+    /// `AVIIF_NO_TIME`: the chunk doesn't advance the stream's time (palette changes, custom data).
+    pub const NO_TIME: u32 = 0x100;
+
+    /// Compressed video chunk kind (`dc`).
+    pub const COMPRESSED_VIDEO: [u8; 2] = *b"dc";
+    /// Uncompressed video chunk kind (`db`).
+    pub const UNCOMPRESSED_VIDEO: [u8; 2] = *b"db";
+    /// Audio chunk kind (`wb`).
+    pub const AUDIO: [u8; 2] = *b"wb";
+    /// Palette change chunk kind (`pc`).
+    pub const PALETTE_CHANGE: [u8; 2] = *b"pc";
+    /// Text chunk kind (`tx`).
+    pub const TEXT: [u8; 2] = *b"tx";
+    /// Data stream chunk kind (`dt`), see `MjpegWriterBuilder::data_stream`.
+    pub const DATA: [u8; 2] = *b"dt";
+
+    /// Creates an entry.
+    pub fn new(chunk_id: [u8; 4], flags: u32, offset: u32, size: u32) -> Self {
+        IndexEntry { chunk_id, flags, offset, size }
+    }
+
+    /// Builds a stream chunk ID from a stream number and a two-character kind,
+    /// e.g. `chunk_id(1, IndexEntry::AUDIO)` is `01wb`.
+    ///
+    /// Stream numbers are written as two hexadecimal digits, so they must be below 256.
+    pub fn chunk_id(stream: u8, kind: [u8; 2]) -> [u8; 4] {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        [HEX[(stream >> 4) as usize], HEX[(stream & 0xF) as usize], kind[0], kind[1]]
+    }
+
+    /// The stream number encoded in the chunk ID, or `None` for chunks that don't belong to a stream.
+    pub fn stream(&self) -> Option<u8> {
+        let digit = |c: u8| (c as char).to_digit(16);
+        Some((digit(self.chunk_id[0])? * 16 + digit(self.chunk_id[1])?) as u8)
+    }
+
+    /// The two-character chunk kind (`dc`, `wb`, ...).
+    pub fn kind(&self) -> [u8; 2] {
+        [self.chunk_id[2], self.chunk_id[3]]
+    }
+
+    /// Returns true if `AVIIF_KEYFRAME` is set.
+    pub fn is_keyframe(&self) -> bool {
+        self.flags & Self::KEYFRAME != 0
+    }
+
+    /// Returns true if this entry is a video frame (`dc` or `db`).
+    pub fn is_video(&self) -> bool {
+        self.stream().is_some() && (self.kind() == Self::COMPRESSED_VIDEO || self.kind() == Self::UNCOMPRESSED_VIDEO)
+    }
+
+    /// Encodes the entry as the 16 bytes stored in `idx1`.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        create_index_entry(self.chunk_id, self.flags, self.offset, self.size)
+    }
+
+    /// Decodes an entry from the 16 bytes stored in `idx1`.
+    pub fn from_bytes(bytes: &[u8; 16]) -> Self {
+        let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        IndexEntry {
+            chunk_id: [bytes[0], bytes[1], bytes[2], bytes[3]],
+            flags: u32_at(4),
+            offset: u32_at(8),
+            size: u32_at(12),
+        }
+    }
+}
+
+/// Where a written frame is stored in the file, see `MjpegWriter::last_frame_offset()`.
+///
+/// Applications that serve frames with HTTP range requests can store these instead of
+/// reproducing the layout of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameLocation {
+    /// The number of the frame, starting at 0.
+    pub frame: u32,
+    /// File offset of the chunk header.
+    pub chunk_offset: u64,
+    /// File offset of the JPEG data (`chunk_offset + 8`).
+    pub data_offset: u64,
+    /// The length of the JPEG data, without the padding byte.
+    pub len: u32,
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use crate::clock::{Clock, SystemClock};
+
+/// Statistics collected by a [`JitterBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JitterStats {
+    /// The number of frames released in order.
+    pub released: u64,
+    /// The number of frames dropped because a later frame had already been released.
+    pub late: u64,
+    /// The number of frames dropped because a frame with the same sequence number was pending.
+    pub duplicates: u64,
+    /// The number of sequence numbers given up on as lost.
+    pub lost: u64,
+}
+
+/// A reordering buffer that restores the capture order of frames from a network source.
+///
+/// Frames pushed with their sequence numbers are released by [`pop`](Self::pop) in sequence
+/// order. When a frame is missing, the buffer holds the frames after it until more than
+/// `depth` frames are pending or, with [`max_latency`](Self::max_latency), until a frame has
+/// waited that long; the missing frame is then counted as lost. Frames arriving after a later
+/// frame was released are dropped.
+///
+/// Sequence numbers must increase by one per frame. Extend wrapping counters, such as 16-bit
+/// RTP sequence numbers, before pushing.
+///
+/// # Examples
+///
+/// ```
+/// use mjpeg_avi_rs::JitterBuffer;
+///
+/// let mut buffer = JitterBuffer::new(4);
+/// buffer.push(0, vec![0]);
+/// buffer.push(2, vec![2]);
+/// buffer.push(1, vec![1]);
+/// let order: Vec<u64> = std::iter::from_fn(|| buffer.pop()).map(|(seq, _)| seq).collect();
+/// assert_eq!(order, [0, 1, 2]);
+/// ```
+#[derive(Debug)]
+pub struct JitterBuffer<C: Clock = SystemClock> {
+    depth: usize,
+    max_latency: Option<Duration>,
+    clock: C,
+    pending: BTreeMap<u64, (Instant, Vec<u8>)>,
+    next: Option<u64>,
+    stats: JitterStats,
+}
+
+impl JitterBuffer {
+    /// Creates a buffer holding up to `depth` frames while waiting for a missing one.
+    pub fn new(depth: usize) -> Self {
+        Self::with_clock(depth, SystemClock)
+    }
+}
+
+impl<C: Clock> JitterBuffer<C> {
+    /// Creates a buffer that measures the latency with `clock`.
+    pub fn with_clock(depth: usize, clock: C) -> Self {
+        JitterBuffer { depth, max_latency: None, clock, pending: BTreeMap::new(), next: None, stats: JitterStats::default() }
+    }
+
+    /// Sets how long a frame waits for missing frames before them at most.
+    pub fn max_latency(&mut self, latency: Duration) -> &mut Self {
+        self.max_latency = Some(latency);
+        self
+    }
+
+    /// Adds a received frame.
+    pub fn push(&mut self, seq: u64, frame: Vec<u8>) {
+        if self.next.is_some_and(|next| seq < next) {
+            self.stats.late += 1;
+            return;
+        }
+        if self.pending.contains_key(&seq) {
+            self.stats.duplicates += 1;
+            return;
+        }
+        self.pending.insert(seq, (self.clock.now(), frame));
+    }
+
+    /// Returns the next frame in order with its sequence number, or `None` if it has to wait.
+    pub fn pop(&mut self) -> Option<(u64, Vec<u8>)> {
+        let (&seq, _) = self.pending.first_key_value()?;
+        let in_order = self.next.is_none_or(|next| seq == next);
+        if !in_order && self.pending.len() <= self.depth && !self.waited_too_long() {
+            return None;
+        }
+        Some(self.release(seq))
+    }
+
+    /// Releases all pending frames in order, giving up on the missing ones.
+    ///
+    /// Call it when the source has ended.
+    pub fn flush(&mut self) -> Vec<(u64, Vec<u8>)> {
+        let seqs: Vec<u64> = self.pending.keys().copied().collect();
+        seqs.into_iter().map(|seq| self.release(seq)).collect()
+    }
+
+    /// The number of frames waiting in the buffer.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if no frame is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// The statistics collected so far.
+    pub fn stats(&self) -> JitterStats {
+        self.stats
+    }
+
+    fn waited_too_long(&self) -> bool {
+        let Some(latency) = self.max_latency else { return false };
+        let now = self.clock.now();
+        self.pending.values().any(|(received, _)| now.saturating_duration_since(*received) >= latency)
+    }
+
+    fn release(&mut self, seq: u64) -> (u64, Vec<u8>) {
+        let (_, frame) = self.pending.remove(&seq).expect("released frame is pending");
+        if let Some(next) = self.next {
+            self.stats.lost += seq - next;
+        }
+        self.next = Some(seq + 1);
+        self.stats.released += 1;
+        (seq, frame)
+    }
+}
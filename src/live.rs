@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use crate::{MjpegError, Result};
+use crate::extract::read_exact_at;
+use crate::index::FrameLocation;
+
+#[derive(Default)]
+struct State {
+    frames: Vec<FrameLocation>,
+    finished: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is consistent after every update, so a panicking holder doesn't poison it
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Creates a connected [`LivePublisher`] and [`LiveTail`] for replaying a recording while it is written.
+///
+/// The recording thread publishes frames once their bytes are in the file, and readers only
+/// see published frames, so they never read a frame that is still being written. Frames are
+/// read from a separate handle of the same file with positioned reads.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{live_tail, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let (publisher, tail) = live_tail();
+///     let replay = std::thread::spawn(move || -> mjpeg_avi_rs::Result<()> {
+///         let file = File::open("live.avi")?;
+///         let mut next = 0;
+///         while tail.wait(next, None).is_some() {
+///             let jpeg = tail.read_frame(&file, next)?;
+///             // Show the frame
+///             next += 1;
+///         }
+///         Ok(())
+///     });
+///
+///     let mut writer = MjpegWriter::new(File::create("live.avi")?, 320, 240, 10)?;
+///     for _ in 0..100 {
+///         writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///         // File isn't buffered, so the frame is visible to other handles
+///         publisher.publish(writer.last_frame_offset().unwrap());
+///     }
+///     writer.finish()?;
+///     drop(publisher);
+///     replay.join().unwrap()
+/// }
+/// ```
+pub fn live_tail() -> (LivePublisher, LiveTail) {
+    let shared = Arc::new(Shared::default());
+    (LivePublisher { shared: shared.clone() }, LiveTail { shared })
+}
+
+/// The recording side of [`live_tail`].
+///
+/// Dropping the publisher marks the recording as finished and wakes all waiting readers.
+pub struct LivePublisher {
+    shared: Arc<Shared>,
+}
+
+impl LivePublisher {
+    /// Publishes a frame whose bytes have reached the file.
+    ///
+    /// Flush buffered writers (or sync the file, if readers must only see durable frames)
+    /// before publishing. Frames must be published in order; a frame that was already
+    /// published is ignored.
+    pub fn publish(&self, frame: FrameLocation) {
+        let mut state = self.shared.lock();
+        if frame.frame as usize == state.frames.len() {
+            state.frames.push(frame);
+            self.shared.changed.notify_all();
+        }
+    }
+
+    /// The number of frames published so far.
+    pub fn len(&self) -> usize {
+        self.shared.lock().frames.len()
+    }
+
+    /// Returns true if no frame has been published.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for LivePublisher {
+    fn drop(&mut self) {
+        self.shared.lock().finished = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// The replay side of [`live_tail`]. Clone it to replay from several threads.
+#[derive(Clone)]
+pub struct LiveTail {
+    shared: Arc<Shared>,
+}
+
+impl LiveTail {
+    /// The number of frames published so far.
+    pub fn len(&self) -> usize {
+        self.shared.lock().frames.len()
+    }
+
+    /// Returns true if no frame has been published.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the publisher was dropped, so no more frames will be published.
+    pub fn is_finished(&self) -> bool {
+        self.shared.lock().finished
+    }
+
+    /// The location of frame `frame`, if it has been published.
+    pub fn get(&self, frame: usize) -> Option<FrameLocation> {
+        self.shared.lock().frames.get(frame).copied()
+    }
+
+    /// Waits until frame `frame` is published, at most for `timeout` if given.
+    ///
+    /// Returns `None` if the timeout elapsed or the recording finished without the frame.
+    pub fn wait(&self, frame: usize, timeout: Option<Duration>) -> Option<FrameLocation> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(&location) = state.frames.get(frame) {
+                return Some(location);
+            }
+            if state.finished {
+                return None;
+            }
+            state = match deadline {
+                None => self.shared.changed.wait(state).unwrap_or_else(|err| err.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.shared.changed.wait_timeout(state, deadline - now).unwrap_or_else(|err| err.into_inner()).0
+                }
+            };
+        }
+    }
+
+    /// Reads the JPEG data of a published frame from `file`, a handle of the recording.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`](crate::MjpegError::FrameIndexOutOfRange)
+    /// if the frame hasn't been published.
+    pub fn read_frame(&self, file: &File, frame: usize) -> Result<Vec<u8>> {
+        let location = self.get(frame).ok_or(MjpegError::FrameIndexOutOfRange)?;
+        let mut data = vec![0u8; location.len as usize];
+        read_exact_at(file, &mut data, location.data_offset)?;
+        Ok(data)
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::Path;
+use crate::{MjpegError, Result};
+
+/// Creates or truncates the file at `path` for recording and takes an exclusive advisory lock
+/// on it, so tools that modify files in place, such as [`edit_metadata`](crate::edit_metadata),
+/// refuse to touch it while it is being written.
+///
+/// Fails with [`MjpegError::Locked`] without truncating the file if another handle, in this or
+/// another process, holds a lock on it. The lock is released when the file is closed, i.e.
+/// when the writer returned by `finish()` and the file are dropped. Readers don't take locks
+/// and can follow the recording. On platforms without file locking, the file is created
+/// without a lock.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{create_locked, MjpegAviWriter, MjpegWriter};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut writer = MjpegWriter::new(create_locked("output.avi")?, 320, 240, 30)?;
+///     writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+pub fn create_locked<P: AsRef<Path>>(path: P) -> Result<File> {
+    // Truncating before locking would destroy a recording in progress
+    let file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+    lock_exclusive(&file)?;
+    file.set_len(0)?;
+    Ok(file)
+}
+
+/// Takes an exclusive advisory lock on `file` without waiting, failing with
+/// [`MjpegError::Locked`] if another handle holds a lock on it.
+pub(crate) fn lock_exclusive(file: &File) -> Result<()> {
+    match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(MjpegError::Locked),
+        Err(TryLockError::Error(err)) if err.kind() == io::ErrorKind::Unsupported => Ok(()),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
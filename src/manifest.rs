@@ -0,0 +1,111 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::Result;
+use crate::catalog::write_json_string;
+use crate::frame_map::frame_timestamp;
+use crate::reader::MjpegReader;
+use crate::sha256::{to_hex, Sha256};
+use crate::timing::rfc3339;
+
+/// A finished segment renamed after its content by [`content_address`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The SHA-256 hash of the file as lowercase hex.
+    pub hash: String,
+    /// The new path of the file, `<hash>.avi` in the original directory.
+    pub path: PathBuf,
+    /// When the recording started, estimated as the modification time minus the duration.
+    pub start_time: Option<SystemTime>,
+    /// The duration of the recording.
+    pub duration: Duration,
+    /// The file size in bytes.
+    pub size: u64,
+}
+
+/// Renames a finished segment to the SHA-256 hash of its content.
+///
+/// The file becomes `<hash>.avi` in the same directory, so identical segments get the same
+/// name and backup tools can skip files they already have. If a file with that name already
+/// exists it has the same content, and the segment replaces it. Collect the entries and write
+/// them with [`write_manifest_json`] to map time ranges to hashes; anyone can then check a file
+/// against the manifest by hashing it again.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{content_address, write_manifest_json, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut writer = MjpegWriter::new(File::create("archive/segment.avi")?, 640, 480, 30)?;
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.finish()?.sync_all()?;
+///     let entry = content_address("archive/segment.avi")?;
+///
+///     let mut json = String::new();
+///     write_manifest_json(&[entry], &mut json)?;
+///     std::fs::write("archive/manifest.json", json)?;
+///     Ok(())
+/// }
+/// ```
+pub fn content_address<P: AsRef<Path>>(path: P) -> Result<ManifestEntry> {
+    let path = path.as_ref();
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let hash = to_hex(&hasher.finish());
+
+    let reader = MjpegReader::new(file)?;
+    let info = reader.info();
+    let duration = frame_timestamp(reader.frame_count() as u64, info.scale, info.rate);
+    // Windows can't rename open files
+    drop(reader);
+
+    let new_path = path.with_file_name(format!("{}.avi", hash));
+    fs::rename(path, &new_path)?;
+    Ok(ManifestEntry {
+        hash,
+        path: new_path,
+        start_time: metadata.modified().ok().and_then(|modified| modified.checked_sub(duration)),
+        duration,
+        size: metadata.len(),
+    })
+}
+
+/// Writes a manifest of content-addressed segments as JSON.
+///
+/// The output is an array with one object per segment: `hash`, `start` (RFC 3339 UTC, or
+/// `null` if unknown), `duration` in seconds and `size` in bytes.
+///
+/// ```text
+/// [{"hash":"9f86d081884c7d65...","start":"2024-05-01T12:00:00.000000Z","duration":60.000000,"size":31457280}]
+/// ```
+pub fn write_manifest_json<O: fmt::Write>(manifest: &[ManifestEntry], out: &mut O) -> Result<()> {
+    out.write_char('[')?;
+    for (i, entry) in manifest.iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        out.write_str(r#"{"hash":"#)?;
+        write_json_string(&entry.hash, out)?;
+        match entry.start_time {
+            Some(start) => write!(out, r#","start":"{}""#, rfc3339(start))?,
+            None => out.write_str(r#","start":null"#)?,
+        }
+        write!(out, r#","duration":{:.6},"size":{}}}"#, entry.duration.as_secs_f64(), entry.size)?;
+    }
+    out.write_char(']')?;
+    Ok(())
+}
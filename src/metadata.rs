@@ -0,0 +1,287 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::{MjpegError, Result};
+use crate::common::create_chunk_header;
+use crate::lock::lock_exclusive;
+use crate::riff::*;
+
+/// The free space left after the `INFO` list when [`edit_metadata`] has to move the frames,
+/// so later edits fit in place.
+const METADATA_SLACK: usize = 1024;
+
+/// A top-level chunk and its list type, zeros if it isn't a list.
+type TopLevelChunk = (Chunk, [u8; 4]);
+
+/// The tags of the `INFO` list of an AVI file, shown by players and file managers.
+///
+/// Unset tags are left out. Give them to
+/// [`MjpegWriterBuilder::metadata`](crate::MjpegWriterBuilder::metadata) to tag every file of
+/// a builder, replace them while recording with
+/// [`MjpegWriter::set_metadata`](crate::MjpegWriter::set_metadata), or write them into a
+/// finished file with [`edit_metadata`]:
+///
+/// ```no_run
+/// # fn record(writer: &mut mjpeg_avi_rs::MjpegWriter<std::fs::File>) -> mjpeg_avi_rs::Result<()> {
+/// let metadata = mjpeg_avi_rs::AviMetadata { title: Some("Gate camera".into()), ..Default::default() };
+/// writer.set_metadata(&metadata)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AviMetadata {
+    /// `INAM`
+    pub title: Option<String>,
+    /// `ICMT`
+    pub comment: Option<String>,
+    /// `IART`
+    pub artist: Option<String>,
+    /// `ICOP`
+    pub copyright: Option<String>,
+    /// `ICRD`, conventionally `YYYY-MM-DD`.
+    pub creation_date: Option<String>,
+    /// `ISFT`
+    pub software: Option<String>,
+    /// Other tags, such as `IKEY` keywords.
+    pub other: Vec<([u8; 4], String)>,
+}
+
+impl AviMetadata {
+    /// Returns true if no tag is set.
+    pub fn is_empty(&self) -> bool {
+        self.tags().next().is_none()
+    }
+
+    /// The payload of the `INFO` list: the list type followed by one chunk per tag.
+    pub fn info_payload(&self) -> Vec<u8> {
+        let mut payload = b"INFO".to_vec();
+        for (id, value) in self.tags() {
+            // Values are NUL-terminated strings
+            let size = value.len() + 1;
+            payload.extend_from_slice(&create_chunk_header(id, size as u32));
+            payload.extend_from_slice(value.as_bytes());
+            payload.resize(payload.len() + 1 + (size & 1), 0);
+        }
+        payload
+    }
+
+    fn tags(&self) -> impl Iterator<Item = ([u8; 4], &str)> {
+        let known = [
+            (*b"INAM", &self.title),
+            (*b"ICMT", &self.comment),
+            (*b"IART", &self.artist),
+            (*b"ICOP", &self.copyright),
+            (*b"ICRD", &self.creation_date),
+            (*b"ISFT", &self.software),
+        ];
+        known
+            .into_iter()
+            .filter_map(|(id, value)| value.as_deref().map(|value| (id, value)))
+            .chain(self.other.iter().map(|(id, value)| (*id, value.as_str())))
+    }
+
+    fn set(&mut self, id: [u8; 4], value: String) {
+        match &id {
+            b"INAM" => self.title = Some(value),
+            b"ICMT" => self.comment = Some(value),
+            b"IART" => self.artist = Some(value),
+            b"ICOP" => self.copyright = Some(value),
+            b"ICRD" => self.creation_date = Some(value),
+            b"ISFT" => self.software = Some(value),
+            _ => self.other.push((id, value)),
+        }
+    }
+}
+
+/// Reads the tags of the `INFO` lists in the first RIFF of an AVI file.
+pub fn read_metadata<R: Read + Seek>(reader: &mut R) -> Result<AviMetadata> {
+    let (_, chunks) = top_level_chunks(reader)?;
+    let mut metadata = AviMetadata::default();
+    for (chunk, list_type) in &chunks {
+        if list_type != b"INFO" {
+            continue;
+        }
+        let mut offset = chunk.data_offset() + 4;
+        while let Some(tag) = read_chunk(reader, offset, chunk.end())? {
+            let value = read_payload(reader, &tag)?;
+            let value = value.split(|&b| b == 0).next().unwrap_or_default();
+            metadata.set(tag.id, String::from_utf8_lossy(value).into_owned());
+            offset = tag.end();
+        }
+    }
+    Ok(metadata)
+}
+
+/// Replaces the `INFO` list of a finished AVI file, so titles and comments can be corrected
+/// after recording. An empty `metadata` removes it.
+///
+/// The list is written in place into the `INFO` and `JUNK` chunks before the `movi` list, such
+/// as the space reserved with
+/// [`MjpegWriterBuilder::reserve_header`](crate::MjpegWriterBuilder::reserve_header). If it
+/// doesn't fit there, the file is rewritten through a temporary file next to it with 1KB of
+/// spare space for later edits; the frames move, but `idx1` offsets are relative to the `movi`
+/// list and stay valid. OpenDML indexes use file offsets, so files written with
+/// [`open_dml`](crate::MjpegWriterBuilder::open_dml) are only edited in place and fail with
+/// [`MjpegError::HeaderSpaceExceeded`] if the list doesn't fit.
+///
+/// Fails with [`MjpegError::Locked`] if the file is held by a writer that opened it with
+/// [`create_locked`](crate::create_locked).
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{edit_metadata, AviMetadata};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let metadata = AviMetadata {
+///         title: Some("Loading dock".to_string()),
+///         comment: Some("Delivery at 10:42".to_string()),
+///         ..Default::default()
+///     };
+///     edit_metadata("output.avi", &metadata)
+/// }
+/// ```
+pub fn edit_metadata<P: AsRef<Path>>(path: P, metadata: &AviMetadata) -> Result<()> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    lock_exclusive(&file)?;
+    let (riff, chunks) = top_level_chunks(&mut file)?;
+    if riff.size == 0 {
+        return Err(invalid("the file is not finished"));
+    }
+    let movi = chunks
+        .iter()
+        .position(|(_, list_type)| list_type == b"movi")
+        .ok_or_else(|| invalid("missing movi list"))?;
+
+    // The INFO lists and JUNK chunks right before the movi list are the space to write into
+    let is_free = |(chunk, list_type): &TopLevelChunk| &chunk.id == b"JUNK" || list_type == b"INFO";
+    let first_free = chunks[..movi].iter().rposition(|chunk| !is_free(chunk)).map_or(0, |i| i + 1);
+    let region_start = chunks[first_free].0.offset;
+    let region_len = (chunks[movi].0.offset - region_start) as usize;
+    // INFO lists elsewhere become JUNK
+    let stray: Vec<u64> = chunks[..first_free]
+        .iter()
+        .filter(|(_, list_type)| list_type == b"INFO")
+        .map(|(chunk, _)| chunk.offset)
+        .collect();
+
+    let mut info = Vec::new();
+    if !metadata.is_empty() {
+        let payload = metadata.info_payload();
+        info.extend_from_slice(&create_chunk_header(*b"LIST", payload.len() as u32));
+        info.extend_from_slice(&payload);
+    }
+
+    if info.len() == region_len || info.len() + 8 <= region_len {
+        for offset in stray {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(b"JUNK")?;
+        }
+        fill_region(&mut info, region_len);
+        file.seek(SeekFrom::Start(region_start))?;
+        file.write_all(&info)?;
+        file.sync_all()?;
+        return Ok(());
+    }
+
+    // Move the frames to make room, unless OpenDML indexes point to them by file offset
+    if has_super_index(&mut file, &chunks)? {
+        return Err(MjpegError::HeaderSpaceExceeded);
+    }
+    let mut head = vec![0u8; region_start as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut head)?;
+    for offset in stray {
+        head[offset as usize..offset as usize + 4].copy_from_slice(b"JUNK");
+    }
+    let len = info.len() + 8 + METADATA_SLACK;
+    fill_region(&mut info, len);
+    let riff_size = (riff.size as u64 + info.len() as u64)
+        .checked_sub(region_len as u64)
+        .filter(|&size| size <= u32::MAX as u64)
+        .ok_or(MjpegError::FileSizeExceeded)?;
+    head[4..8].copy_from_slice(&(riff_size as u32).to_le_bytes());
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".metadata.tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let result = (|| -> Result<()> {
+        let mut temp = File::create(&temp_path)?;
+        temp.write_all(&head)?;
+        temp.write_all(&info)?;
+        file.seek(SeekFrom::Start(chunks[movi].0.offset))?;
+        io::copy(&mut file, &mut temp)?;
+        temp.sync_all()?;
+        Ok(())
+    })();
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    drop(file);
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Inserts the `INFO` list of `metadata` before the `movi` list of a prepared header.
+pub(crate) fn add_info_list(header: &[u8], metadata: &AviMetadata) -> Vec<u8> {
+    let payload = metadata.info_payload();
+    let movi_list = header.len() - 12;
+    let mut extended = Vec::with_capacity(header.len() + 8 + payload.len());
+    extended.extend_from_slice(&header[..movi_list]);
+    extended.extend_from_slice(&create_chunk_header(*b"LIST", payload.len() as u32));
+    extended.extend_from_slice(&payload);
+    extended.extend_from_slice(&header[movi_list..]);
+    extended
+}
+
+/// Pads a LIST chunk with a JUNK chunk to `len` bytes.
+fn fill_region(info: &mut Vec<u8>, len: usize) {
+    if info.len() < len {
+        let junk = len - info.len() - 8;
+        info.extend_from_slice(&create_chunk_header(*b"JUNK", junk as u32));
+        info.resize(len, 0);
+    }
+}
+
+/// Returns true if a stream list of the `hdrl` list has an OpenDML `indx` super index.
+fn has_super_index<R: Read + Seek>(reader: &mut R, chunks: &[TopLevelChunk]) -> Result<bool> {
+    for (hdrl, _) in chunks.iter().filter(|(_, list_type)| list_type == b"hdrl") {
+        let mut offset = hdrl.data_offset() + 4;
+        while let Some(strl) = read_chunk(reader, offset, hdrl.end())? {
+            if strl.is_list() && &read_list_type(reader, &strl)? == b"strl" {
+                let mut offset = strl.data_offset() + 4;
+                while let Some(chunk) = read_chunk(reader, offset, strl.end())? {
+                    if &chunk.id == b"indx" {
+                        return Ok(true);
+                    }
+                    offset = chunk.end();
+                }
+            }
+            offset = strl.end();
+        }
+    }
+    Ok(false)
+}
+
+/// Reads the RIFF header and the top-level chunks of the first RIFF up to the `movi` list.
+fn top_level_chunks<R: Read + Seek>(reader: &mut R) -> Result<(Chunk, Vec<TopLevelChunk>)> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let riff = read_chunk(reader, 0, file_len)?.ok_or_else(|| invalid("file is too short"))?;
+    if &riff.id != b"RIFF" || &read_list_type(reader, &riff)? != b"AVI " {
+        return Err(invalid("not a RIFF AVI file"));
+    }
+    let riff_end = riff.end_within(file_len);
+    let mut chunks = Vec::new();
+    let mut offset = riff.data_offset() + 4;
+    while let Some(chunk) = read_chunk(reader, offset, riff_end)? {
+        let list_type = if chunk.is_list() { read_list_type(reader, &chunk)? } else { [0; 4] };
+        chunks.push((chunk, list_type));
+        if &list_type == b"movi" {
+            break;
+        }
+        offset = chunk.end();
+    }
+    Ok((riff, chunks))
+}
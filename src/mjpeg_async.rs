@@ -1,6 +1,13 @@
 use std::io::{IoSlice, SeekFrom};
-use crate::{MjpegError, Result};
+use crate::Result;
 use crate::common::*;
+use crate::index::{FrameLocation, IndexEntry};
+use crate::bookmark::{bookmark_payload, BOOKMARK_CHUNK_ID};
+use crate::metadata::AviMetadata;
+use crate::timecode::TIMECODE_FOURCC;
+use crate::custom::ChunkPlacement;
+use crate::frame_map::FrameMapEntry;
+use crate::builder::MjpegWriterBuilder;
 use crate::writer::AsyncWriter;
 
 #[cfg(any(feature = "async", feature = "tokio"))]
@@ -18,6 +25,7 @@ pub trait MjpegAviWriterAsync<W: AsyncWriter> {
     ///
     /// This method is more efficient than `add_frame` when the JPEG data is already
     /// in multiple chunks, as it avoids copying them into a single buffer.
+    #[allow(clippy::needless_lifetimes)]
     fn add_frame_vectored<'a, 'b>(&'a mut self, bufs: &'b [&'b [u8]]) -> impl Future<Output = Result<()>> + Send;
 
     /// Asynchronously finalizes the AVI file.
@@ -34,9 +42,7 @@ pub trait MjpegAviWriterAsync<W: AsyncWriter> {
 #[cfg(any(feature = "async", feature = "tokio"))]
 pub struct MjpegAsyncWriter<W: AsyncWriter> {
     writer: W,
-    frame_sizes: Vec<u32>,
-    jpeg_total_size: u64,
-    estimated_file_size: u64,
+    state: MoviState,
 }
 
 #[cfg(any(feature = "async", feature = "tokio"))]
@@ -51,130 +57,277 @@ impl<W: AsyncWriter> MjpegAsyncWriter<W> {
     /// * `width` - The width of the video frames.
     /// * `height` - The height of the video frames.
     /// * `fps` - The frames per second of the video.
-    pub async fn new(mut writer: W, width: u32, height: u32, fps: u32) -> Result<Self> {
-        if fps == 0 {
-            return Err(MjpegError::InvalidFrameSize);
-        }
+    pub async fn new(writer: W, width: u32, height: u32, fps: u32) -> Result<Self> {
+        MjpegWriterBuilder::new(width, height, fps).build_async(writer).await
+    }
 
-        let header = create_header_template(fps, width, height);
-        writer.write_all(&header).await?;
+    /// Writes a prepared header and creates the writer.
+    pub(crate) async fn with_state(mut writer: W, state: MoviState) -> Result<Self> {
+        writer.write_all(state.header()).await?;
 
         Ok(MjpegAsyncWriter {
             writer,
-            frame_sizes: Vec::new(),
-            jpeg_total_size: 0,
-            estimated_file_size: 256,
+            state,
         })
     }
-}
 
-// No need for new_tokio - regular new() works directly with tokio::fs::File!
+    /// The location of the most recently written frame, or `None` if no frame has been written.
+    pub fn last_frame_offset(&self) -> Option<FrameLocation> {
+        self.state.last_frame()
+    }
 
-#[cfg(feature = "async")]
-impl MjpegAsyncWriter<futures::io::Cursor<Vec<u8>>> {
-    /// Creates a new `MjpegAsyncWriter` with an in-memory cursor.
-    pub async fn new_cursor(width: u32, height: u32, fps: u32) -> Result<Self> {
-        let cursor = futures::io::Cursor::new(Vec::new());
-        Self::new(cursor, width, height, fps).await
+    /// The locations and timestamps of the frames written so far, see [`FrameMapEntry`].
+    pub fn frame_map(&self) -> Vec<FrameMapEntry> {
+        self.state.frame_map()
     }
-}
 
-#[cfg(any(feature = "async", feature = "tokio"))]
-impl<W: AsyncWriter> MjpegAsyncWriter<W> {    
-    fn check_limits(&self, frame_size: usize) -> Result<()> {
-        if self.frame_sizes.len() >= MAX_FRAME_COUNT as usize {
-            return Err(MjpegError::FrameCountExceeded);
+    /// Stores a chunk in the header space reserved with
+    /// [`MjpegWriterBuilder::reserve_header`](crate::MjpegWriterBuilder::reserve_header).
+    ///
+    /// See [`MjpegWriter::write_header_chunk`](crate::MjpegWriter::write_header_chunk).
+    pub fn write_header_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8]) -> Result<()> {
+        self.state.write_header_chunk(chunk_id, payload)
+    }
+
+    /// Replaces the tags of the `INFO` list in the header, written with the header at
+    /// `finish()`.
+    ///
+    /// See [`MjpegWriter::set_metadata`](crate::MjpegWriter::set_metadata).
+    pub fn set_metadata(&mut self, metadata: &AviMetadata) -> Result<()> {
+        self.state.set_metadata(metadata)
+    }
+
+    /// Sets the `dwQuality` of video stream 0, from 0 to 10000, written with the header at
+    /// `finish()`, for encoders that only know their JPEG quality once recording, see
+    /// [`MjpegWriterBuilder::stream_quality`](crate::MjpegWriterBuilder::stream_quality).
+    ///
+    /// Returns [`MjpegError::InvalidStreamQuality`](crate::MjpegError::InvalidStreamQuality) for
+    /// values above 10000.
+    pub fn set_stream_quality(&mut self, quality: u32) -> Result<()> {
+        self.state.set_stream_quality(quality)
+    }
+
+    /// Writes a chunk with an arbitrary chunk ID into the `movi` list.
+    ///
+    /// This is a low-level escape hatch for embedding data this crate doesn't model, such as
+    /// vendor-specific metadata. With `index_flags`, the chunk gets an `idx1` entry with those
+    /// flags (use [`IndexEntry::NO_TIME`](crate::IndexEntry::NO_TIME) for data outside of any
+    /// stream's timeline); with `None` it is skipped by players that follow the index.
+    ///
+    /// The caller is responsible for using chunk IDs that players don't misinterpret.
+    /// A chunk with the ID `00dc` is counted as a video frame.
+    pub async fn write_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8], index_flags: Option<u32>) -> Result<()> {
+        let chunk = self.state.prepare_chunk(chunk_id, index_flags, payload.len())?;
+        self.write_prepared(chunk, [IoSlice::new(payload)].into_iter()).await
+    }
+
+    /// Writes an application-specific chunk into the `movi` list or the top level of the RIFF
+    /// file.
+    ///
+    /// See [`MjpegWriter::write_custom_chunk`](crate::MjpegWriter::write_custom_chunk).
+    pub async fn write_custom_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8], placement: ChunkPlacement) -> Result<()> {
+        match placement {
+            ChunkPlacement::Movi => {
+                let chunk = self.state.prepare_custom_chunk(chunk_id, payload.len())?;
+                self.write_prepared(chunk, [IoSlice::new(payload)].into_iter()).await
+            }
+            ChunkPlacement::Header => self.state.write_custom_header_chunk(chunk_id, payload),
         }
-        
-        if frame_size > u32::MAX as usize {
-            return Err(MjpegError::FrameSizeExceeded);
+    }
+
+    /// Adds a bookmark with `label` before the next frame, see [`Bookmark`](crate::Bookmark).
+    pub async fn add_bookmark(&mut self, label: &str) -> Result<()> {
+        let payload = bookmark_payload(self.state.frame_count(), label);
+        self.write_chunk(BOOKMARK_CHUNK_ID, &payload, Some(IndexEntry::NO_TIME)).await
+    }
+
+    /// Asynchronously adds audio samples to the audio stream as `wb` chunks.
+    ///
+    /// See [`MjpegWriter::add_audio_samples`](crate::MjpegWriter::add_audio_samples).
+    pub async fn add_audio_samples(&mut self, samples: &[u8]) -> Result<()> {
+        if !self.state.buffer_audio(samples)? {
+            return self.write_audio(samples).await;
         }
-        
-        let padded_size = if frame_size % 2 == 1 { frame_size + 1 } else { frame_size };
-        let new_frame_data_size = 8 + padded_size;
-        let new_index_size = 16;
-        let estimated_new_size = self.estimated_file_size + new_frame_data_size as u64 + new_index_size;
-        
-        if estimated_new_size > MAX_AVI_FILE_SIZE {
-            return Err(MjpegError::FileSizeExceeded);
+        self.interleave_audio().await
+    }
+
+    /// Asynchronously adds a JPEG frame to video stream `stream`.
+    ///
+    /// See [`MjpegWriter::add_frame_for_stream`](crate::MjpegWriter::add_frame_for_stream).
+    pub async fn add_frame_for_stream(&mut self, stream: usize, jpeg_binary: &[u8]) -> Result<()> {
+        if stream == 0 {
+            self.write_timecode(Some(jpeg_binary.len())).await?;
         }
-        
-        Ok(())
+        let chunk = self.state.prepare_stream_frame(stream, jpeg_binary.len())?;
+        self.write_prepared(chunk, [IoSlice::new(jpeg_binary)].into_iter()).await?;
+        self.interleave_audio().await
     }
-}
 
-#[cfg(any(feature = "async", feature = "tokio"))]
-impl<W: AsyncWriter> MjpegAviWriterAsync<W> for MjpegAsyncWriter<W> {
-    async fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
-        self.add_frame_vectored(&[jpeg_binary]).await
+    /// Asynchronously adds a sample to the data stream `fourcc`, aligned with the next frame.
+    ///
+    /// See [`MjpegWriter::add_data`](crate::MjpegWriter::add_data).
+    pub async fn add_data(&mut self, fourcc: [u8; 4], data: &[u8]) -> Result<()> {
+        let chunk = self.state.prepare_data(fourcc, data.len())?;
+        self.write_prepared(chunk, [IoSlice::new(data)].into_iter()).await
     }
 
-    async fn add_frame_vectored<'a, 'b>(&'a mut self, bufs: &'b [&'b [u8]]) -> Result<()> {
-        let frame_size: usize = bufs.iter().map(|s| s.len()).sum();
-        if frame_size == 0 {
-            return Err(MjpegError::InvalidFrameSize);
-        }
+    /// Adds a dropped frame: an empty `00dc` chunk that keeps the timeline, during which players
+    /// show the previous frame.
+    pub async fn add_dropped_frame(&mut self) -> Result<()> {
+        self.write_timecode(None).await?;
+        let chunk = self.state.prepare_dropped_frame()?;
+        self.write_prepared(chunk, [IoSlice::new(&[])].into_iter()).await?;
+        self.interleave_audio().await
+    }
 
-        self.check_limits(frame_size)?;
+    /// Asynchronously adds a single JPEG frame from a slice of `IoSlice`s.
+    ///
+    /// Like `add_frame_vectored`, for callers that already hold `IoSlice` arrays, such as
+    /// scatter-gather capture drivers. The slices are written along with the chunk header
+    /// without re-slicing them.
+    pub async fn add_frame_ioslices(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let frame_size: usize = bufs.iter().map(|buf| buf.len()).sum();
+        self.write_timecode(Some(frame_size)).await?;
+        let chunk = self.state.prepare_frame(frame_size)?;
+        self.write_prepared(chunk, bufs.iter().copied()).await?;
+        self.interleave_audio().await
+    }
 
-        let odd = frame_size % 2 == 1;
-        let padded_size = if odd { frame_size + 1 } else { frame_size };
-        let padded_size_u32 = padded_size as u32;
+    /// Asynchronously adds a JPEG frame followed by a companion chunk holding `meta`.
+    ///
+    /// See [`MjpegWriter::add_frame_with_meta`](crate::MjpegWriter::add_frame_with_meta).
+    pub async fn add_frame_with_meta(&mut self, jpeg_binary: &[u8], meta: &[u8]) -> Result<()> {
+        self.write_timecode(Some(jpeg_binary.len())).await?;
+        let chunk = self.state.prepare_frame(jpeg_binary.len())?;
+        self.write_prepared(chunk, [IoSlice::new(jpeg_binary)].into_iter()).await?;
+        if !meta.is_empty() {
+            let chunk = self.state.prepare_frame_meta(meta.len())?;
+            self.write_prepared(chunk, [IoSlice::new(meta)].into_iter()).await?;
+        }
+        self.interleave_audio().await
+    }
 
-        let chunk_header = create_frame_chunk_header(padded_size_u32);
+    /// Starts a `LIST` chunk in the `movi` list. Chunks and frames written until the matching
+    /// [`end_list()`](Self::end_list) are placed inside it.
+    ///
+    /// Lists can be nested. With `index_flags`, the list gets an `idx1` entry with those flags
+    /// and the `AVIIF_LIST` flag, as `rec ` lists do. Lists still open at `finish()` are closed.
+    pub async fn begin_list(&mut self, list_type: [u8; 4], index_flags: Option<u32>) -> Result<()> {
+        let list = self.state.prepare_list(list_type, index_flags)?;
+        self.writer.write_all(&list.header).await?;
+        self.state.commit_list(list);
+        Ok(())
+    }
+
+    /// Closes the innermost list started by [`begin_list()`](Self::begin_list).
+    ///
+    /// Returns [`MjpegError::NoOpenList`](crate::MjpegError::NoOpenList) if no list is open.
+    pub async fn end_list(&mut self) -> Result<()> {
+        let patch = self.state.end_list()?;
+        self.writer.seek(SeekFrom::Start(patch.pos)).await?;
+        self.writer.write_all(&patch.size).await?;
+        self.writer.seek(SeekFrom::Start(patch.resume)).await?;
+        Ok(())
+    }
 
-        let mut bufs_to_write = Vec::with_capacity(bufs.len() + 2);
-        bufs_to_write.push(IoSlice::new(&chunk_header));
-        for buf in bufs {
-            bufs_to_write.push(IoSlice::new(buf));
+    /// Writes audio samples as `wb` chunks.
+    async fn write_audio(&mut self, samples: &[u8]) -> Result<()> {
+        for payload in self.state.audio_chunks(samples)? {
+            let chunk = self.state.prepare_audio(payload.len())?;
+            self.write_prepared(chunk, [IoSlice::new(payload)].into_iter()).await?;
         }
-        let padding_byte = [0u8];
-        if odd {
-            bufs_to_write.push(IoSlice::new(&padding_byte));
+        Ok(())
+    }
+
+    /// Writes the buffered audio samples if the interleave policy says they are due.
+    async fn interleave_audio(&mut self) -> Result<()> {
+        if !self.state.audio_due() {
+            return Ok(());
         }
+        let pending = self.state.take_audio();
+        self.write_audio(&pending).await
+    }
 
-        self.writer.write_all_vectored(&bufs_to_write).await?;
+    /// Writes the timecode of the next frame of stream 0 right before it, if the writer has a
+    /// timecode stream. `frame_size` is `None` for a dropped frame.
+    async fn write_timecode(&mut self, frame_size: Option<usize>) -> Result<()> {
+        let Some(payload) = self.state.timecode_payload() else {
+            return Ok(());
+        };
+        // Fail before the timecode if the frame can't be written after it
+        match frame_size {
+            Some(size) => self.state.prepare_frame(size)?,
+            None => self.state.prepare_dropped_frame()?,
+        };
+        let chunk = self.state.prepare_data(TIMECODE_FOURCC, payload.len())?;
+        self.write_prepared(chunk, [IoSlice::new(&payload)].into_iter()).await
+    }
 
-        self.frame_sizes.push(padded_size_u32);
-        self.jpeg_total_size += padded_size as u64;
-        self.estimated_file_size += 8 + padded_size as u64 + 16;
+    /// Writes a prepared chunk header, the payload and the padding byte.
+    async fn write_prepared<'a, I: ExactSizeIterator<Item = IoSlice<'a>>>(&mut self, chunk: PreparedChunk, payload: I) -> Result<()> {
+        if let Some(segment_break) = &chunk.segment_break {
+            self.writer.write_all(&segment_break.data).await?;
+            for (pos, bytes) in segment_break.patches {
+                self.writer.seek(SeekFrom::Start(pos)).await?;
+                self.writer.write_all(&bytes).await?;
+            }
+            self.writer.seek(SeekFrom::Start(segment_break.resume)).await?;
+        }
+        let bufs_to_write = chunk.io_slices(payload);
+        self.writer.write_all_vectored(&bufs_to_write).await?;
+        drop(bufs_to_write);
+        self.state.commit(chunk);
 
         Ok(())
     }
+}
+
+// No need for new_tokio - regular new() works directly with tokio::fs::File!
+
+#[cfg(feature = "async")]
+impl MjpegAsyncWriter<futures::io::Cursor<Vec<u8>>> {
+    /// Creates a new `MjpegAsyncWriter` with an in-memory cursor.
+    pub async fn new_cursor(width: u32, height: u32, fps: u32) -> Result<Self> {
+        let cursor = futures::io::Cursor::new(Vec::new());
+        Self::new(cursor, width, height, fps).await
+    }
+}
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+impl<W: AsyncWriter> MjpegAviWriterAsync<W> for MjpegAsyncWriter<W> {
+    async fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary]).await
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    async fn add_frame_vectored<'a, 'b>(&'a mut self, bufs: &'b [&'b [u8]]) -> Result<()> {
+        let frame_size: usize = bufs.iter().map(|s| s.len()).sum();
+        self.write_timecode(Some(frame_size)).await?;
+        let chunk = self.state.prepare_frame(frame_size)?;
+        self.write_prepared(chunk, bufs.iter().map(|buf| IoSlice::new(buf))).await?;
+        self.interleave_audio().await
+    }
 
     async fn finish(mut self) -> Result<W> {
-        let frame_count = self.frame_sizes.len();
-        
-        let file_sizes = calculate_file_sizes(&self.frame_sizes, self.jpeg_total_size)?;
-        
-        let idx_header = create_idx_header(file_sizes.index_size);
-        self.writer.write_all(&idx_header).await?;
-        
-        let mut offset = 4u32;
-        for &size in &self.frame_sizes {
-            let entry = create_index_entry(offset, size);
-            self.writer.write_all(&entry).await?;
-            
-            offset = offset.checked_add(8)
-                .and_then(|o| o.checked_add(size))
-                .ok_or(MjpegError::FileSizeExceeded)?;
+        let pending = self.state.take_audio();
+        if !pending.is_empty() {
+            self.write_audio(&pending).await?;
+        }
+        while self.state.has_open_list() {
+            self.end_list().await?;
         }
 
-        let frame_count_u32 = frame_count as u32;
-        
-        let sizes = [
-            (4, file_sizes.total_file_size.to_le_bytes()),
-            (48, frame_count_u32.to_le_bytes()),
-            (140, frame_count_u32.to_le_bytes()),
-            (240, frame_count_u32.to_le_bytes()),
-            (248, file_sizes.movi_size.to_le_bytes()),
-        ];
-        
-        for (pos, bytes) in sizes {
+        let index = self.state.index_chunk()?;
+        self.writer.write_all(&index).await?;
+        for (pos, bytes) in self.state.segment_patches()? {
             self.writer.seek(SeekFrom::Start(pos)).await?;
             self.writer.write_all(&bytes).await?;
         }
+
+        let header = self.state.finished_header()?;
+        self.writer.seek(SeekFrom::Start(0)).await?;
+        self.writer.write_all(&header).await?;
         
         Ok(self.writer)
     }
-}
\ No newline at end of file
+}
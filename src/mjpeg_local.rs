@@ -0,0 +1,307 @@
+use std::future::Future;
+use std::io::{IoSlice, SeekFrom};
+use crate::Result;
+use crate::common::*;
+use crate::index::{FrameLocation, IndexEntry};
+use crate::bookmark::{bookmark_payload, BOOKMARK_CHUNK_ID};
+use crate::metadata::AviMetadata;
+use crate::timecode::TIMECODE_FOURCC;
+use crate::custom::ChunkPlacement;
+use crate::frame_map::FrameMapEntry;
+use crate::builder::MjpegWriterBuilder;
+use crate::writer::LocalAsyncWriter;
+
+/// A variant of `MjpegAviWriterAsync` whose futures don't need to be `Send`.
+pub trait MjpegAviWriterLocal<W: LocalAsyncWriter> {
+    /// Asynchronously adds a single JPEG frame to the AVI file.
+    ///
+    /// The `jpeg_binary` should be a complete JPEG file binary.
+    fn add_frame(&mut self, jpeg_binary: &[u8]) -> impl Future<Output = Result<()>>;
+
+    /// Asynchronously adds a single JPEG frame to the AVI file from a slice of buffers.
+    #[allow(clippy::needless_lifetimes)]
+    fn add_frame_vectored<'a, 'b>(&'a mut self, bufs: &'b [&'b [u8]]) -> impl Future<Output = Result<()>>;
+
+    /// Asynchronously finalizes the AVI file.
+    ///
+    /// This method consumes the writer and must be called to finalize the AVI file.
+    fn finish(self) -> impl Future<Output = Result<W>> where Self: Sized;
+}
+
+/// An asynchronous writer for single-threaded executors.
+///
+/// It writes the same files as `MjpegAsyncWriter`, but works with [`LocalAsyncWriter`]s, so
+/// `!Send` writers can be used on wasm, embassy or a tokio `LocalSet`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "async")]
+/// # async fn record() -> mjpeg_avi_rs::Result<()> {
+/// use mjpeg_avi_rs::{MjpegAviWriterLocal, MjpegLocalWriter};
+///
+/// let mut writer = MjpegLocalWriter::new(futures::io::Cursor::new(Vec::new()), 320, 240, 30).await?;
+/// writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9]).await?;
+/// let output = writer.finish().await?.into_inner();
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "The writer must be finalized using .finish() to produce a valid AVI file"]
+pub struct MjpegLocalWriter<W: LocalAsyncWriter> {
+    writer: W,
+    state: MoviState,
+}
+
+impl<W: LocalAsyncWriter> MjpegLocalWriter<W> {
+    /// Creates a new `MjpegLocalWriter` and asynchronously writes the AVI header.
+    pub async fn new(writer: W, width: u32, height: u32, fps: u32) -> Result<Self> {
+        MjpegWriterBuilder::new(width, height, fps).build_local(writer).await
+    }
+
+    /// Writes a prepared header and creates the writer.
+    pub(crate) async fn with_state(mut writer: W, state: MoviState) -> Result<Self> {
+        writer.write_all(state.header()).await?;
+
+        Ok(MjpegLocalWriter {
+            writer,
+            state,
+        })
+    }
+
+    /// The location of the most recently written frame, or `None` if no frame has been written.
+    pub fn last_frame_offset(&self) -> Option<FrameLocation> {
+        self.state.last_frame()
+    }
+
+    /// The locations and timestamps of the frames written so far, see [`FrameMapEntry`].
+    pub fn frame_map(&self) -> Vec<FrameMapEntry> {
+        self.state.frame_map()
+    }
+
+    /// Stores a chunk in the header space reserved with
+    /// [`MjpegWriterBuilder::reserve_header`](crate::MjpegWriterBuilder::reserve_header).
+    ///
+    /// See [`MjpegWriter::write_header_chunk`](crate::MjpegWriter::write_header_chunk).
+    pub fn write_header_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8]) -> Result<()> {
+        self.state.write_header_chunk(chunk_id, payload)
+    }
+
+    /// Replaces the tags of the `INFO` list in the header, written with the header at
+    /// `finish()`.
+    ///
+    /// See [`MjpegWriter::set_metadata`](crate::MjpegWriter::set_metadata).
+    pub fn set_metadata(&mut self, metadata: &AviMetadata) -> Result<()> {
+        self.state.set_metadata(metadata)
+    }
+
+    /// Sets the `dwQuality` of video stream 0, written with the header at `finish()`.
+    ///
+    /// See [`MjpegWriter::set_stream_quality`](crate::MjpegWriter::set_stream_quality).
+    pub fn set_stream_quality(&mut self, quality: u32) -> Result<()> {
+        self.state.set_stream_quality(quality)
+    }
+
+    /// Writes a chunk with an arbitrary chunk ID into the `movi` list.
+    ///
+    /// See [`MjpegWriter::write_chunk`](crate::MjpegWriter::write_chunk).
+    pub async fn write_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8], index_flags: Option<u32>) -> Result<()> {
+        let chunk = self.state.prepare_chunk(chunk_id, index_flags, payload.len())?;
+        self.write_prepared(chunk, [IoSlice::new(payload)].into_iter()).await
+    }
+
+    /// Writes an application-specific chunk into the `movi` list or the top level of the RIFF
+    /// file.
+    ///
+    /// See [`MjpegWriter::write_custom_chunk`](crate::MjpegWriter::write_custom_chunk).
+    pub async fn write_custom_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8], placement: ChunkPlacement) -> Result<()> {
+        match placement {
+            ChunkPlacement::Movi => {
+                let chunk = self.state.prepare_custom_chunk(chunk_id, payload.len())?;
+                self.write_prepared(chunk, [IoSlice::new(payload)].into_iter()).await
+            }
+            ChunkPlacement::Header => self.state.write_custom_header_chunk(chunk_id, payload),
+        }
+    }
+
+    /// Adds a bookmark with `label` before the next frame, see [`Bookmark`](crate::Bookmark).
+    pub async fn add_bookmark(&mut self, label: &str) -> Result<()> {
+        let payload = bookmark_payload(self.state.frame_count(), label);
+        self.write_chunk(BOOKMARK_CHUNK_ID, &payload, Some(IndexEntry::NO_TIME)).await
+    }
+
+    /// Asynchronously adds audio samples to the audio stream as `wb` chunks.
+    ///
+    /// See [`MjpegWriter::add_audio_samples`](crate::MjpegWriter::add_audio_samples).
+    pub async fn add_audio_samples(&mut self, samples: &[u8]) -> Result<()> {
+        if !self.state.buffer_audio(samples)? {
+            return self.write_audio(samples).await;
+        }
+        self.interleave_audio().await
+    }
+
+    /// Asynchronously adds a JPEG frame to video stream `stream`.
+    ///
+    /// See [`MjpegWriter::add_frame_for_stream`](crate::MjpegWriter::add_frame_for_stream).
+    pub async fn add_frame_for_stream(&mut self, stream: usize, jpeg_binary: &[u8]) -> Result<()> {
+        if stream == 0 {
+            self.write_timecode(Some(jpeg_binary.len())).await?;
+        }
+        let chunk = self.state.prepare_stream_frame(stream, jpeg_binary.len())?;
+        self.write_prepared(chunk, [IoSlice::new(jpeg_binary)].into_iter()).await?;
+        self.interleave_audio().await
+    }
+
+    /// Asynchronously adds a sample to the data stream `fourcc`, aligned with the next frame.
+    ///
+    /// See [`MjpegWriter::add_data`](crate::MjpegWriter::add_data).
+    pub async fn add_data(&mut self, fourcc: [u8; 4], data: &[u8]) -> Result<()> {
+        let chunk = self.state.prepare_data(fourcc, data.len())?;
+        self.write_prepared(chunk, [IoSlice::new(data)].into_iter()).await
+    }
+
+    /// Adds a dropped frame: an empty `00dc` chunk that keeps the timeline, during which players
+    /// show the previous frame.
+    pub async fn add_dropped_frame(&mut self) -> Result<()> {
+        self.write_timecode(None).await?;
+        let chunk = self.state.prepare_dropped_frame()?;
+        self.write_prepared(chunk, [IoSlice::new(&[])].into_iter()).await?;
+        self.interleave_audio().await
+    }
+
+    /// Asynchronously adds a single JPEG frame from a slice of `IoSlice`s.
+    ///
+    /// Like `add_frame_vectored`, for callers that already hold `IoSlice` arrays, such as
+    /// scatter-gather capture drivers. The slices are written along with the chunk header
+    /// without re-slicing them.
+    pub async fn add_frame_ioslices(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let frame_size: usize = bufs.iter().map(|buf| buf.len()).sum();
+        self.write_timecode(Some(frame_size)).await?;
+        let chunk = self.state.prepare_frame(frame_size)?;
+        self.write_prepared(chunk, bufs.iter().copied()).await?;
+        self.interleave_audio().await
+    }
+
+    /// Asynchronously adds a JPEG frame followed by a companion chunk holding `meta`.
+    ///
+    /// See [`MjpegWriter::add_frame_with_meta`](crate::MjpegWriter::add_frame_with_meta).
+    pub async fn add_frame_with_meta(&mut self, jpeg_binary: &[u8], meta: &[u8]) -> Result<()> {
+        self.write_timecode(Some(jpeg_binary.len())).await?;
+        let chunk = self.state.prepare_frame(jpeg_binary.len())?;
+        self.write_prepared(chunk, [IoSlice::new(jpeg_binary)].into_iter()).await?;
+        if !meta.is_empty() {
+            let chunk = self.state.prepare_frame_meta(meta.len())?;
+            self.write_prepared(chunk, [IoSlice::new(meta)].into_iter()).await?;
+        }
+        self.interleave_audio().await
+    }
+
+    /// Starts a `LIST` chunk in the `movi` list.
+    ///
+    /// See [`MjpegWriter::begin_list`](crate::MjpegWriter::begin_list).
+    pub async fn begin_list(&mut self, list_type: [u8; 4], index_flags: Option<u32>) -> Result<()> {
+        let list = self.state.prepare_list(list_type, index_flags)?;
+        self.writer.write_all(&list.header).await?;
+        self.state.commit_list(list);
+        Ok(())
+    }
+
+    /// Closes the innermost list started by [`begin_list()`](Self::begin_list).
+    ///
+    /// Returns [`MjpegError::NoOpenList`](crate::MjpegError::NoOpenList) if no list is open.
+    pub async fn end_list(&mut self) -> Result<()> {
+        let patch = self.state.end_list()?;
+        self.writer.seek(SeekFrom::Start(patch.pos)).await?;
+        self.writer.write_all(&patch.size).await?;
+        self.writer.seek(SeekFrom::Start(patch.resume)).await?;
+        Ok(())
+    }
+
+    /// Writes audio samples as `wb` chunks.
+    async fn write_audio(&mut self, samples: &[u8]) -> Result<()> {
+        for payload in self.state.audio_chunks(samples)? {
+            let chunk = self.state.prepare_audio(payload.len())?;
+            self.write_prepared(chunk, [IoSlice::new(payload)].into_iter()).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered audio samples if the interleave policy says they are due.
+    async fn interleave_audio(&mut self) -> Result<()> {
+        if !self.state.audio_due() {
+            return Ok(());
+        }
+        let pending = self.state.take_audio();
+        self.write_audio(&pending).await
+    }
+
+    /// Writes the timecode of the next frame of stream 0 right before it, if the writer has a
+    /// timecode stream. `frame_size` is `None` for a dropped frame.
+    async fn write_timecode(&mut self, frame_size: Option<usize>) -> Result<()> {
+        let Some(payload) = self.state.timecode_payload() else {
+            return Ok(());
+        };
+        // Fail before the timecode if the frame can't be written after it
+        match frame_size {
+            Some(size) => self.state.prepare_frame(size)?,
+            None => self.state.prepare_dropped_frame()?,
+        };
+        let chunk = self.state.prepare_data(TIMECODE_FOURCC, payload.len())?;
+        self.write_prepared(chunk, [IoSlice::new(&payload)].into_iter()).await
+    }
+
+    /// Writes a prepared chunk header, the payload and the padding byte.
+    async fn write_prepared<'a, I: ExactSizeIterator<Item = IoSlice<'a>>>(&mut self, chunk: PreparedChunk, payload: I) -> Result<()> {
+        if let Some(segment_break) = &chunk.segment_break {
+            self.writer.write_all(&segment_break.data).await?;
+            for (pos, bytes) in segment_break.patches {
+                self.writer.seek(SeekFrom::Start(pos)).await?;
+                self.writer.write_all(&bytes).await?;
+            }
+            self.writer.seek(SeekFrom::Start(segment_break.resume)).await?;
+        }
+        let bufs_to_write = chunk.io_slices(payload);
+        self.writer.write_all_vectored(&bufs_to_write).await?;
+        drop(bufs_to_write);
+        self.state.commit(chunk);
+
+        Ok(())
+    }
+}
+
+impl<W: LocalAsyncWriter> MjpegAviWriterLocal<W> for MjpegLocalWriter<W> {
+    async fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary]).await
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    async fn add_frame_vectored<'a, 'b>(&'a mut self, bufs: &'b [&'b [u8]]) -> Result<()> {
+        let frame_size: usize = bufs.iter().map(|s| s.len()).sum();
+        self.write_timecode(Some(frame_size)).await?;
+        let chunk = self.state.prepare_frame(frame_size)?;
+        self.write_prepared(chunk, bufs.iter().map(|buf| IoSlice::new(buf))).await?;
+        self.interleave_audio().await
+    }
+
+    async fn finish(mut self) -> Result<W> {
+        let pending = self.state.take_audio();
+        if !pending.is_empty() {
+            self.write_audio(&pending).await?;
+        }
+        while self.state.has_open_list() {
+            self.end_list().await?;
+        }
+
+        let index = self.state.index_chunk()?;
+        self.writer.write_all(&index).await?;
+        for (pos, bytes) in self.state.segment_patches()? {
+            self.writer.seek(SeekFrom::Start(pos)).await?;
+            self.writer.write_all(&bytes).await?;
+        }
+
+        let header = self.state.finished_header()?;
+        self.writer.seek(SeekFrom::Start(0)).await?;
+        self.writer.write_all(&header).await?;
+
+        Ok(self.writer)
+    }
+}
@@ -1,6 +1,13 @@
 use std::io::{IoSlice, SeekFrom};
-use crate::{MjpegError, Result};
+use crate::Result;
 use crate::common::*;
+use crate::index::{FrameLocation, IndexEntry};
+use crate::bookmark::{bookmark_payload, BOOKMARK_CHUNK_ID};
+use crate::metadata::AviMetadata;
+use crate::timecode::TIMECODE_FOURCC;
+use crate::custom::ChunkPlacement;
+use crate::frame_map::FrameMapEntry;
+use crate::builder::MjpegWriterBuilder;
 use crate::writer::Writer;
 
 /// A trait for synchronously writing MJPEG AVI files.
@@ -29,9 +36,7 @@ pub trait MjpegAviWriter<W: Writer> {
 #[must_use = "The writer must be finalized using .finish() to produce a valid AVI file"]
 pub struct MjpegWriter<W: Writer> {
     writer: W,
-    frame_sizes: Vec<u32>,
-    jpeg_total_size: u64,
-    estimated_file_size: u64,
+    state: MoviState,
 }
 
 impl<W: Writer> MjpegWriter<W> {
@@ -45,127 +50,301 @@ impl<W: Writer> MjpegWriter<W> {
     /// * `width` - The width of the video frames.
     /// * `height` - The height of the video frames.
     /// * `fps` - The frames per second of the video.
-    pub fn new(mut writer: W, width: u32, height: u32, fps: u32) -> Result<Self> {
-        if fps == 0 {
-            return Err(MjpegError::InvalidFrameSize);
-        }
+    pub fn new(writer: W, width: u32, height: u32, fps: u32) -> Result<Self> {
+        MjpegWriterBuilder::new(width, height, fps).build(writer)
+    }
 
-        create_header_data(&mut writer, fps, width, height)?;
+    /// Writes a prepared header and creates the writer.
+    pub(crate) fn with_state(mut writer: W, state: MoviState) -> Result<Self> {
+        writer.write_all(state.header())?;
 
         Ok(MjpegWriter {
             writer,
-            frame_sizes: Vec::new(),
-            jpeg_total_size: 0,
-            estimated_file_size: 256, // Header size
+            state,
         })
     }
-    
-    fn check_limits(&self, frame_size: usize) -> Result<()> {
-        // Frame count limit check
-        if self.frame_sizes.len() >= MAX_FRAME_COUNT as usize {
-            return Err(MjpegError::FrameCountExceeded);
-        }
 
-        // Check if frame size fits in u32
-        if frame_size > u32::MAX as usize {
-            return Err(MjpegError::FrameSizeExceeded);
-        }
+    /// The location of the most recently written frame, or `None` if no frame has been written.
+    pub fn last_frame_offset(&self) -> Option<FrameLocation> {
+        self.state.last_frame()
+    }
 
-        // Estimated file size check
-        let padded_size = if frame_size % 2 == 1 { frame_size + 1 } else { frame_size };
-        let new_frame_data_size = 8 + padded_size; // chunk header + data
-        let new_index_size = 16; // index entry size
-        let estimated_new_size = self.estimated_file_size + new_frame_data_size as u64 + new_index_size;
+    /// The locations and timestamps of the frames written so far, see [`FrameMapEntry`].
+    pub fn frame_map(&self) -> Vec<FrameMapEntry> {
+        self.state.frame_map()
+    }
 
-        if estimated_new_size > MAX_AVI_FILE_SIZE {
-            return Err(MjpegError::FileSizeExceeded);
-        }
+    /// The number of frames written so far, including dropped frames.
+    pub(crate) fn frame_count(&self) -> u32 {
+        self.state.frame_count()
+    }
 
-        Ok(())
+    /// Stores a chunk in the header space reserved with
+    /// [`MjpegWriterBuilder::reserve_header`](crate::MjpegWriterBuilder::reserve_header).
+    ///
+    /// The chunk is written with the header at `finish()`, at the top level of the RIFF file
+    /// between the `hdrl` and `movi` lists, so it can hold data only known at the end of the
+    /// recording. Returns [`MjpegError::HeaderSpaceExceeded`](crate::MjpegError::HeaderSpaceExceeded)
+    /// if the chunk doesn't fit in the remaining space.
+    pub fn write_header_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8]) -> Result<()> {
+        self.state.write_header_chunk(chunk_id, payload)
     }
-}
 
-impl<W: Writer> MjpegAviWriter<W> for MjpegWriter<W> {
-    fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
-        self.add_frame_vectored(&[jpeg_binary])
+    /// Replaces the tags of the `INFO` list in the header, written with the header at
+    /// `finish()`, see [`MjpegWriterBuilder::metadata`](crate::MjpegWriterBuilder::metadata).
+    ///
+    /// The list must fit in the space of the list given to the builder and the reserved header
+    /// space after it; without a list from the builder it goes into the reserved space like
+    /// [`write_header_chunk`](Self::write_header_chunk). Returns
+    /// [`MjpegError::HeaderSpaceExceeded`](crate::MjpegError::HeaderSpaceExceeded) if it
+    /// doesn't fit. An empty `metadata` removes the list.
+    pub fn set_metadata(&mut self, metadata: &AviMetadata) -> Result<()> {
+        self.state.set_metadata(metadata)
     }
 
-    fn add_frame_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
-        let frame_size: usize = bufs.iter().map(|s| s.len()).sum();
-        if frame_size == 0 {
-            return Err(MjpegError::InvalidFrameSize);
-        }
+    /// Sets the `dwQuality` of video stream 0, from 0 to 10000, written with the header at
+    /// `finish()`, for encoders that only know their JPEG quality once recording, see
+    /// [`MjpegWriterBuilder::stream_quality`](crate::MjpegWriterBuilder::stream_quality).
+    ///
+    /// Returns [`MjpegError::InvalidStreamQuality`](crate::MjpegError::InvalidStreamQuality) for
+    /// values above 10000.
+    pub fn set_stream_quality(&mut self, quality: u32) -> Result<()> {
+        self.state.set_stream_quality(quality)
+    }
 
-        self.check_limits(frame_size)?;
+    /// Writes a chunk with an arbitrary chunk ID into the `movi` list.
+    ///
+    /// This is a low-level escape hatch for embedding data this crate doesn't model, such as
+    /// vendor-specific metadata. With `index_flags`, the chunk gets an `idx1` entry with those
+    /// flags (use [`IndexEntry::NO_TIME`](crate::IndexEntry::NO_TIME) for data outside of any
+    /// stream's timeline); with `None` it is skipped by players that follow the index.
+    ///
+    /// The caller is responsible for using chunk IDs that players don't misinterpret.
+    /// A chunk with the ID `00dc` is counted as a video frame.
+    pub fn write_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8], index_flags: Option<u32>) -> Result<()> {
+        let chunk = self.state.prepare_chunk(chunk_id, index_flags, payload.len())?;
+        self.write_prepared(chunk, [IoSlice::new(payload)].into_iter())
+    }
 
-        let odd = frame_size % 2 == 1;
-        let padded_size = if odd { frame_size + 1 } else { frame_size };
-        let padded_size_u32 = padded_size as u32;
+    /// Writes an application-specific chunk, such as calibration data or a session ID, into
+    /// the `movi` list or the top level of the RIFF file, see [`ChunkPlacement`](crate::ChunkPlacement).
+    ///
+    /// Unlike [`write_chunk`](Self::write_chunk), the chunk ID is checked: it must be four
+    /// printable ASCII characters and not one that players or this crate interpret, such as
+    /// `00dc`, `ix00`, `idx1` or `LIST`, or this fails with
+    /// [`MjpegError::ReservedChunkId`](crate::MjpegError::ReservedChunkId). The chunks are read
+    /// back with [`MjpegReader::custom_chunks`](crate::MjpegReader::custom_chunks).
+    pub fn write_custom_chunk(&mut self, chunk_id: [u8; 4], payload: &[u8], placement: ChunkPlacement) -> Result<()> {
+        match placement {
+            ChunkPlacement::Movi => {
+                let chunk = self.state.prepare_custom_chunk(chunk_id, payload.len())?;
+                self.write_prepared(chunk, [IoSlice::new(payload)].into_iter())
+            }
+            ChunkPlacement::Header => self.state.write_custom_header_chunk(chunk_id, payload),
+        }
+    }
+
+    /// Adds a bookmark with `label` before the next frame, see [`Bookmark`](crate::Bookmark).
+    pub fn add_bookmark(&mut self, label: &str) -> Result<()> {
+        let payload = bookmark_payload(self.state.frame_count(), label);
+        self.write_chunk(BOOKMARK_CHUNK_ID, &payload, Some(IndexEntry::NO_TIME))
+    }
 
-        let chunk_header = create_frame_chunk_header(padded_size_u32);
-        
-        let mut bufs_to_write = Vec::with_capacity(bufs.len() + 2);
-        bufs_to_write.push(IoSlice::new(&chunk_header));
-        for buf in bufs {
-            bufs_to_write.push(IoSlice::new(buf));
+    /// Adds audio samples to the audio stream as `wb` chunks after the frames written so far.
+    ///
+    /// `samples` holds whole samples of all channels, interleaved, in the format given to
+    /// [`MjpegWriterBuilder::audio`](crate::MjpegWriterBuilder::audio), or whole MP3 frames for
+    /// an MP3 stream, which are written one frame per chunk. Fails with
+    /// [`MjpegError::NoAudioStream`](crate::MjpegError::NoAudioStream) if the writer has no
+    /// audio stream, and with `InvalidFrameSize` if `samples` is empty or ends with a partial
+    /// sample or frame. Under an [`InterleavePolicy`](crate::InterleavePolicy) other than
+    /// `Immediate`, the samples are buffered and written after a later frame.
+    pub fn add_audio_samples(&mut self, samples: &[u8]) -> Result<()> {
+        if !self.state.buffer_audio(samples)? {
+            return self.write_audio(samples);
         }
-        let padding_byte = [0u8];
-        if odd {
-            bufs_to_write.push(IoSlice::new(&padding_byte));
+        self.interleave_audio()
+    }
+
+    /// Adds a JPEG frame to video stream `stream`: 0 for the stream given to `new()`, 1 and up
+    /// for the streams added with
+    /// [`MjpegWriterBuilder::video_stream`](crate::MjpegWriterBuilder::video_stream).
+    ///
+    /// Fails with [`MjpegError::NoVideoStream`](crate::MjpegError::NoVideoStream) if the writer
+    /// has no such video stream. Frames of the streams added in the same frame interval are
+    /// shown together.
+    pub fn add_frame_for_stream(&mut self, stream: usize, jpeg_binary: &[u8]) -> Result<()> {
+        if stream == 0 {
+            self.write_timecode(Some(jpeg_binary.len()))?;
         }
+        let chunk = self.state.prepare_stream_frame(stream, jpeg_binary.len())?;
+        self.write_prepared(chunk, [IoSlice::new(jpeg_binary)].into_iter())?;
+        self.interleave_audio()
+    }
 
-        self.writer.write_all_vectored(&bufs_to_write)?;
+    /// Adds a sample to the data stream `fourcc`, such as a GPS fix or IMU readings, aligned
+    /// with the next frame, see
+    /// [`MjpegWriterBuilder::data_stream`](crate::MjpegWriterBuilder::data_stream).
+    ///
+    /// Write the data of a frame right before the frame. Fails with
+    /// [`MjpegError::NoDataStream`](crate::MjpegError::NoDataStream) if the writer has no such
+    /// data stream, and with `InvalidFrameSize` if `data` is empty.
+    pub fn add_data(&mut self, fourcc: [u8; 4], data: &[u8]) -> Result<()> {
+        let chunk = self.state.prepare_data(fourcc, data.len())?;
+        self.write_prepared(chunk, [IoSlice::new(data)].into_iter())
+    }
 
-        self.frame_sizes.push(padded_size_u32);
-        self.jpeg_total_size += padded_size as u64;
-        self.estimated_file_size += 8 + padded_size as u64 + 16; // chunk + index entry
+    /// Adds a dropped frame: an empty `00dc` chunk that keeps the timeline, during which players
+    /// show the previous frame.
+    pub fn add_dropped_frame(&mut self) -> Result<()> {
+        self.write_timecode(None)?;
+        let chunk = self.state.prepare_dropped_frame()?;
+        self.write_prepared(chunk, [IoSlice::new(&[])].into_iter())?;
+        self.interleave_audio()
+    }
 
+    /// Adds a single JPEG frame from a slice of `IoSlice`s.
+    ///
+    /// Like `add_frame_vectored`, for callers that already hold `IoSlice` arrays, such as
+    /// scatter-gather capture drivers. The slices are written along with the chunk header
+    /// without re-slicing them.
+    pub fn add_frame_ioslices(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let frame_size: usize = bufs.iter().map(|buf| buf.len()).sum();
+        self.write_timecode(Some(frame_size))?;
+        let chunk = self.state.prepare_frame(frame_size)?;
+        self.write_prepared(chunk, bufs.iter().copied())?;
+        self.interleave_audio()
+    }
+
+    /// Adds a JPEG frame followed by a companion chunk holding `meta`, a small blob such as the
+    /// exposure or the sensor timestamp of the frame.
+    ///
+    /// The metadata is stored in an `fmta` chunk right after the `00dc` chunk, indexed with the
+    /// `AVIIF_NO_TIME` flag so players skip it, and read back with
+    /// [`MjpegReader::frame_meta`](crate::MjpegReader::frame_meta). An empty `meta` writes the
+    /// frame alone. If the frame fits but the metadata doesn't, the frame is kept without it
+    /// and the error is returned.
+    pub fn add_frame_with_meta(&mut self, jpeg_binary: &[u8], meta: &[u8]) -> Result<()> {
+        self.write_timecode(Some(jpeg_binary.len()))?;
+        let chunk = self.state.prepare_frame(jpeg_binary.len())?;
+        self.write_prepared(chunk, [IoSlice::new(jpeg_binary)].into_iter())?;
+        if !meta.is_empty() {
+            let chunk = self.state.prepare_frame_meta(meta.len())?;
+            self.write_prepared(chunk, [IoSlice::new(meta)].into_iter())?;
+        }
+        self.interleave_audio()
+    }
+
+    /// Starts a `LIST` chunk in the `movi` list. Chunks and frames written until the matching
+    /// [`end_list()`](Self::end_list) are placed inside it.
+    ///
+    /// Lists can be nested. With `index_flags`, the list gets an `idx1` entry with those flags
+    /// and the `AVIIF_LIST` flag, as `rec ` lists do. Lists still open at `finish()` are closed.
+    pub fn begin_list(&mut self, list_type: [u8; 4], index_flags: Option<u32>) -> Result<()> {
+        let list = self.state.prepare_list(list_type, index_flags)?;
+        self.writer.write_all(&list.header)?;
+        self.state.commit_list(list);
         Ok(())
     }
 
-    fn finish(mut self) -> Result<W> {
-        let frame_count = self.frame_sizes.len();
+    /// Closes the innermost list started by [`begin_list()`](Self::begin_list).
+    ///
+    /// Returns [`MjpegError::NoOpenList`](crate::MjpegError::NoOpenList) if no list is open.
+    pub fn end_list(&mut self) -> Result<()> {
+        let patch = self.state.end_list()?;
+        self.writer.seek(SeekFrom::Start(patch.pos))?;
+        self.writer.write_all(&patch.size)?;
+        self.writer.seek(SeekFrom::Start(patch.resume))?;
+        Ok(())
+    }
 
-        // Calculate file sizes
-        let file_sizes = calculate_file_sizes(&self.frame_sizes, self.jpeg_total_size)?;
+    /// Writes audio samples as `wb` chunks.
+    fn write_audio(&mut self, samples: &[u8]) -> Result<()> {
+        for payload in self.state.audio_chunks(samples)? {
+            let chunk = self.state.prepare_audio(payload.len())?;
+            self.write_prepared(chunk, [IoSlice::new(payload)].into_iter())?;
+        }
+        Ok(())
+    }
 
-        // Write idx1 chunk header
-        let idx_header = create_idx_header(file_sizes.index_size);
-        self.writer.write_all(&idx_header)?;
+    /// Writes the buffered audio samples if the interleave policy says they are due.
+    fn interleave_audio(&mut self) -> Result<()> {
+        if !self.state.audio_due() {
+            return Ok(());
+        }
+        let pending = self.state.take_audio();
+        self.write_audio(&pending)
+    }
 
-        // Build index table
-        let mut offset = 4u32;
-        for &size in &self.frame_sizes {
-            let entry = create_index_entry(offset, size);
-            self.writer.write_all(&entry)?;
+    /// Writes the timecode of the next frame of stream 0 right before it, if the writer has a
+    /// timecode stream. `frame_size` is `None` for a dropped frame.
+    fn write_timecode(&mut self, frame_size: Option<usize>) -> Result<()> {
+        let Some(payload) = self.state.timecode_payload() else {
+            return Ok(());
+        };
+        // Fail before the timecode if the frame can't be written after it
+        match frame_size {
+            Some(size) => self.state.prepare_frame(size)?,
+            None => self.state.prepare_dropped_frame()?,
+        };
+        let chunk = self.state.prepare_data(TIMECODE_FOURCC, payload.len())?;
+        self.write_prepared(chunk, [IoSlice::new(&payload)].into_iter())
+    }
 
-            offset = offset.checked_add(8)
-                .and_then(|o| o.checked_add(size))
-                .ok_or(MjpegError::FileSizeExceeded)?;
+    /// Writes a prepared chunk header, the payload and the padding byte.
+    fn write_prepared<'a, I: ExactSizeIterator<Item = IoSlice<'a>>>(&mut self, chunk: PreparedChunk, payload: I) -> Result<()> {
+        if let Some(segment_break) = &chunk.segment_break {
+            self.writer.write_all(&segment_break.data)?;
+            for (pos, bytes) in segment_break.patches {
+                self.writer.seek(SeekFrom::Start(pos))?;
+                self.writer.write_all(&bytes)?;
+            }
+            self.writer.seek(SeekFrom::Start(segment_break.resume))?;
         }
+        let bufs_to_write = chunk.io_slices(payload);
+        self.writer.write_all_vectored(&bufs_to_write)?;
+        drop(bufs_to_write);
+        self.state.commit(chunk);
+
+        Ok(())
+    }
+}
+
+impl<W: Writer> MjpegAviWriter<W> for MjpegWriter<W> {
+    fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary])
+    }
 
-        let frame_count_u32 = frame_count as u32; // Checked in calculate_file_sizes
+    fn add_frame_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        let frame_size: usize = bufs.iter().map(|s| s.len()).sum();
+        self.write_timecode(Some(frame_size))?;
+        let chunk = self.state.prepare_frame(frame_size)?;
+        self.write_prepared(chunk, bufs.iter().map(|buf| IoSlice::new(buf)))?;
+        self.interleave_audio()
+    }
 
-        // Write size values in one go
-        let sizes = [
-            (4, file_sizes.total_file_size.to_le_bytes()),    // RIFF file size
-            (48, frame_count_u32.to_le_bytes()),              // totalframes
-            (140, frame_count_u32.to_le_bytes()),             // length
-            (240, frame_count_u32.to_le_bytes()),             // odml totalframes
-            (248, file_sizes.movi_size.to_le_bytes()),        // movi size
-        ];
+    fn finish(mut self) -> Result<W> {
+        let pending = self.state.take_audio();
+        if !pending.is_empty() {
+            self.write_audio(&pending)?;
+        }
+        while self.state.has_open_list() {
+            self.end_list()?;
+        }
 
-        for (pos, bytes) in sizes {
+        // Write the index chunks
+        let index = self.state.index_chunk()?;
+        self.writer.write_all(&index)?;
+        for (pos, bytes) in self.state.segment_patches()? {
             self.writer.seek(SeekFrom::Start(pos))?;
             self.writer.write_all(&bytes)?;
         }
 
+        // Rewrite the header with the final sizes in one go
+        let header = self.state.finished_header()?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+
         Ok(self.writer)
     }
 }
-
-fn create_header_data<W: Writer>(writer: &mut W, fps: u32, width: u32, height: u32) -> Result<()> {
-    let header = create_header_template(fps, width, height);
-    writer.write_all(&header)?;
-    Ok(())
-}
\ No newline at end of file
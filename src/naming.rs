@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+use crate::{MjpegError, Result};
+
+/// Names segment files after their local start time and tells when to rotate at local midnight.
+///
+/// The template is a `strftime` format such as `cam/%Y-%m-%d/%H%M%S%z.avi`, expanded in an
+/// explicit time zone rather than the process time zone. Include `%z` to keep names unique and
+/// sortable across the hour that repeats when DST ends.
+///
+/// Rotation follows the calendar of the zone: [`next_midnight`](Self::next_midnight) is the
+/// start of the next local day, which may be 23 or 25 hours away, or 01:00 where DST begins at
+/// midnight. Adding 24 hours to the last midnight gets these days wrong. Enabled by the `tz`
+/// feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, SegmentNaming};
+/// use std::fs::File;
+/// use std::time::SystemTime;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let naming = SegmentNaming::in_zone("cam/%Y%m%d_%H%M%S%z.avi", "Europe/Berlin")?;
+///     let start = SystemTime::now();
+///     let rotate_at = naming.next_midnight(start)?;
+///     let mut writer = MjpegWriter::new(File::create(naming.path(start)?)?, 640, 480, 30)?;
+///     while SystemTime::now() < rotate_at {
+///         writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     }
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SegmentNaming {
+    template: String,
+    tz: TimeZone,
+}
+
+impl SegmentNaming {
+    /// Creates a naming scheme expanding `template` in `tz`.
+    ///
+    /// Use [`TimeZone::posix`] for a fixed rule such as `CET-1CEST,M3.5.0,M10.5.0/3` on devices
+    /// without a time zone database.
+    pub fn new(template: &str, tz: TimeZone) -> Self {
+        SegmentNaming { template: template.to_string(), tz }
+    }
+
+    /// Creates a naming scheme in the IANA time zone `name`, such as `America/New_York`.
+    pub fn in_zone(template: &str, name: &str) -> Result<Self> {
+        Ok(Self::new(template, TimeZone::get(name).map_err(tz_error)?))
+    }
+
+    /// The time zone of the names.
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.tz
+    }
+
+    /// The path of a segment starting at `time`.
+    pub fn path(&self, time: SystemTime) -> Result<PathBuf> {
+        let zoned = self.zoned(time)?;
+        let name = jiff::fmt::strtime::format(&self.template, &zoned).map_err(tz_error)?;
+        Ok(PathBuf::from(name))
+    }
+
+    /// The start of the local day after the one containing `time`.
+    pub fn next_midnight(&self, time: SystemTime) -> Result<SystemTime> {
+        let zoned = self.zoned(time)?;
+        let midnight = zoned.tomorrow().and_then(|day| day.start_of_day()).map_err(tz_error)?;
+        Ok(SystemTime::from(midnight.timestamp()))
+    }
+
+    /// Returns true if `a` and `b` fall on the same local day, i.e. no rotation lies between them.
+    pub fn same_day(&self, a: SystemTime, b: SystemTime) -> Result<bool> {
+        Ok(self.zoned(a)?.date() == self.zoned(b)?.date())
+    }
+
+    fn zoned(&self, time: SystemTime) -> Result<Zoned> {
+        let timestamp = Timestamp::try_from(time).map_err(tz_error)?;
+        Ok(timestamp.to_zoned(self.tz.clone()))
+    }
+}
+
+pub(crate) fn tz_error(err: jiff::Error) -> MjpegError {
+    MjpegError::Io(err.to_string())
+}
@@ -0,0 +1,152 @@
+use std::marker::PhantomData;
+use crate::Result;
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::writer::Writer;
+#[cfg(feature = "xxhash")]
+use crate::dedup::{DuplicateStats, DuplicateTracker, FrameHasher, Xxh3Hasher};
+
+/// A hook that sees every frame written through an [`ObservedWriter`].
+///
+/// Observers are for analytics and monitoring; they can't change or reject frames.
+pub trait FrameObserver {
+    /// Called after a frame has been written. `bufs` is the frame data as passed to the writer.
+    fn on_frame(&mut self, bufs: &[&[u8]]);
+
+    /// Called when the writer has been finalized successfully.
+    fn on_finish(&mut self) {}
+}
+
+impl<O: FrameObserver + ?Sized> FrameObserver for &mut O {
+    fn on_frame(&mut self, bufs: &[&[u8]]) {
+        (**self).on_frame(bufs)
+    }
+
+    fn on_finish(&mut self) {
+        (**self).on_finish()
+    }
+}
+
+/// Wraps a writer and reports every written frame to a [`FrameObserver`].
+///
+/// The observer is consumed by `finish()`, so pass `&mut observer` to inspect it afterwards.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameObserver, MjpegAviWriter, MjpegWriter, ObservedWriter};
+/// use std::fs::File;
+///
+/// struct ByteCounter(u64);
+///
+/// impl FrameObserver for ByteCounter {
+///     fn on_frame(&mut self, bufs: &[&[u8]]) {
+///         self.0 += bufs.iter().map(|b| b.len() as u64).sum::<u64>();
+///     }
+/// }
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut counter = ByteCounter(0);
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 320, 240, 30)?;
+///     let mut writer = ObservedWriter::new(writer, &mut counter);
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.finish()?;
+///     println!("{} bytes of JPEG data", counter.0);
+///     Ok(())
+/// }
+/// ```
+pub struct ObservedWriter<W: Writer, M: MjpegAviWriter<W>, O: FrameObserver> {
+    inner: M,
+    observer: O,
+    _writer: PhantomData<W>,
+}
+
+impl<W: Writer, M: MjpegAviWriter<W>, O: FrameObserver> ObservedWriter<W, M, O> {
+    /// Wraps `inner`, reporting its frames to `observer`.
+    pub fn new(inner: M, observer: O) -> Self {
+        ObservedWriter { inner, observer, _writer: PhantomData }
+    }
+
+    /// The wrapped writer.
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    /// The observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// The observer, mutably.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+}
+
+impl<W: Writer, M: MjpegAviWriter<W>, O: FrameObserver> MjpegAviWriter<W> for ObservedWriter<W, M, O> {
+    fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary])
+    }
+
+    fn add_frame_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        self.inner.add_frame_vectored(bufs)?;
+        self.observer.on_frame(bufs);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W> {
+        let writer = self.inner.finish()?;
+        self.observer.on_finish();
+        Ok(writer)
+    }
+}
+
+/// An observer that hashes every frame and counts runs of duplicate frames.
+///
+/// It only measures: use it to evaluate how much a recording of a mostly static scene would
+/// shrink if repeated frames were not stored, e.g. by a [`DedupWriter`](crate::DedupWriter).
+/// Frames are compared with XXH3 hashes by default, or by the hashes of another
+/// [`FrameHasher`] given to [`with_hasher`](Self::with_hasher). Enabled by the `xxhash`
+/// feature.
+#[cfg(feature = "xxhash")]
+#[derive(Debug, Clone, Default)]
+pub struct HashObserver<H: FrameHasher = Xxh3Hasher> {
+    tracker: DuplicateTracker<H>,
+    finished: bool,
+}
+
+#[cfg(feature = "xxhash")]
+impl HashObserver {
+    /// Creates an observer comparing XXH3 hashes, so only identical frames are duplicates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl<H: FrameHasher> HashObserver<H> {
+    /// Creates an observer comparing frames by the hashes of `hasher`.
+    pub fn with_hasher(hasher: H) -> Self {
+        HashObserver { tracker: DuplicateTracker::new(hasher), finished: false }
+    }
+
+    /// The statistics collected so far.
+    pub fn stats(&self) -> DuplicateStats {
+        self.tracker.stats()
+    }
+
+    /// Returns true once the observed writer has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl<H: FrameHasher> FrameObserver for HashObserver<H> {
+    fn on_frame(&mut self, bufs: &[&[u8]]) {
+        self.tracker.check(bufs);
+    }
+
+    fn on_finish(&mut self) {
+        self.finished = true;
+    }
+}
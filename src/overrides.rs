@@ -0,0 +1,178 @@
+use crate::{MjpegError, Result};
+use crate::riff::le_u32;
+
+/// Values written into the `avih` main header and the `strh` and `strf` headers of video
+/// stream 0 in place of the ones the builder computes, for legacy decoders that need values
+/// the builder doesn't model.
+///
+/// Unset fields keep the computed value. Set fields are written when the file is created and
+/// again at `finish()`, so they also replace the values filled in then, such as the frame
+/// counts. Give them to
+/// [`MjpegWriterBuilder::header_overrides`](crate::MjpegWriterBuilder::header_overrides),
+/// which fails to build with [`MjpegError::InvalidHeaderOverride`], naming the offending
+/// field, if the result is obviously inconsistent: a zero frame duration or rate, a zero frame
+/// size, `strf` dimensions that don't match the `avih` ones, or `biPlanes` other than 1.
+///
+/// ```
+/// # fn main() -> mjpeg_avi_rs::Result<()> {
+/// use mjpeg_avi_rs::{HeaderOverrides, MjpegWriterBuilder};
+///
+/// let mut builder = MjpegWriterBuilder::new(720, 480, 30);
+/// // An NTSC decoder that expects 30000/1001 fps and a fixed buffer size
+/// builder.header_overrides(HeaderOverrides {
+///     avih_micro_sec_per_frame: Some(33367),
+///     strh_scale: Some(1001),
+///     strh_rate: Some(30000),
+///     strh_suggested_buffer_size: Some(1 << 20),
+///     ..Default::default()
+/// });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderOverrides {
+    /// `avih` `dwMicroSecPerFrame`
+    pub avih_micro_sec_per_frame: Option<u32>,
+    /// `avih` `dwMaxBytesPerSec`
+    pub avih_max_bytes_per_sec: Option<u32>,
+    /// `avih` `dwPaddingGranularity`
+    pub avih_padding_granularity: Option<u32>,
+    /// `avih` `dwFlags`, such as `AVIF_HASINDEX` (0x10) and `AVIF_ISINTERLEAVED` (0x100).
+    pub avih_flags: Option<u32>,
+    /// `avih` `dwTotalFrames`
+    pub avih_total_frames: Option<u32>,
+    /// `avih` `dwInitialFrames`
+    pub avih_initial_frames: Option<u32>,
+    /// `avih` `dwSuggestedBufferSize`
+    pub avih_suggested_buffer_size: Option<u32>,
+    /// `avih` `dwWidth`
+    pub avih_width: Option<u32>,
+    /// `avih` `dwHeight`
+    pub avih_height: Option<u32>,
+    /// `strh` `dwFlags`
+    pub strh_flags: Option<u32>,
+    /// `strh` `wPriority`
+    pub strh_priority: Option<u16>,
+    /// `strh` `wLanguage`
+    pub strh_language: Option<u16>,
+    /// `strh` `dwInitialFrames`
+    pub strh_initial_frames: Option<u32>,
+    /// `strh` `dwScale`
+    pub strh_scale: Option<u32>,
+    /// `strh` `dwRate`
+    pub strh_rate: Option<u32>,
+    /// `strh` `dwStart`
+    pub strh_start: Option<u32>,
+    /// `strh` `dwLength`
+    pub strh_length: Option<u32>,
+    /// `strh` `dwSuggestedBufferSize`
+    pub strh_suggested_buffer_size: Option<u32>,
+    /// `strh` `dwQuality`
+    pub strh_quality: Option<u32>,
+    /// `strh` `dwSampleSize`
+    pub strh_sample_size: Option<u32>,
+    /// `strf` `biWidth`
+    pub strf_width: Option<i32>,
+    /// `strf` `biHeight`, negative for top-down frames.
+    pub strf_height: Option<i32>,
+    /// `strf` `biPlanes`
+    pub strf_planes: Option<u16>,
+    /// `strf` `biBitCount`
+    pub strf_bit_count: Option<u16>,
+    /// `strf` `biSizeImage`
+    pub strf_size_image: Option<u32>,
+    /// `strf` `biXPelsPerMeter`
+    pub strf_x_pels_per_meter: Option<i32>,
+    /// `strf` `biYPelsPerMeter`
+    pub strf_y_pels_per_meter: Option<i32>,
+    /// `strf` `biClrUsed`
+    pub strf_clr_used: Option<u32>,
+    /// `strf` `biClrImportant`
+    pub strf_clr_important: Option<u32>,
+}
+
+impl HeaderOverrides {
+    /// Returns true if no field is set.
+    pub fn is_empty(&self) -> bool {
+        self.patches().is_empty()
+    }
+
+    /// The names of the set fields, such as `strh.dwRate`.
+    pub(crate) fn names(&self) -> Vec<&'static str> {
+        self.patches().into_iter().map(|(name, _, _)| name).collect()
+    }
+
+    /// Writes the set fields into a header.
+    pub(crate) fn apply(&self, header: &mut [u8]) {
+        for (_, pos, bytes) in self.patches() {
+            header[pos..pos + bytes.len()].copy_from_slice(&bytes);
+        }
+    }
+
+    /// The set fields as (name, header offset, value).
+    pub(crate) fn patches(&self) -> Vec<(&'static str, usize, Vec<u8>)> {
+        let u32s = [
+            ("avih.dwMicroSecPerFrame", 32, self.avih_micro_sec_per_frame),
+            ("avih.dwMaxBytesPerSec", 36, self.avih_max_bytes_per_sec),
+            ("avih.dwPaddingGranularity", 40, self.avih_padding_granularity),
+            ("avih.dwFlags", 44, self.avih_flags),
+            ("avih.dwTotalFrames", 48, self.avih_total_frames),
+            ("avih.dwInitialFrames", 52, self.avih_initial_frames),
+            ("avih.dwSuggestedBufferSize", 60, self.avih_suggested_buffer_size),
+            ("avih.dwWidth", 64, self.avih_width),
+            ("avih.dwHeight", 68, self.avih_height),
+            ("strh.dwFlags", 116, self.strh_flags),
+            ("strh.dwInitialFrames", 124, self.strh_initial_frames),
+            ("strh.dwScale", 128, self.strh_scale),
+            ("strh.dwRate", 132, self.strh_rate),
+            ("strh.dwStart", 136, self.strh_start),
+            ("strh.dwLength", 140, self.strh_length),
+            ("strh.dwSuggestedBufferSize", 144, self.strh_suggested_buffer_size),
+            ("strh.dwQuality", 148, self.strh_quality),
+            ("strh.dwSampleSize", 152, self.strh_sample_size),
+            ("strf.biSizeImage", 200, self.strf_size_image),
+            ("strf.biClrUsed", 212, self.strf_clr_used),
+            ("strf.biClrImportant", 216, self.strf_clr_important),
+        ];
+        let u16s = [
+            ("strh.wPriority", 120, self.strh_priority),
+            ("strh.wLanguage", 122, self.strh_language),
+            ("strf.biPlanes", 192, self.strf_planes),
+            ("strf.biBitCount", 194, self.strf_bit_count),
+        ];
+        let i32s = [
+            ("strf.biWidth", 184, self.strf_width),
+            ("strf.biHeight", 188, self.strf_height),
+            ("strf.biXPelsPerMeter", 204, self.strf_x_pels_per_meter),
+            ("strf.biYPelsPerMeter", 208, self.strf_y_pels_per_meter),
+        ];
+        let mut patches = Vec::new();
+        patches.extend(u32s.into_iter().filter_map(|(name, pos, value)| Some((name, pos, value?.to_le_bytes().to_vec()))));
+        patches.extend(u16s.into_iter().filter_map(|(name, pos, value)| Some((name, pos, value?.to_le_bytes().to_vec()))));
+        patches.extend(i32s.into_iter().filter_map(|(name, pos, value)| Some((name, pos, value?.to_le_bytes().to_vec()))));
+        patches.sort_by_key(|&(_, pos, _)| pos);
+        patches
+    }
+}
+
+/// Checks a header with overrides applied for values that no decoder can play.
+pub(crate) fn check_header(header: &[u8]) -> Result<()> {
+    let (width, height) = (le_u32(header, 64), le_u32(header, 68));
+    let strf_width = le_u32(header, 184) as i32;
+    let strf_height = le_u32(header, 188) as i32;
+    let planes = u16::from_le_bytes([header[192], header[193]]);
+    let invalid = [
+        ("avih.dwMicroSecPerFrame", le_u32(header, 32) == 0),
+        ("strh.dwScale", le_u32(header, 128) == 0),
+        ("strh.dwRate", le_u32(header, 132) == 0),
+        ("avih.dwWidth", width == 0),
+        ("avih.dwHeight", height == 0),
+        ("strf.biWidth", strf_width as u32 != width),
+        ("strf.biHeight", strf_height.unsigned_abs() != height),
+        ("strf.biPlanes", planes != 1),
+    ];
+    match invalid.into_iter().find(|&(_, invalid)| invalid) {
+        Some((field, _)) => Err(MjpegError::InvalidHeaderOverride(field)),
+        None => Ok(()),
+    }
+}
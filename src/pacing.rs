@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+use crate::{MjpegError, Result};
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::writer::Writer;
+use crate::source::FrameSource;
+use crate::clock::{Clock, SystemClock};
+
+#[cfg(feature = "tokio")]
+use crate::mjpeg_async::MjpegAviWriterAsync;
+#[cfg(feature = "tokio")]
+use crate::writer::AsyncWriter;
+#[cfg(feature = "tokio")]
+use crate::source::AsyncFrameSource;
+
+/// What the pull loop does when a frame source was too slow and tick deadlines have passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedTickPolicy {
+    /// Pull the missed ticks back-to-back until the loop has caught up with the schedule.
+    Burst,
+    /// Restart the schedule from the current time.
+    Delay,
+    /// Drop the missed ticks and wait for the next deadline on the original schedule.
+    #[default]
+    Skip,
+}
+
+/// Statistics returned by the pull loops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PacingStats {
+    /// The number of frames that were pulled and written.
+    pub frames: u64,
+    /// The number of ticks dropped by [`MissedTickPolicy::Skip`].
+    pub missed_ticks: u64,
+}
+
+/// Drift-free tick schedule shared by the sync and async pull loops.
+pub(crate) struct Ticker {
+    period: Duration,
+    pub(crate) next: Instant,
+    policy: MissedTickPolicy,
+}
+
+impl Ticker {
+    pub(crate) fn new(fps: u32, policy: MissedTickPolicy) -> Result<Self> {
+        if fps == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        Ok(Ticker {
+            period: Duration::from_secs(1) / fps,
+            next: Instant::now(),
+            policy,
+        })
+    }
+
+    /// Creates a schedule with an arbitrary period, such as the frame duration of a file.
+    pub(crate) fn with_period(period: Duration, policy: MissedTickPolicy) -> Self {
+        Ticker { period, next: Instant::now(), policy }
+    }
+
+    /// Moves to the deadline after the current one and returns how many ticks were dropped.
+    pub(crate) fn advance(&mut self, now: Instant) -> u64 {
+        // Deadlines are multiples of the period from the start, so sleep jitter doesn't accumulate
+        self.next += self.period;
+        if now <= self.next {
+            return 0;
+        }
+
+        match self.policy {
+            MissedTickPolicy::Burst => 0,
+            MissedTickPolicy::Delay => {
+                self.next = now;
+                0
+            }
+            MissedTickPolicy::Skip => {
+                let missed = ((now - self.next).as_nanos() / self.period.as_nanos()) as u64 + 1;
+                self.next += self.period.saturating_mul(u32::try_from(missed).unwrap_or(u32::MAX));
+                missed
+            }
+        }
+    }
+}
+
+/// Pulls a frame from `source` at `fps` ticks per second and writes every returned frame.
+///
+/// The source is any [`FrameSource`], such as a closure. The loop stops when the source
+/// returns `Ok(None)`. The writer is not finished, so more frames can be added afterwards.
+/// Returns [`MjpegError::InvalidFrameSize`] if `fps` is zero.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{pull_at_fps, MissedTickPolicy, MjpegAviWriter, MjpegWriter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 320, 240, 10)?;
+///     let mut remaining = 100;
+///     pull_at_fps(&mut writer, 10, MissedTickPolicy::Skip, || {
+///         remaining -= 1;
+///         // Grab a JPEG from the camera here
+///         Ok((remaining > 0).then(|| vec![0xFF, 0xD8, 0xFF, 0xD9]))
+///     })?;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+pub fn pull_at_fps<W, M, S>(writer: &mut M, fps: u32, policy: MissedTickPolicy, source: S) -> Result<PacingStats>
+where
+    W: Writer,
+    M: MjpegAviWriter<W>,
+    S: FrameSource,
+{
+    pull_at_fps_with_clock(writer, fps, policy, source, SystemClock)
+}
+
+/// [`pull_at_fps`] reading the time and sleeping through `clock`, see [`Clock`].
+pub fn pull_at_fps_with_clock<W, M, S, C>(writer: &mut M, fps: u32, policy: MissedTickPolicy, mut source: S, clock: C) -> Result<PacingStats>
+where
+    W: Writer,
+    M: MjpegAviWriter<W>,
+    S: FrameSource,
+    C: Clock,
+{
+    let mut ticker = Ticker::new(fps, policy)?;
+    ticker.next = clock.now();
+    let mut stats = PacingStats::default();
+    let mut frame = Vec::new();
+
+    loop {
+        let now = clock.now();
+        if now < ticker.next {
+            clock.sleep(ticker.next - now);
+        }
+
+        if !source.next_frame_into(&mut frame)? {
+            break;
+        }
+        writer.add_frame(&frame)?;
+        stats.frames += 1;
+        stats.missed_ticks += ticker.advance(clock.now());
+    }
+
+    Ok(stats)
+}
+
+/// Asynchronous version of [`pull_at_fps`] using the tokio timer.
+#[cfg(feature = "tokio")]
+pub async fn pull_at_fps_async<W, M, S>(writer: &mut M, fps: u32, policy: MissedTickPolicy, mut source: S) -> Result<PacingStats>
+where
+    W: AsyncWriter,
+    M: MjpegAviWriterAsync<W>,
+    S: AsyncFrameSource,
+{
+    let mut ticker = Ticker::new(fps, policy)?;
+    let mut stats = PacingStats::default();
+    let mut frame = Vec::new();
+
+    loop {
+        tokio::time::sleep_until(tokio::time::Instant::from_std(ticker.next)).await;
+
+        if !source.next_frame_into(&mut frame).await? {
+            break;
+        }
+        writer.add_frame(&frame).await?;
+        stats.frames += 1;
+        stats.missed_ticks += ticker.advance(Instant::now());
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::mjpeg_sync::{MjpegAviWriter, MjpegWriter};
+use crate::writer::Writer;
+
+/// The bytes of the `idx1` chunk header of a file.
+const INDEX_HEADER_BYTES: u64 = 8;
+/// The bytes of a chunk header plus its `idx1` entry.
+const FRAME_OVERHEAD: u64 = 8 + 16;
+
+/// Aggregate statistics of a [`WriterPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// The number of writers open now.
+    pub open: usize,
+    /// The largest number of writers open at the same time.
+    pub peak_open: usize,
+    /// The number of frames written through the pool.
+    pub frames: u64,
+    /// The bytes charged to all tenants, see [`WriterPool::budget`].
+    pub bytes: u64,
+    /// The number of writers and frames rejected because a limit was reached.
+    pub rejected: u64,
+}
+
+/// Statistics of one tenant of a [`WriterPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TenantStats {
+    /// The number of writers of the tenant open now.
+    pub open: usize,
+    /// The number of frames the tenant has written.
+    pub frames: u64,
+    /// The bytes charged to the tenant.
+    pub bytes: u64,
+    /// The disk budget of the tenant, if any.
+    pub budget: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    stats: PoolStats,
+    tenants: HashMap<String, TenantStats>,
+}
+
+/// Caps the writers a recording server keeps open and the disk space each tenant may use.
+///
+/// Every camera of a tenant records through a [`PooledWriter`] from [`open`](Self::open). The
+/// pool refuses new writers beyond its capacity with [`MjpegError::PoolFull`], and frames that
+/// would take a tenant beyond its budget with [`MjpegError::BudgetExceeded`]; the writer stays
+/// usable and can be finished. Writers of the same configuration share the header prepared by
+/// their [`MjpegWriterBuilder`].
+///
+/// A tenant is charged the size of the files its writers produce. Call
+/// [`reclaim`](Self::reclaim) when retention deletes one of them. Clones share the pool, so
+/// writers can record on their own threads.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriterBuilder, WriterPool};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let pool = WriterPool::new(64);
+///     pool.budget("acme", 50 * 1024 * 1024 * 1024);
+///     let builder = MjpegWriterBuilder::new(1280, 720, 15);
+///
+///     let mut writer = pool.open("acme", &builder, File::create("acme/cam1.avi")?)?;
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.finish()?;
+///     println!("{:?}", pool.tenant_stats("acme"));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WriterPool {
+    capacity: usize,
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl WriterPool {
+    /// Creates a pool allowing `capacity` writers to be open at the same time.
+    pub fn new(capacity: usize) -> Self {
+        WriterPool { capacity, state: Arc::default() }
+    }
+
+    /// The maximum number of open writers.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Limits the bytes `tenant` may use to `bytes`. Tenants without a budget are unlimited.
+    pub fn budget(&self, tenant: &str, bytes: u64) {
+        self.lock().tenants.entry(tenant.to_string()).or_default().budget = Some(bytes);
+    }
+
+    /// Returns `bytes` of deleted files to the budget of `tenant`.
+    pub fn reclaim(&self, tenant: &str, bytes: u64) {
+        let mut state = self.lock();
+        if let Some(tenant) = state.tenants.get_mut(tenant) {
+            let bytes = bytes.min(tenant.bytes);
+            tenant.bytes -= bytes;
+            state.stats.bytes -= bytes;
+        }
+    }
+
+    /// Creates a writer for `tenant` with `builder`, writing to `writer`.
+    pub fn open<W: Writer>(&self, tenant: &str, builder: &MjpegWriterBuilder, writer: W) -> Result<PooledWriter<W>> {
+        let header_bytes = builder.header_len() as u64 + INDEX_HEADER_BYTES;
+        {
+            let mut state = self.lock();
+            if state.stats.open >= self.capacity {
+                state.stats.rejected += 1;
+                return Err(MjpegError::PoolFull);
+            }
+            charge(&mut state, tenant, header_bytes)?;
+            let stats = &mut state.stats;
+            stats.open += 1;
+            stats.peak_open = stats.peak_open.max(stats.open);
+            state.tenants.get_mut(tenant).expect("charged tenant").open += 1;
+        }
+
+        // The slot is released by the PooledWriter, also if the header can't be written
+        let mut pooled = PooledWriter { inner: None, pool: self.clone(), tenant: tenant.to_string() };
+        match builder.build(writer) {
+            Ok(writer) => pooled.inner = Some(writer),
+            Err(err) => {
+                self.reclaim(tenant, header_bytes);
+                return Err(err);
+            }
+        }
+        Ok(pooled)
+    }
+
+    /// The aggregate statistics.
+    pub fn stats(&self) -> PoolStats {
+        self.lock().stats
+    }
+
+    /// The statistics of `tenant`, `None` if it has neither a budget nor opened a writer.
+    pub fn tenant_stats(&self, tenant: &str) -> Option<TenantStats> {
+        self.lock().tenants.get(tenant).copied()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // The state stays consistent even if a thread panicked while holding the lock
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Charges `bytes` to `tenant`, failing if its budget doesn't allow it.
+fn charge(state: &mut PoolState, tenant: &str, bytes: u64) -> Result<()> {
+    let entry = state.tenants.entry(tenant.to_string()).or_default();
+    if entry.budget.is_some_and(|budget| entry.bytes + bytes > budget) {
+        state.stats.rejected += 1;
+        return Err(MjpegError::BudgetExceeded(tenant.to_string()));
+    }
+    entry.bytes += bytes;
+    state.stats.bytes += bytes;
+    Ok(())
+}
+
+/// A writer opened by [`WriterPool::open`]. Its slot is released when it is finished or dropped.
+pub struct PooledWriter<W: Writer> {
+    inner: Option<MjpegWriter<W>>,
+    pool: WriterPool,
+    tenant: String,
+}
+
+impl<W: Writer> PooledWriter<W> {
+    /// The tenant of the writer.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// The wrapped writer, for bookmarks and custom chunks; their bytes are not charged.
+    pub fn inner_mut(&mut self) -> &mut MjpegWriter<W> {
+        self.inner.as_mut().expect("writer present until finished")
+    }
+}
+
+impl<W: Writer> MjpegAviWriter<W> for PooledWriter<W> {
+    fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary])
+    }
+
+    fn add_frame_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        let len: u64 = bufs.iter().map(|buf| buf.len() as u64).sum();
+        let bytes = FRAME_OVERHEAD + len + len % 2;
+        charge(&mut self.pool.lock(), &self.tenant, bytes)?;
+        if let Err(err) = self.inner_mut().add_frame_vectored(bufs) {
+            self.pool.reclaim(&self.tenant, bytes);
+            return Err(err);
+        }
+
+        let mut state = self.pool.lock();
+        state.stats.frames += 1;
+        state.tenants.get_mut(&self.tenant).expect("charged tenant").frames += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W> {
+        self.inner.take().expect("writer present until finished").finish()
+    }
+}
+
+impl<W: Writer> Drop for PooledWriter<W> {
+    fn drop(&mut self) {
+        let mut state = self.pool.lock();
+        state.stats.open -= 1;
+        if let Some(tenant) = state.tenants.get_mut(&self.tenant) {
+            tenant.open -= 1;
+        }
+    }
+}
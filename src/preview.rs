@@ -0,0 +1,252 @@
+use std::io::{Cursor, Read, Seek};
+use std::time::{Duration, Instant};
+use zune_jpeg::JpegDecoder;
+use zune_jpeg::zune_core::colorspace::ColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use crate::{MjpegError, Result};
+use crate::clock::{Clock, SystemClock};
+use crate::frame_map::{frame_timestamp, FrameMapEntry};
+use crate::reader::MjpegReader;
+
+/// A decoded frame as tightly packed 8-bit RGBA pixels, row by row from the top.
+///
+/// This is the layout GPU and GUI toolkits take for textures, e.g. a wgpu texture in
+/// `Rgba8UnormSrgb` or `egui::ColorImage::from_rgba_unmultiplied`. Enabled by the `preview`
+/// feature.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RgbaFrame {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// `width * height * 4` bytes of pixel data.
+    pub pixels: Vec<u8>,
+}
+
+/// Decodes a JPEG frame into RGBA pixels with `zune-jpeg`, see [`RgbaFrame`].
+///
+/// Enabled by the `preview` feature. Use [`decode_rgba_into`] to reuse the pixel buffer while
+/// scrubbing through a recording.
+pub fn decode_rgba(jpeg: &[u8]) -> Result<RgbaFrame> {
+    let mut frame = RgbaFrame::default();
+    decode_rgba_into(jpeg, &mut frame)?;
+    Ok(frame)
+}
+
+/// Decodes a JPEG frame into `frame`, reusing its pixel buffer.
+///
+/// Returns [`MjpegError::Codec`] if the data isn't a decodable JPEG image, such as an empty
+/// dropped frame; `frame` is emptied then, keeping its allocation.
+pub fn decode_rgba_into(jpeg: &[u8], frame: &mut RgbaFrame) -> Result<()> {
+    let mut pixels = std::mem::take(&mut frame.pixels);
+    match decode(jpeg, ColorSpace::RGBA, &mut pixels) {
+        Ok((width, height)) => {
+            *frame = RgbaFrame { width, height, pixels };
+            Ok(())
+        }
+        Err(err) => {
+            pixels.clear();
+            *frame = RgbaFrame { width: 0, height: 0, pixels };
+            Err(err)
+        }
+    }
+}
+
+/// Decodes the luma of a JPEG frame, returning the width, the height and one byte per pixel.
+pub(crate) fn decode_luma(jpeg: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let mut pixels = Vec::new();
+    let (width, height) = decode(jpeg, ColorSpace::Luma, &mut pixels)?;
+    Ok((width, height, pixels))
+}
+
+fn decode(jpeg: &[u8], colorspace: ColorSpace, pixels: &mut Vec<u8>) -> Result<(u32, u32)> {
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(colorspace);
+    let mut decoder = JpegDecoder::new_with_options(Cursor::new(jpeg), options);
+    decoder.decode_headers().map_err(codec_error)?;
+    let missing_size = || MjpegError::Codec("missing frame size".to_string());
+    let (width, height) = decoder.dimensions().ok_or_else(missing_size)?;
+    pixels.resize(decoder.output_buffer_size().ok_or_else(missing_size)?, 0);
+    decoder.decode_into(pixels).map_err(codec_error)?;
+    Ok((width as u32, height as u32))
+}
+
+fn codec_error(err: zune_jpeg::errors::DecodeErrors) -> MjpegError {
+    MjpegError::Codec(err.to_string())
+}
+
+/// The state of a scrubbable preview of a recording, for review apps built on egui, iced or
+/// another immediate-mode GUI.
+///
+/// The player owns the reader and the decoded current frame. The app calls
+/// [`update`](Self::update) once per GUI frame; it advances the position while playing and
+/// returns the frame when it changed, so the texture is only uploaded then. Seeking and
+/// stepping take effect at the next update. Dropped frames keep showing the frame before them,
+/// and playback pauses at the last frame. Enabled by the `preview` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegReader, PreviewPlayer};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut player = PreviewPlayer::new(MjpegReader::new(File::open("recording.avi")?)?);
+///     player.play();
+///     // In the GUI's frame callback
+///     if let Some(frame) = player.update()? {
+///         println!("upload {}x{} texture", frame.width, frame.height);
+///     }
+///     if let Some(wait) = player.next_frame_in() {
+///         println!("request a repaint in {:?}", wait);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct PreviewPlayer<R: Read + Seek, C: Clock = SystemClock> {
+    reader: MjpegReader<R>,
+    clock: C,
+    map: Vec<FrameMapEntry>,
+    /// The frame to show
+    position: usize,
+    /// The frame in `current`, `None` before the first update or after a decode error
+    shown: Option<usize>,
+    current: RgbaFrame,
+    /// The position and time playback started from, `None` while paused
+    playing: Option<(usize, Instant)>,
+}
+
+impl<R: Read + Seek> PreviewPlayer<R> {
+    /// Creates a paused player at the first frame.
+    pub fn new(reader: MjpegReader<R>) -> Self {
+        Self::with_clock(reader, SystemClock)
+    }
+}
+
+impl<R: Read + Seek, C: Clock> PreviewPlayer<R, C> {
+    /// Creates a player timing playback with `clock`.
+    pub fn with_clock(reader: MjpegReader<R>, clock: C) -> Self {
+        let map = reader.frame_map();
+        PreviewPlayer { reader, clock, map, position: 0, shown: None, current: RgbaFrame::default(), playing: None }
+    }
+
+    /// The number of frames.
+    pub fn frame_count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// The duration of the recording.
+    pub fn duration(&self) -> Duration {
+        let info = self.reader.info();
+        frame_timestamp(self.map.len() as u64, info.scale, info.rate)
+    }
+
+    /// The number of the frame to show.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The timestamp of the frame to show.
+    pub fn timestamp(&self) -> Duration {
+        self.map.get(self.position).map_or(Duration::ZERO, |entry| entry.timestamp)
+    }
+
+    /// Returns true while playing.
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    /// Starts playing at the frame rate of the file, from the start if at the last frame.
+    pub fn play(&mut self) {
+        if self.playing.is_none() {
+            if self.position + 1 >= self.map.len() {
+                self.position = 0;
+            }
+            self.playing = Some((self.position, self.clock.now()));
+        }
+    }
+
+    /// Pauses at the current position.
+    pub fn pause(&mut self) {
+        self.advance();
+        self.playing = None;
+    }
+
+    /// Plays if paused, pauses if playing.
+    pub fn toggle(&mut self) {
+        if self.is_playing() {
+            self.pause();
+        } else {
+            self.play();
+        }
+    }
+
+    /// Moves to `frame`, clamped to the last frame. Playback continues from there.
+    pub fn seek(&mut self, frame: usize) {
+        self.position = frame.min(self.map.len().saturating_sub(1));
+        if self.playing.is_some() {
+            self.playing = Some((self.position, self.clock.now()));
+        }
+    }
+
+    /// Moves to the frame shown at `time`, e.g. from a timeline slider.
+    pub fn seek_time(&mut self, time: Duration) {
+        self.seek(self.frame_at(time));
+    }
+
+    /// Moves `frames` forward, or backward if negative, and pauses.
+    pub fn step(&mut self, frames: isize) {
+        self.pause();
+        self.seek(self.position.saturating_add_signed(frames));
+    }
+
+    /// Advances playback and decodes the frame to show if it changed.
+    ///
+    /// Returns the new frame for uploading, or `None` if the shown frame is still current.
+    pub fn update(&mut self) -> Result<Option<&RgbaFrame>> {
+        self.advance();
+        // The last frame with data at or before the position
+        let Some(frame) = (0..=self.position).rev().find(|&i| self.map.get(i).is_some_and(|entry| entry.len > 0)) else {
+            return Ok(None);
+        };
+        if self.shown == Some(frame) {
+            return Ok(None);
+        }
+        self.shown = None;
+        decode_rgba_into(&self.reader.read_frame(frame)?, &mut self.current)?;
+        self.shown = Some(frame);
+        Ok(Some(&self.current))
+    }
+
+    /// The frame decoded by the last update.
+    pub fn current(&self) -> &RgbaFrame {
+        &self.current
+    }
+
+    /// The time until the next frame is due while playing, to schedule a repaint.
+    pub fn next_frame_in(&self) -> Option<Duration> {
+        let (start, started) = self.playing?;
+        let next = self.map.get(self.position + 1)?;
+        let due = started + (next.timestamp - self.map[start].timestamp);
+        Some(due.saturating_duration_since(self.clock.now()))
+    }
+
+    /// Unwraps the reader.
+    pub fn into_reader(self) -> MjpegReader<R> {
+        self.reader
+    }
+
+    /// Moves the position to the frame due now while playing.
+    fn advance(&mut self) {
+        let Some((start, started)) = self.playing else { return };
+        let elapsed = self.clock.now().saturating_duration_since(started);
+        self.position = self.frame_at(self.map[start].timestamp + elapsed);
+        if self.position + 1 >= self.map.len() {
+            self.playing = None;
+        }
+    }
+
+    /// The last frame whose timestamp is not after `time`.
+    fn frame_at(&self, time: Duration) -> usize {
+        self.map.partition_point(|entry| entry.timestamp <= time).saturating_sub(1)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::common::{append_to_hdrl, create_chunk_header};
+
+/// The ID of the chunk at the end of the `hdrl` list that describes the producer of a file.
+pub(crate) const PRODUCER_CHUNK_ID: [u8; 4] = *b"mjpv";
+
+/// The crate version and writer settings that produced a file.
+///
+/// Writers built by [`MjpegWriterBuilder`](crate::MjpegWriterBuilder) store them in an `mjpv`
+/// chunk at the end of the `hdrl` list, which players skip, and [`MjpegReader`](crate::MjpegReader)
+/// reads them back into [`AviInfo::producer`](crate::AviInfo::producer), so tools can tell
+/// which version wrote a file and how it was configured, e.g. to work around the quirks of
+/// older versions. The payload is text: `key=value` lines, starting with `name` and `version`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProducerInfo {
+    /// The name of the crate, `mjpeg-avi-rs`.
+    pub name: String,
+    /// The version of the crate, e.g. `0.1.0`.
+    pub version: String,
+    /// The writer settings, such as `fps` or `open_dml`, in the order they were written.
+    pub settings: Vec<(String, String)>,
+}
+
+impl ProducerInfo {
+    /// Describes this version of the crate with the given settings.
+    pub(crate) fn current(settings: Vec<(String, String)>) -> Self {
+        ProducerInfo { name: env!("CARGO_PKG_NAME").to_string(), version: env!("CARGO_PKG_VERSION").to_string(), settings }
+    }
+
+    /// The value of the setting `key`.
+    pub fn setting(&self, key: &str) -> Option<&str> {
+        self.settings.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+
+    /// The payload of the `mjpv` chunk.
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        let mut payload = format!("name={}\nversion={}\n", self.name, self.version);
+        for (key, value) in &self.settings {
+            payload.push_str(&format!("{}={}\n", key, value));
+        }
+        payload.into_bytes()
+    }
+
+    /// Parses the payload of an `mjpv` chunk. Returns `None` if it has no name.
+    pub(crate) fn parse(payload: &[u8]) -> Option<Self> {
+        let mut info = ProducerInfo::default();
+        for line in String::from_utf8_lossy(payload).lines() {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            match key {
+                "name" => info.name = value.to_string(),
+                "version" => info.version = value.to_string(),
+                _ => info.settings.push((key.to_string(), value.to_string())),
+            }
+        }
+        (!info.name.is_empty()).then_some(info)
+    }
+}
+
+/// Appends the `mjpv` chunk describing `info` at the end of the `hdrl` list.
+pub(crate) fn add_producer_chunk(header: &[u8], info: &ProducerInfo) -> Vec<u8> {
+    let payload = info.payload();
+    let mut chunk = create_chunk_header(PRODUCER_CHUNK_ID, payload.len() as u32).to_vec();
+    chunk.extend_from_slice(&payload);
+    chunk.resize(chunk.len() + (payload.len() & 1), 0);
+    append_to_hdrl(header, &chunk)
+}
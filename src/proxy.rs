@@ -0,0 +1,63 @@
+use crate::Result;
+use crate::builder::MjpegWriterBuilder;
+use crate::encode::{EncodeStats, FrameEncoder};
+use crate::mjpeg_sync::{MjpegAviWriter, MjpegWriter};
+use crate::writer::Writer;
+
+/// Records every frame twice in one pass: unchanged into a full-resolution file and
+/// re-encoded into a proxy file, typically scaled down for editing.
+///
+/// The proxy frames are produced by a [`FrameEncoder`], so any filters and quality settings
+/// can be used; the proxy header gets the encoder's output size. Enabled by the `encode` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameEncoder, MjpegWriterBuilder, ProxyRecorder, ScaleFilter};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut encoder = FrameEncoder::new(70);
+///     encoder.add_filter(ScaleFilter::fit(640, 360));
+///     let builder = MjpegWriterBuilder::new(1920, 1080, 30);
+///     let mut recorder = ProxyRecorder::new(&builder, encoder, File::create("full.avi")?, File::create("proxy.avi")?)?;
+///     recorder.add_frame(&std::fs::read("frame.jpg")?)?;
+///     recorder.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[must_use = "The recorder must be finalized using .finish() to produce valid AVI files"]
+pub struct ProxyRecorder<W: Writer, P: Writer> {
+    full: MjpegWriter<W>,
+    proxy: MjpegWriter<P>,
+    encoder: FrameEncoder,
+}
+
+impl<W: Writer, P: Writer> ProxyRecorder<W, P> {
+    /// Creates the full-resolution file with `builder` and the proxy file for the frames of `encoder`.
+    pub fn new(builder: &MjpegWriterBuilder, encoder: FrameEncoder, full: W, proxy: P) -> Result<Self> {
+        let full = builder.build(full)?;
+        let proxy = encoder.writer_builder(builder.width(), builder.height(), builder.fps()).build(proxy)?;
+        Ok(ProxyRecorder { full, proxy, encoder })
+    }
+
+    /// Adds a JPEG frame to both files.
+    pub fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        // Re-encode first, so a frame that can't be decoded is added to neither file
+        let proxy_frame = self.encoder.reencode(jpeg_binary)?;
+        self.full.add_frame(jpeg_binary)?;
+        self.proxy.add_frame(&proxy_frame.jpeg)
+    }
+
+    /// The statistics of the proxy encoder.
+    pub fn proxy_stats(&self) -> &EncodeStats {
+        self.encoder.stats()
+    }
+
+    /// Finalizes both files, returning the full-resolution and the proxy writer.
+    pub fn finish(self) -> Result<(W, P)> {
+        let full = self.full.finish()?;
+        let proxy = self.proxy.finish()?;
+        Ok((full, proxy))
+    }
+}
@@ -0,0 +1,174 @@
+use crate::import::find_segment;
+use crate::observer::FrameObserver;
+
+/// The luminance quantization table of the JPEG standard (Annex K), which the IJG quality
+/// setting scales.
+pub(crate) const STANDARD_LUMA_TABLE: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// The natural (row by row) position of each coefficient of a table in zigzag order, the order
+/// `DQT` segments store them in.
+pub(crate) const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10,
+    17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// A hook called when the estimated quality changes: frame number, previous and new quality.
+type ChangeHook = Box<dyn FnMut(u64, u8, u8) + Send>;
+
+/// Estimates the JPEG quality, 1 to 100 on the IJG scale used by libjpeg and most cameras,
+/// from the luminance quantization table of a frame.
+///
+/// The table is compared with the standard table the IJG quality setting scales, so the
+/// estimate is exact for encoders that use it and a comparable figure for those that don't.
+/// Only the `DQT` segments are parsed, not the image data. Returns `None` if the frame has no
+/// luminance table, such as an empty dropped frame or an MJPEG frame relying on default tables.
+pub fn estimate_quality(jpeg: &[u8]) -> Option<u8> {
+    let table = find_segment(jpeg, |marker, payload| if marker == 0xDB { luma_table(payload) } else { None })?;
+    // Values clipped to 1 or 255 don't tell the scale, unless all are
+    let clipped = |q: u32| q <= 1 || q >= 255;
+    let all_clipped = table.iter().all(|&q| clipped(q));
+    let (sum, standard) = table
+        .iter()
+        .zip(STANDARD_LUMA_TABLE)
+        .filter(|&(&q, _)| all_clipped || !clipped(q))
+        .fold((0, 0), |(sum, standard), (&q, s)| (sum + q, standard + s as u32));
+    // Invert the IJG scaling: 5000 / quality below 50, 200 - 2 * quality from 50
+    let scale = (sum * 100 + standard / 2) / standard;
+    let quality = if scale <= 100 { (200 - scale).div_ceil(2) } else { (5000 + scale / 2) / scale };
+    Some(quality.clamp(1, 100) as u8)
+}
+
+/// Finds table 0, the luminance table, in the payload of a `DQT` segment, which can hold
+/// several tables, and returns it in natural order.
+fn luma_table(mut payload: &[u8]) -> Option<[u32; 64]> {
+    while let Some(&info) = payload.first() {
+        let wide = info >> 4 != 0;
+        let len = if wide { 128 } else { 64 };
+        let values = payload.get(1..1 + len)?;
+        if info & 0x0F == 0 {
+            let mut table = [0; 64];
+            for (i, &pos) in ZIGZAG.iter().enumerate() {
+                table[pos] = if wide { u16::from_be_bytes([values[2 * i], values[2 * i + 1]]) as u32 } else { values[i] as u32 };
+            }
+            return Some(table);
+        }
+        payload = &payload[1 + len..];
+    }
+    None
+}
+
+/// Statistics collected by [`QualityObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QualityStats {
+    /// The number of frames observed.
+    pub frames: u64,
+    /// The number of frames without a luminance table, whose quality is unknown.
+    pub unknown: u64,
+    /// The estimated quality of the first frame with a known quality.
+    pub first: Option<u8>,
+    /// The estimated quality of the last frame with a known quality.
+    pub last: Option<u8>,
+    /// The lowest estimated quality.
+    pub min: Option<u8>,
+    /// The highest estimated quality.
+    pub max: Option<u8>,
+    /// The number of times the quality changed from one frame to the next.
+    pub changes: u64,
+}
+
+/// An observer that estimates the JPEG quality of every frame with [`estimate_quality`], to
+/// detect cameras that silently lower their quality during a session, e.g. to keep up their
+/// frame rate on a congested network.
+///
+/// The estimate only parses the quantization tables, so it costs little next to writing the
+/// frame. The statistics report the range of qualities seen; a hook set with
+/// [`on_change`](Self::on_change) is called whenever the quality differs from the frame before,
+/// to log or alert.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, ObservedWriter, QualityObserver};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut observer = QualityObserver::new();
+///     observer.on_change(|frame, previous, quality| eprintln!("frame {}: quality {} -> {}", frame, previous, quality));
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 15)?;
+///     let mut writer = ObservedWriter::new(writer, &mut observer);
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.finish()?;
+///     println!("{:?}", observer.stats());
+///     Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct QualityObserver {
+    stats: QualityStats,
+    hook: Option<ChangeHook>,
+    buf: Vec<u8>,
+}
+
+impl QualityObserver {
+    /// Creates an observer without a change hook.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the hook called with the frame number, the previous and the new quality when the
+    /// estimated quality changes.
+    pub fn on_change<F: FnMut(u64, u8, u8) + Send + 'static>(&mut self, hook: F) -> &mut Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// The statistics collected so far.
+    pub fn stats(&self) -> &QualityStats {
+        &self.stats
+    }
+}
+
+impl FrameObserver for QualityObserver {
+    fn on_frame(&mut self, bufs: &[&[u8]]) {
+        let frame = self.stats.frames;
+        self.stats.frames += 1;
+        let quality = match bufs {
+            [jpeg] => estimate_quality(jpeg),
+            _ => {
+                self.buf.clear();
+                bufs.iter().for_each(|buf| self.buf.extend_from_slice(buf));
+                estimate_quality(&self.buf)
+            }
+        };
+        let Some(quality) = quality else {
+            self.stats.unknown += 1;
+            return;
+        };
+        let stats = &mut self.stats;
+        if let Some(previous) = stats.last.filter(|&previous| previous != quality) {
+            stats.changes += 1;
+            if let Some(hook) = &mut self.hook {
+                hook(frame, previous, quality);
+            }
+        }
+        stats.first.get_or_insert(quality);
+        stats.last = Some(quality);
+        stats.min = Some(stats.min.map_or(quality, |min| min.min(quality)));
+        stats.max = Some(stats.max.map_or(quality, |max| max.max(quality)));
+    }
+}
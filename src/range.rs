@@ -0,0 +1,255 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use crate::{MjpegError, Result};
+
+/// The default number of bytes [`RangeReader`] fetches at once.
+const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// A source of bytes that can be read at arbitrary offsets, such as a file in object storage
+/// that supports HTTP range requests.
+///
+/// Wrap a source in a [`RangeReader`] to read it with [`MjpegReader`](crate::MjpegReader).
+pub trait RangeSource {
+    /// The total size of the source in bytes.
+    fn size(&mut self) -> Result<u64>;
+
+    /// Fills `buf` with the bytes starting at `offset`.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()>;
+}
+
+impl RangeSource for File {
+    fn size(&mut self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buf)?;
+        Ok(())
+    }
+}
+
+impl RangeSource for &[u8] {
+    fn size(&mut self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|start| self.get(start..start.checked_add(buf.len())?))
+            .ok_or_else(|| MjpegError::from(io::Error::from(io::ErrorKind::UnexpectedEof)))?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Adapts a [`RangeSource`] to `Read` + `Seek`.
+///
+/// Small reads, like the chunk headers parsed by [`MjpegReader`](crate::MjpegReader), are served
+/// from a block fetched with one range request; reads of at least a block, like frames, are
+/// fetched directly.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "http")]
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     use mjpeg_avi_rs::{HttpSource, MjpegReader, RangeReader};
+///
+///     let source = HttpSource::new("http://storage.example.com/camera1/2024-01-01.avi");
+///     let mut reader = MjpegReader::new(RangeReader::new(source)?)?;
+///     let jpeg = reader.read_frame(reader.frame_count() - 1)?;
+///     std::fs::write("last.jpg", jpeg)?;
+///     Ok(())
+/// }
+/// # #[cfg(not(feature = "http"))]
+/// # fn main() {}
+/// ```
+pub struct RangeReader<S: RangeSource> {
+    source: S,
+    len: u64,
+    pos: u64,
+    block: Vec<u8>,
+    block_start: u64,
+    block_size: usize,
+}
+
+impl<S: RangeSource> RangeReader<S> {
+    /// Creates a reader, querying the length of the source.
+    pub fn new(mut source: S) -> Result<Self> {
+        let len = source.size()?;
+        Ok(RangeReader {
+            source,
+            len,
+            pos: 0,
+            block: Vec::new(),
+            block_start: 0,
+            block_size: DEFAULT_BLOCK_SIZE,
+        })
+    }
+
+    /// Sets the number of bytes fetched at once for small reads. Default: 64KiB.
+    pub fn block_size(&mut self, size: usize) -> &mut Self {
+        self.block_size = size.max(1);
+        self
+    }
+
+    /// The total length of the source.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the source is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Consumes the reader, returning the source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: RangeSource> Read for RangeReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let n = buf.len().min((self.len - self.pos) as usize);
+
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.pos < self.block_start || self.pos >= block_end {
+            if n >= self.block_size {
+                self.source.read_at(self.pos, &mut buf[..n]).map_err(to_io_error)?;
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            let size = self.block_size.min((self.len - self.pos) as usize);
+            self.block.resize(size, 0);
+            self.source.read_at(self.pos, &mut self.block).map_err(|e| {
+                self.block.clear();
+                to_io_error(e)
+            })?;
+            self.block_start = self.pos;
+        }
+
+        let at = (self.pos - self.block_start) as usize;
+        let n = n.min(self.block.len() - at);
+        buf[..n].copy_from_slice(&self.block[at..at + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<S: RangeSource> Seek for RangeReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = new_pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
+
+fn to_io_error(err: MjpegError) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+/// A [`RangeSource`] that reads a file over HTTP with range requests.
+///
+/// Enabled by the `http` feature. Plain HTTP works out of the box; enable one of reqwest's
+/// TLS features in your own `Cargo.toml` for HTTPS. Credentials and extra headers set with
+/// [`basic_auth`](Self::basic_auth) and [`header`](Self::header) are sent with every request.
+#[cfg(feature = "http")]
+pub struct HttpSource {
+    client: reqwest::blocking::Client,
+    url: String,
+    options: RequestOptions,
+}
+
+#[cfg(feature = "http")]
+impl HttpSource {
+    /// Creates a source for `url` with a default client.
+    pub fn new<U: Into<String>>(url: U) -> Self {
+        Self::with_client(reqwest::blocking::Client::new(), url)
+    }
+
+    /// Creates a source for `url` that sends its requests with `client`, e.g. one with
+    /// authentication headers or timeouts.
+    pub fn with_client<U: Into<String>>(client: reqwest::blocking::Client, url: U) -> Self {
+        HttpSource { client, url: url.into(), options: RequestOptions::default() }
+    }
+
+    /// Sends HTTP Basic credentials with every request.
+    pub fn basic_auth<U: Into<String>, P: Into<String>>(&mut self, username: U, password: Option<P>) -> &mut Self {
+        self.options.basic_auth = Some((username.into(), password.map(Into::into)));
+        self
+    }
+
+    /// Adds a header sent with every request, such as an API token.
+    pub fn header<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) -> &mut Self {
+        self.options.headers.push((name.into(), value.into()));
+        self
+    }
+
+    fn request(&self, method: reqwest::Method) -> reqwest::blocking::RequestBuilder {
+        self.options.apply(self.client.request(method, &self.url))
+    }
+}
+
+/// Credentials and extra headers sent with every request of an HTTP client.
+#[cfg(feature = "http")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestOptions {
+    pub(crate) basic_auth: Option<(String, Option<String>)>,
+    pub(crate) headers: Vec<(String, String)>,
+}
+
+#[cfg(feature = "http")]
+impl RequestOptions {
+    pub(crate) fn apply(&self, mut request: reqwest::blocking::RequestBuilder) -> reqwest::blocking::RequestBuilder {
+        if let Some((username, password)) = &self.basic_auth {
+            request = request.basic_auth(username, password.as_ref());
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+}
+
+#[cfg(feature = "http")]
+impl RangeSource for HttpSource {
+    fn size(&mut self) -> Result<u64> {
+        let response = self.request(reqwest::Method::HEAD).send().map_err(http_error)?;
+        if !response.status().is_success() {
+            return Err(MjpegError::Io(format!("HEAD {} failed: {}", self.url, response.status())));
+        }
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok())
+            .ok_or_else(|| MjpegError::Io(format!("HEAD {} returned no Content-Length", self.url)))
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let range = format!("bytes={}-{}", offset, offset + buf.len() as u64 - 1);
+        let mut response = self.request(reqwest::Method::GET).header(reqwest::header::RANGE, range).send().map_err(http_error)?;
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(MjpegError::Io(format!("range request to {} failed: {}", self.url, response.status())));
+        }
+        response.read_exact(buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "http")]
+pub(crate) fn http_error(err: reqwest::Error) -> MjpegError {
+    MjpegError::Io(err.to_string())
+}
@@ -0,0 +1,612 @@
+use std::io::{IoSliceMut, Read, Seek, SeekFrom};
+use crate::{MjpegError, Result};
+use crate::riff::*;
+use crate::index::IndexEntry;
+use crate::frame_map::{frame_timestamp, FrameMapEntry};
+use crate::bookmark::{parse_bookmark, Bookmark, BOOKMARK_CHUNK_ID};
+use crate::producer::{ProducerInfo, PRODUCER_CHUNK_ID};
+use crate::data::DataSample;
+use crate::timecode::{Timecode, TIMECODE_FOURCC};
+use crate::custom::{ChunkPlacement, CustomChunk, FRAME_META_CHUNK_ID};
+
+/// Header fields of an AVI file, as read by [`MjpegReader`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AviInfo {
+    /// `avih` dwMicroSecPerFrame
+    pub micro_sec_per_frame: u32,
+    /// `avih` dwMaxBytesPerSec
+    pub max_bytes_per_sec: u32,
+    /// `avih` dwFlags
+    pub flags: u32,
+    /// `avih` dwTotalFrames
+    pub total_frames: u32,
+    /// `avih` dwStreams
+    pub streams: u32,
+    /// `avih` dwSuggestedBufferSize
+    pub suggested_buffer_size: u32,
+    /// The width of the video frames.
+    pub width: u32,
+    /// The height of the video frames.
+    pub height: u32,
+    /// `strh` dwScale of the video stream
+    pub scale: u32,
+    /// `strh` dwRate of the video stream
+    pub rate: u32,
+    /// `strh` dwLength of the video stream
+    pub length: u32,
+    /// `strh` dwQuality of the video stream
+    pub quality: u32,
+    /// `strf` biCompression of the video stream
+    pub compression: [u8; 4],
+    /// The crate version and settings that wrote the file, if it was written by this crate.
+    pub producer: Option<ProducerInfo>,
+    /// The fourccs of the data streams, see
+    /// [`MjpegWriterBuilder::data_stream`](crate::MjpegWriterBuilder::data_stream).
+    pub data_streams: Vec<[u8; 4]>,
+    /// The display aspect ratio (width, height) from the `vprp` chunk of the video stream,
+    /// see [`MjpegWriterBuilder::aspect_ratio`](crate::MjpegWriterBuilder::aspect_ratio).
+    pub aspect_ratio: Option<(u16, u16)>,
+}
+
+impl AviInfo {
+    /// The frame rate (`dwRate / dwScale`).
+    pub fn fps(&self) -> f64 {
+        if self.scale == 0 {
+            return 0.0;
+        }
+        self.rate as f64 / self.scale as f64
+    }
+}
+
+/// A reader for MJPEG AVI files.
+///
+/// The reader parses the headers and the frame index when it is created and then reads
+/// frames on demand. Files without an `idx1` index (for example, files whose recording
+/// was interrupted) are handled by scanning the `movi` list instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::MjpegReader;
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = MjpegReader::new(File::open("output.avi")?)?;
+///     println!("{}x{} @ {}fps", reader.info().width, reader.info().height, reader.info().fps());
+///     for i in 0..reader.frame_count() {
+///         let jpeg = reader.read_frame(i)?;
+///         std::fs::write(format!("frame_{:05}.jpg", i), jpeg)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct MjpegReader<R: Read + Seek> {
+    reader: R,
+    info: AviInfo,
+    /// The positions in `index` where the entries of each RIFF start and the file offsets of
+    /// the 'movi' list type of that RIFF, which their offsets are relative to
+    segments: Vec<(usize, u64)>,
+    index: Vec<IndexEntry>,
+    /// Frame numbers and positions in `index` of the video frames
+    frames: Vec<(u64, usize)>,
+    /// The chunk IDs of the data streams, in the order of `AviInfo::data_streams`
+    data_chunk_ids: Vec<[u8; 4]>,
+    /// The chunks at the top level of the first RIFF other than the lists, `idx1` and `JUNK`
+    top_level: Vec<Chunk>,
+    /// Check the JPEG structure of every frame read
+    verify: bool,
+}
+
+impl<R: Read + Seek> MjpegReader<R> {
+    /// Parses the headers and the frame index of an AVI file.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        let riff = read_chunk(&mut reader, 0, file_len)?.ok_or_else(|| invalid("file is too short"))?;
+        if &riff.id != b"RIFF" || &read_list_type(&mut reader, &riff)? != b"AVI " {
+            return Err(invalid("not a RIFF AVI file"));
+        }
+        // Unfinished files have a zero size and truncated files are read as far as they go
+        let riff_end = if riff.size == 0 { file_len } else { riff.end().min(file_len) };
+
+        let mut info = None;
+        let mut movi = None;
+        let mut index = None;
+        let mut top_level = Vec::new();
+
+        let mut offset = riff.data_offset() + 4;
+        while let Some(chunk) = read_chunk(&mut reader, offset, riff_end)? {
+            match &chunk.id {
+                b"LIST" => match &read_list_type(&mut reader, &chunk)? {
+                    b"hdrl" => info = Some(parse_hdrl(&mut reader, &chunk, riff_end)?),
+                    b"movi" if chunk.size == 0 => {
+                        // The size is only patched in by finish(), so the frames run to the end
+                        movi = Some(Chunk { size: (riff_end - chunk.data_offset()) as u32, ..chunk });
+                        break;
+                    }
+                    b"movi" => movi = Some(chunk),
+                    _ => {}
+                },
+                b"idx1" if chunk.data_offset() + chunk.size as u64 <= riff_end => index = Some(read_payload(&mut reader, &chunk)?),
+                b"idx1" | b"JUNK" => {}
+                _ if chunk.end() <= riff_end => top_level.push(chunk),
+                _ => {}
+            }
+            offset = chunk.end();
+        }
+
+        let (info, video_stream, data_chunk_ids) = info.ok_or_else(|| invalid("missing hdrl list"))?;
+        let movi = movi.ok_or_else(|| invalid("missing movi list"))?;
+        let movi_base = movi.data_offset();
+        let mut index = match index {
+            Some(index) => parse_index(&mut reader, &index, movi_base, riff_end)?,
+            None => scan_movi(&mut reader, &movi, riff_end)?,
+        };
+
+        // OpenDML extension segments follow the first RIFF
+        let mut segments = vec![(0, movi_base)];
+        let mut offset = if riff.size == 0 { file_len } else { riff.end() };
+        while let Some(avix) = read_chunk(&mut reader, offset, file_len)? {
+            if &avix.id != b"RIFF" || &read_list_type(&mut reader, &avix)? != b"AVIX" {
+                break;
+            }
+            let avix_end = avix.end_within(file_len);
+            if let Some(movi) = read_chunk(&mut reader, avix.data_offset() + 4, avix_end)? {
+                if movi.is_list() && &read_list_type(&mut reader, &movi)? == b"movi" {
+                    let movi = Chunk { size: (movi.end_within(avix_end) - movi.data_offset()) as u32, ..movi };
+                    segments.push((index.len(), movi.data_offset()));
+                    index.extend(scan_movi(&mut reader, &movi, avix_end)?);
+                }
+            }
+            offset = avix_end;
+        }
+
+        let frames = index
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_video() && entry.stream() == Some(video_stream))
+            .map(|(i, _)| i)
+            .enumerate()
+            .map(|(frame, i)| (frame as u64, i))
+            .collect();
+
+        Ok(MjpegReader { reader, info, segments, index, frames, data_chunk_ids, top_level, verify: false })
+    }
+
+    /// The header fields of the file.
+    pub fn info(&self) -> &AviInfo {
+        &self.info
+    }
+
+    /// The number of video frames in the file.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The index of all chunks in the `movi` list.
+    ///
+    /// This is the `idx1` index, or an index rebuilt by scanning the `movi` list if the file has none.
+    /// Offsets are always relative to the `movi` list type, even if the file stored absolute offsets.
+    /// The chunks of OpenDML `RIFF AVIX` segments follow, found by scanning their `movi` lists,
+    /// with offsets relative to the `movi` list of their segment.
+    pub fn index(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    /// The index entry of frame `index`.
+    pub fn frame_entry(&self, index: usize) -> Option<&IndexEntry> {
+        self.frames.get(index).map(|&(_, i)| &self.index[i])
+    }
+
+    /// The timestamps and byte ranges of the video frames, see [`FrameMapEntry`].
+    pub fn frame_map(&self) -> Vec<FrameMapEntry> {
+        self.frames
+            .iter()
+            .map(|&(frame, i)| FrameMapEntry {
+                timestamp: frame_timestamp(frame, self.info.scale, self.info.rate),
+                offset: self.movi_base(i) + self.index[i].offset as u64 + 8,
+                len: self.index[i].size,
+            })
+            .collect()
+    }
+
+    /// Reads the bookmarks added with `add_bookmark()`, see [`Bookmark`].
+    pub fn bookmarks(&mut self) -> Result<Vec<Bookmark>> {
+        let entries: Vec<usize> = (0..self.index.len()).filter(|&i| self.index[i].chunk_id == BOOKMARK_CHUNK_ID).collect();
+        let mut bookmarks = Vec::with_capacity(entries.len());
+        for i in entries {
+            let payload = self.read_at(i)?;
+            bookmarks.extend(parse_bookmark(&payload, self.info.scale, self.info.rate));
+        }
+        Ok(bookmarks)
+    }
+
+    /// Reads the metadata of frame `index` added with `add_frame_with_meta()`, or `None` if the
+    /// frame has none.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`] if `index` is not less than `frame_count()`.
+    pub fn frame_meta(&mut self, index: usize) -> Result<Option<Vec<u8>>> {
+        let &(_, i) = self.frames.get(index).ok_or(MjpegError::FrameIndexOutOfRange)?;
+        if self.index.get(i + 1).is_none_or(|entry| entry.chunk_id != FRAME_META_CHUNK_ID) {
+            return Ok(None);
+        }
+        self.read_at(i + 1).map(Some)
+    }
+
+    /// Reads the chunks with the ID `chunk_id` written with `write_custom_chunk()`, see
+    /// [`CustomChunk`]: first those at the top level, then those in the `movi` list, each in
+    /// file order.
+    pub fn custom_chunks(&mut self, chunk_id: [u8; 4]) -> Result<Vec<CustomChunk>> {
+        let mut chunks = Vec::new();
+        for chunk in self.top_level.clone().into_iter().filter(|chunk| chunk.id == chunk_id) {
+            chunks.push(CustomChunk { placement: ChunkPlacement::Header, frame: None, data: read_payload(&mut self.reader, &chunk)? });
+        }
+        let entries: Vec<usize> = (0..self.index.len()).filter(|&i| self.index[i].chunk_id == chunk_id).collect();
+        for i in entries {
+            let frame = self.frames.partition_point(|&(_, pos)| pos < i) as u32;
+            chunks.push(CustomChunk { placement: ChunkPlacement::Movi, frame: Some(frame), data: self.read_at(i)? });
+        }
+        Ok(chunks)
+    }
+
+    /// Reads the JPEG data of frame `index`.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`] if `index` is not less than `frame_count()`,
+    /// and [`MjpegError::CorruptFrame`] if [`verify_frames`](Self::verify_frames) is enabled
+    /// and the frame is damaged.
+    pub fn read_frame(&mut self, index: usize) -> Result<Vec<u8>> {
+        let &(_, i) = self.frames.get(index).ok_or(MjpegError::FrameIndexOutOfRange)?;
+        if !self.verify {
+            return self.read_at(i);
+        }
+        match self.read_verified(i)? {
+            Some(data) => Ok(data),
+            None => Err(MjpegError::CorruptFrame(index)),
+        }
+    }
+
+    /// Reads the samples of the data stream `fourcc` added with `add_data()`, see [`DataSample`].
+    ///
+    /// Each sample gets the frame that follows it in the file, so telemetry stays aligned with
+    /// the frames even if samples are missing or come at another rate. Fails with
+    /// [`MjpegError::NoDataStream`] if the file has no such data stream.
+    pub fn data_samples(&mut self, fourcc: [u8; 4]) -> Result<Vec<DataSample>> {
+        let stream = self.info.data_streams.iter().position(|&data| data == fourcc).ok_or(MjpegError::NoDataStream(fourcc))?;
+        let chunk_id = self.data_chunk_ids[stream];
+        let entries: Vec<usize> = (0..self.index.len()).filter(|&i| self.index[i].chunk_id == chunk_id).collect();
+        let mut samples = Vec::with_capacity(entries.len());
+        for i in entries {
+            let frame = self.frames.partition_point(|&(_, pos)| pos < i) as u32;
+            samples.push(DataSample {
+                frame,
+                timestamp: frame_timestamp(frame as u64, self.info.scale, self.info.rate),
+                data: self.read_at(i)?,
+            });
+        }
+        Ok(samples)
+    }
+
+    /// Reads the frame timecodes written with
+    /// [`MjpegWriterBuilder::timecode`](crate::MjpegWriterBuilder::timecode), as pairs of the
+    /// frame number and its timecode.
+    ///
+    /// Fails with [`MjpegError::NoDataStream`] if the file has no timecode stream, and with
+    /// [`MjpegError::InvalidAvi`] if a timecode can't be parsed.
+    pub fn timecodes(&mut self) -> Result<Vec<(u32, Timecode)>> {
+        self.data_samples(TIMECODE_FOURCC)?
+            .into_iter()
+            .map(|sample| Ok((sample.frame, String::from_utf8_lossy(&sample.data).parse()?)))
+            .collect()
+    }
+
+    /// Reads the JPEG data of frame `index` into `bufs`, filling them in order, and returns its
+    /// size.
+    ///
+    /// The counterpart of the writer's vectored writes for servers that extract or replay
+    /// many frames: nothing is allocated, so the frames can go straight into pooled or
+    /// preallocated buffers. Size the buffers with [`frame_entry`](Self::frame_entry); fails
+    /// with [`MjpegError::InvalidFrameSize`] if they can't hold the frame. Otherwise like
+    /// [`read_frame`](Self::read_frame).
+    pub fn read_frame_into(&mut self, index: usize, bufs: &mut [IoSliceMut<'_>]) -> Result<usize> {
+        let &(_, pos) = self.frames.get(index).ok_or(MjpegError::FrameIndexOutOfRange)?;
+        let entry = self.index[pos];
+        let len = entry.size as usize;
+        if bufs.iter().map(|buf| buf.len()).sum::<usize>() < len {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+
+        let chunk_offset = self.movi_base(pos) + entry.offset as u64;
+        if self.verify {
+            let mut header = [0u8; 8];
+            self.reader.seek(SeekFrom::Start(chunk_offset))?;
+            self.reader.read_exact(&mut header)?;
+            if header[..4] != entry.chunk_id || le_u32(&header, 4) != entry.size {
+                return Err(MjpegError::CorruptFrame(index));
+            }
+        } else {
+            self.reader.seek(SeekFrom::Start(chunk_offset + 8))?;
+        }
+        let mut remaining = len;
+        for buf in bufs.iter_mut() {
+            let n = remaining.min(buf.len());
+            self.reader.read_exact(&mut buf[..n])?;
+            remaining -= n;
+        }
+
+        // The byte at offset `at` of the frame
+        let byte = |mut at: usize| {
+            for buf in bufs.iter() {
+                if at < buf.len() {
+                    return buf[at];
+                }
+                at -= buf.len();
+            }
+            0
+        };
+        if self.verify && !is_intact(len, byte) {
+            return Err(MjpegError::CorruptFrame(index));
+        }
+        Ok(len)
+    }
+
+    /// Reads the JPEG data of frame `index` into `buf`, replacing its contents.
+    ///
+    /// Reuses the allocation of `buf`, so a loop over the frames with one buffer only
+    /// allocates when a frame is larger than all before it. Otherwise like
+    /// [`read_frame`](Self::read_frame).
+    pub fn read_frame_to(&mut self, index: usize, buf: &mut Vec<u8>) -> Result<()> {
+        let len = self.frame_entry(index).ok_or(MjpegError::FrameIndexOutOfRange)?.size as usize;
+        buf.clear();
+        buf.resize(len, 0);
+        self.read_frame_into(index, &mut [IoSliceMut::new(buf)])?;
+        Ok(())
+    }
+
+    /// Reads frame `index` and decodes it into RGBA pixels for a preview texture, see
+    /// [`decode_rgba`](crate::decode_rgba). Enabled by the `preview` feature.
+    #[cfg(feature = "preview")]
+    pub fn read_frame_rgba(&mut self, index: usize) -> Result<crate::RgbaFrame> {
+        crate::decode_rgba(&self.read_frame(index)?)
+    }
+
+    /// Checks the structure of every frame read by `read_frame()`. Default: disabled.
+    ///
+    /// AVI files have no checksums, so a frame is considered intact if its chunk header
+    /// matches the index and its data starts with the JPEG SOI marker and ends with the EOI
+    /// marker (ignoring zero padding). Empty dropped frames are intact.
+    pub fn verify_frames(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Checks every frame and drops the damaged ones, returning their former frame numbers.
+    ///
+    /// Frames that fail the checks of [`verify_frames`](Self::verify_frames) or can't be read,
+    /// for example because the file is truncated, are removed, so `frame_count()`,
+    /// `read_frame()` and `frame_map()` only see the intact frames and playback or extraction
+    /// of a partially corrupted file continues past the damage. The frame map keeps the
+    /// original timestamps, so the damage shows as a gap.
+    pub fn skip_corrupt_frames(&mut self) -> Result<Vec<usize>> {
+        let mut corrupt = Vec::new();
+        for frame in 0..self.frames.len() {
+            match self.read_verified(self.frames[frame].1) {
+                Ok(Some(_)) => {}
+                Ok(None) | Err(MjpegError::Io(_)) => corrupt.push(frame),
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut frame = 0;
+        self.frames.retain(|_| {
+            frame += 1;
+            corrupt.binary_search(&(frame - 1)).is_err()
+        });
+        Ok(corrupt)
+    }
+
+    /// Reads the payload of the chunk described by an index entry, taken as relative to the
+    /// `movi` list of the first RIFF.
+    ///
+    /// Entries of OpenDML `RIFF AVIX` segments are relative to the `movi` list of their own
+    /// segment, which an entry doesn't record, so this returns
+    /// [`MjpegError::AmbiguousIndexEntry`] for files with such segments; read the entries of
+    /// [`index()`](Self::index) with [`read_index_entry`](Self::read_index_entry) instead,
+    /// which knows their segment.
+    pub fn read_entry(&mut self, entry: &IndexEntry) -> Result<Vec<u8>> {
+        if self.segments.len() > 1 {
+            return Err(MjpegError::AmbiguousIndexEntry);
+        }
+        self.read_payload_at(self.segments[0].1, entry)
+    }
+
+    /// Reads the payload of the chunk of entry `pos` of [`index()`](Self::index), from the RIFF
+    /// it belongs to.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`] if `pos` is not less than `index().len()`.
+    pub fn read_index_entry(&mut self, pos: usize) -> Result<Vec<u8>> {
+        if pos >= self.index.len() {
+            return Err(MjpegError::FrameIndexOutOfRange);
+        }
+        self.read_at(pos)
+    }
+
+    /// Reads the payload of the chunk at position `pos` of the index.
+    fn read_at(&mut self, pos: usize) -> Result<Vec<u8>> {
+        let entry = self.index[pos];
+        self.read_payload_at(self.movi_base(pos), &entry)
+    }
+
+    fn read_payload_at(&mut self, movi_base: u64, entry: &IndexEntry) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; entry.size as usize];
+        self.reader.seek(SeekFrom::Start(movi_base + entry.offset as u64 + 8))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// The file offset of the 'movi' list type the entry at `pos` of the index is relative to.
+    fn movi_base(&self, pos: usize) -> u64 {
+        self.segments[self.segments.partition_point(|&(start, _)| start <= pos) - 1].1
+    }
+
+    /// Reads the frame at position `pos` of the index and its chunk header, returning `None`
+    /// if the frame is damaged.
+    fn read_verified(&mut self, pos: usize) -> Result<Option<Vec<u8>>> {
+        let entry = self.index[pos];
+        let mut header = [0u8; 8];
+        self.reader.seek(SeekFrom::Start(self.movi_base(pos) + entry.offset as u64))?;
+        self.reader.read_exact(&mut header)?;
+        if header[..4] != entry.chunk_id || le_u32(&header, 4) != entry.size {
+            return Ok(None);
+        }
+
+        let mut data = vec![0u8; entry.size as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(is_intact(data.len(), |at| data[at]).then_some(data))
+    }
+
+    /// Consumes the reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Returns true if the `len` bytes of a frame, read with `byte`, are empty or start with the
+/// JPEG SOI marker and end with the EOI marker, ignoring zero padding.
+fn is_intact(len: usize, byte: impl Fn(usize) -> u8) -> bool {
+    let end = (0..len).rev().find(|&at| byte(at) != 0).map_or(0, |at| at + 1);
+    len == 0 || (end >= 4 && [byte(0), byte(1), byte(end - 2), byte(end - 1)] == [0xFF, 0xD8, 0xFF, 0xD9])
+}
+
+/// Parses the header list, returning the header fields and the stream number of the video stream.
+fn parse_hdrl<R: Read + Seek>(reader: &mut R, hdrl: &Chunk, end: u64) -> Result<(AviInfo, u8, Vec<[u8; 4]>)> {
+    let mut info = AviInfo::default();
+    let mut has_avih = false;
+    let mut has_video = false;
+    let mut video_stream = 0u8;
+    let mut stream = 0u8;
+    let mut data_chunk_ids = Vec::new();
+
+    let hdrl_end = hdrl.end().min(end);
+    let mut offset = hdrl.data_offset() + 4;
+    while let Some(chunk) = read_chunk(reader, offset, hdrl_end)? {
+        if &chunk.id == b"avih" && chunk.size >= 40 {
+            let avih = read_payload(reader, &chunk)?;
+            info.micro_sec_per_frame = le_u32(&avih, 0);
+            info.max_bytes_per_sec = le_u32(&avih, 4);
+            info.flags = le_u32(&avih, 12);
+            info.total_frames = le_u32(&avih, 16);
+            info.streams = le_u32(&avih, 24);
+            info.suggested_buffer_size = le_u32(&avih, 28);
+            info.width = le_u32(&avih, 32);
+            info.height = le_u32(&avih, 36);
+            has_avih = true;
+        } else if chunk.id == PRODUCER_CHUNK_ID {
+            info.producer = ProducerInfo::parse(&read_payload(reader, &chunk)?);
+        } else if &chunk.id == b"LIST" && &read_list_type(reader, &chunk)? == b"strl" {
+            if !has_video {
+                has_video = parse_video_strl(reader, &chunk, &mut info)?;
+                video_stream = stream;
+            }
+            if let Some(fourcc) = data_stream_fourcc(reader, &chunk)? {
+                info.data_streams.push(fourcc);
+                data_chunk_ids.push(IndexEntry::chunk_id(stream, IndexEntry::DATA));
+            }
+            stream = stream.wrapping_add(1);
+        }
+        offset = chunk.end();
+    }
+
+    if !has_avih {
+        return Err(invalid("missing avih chunk"));
+    }
+    if !has_video {
+        return Err(invalid("missing video stream header"));
+    }
+    Ok((info, video_stream, data_chunk_ids))
+}
+
+/// The `fccHandler` of a `dats` stream list, `None` for other stream types.
+fn data_stream_fourcc<R: Read + Seek>(reader: &mut R, strl: &Chunk) -> Result<Option<[u8; 4]>> {
+    let strh = read_chunk(reader, strl.data_offset() + 4, strl.end())?;
+    let Some(strh) = strh.filter(|chunk| &chunk.id == b"strh" && chunk.size >= 8) else {
+        return Ok(None);
+    };
+    let strh = read_payload(reader, &strh)?;
+    Ok((&fourcc(&strh, 0) == b"dats").then(|| fourcc(&strh, 4)))
+}
+
+/// Fills `info` from a `vids` stream list. Returns false for other stream types.
+fn parse_video_strl<R: Read + Seek>(reader: &mut R, strl: &Chunk, info: &mut AviInfo) -> Result<bool> {
+    let mut offset = strl.data_offset() + 4;
+    let mut is_video = false;
+    while let Some(chunk) = read_chunk(reader, offset, strl.end())? {
+        match &chunk.id {
+            b"strh" if chunk.size >= 48 => {
+                let strh = read_payload(reader, &chunk)?;
+                if &fourcc(&strh, 0) != b"vids" {
+                    return Ok(false);
+                }
+                info.scale = le_u32(&strh, 20);
+                info.rate = le_u32(&strh, 24);
+                info.length = le_u32(&strh, 32);
+                info.quality = le_u32(&strh, 40);
+                is_video = true;
+            }
+            b"strf" if is_video && chunk.size >= 20 => {
+                let strf = read_payload(reader, &chunk)?;
+                // biHeight may be negative for top-down bitmaps
+                info.width = le_u32(&strf, 4);
+                info.height = (le_u32(&strf, 8) as i32).unsigned_abs();
+                info.compression = fourcc(&strf, 16);
+            }
+            b"vprp" if is_video && chunk.size >= 36 => {
+                let vprp = read_payload(reader, &chunk)?;
+                let aspect = le_u32(&vprp, 20);
+                info.aspect_ratio = Some(((aspect >> 16) as u16, aspect as u16)).filter(|&(width, height)| width > 0 && height > 0);
+            }
+            _ => {}
+        }
+        offset = chunk.end();
+    }
+    Ok(is_video)
+}
+
+fn parse_index<R: Read + Seek>(reader: &mut R, index: &[u8], movi_base: u64, end: u64) -> Result<Vec<IndexEntry>> {
+    let mut entries: Vec<IndexEntry> = index
+        .chunks_exact(16)
+        .map(|entry| IndexEntry::from_bytes(entry.try_into().unwrap()))
+        .collect();
+
+    // idx1 offsets are relative to the 'movi' list type, but some writers use absolute offsets.
+    // Padded files have relative offsets beyond the list too, so check where the first one points
+    if let Some(first) = entries.first().filter(|entry| entry.offset as u64 >= movi_base) {
+        let relative = read_chunk(reader, movi_base + first.offset as u64, end)?.is_some_and(|chunk| chunk.id == first.chunk_id || chunk.is_list());
+        if !relative {
+            for entry in &mut entries {
+                entry.offset = (entry.offset as u64).saturating_sub(movi_base) as u32;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn scan_movi<R: Read + Seek>(reader: &mut R, movi: &Chunk, end: u64) -> Result<Vec<IndexEntry>> {
+    let movi_base = movi.data_offset();
+    let movi_end = movi.end().min(end);
+    let mut entries = Vec::new();
+    let mut offset = movi_base + 4;
+    while let Some(chunk) = read_chunk(reader, offset, movi_end)? {
+        if chunk.data_offset() + chunk.size as u64 > movi_end {
+            break; // Partially written chunk
+        }
+        if chunk.is_list() {
+            // Lists are indexed by their list type, like 'rec ' lists, and their chunks are indexed too
+            let list_type = read_list_type(reader, &chunk)?;
+            entries.push(IndexEntry::new(list_type, IndexEntry::LIST, (chunk.offset - movi_base) as u32, chunk.size));
+            offset = chunk.data_offset() + 4;
+        } else {
+            entries.push(IndexEntry::new(chunk.id, IndexEntry::KEYFRAME, (chunk.offset - movi_base) as u32, chunk.size));
+            offset = chunk.end();
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,108 @@
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::time::Duration;
+use crate::Result;
+use crate::builder::MjpegWriterBuilder;
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::reader::MjpegReader;
+use crate::writer::Writer;
+
+#[cfg(feature = "encode")]
+use crate::encode::FrameEncoder;
+
+/// Statistics of a [`redact`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RedactionStats {
+    /// The number of frames written unchanged.
+    pub kept: u64,
+    /// The number of frames left out.
+    pub dropped: u64,
+    /// The number of frames written re-encoded, see [`RedactionProfile::replace_with`].
+    pub replaced: u64,
+}
+
+/// The time windows [`redact`] removes from an export, such as privacy windows.
+///
+/// Frames whose timestamp falls in a window are dropped, or with the `encode` feature
+/// re-encoded through a [`FrameEncoder`] with a blurring or masking filter.
+#[derive(Debug, Default)]
+pub struct RedactionProfile {
+    windows: Vec<Range<Duration>>,
+    #[cfg(feature = "encode")]
+    encoder: Option<FrameEncoder>,
+}
+
+impl RedactionProfile {
+    /// Creates a profile without windows.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a window of timestamps to redact. The end is exclusive.
+    pub fn window(&mut self, window: Range<Duration>) -> &mut Self {
+        self.windows.push(window);
+        self
+    }
+
+    /// Re-encodes the frames in the windows with `encoder` instead of dropping them.
+    ///
+    /// The encoder's filters, such as a [`BlurFilter`](crate::BlurFilter), must keep the frame
+    /// size. Enabled by the `encode` feature.
+    #[cfg(feature = "encode")]
+    pub fn replace_with(&mut self, encoder: FrameEncoder) -> &mut Self {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Returns true if a frame at `timestamp` is redacted.
+    pub fn is_redacted(&self, timestamp: Duration) -> bool {
+        self.windows.iter().any(|window| window.contains(&timestamp))
+    }
+}
+
+/// Writes a sanitized copy of the video frames of `reader` for sharing.
+///
+/// Frames in the windows of `profile` are dropped or replaced, the others are copied as in
+/// [`remux`](crate::remux); the original file is not modified. Dropping frames shortens the
+/// copy, so replace them to keep the timeline.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{redact, MjpegReader, RedactionProfile};
+/// use std::fs::File;
+/// use std::time::Duration;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = MjpegReader::new(File::open("original.avi")?)?;
+///     let mut profile = RedactionProfile::new();
+///     profile.window(Duration::from_secs(60)..Duration::from_secs(90));
+///     let (_file, stats) = redact(&mut reader, &mut profile, File::create("shared.avi")?)?;
+///     println!("{} frames redacted", stats.dropped);
+///     Ok(())
+/// }
+/// ```
+pub fn redact<R: Read + Seek, W: Writer>(reader: &mut MjpegReader<R>, profile: &mut RedactionProfile, writer: W) -> Result<(W, RedactionStats)> {
+    let info = reader.info();
+    let fps = if info.scale == 1 { info.rate } else { info.fps().round() as u32 };
+    let mut writer = MjpegWriterBuilder::new(info.width, info.height, fps).unchecked_dimensions(true).build(writer)?;
+    let mut stats = RedactionStats::default();
+
+    for (i, entry) in reader.frame_map().into_iter().enumerate() {
+        if !profile.is_redacted(entry.timestamp) {
+            writer.add_frame(&reader.read_frame(i)?)?;
+            stats.kept += 1;
+            continue;
+        }
+
+        #[cfg(feature = "encode")]
+        if let Some(encoder) = &mut profile.encoder {
+            let frame = encoder.reencode(&reader.read_frame(i)?)?;
+            writer.add_frame(&frame.jpeg)?;
+            stats.replaced += 1;
+            continue;
+        }
+        stats.dropped += 1;
+    }
+    Ok((writer.finish()?, stats))
+}
@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::reader::MjpegReader;
+use crate::writer::{StdWriter, Writer};
+
+/// Writes the video frames of `reader` into a new file with the same dimensions and frame rate.
+///
+/// Only the video frames are copied; the headers are regenerated and other chunks are dropped.
+/// A non-integer frame rate is rounded to the nearest integer.
+pub fn remux<R: Read + Seek, W: Writer>(reader: &mut MjpegReader<R>, writer: W) -> Result<W> {
+    let info = reader.info();
+    let fps = if info.scale == 1 { info.rate } else { info.fps().round() as u32 };
+    let mut writer = MjpegWriterBuilder::new(info.width, info.height, fps).unchecked_dimensions(true).build(writer)?;
+    for i in 0..reader.frame_count() {
+        writer.add_frame(&reader.read_frame(i)?)?;
+    }
+    writer.finish()
+}
+
+/// Checks that remuxing a file produced by this crate reproduces it byte for byte.
+///
+/// The remuxed output is compared with the source while it is written, so no second copy of
+/// the file is kept in memory. Returns [`MjpegError::RemuxMismatch`] with the offset of the
+/// first differing byte otherwise. Use it as a regression check for archival pipelines:
+/// a file that passes holds exactly the frames, dimensions and frame rate of its headers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     mjpeg_avi_rs::verify_remux(File::open("archive.avi")?)?;
+///     Ok(())
+/// }
+/// ```
+pub fn verify_remux<R: Read + Seek>(source: R) -> Result<()> {
+    let source = RefCell::new(source);
+    let source_len = source.borrow_mut().seek(SeekFrom::End(0))?;
+
+    let mut reader = MjpegReader::new(Shared(&source))?;
+    let compare = CompareWriter { source: Shared(&source), pos: 0, len: 0, mismatches: BTreeSet::new() };
+    let compare = remux(&mut reader, StdWriter::new(compare))?.into_inner();
+
+    if let Some(&offset) = compare.mismatches.first() {
+        return Err(MjpegError::RemuxMismatch(offset));
+    }
+    if compare.len != source_len {
+        return Err(MjpegError::RemuxMismatch(compare.len.min(source_len)));
+    }
+    Ok(())
+}
+
+/// Lets the reader and the compare writer use the same source, each seeking before every access.
+struct Shared<'a, R>(&'a RefCell<R>);
+
+impl<R: Read> Read for Shared<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl<R: Seek> Seek for Shared<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
+
+/// The number of differing bytes after which no more are recorded; the file is different anyway.
+const MAX_MISMATCHES: usize = 4096;
+
+/// A writer that compares everything written to it with the source at the same offset.
+///
+/// Header placeholders differ until they are patched by `finish()`, so a mismatch is
+/// forgotten when the byte is overwritten with the expected value.
+struct CompareWriter<'a, R> {
+    source: Shared<'a, R>,
+    pos: u64,
+    len: u64,
+    mismatches: BTreeSet<u64>,
+}
+
+impl<R: Read + Seek> Write for CompareWriter<'_, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut expected = vec![0u8; buf.len()];
+        self.source.seek(SeekFrom::Start(self.pos))?;
+        let mut read = 0;
+        while read < expected.len() {
+            match self.source.read(&mut expected[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        let end = self.pos + buf.len() as u64;
+        let mut rest = self.mismatches.split_off(&self.pos);
+        self.mismatches.append(&mut rest.split_off(&end));
+        for (i, &byte) in buf.iter().enumerate() {
+            if self.mismatches.len() >= MAX_MISMATCHES {
+                break;
+            }
+            if i >= read || byte != expected[i] {
+                self.mismatches.insert(self.pos + i as u64);
+            }
+        }
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<R> Seek for CompareWriter<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // The writer only seeks to absolute positions to patch the headers
+        match pos {
+            SeekFrom::Start(offset) => self.pos = offset,
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "only absolute seeks are supported")),
+        }
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::Result;
+use crate::frame_map::frame_timestamp;
+use crate::pacing::{MissedTickPolicy, PacingStats, Ticker};
+use crate::reader::MjpegReader;
+
+const BOUNDARY: &str = "mjpegavirsframe";
+
+/// The `Content-Type` of the streams written by [`stream_multipart`].
+pub const MULTIPART_CONTENT_TYPE: &str = "multipart/x-mixed-replace; boundary=mjpegavirsframe";
+
+/// Writes the frames of a file as a `multipart/x-mixed-replace` MJPEG stream, which browsers
+/// show like a live camera.
+///
+/// With `realtime`, the frames are paced at the frame rate of the file; when the output is too
+/// slow, frames are dropped to keep up, as with [`MissedTickPolicy::Skip`]. Without it, the
+/// frames are written as fast as the output accepts them. The returned statistics count the
+/// written and the dropped frames.
+pub fn stream_multipart<R: Read + Seek, O: Write>(reader: &mut MjpegReader<R>, out: &mut O, realtime: bool) -> Result<PacingStats> {
+    let info = reader.info();
+    let period = frame_timestamp(1, info.scale, info.rate);
+    let mut ticker = Ticker::with_period(period, MissedTickPolicy::Skip);
+    let mut stats = PacingStats::default();
+
+    let mut frame = 0;
+    while frame < reader.frame_count() {
+        if realtime {
+            let now = Instant::now();
+            if now < ticker.next {
+                std::thread::sleep(ticker.next - now);
+            }
+        }
+
+        let jpeg = reader.read_frame(frame)?;
+        write!(out, "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", BOUNDARY, jpeg.len())?;
+        out.write_all(&jpeg)?;
+        out.write_all(b"\r\n")?;
+        out.flush()?;
+        stats.frames += 1;
+        frame += 1;
+
+        if realtime && period > Duration::ZERO {
+            let missed = ticker.advance(Instant::now());
+            stats.missed_ticks += missed;
+            frame += missed as usize;
+        }
+    }
+    Ok(stats)
+}
+
+/// A minimal HTTP server that replays the AVI files in a directory as MJPEG streams.
+///
+/// `GET /path/to/file.avi` streams the file relative to the directory with
+/// [`stream_multipart`] at its original frame rate, so recorded footage can be viewed in any
+/// browser without transcoding. Each connection is served on its own thread and closed at
+/// the end of the file. Only `.avi` files inside the directory are served; other requests
+/// get `404 Not Found`. Paths are not percent-decoded.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::ReplayServer;
+/// use std::net::TcpListener;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     // Open http://localhost:8080/cam1/0001.avi in a browser
+///     ReplayServer::new(TcpListener::bind("0.0.0.0:8080")?, "/var/recordings").run()
+/// }
+/// ```
+pub struct ReplayServer {
+    listener: TcpListener,
+    dir: PathBuf,
+}
+
+impl ReplayServer {
+    /// Creates a server for the files in `dir` that accepts connections on `listener`.
+    pub fn new<P: Into<PathBuf>>(listener: TcpListener, dir: P) -> Self {
+        ReplayServer { listener, dir: dir.into() }
+    }
+
+    /// Accepts connections until accepting fails.
+    pub fn run(self) -> Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            let dir = self.dir.clone();
+            std::thread::spawn(move || {
+                // The client closing the connection ends the stream
+                let _ = serve_connection(stream, &dir);
+            });
+        }
+    }
+}
+
+fn serve_connection(mut stream: TcpStream, dir: &Path) -> Result<()> {
+    let mut request = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    request.read_line(&mut request_line)?;
+    // Skip the request headers
+    let mut line = String::new();
+    while request.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let reader = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", target, _] => replay_path(dir, target).and_then(|path| MjpegReader::new(File::open(path).ok()?).ok()),
+        _ => None,
+    };
+    let Some(mut reader) = reader else {
+        stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+        return Ok(());
+    };
+
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n", MULTIPART_CONTENT_TYPE)?;
+    stream_multipart(&mut reader, &mut stream, true)?;
+    Ok(())
+}
+
+/// Maps a request target to an `.avi` file inside `dir`, rejecting paths that leave it.
+fn replay_path(dir: &Path, target: &str) -> Option<PathBuf> {
+    let target = target.split(['?', '#']).next()?.trim_start_matches('/');
+    let relative = Path::new(target);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    if !relative.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("avi")) {
+        return None;
+    }
+    Some(dir.join(relative))
+}
@@ -0,0 +1,211 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::lock::lock_exclusive;
+use crate::mjpeg_sync::MjpegAviWriter;
+use crate::reader::MjpegReader;
+use crate::riff::*;
+use crate::writer::Writer;
+
+/// The measured drift of the clock that paced a recording, for [`retime`] and [`resample`].
+///
+/// A camera whose clock runs fast captures more frames per real second than its nominal
+/// frame rate, so its recordings play longer than the time they cover; one that runs slow
+/// plays shorter. The drift is the playback time gained over real time, e.g. +3.2s per hour
+/// for a recording of one hour that plays for 3603.2s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockDrift {
+    /// Playback time per real time
+    ratio: f64,
+}
+
+impl ClockDrift {
+    /// A drift of `seconds` of playback time gained per hour, negative if the clock runs slow.
+    pub fn per_hour(seconds: f64) -> Self {
+        ClockDrift { ratio: (3600.0 + seconds) / 3600.0 }
+    }
+
+    /// The drift of a recording that plays for `recorded` but covers `real` time, e.g. as
+    /// measured against a reference clock.
+    pub fn between(recorded: Duration, real: Duration) -> Self {
+        ClockDrift { ratio: recorded.as_secs_f64() / real.as_secs_f64() }
+    }
+
+    /// The playback time per real time, above 1 for clocks that run fast.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// The real time covered by `recorded` playback time.
+    pub fn correct(&self, recorded: Duration) -> Duration {
+        recorded.div_f64(self.ratio)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.ratio.is_finite() && self.ratio > 0.0 {
+            Ok(())
+        } else {
+            Err(MjpegError::InvalidFrameSize)
+        }
+    }
+}
+
+/// Statistics of a [`resample`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResampleStats {
+    /// The number of frames written.
+    pub frames: u64,
+    /// The number of frames repeated as dropped frames to stretch the recording.
+    pub duplicated: u64,
+    /// The number of source frames left out to shorten the recording.
+    pub dropped: u64,
+}
+
+/// Corrects the playback duration of a recording by rewriting the frame rate in its header.
+///
+/// The video `strh` rate and the `avih` frame interval of the file at `path` are changed in
+/// place, so that the frames, unchanged, play for the real time they cover: a 30fps
+/// recording with a drift of +3.2s per hour plays at about 30.027fps. Nothing else moves, so
+/// it is quick for any file size, but the frame rate is no longer an integer, which some
+/// players and [`remux`](crate::remux) round. Audio streams keep their rate; use it for
+/// video-only recordings, or [`resample`] to keep an integer frame rate.
+///
+/// Fails with [`MjpegError::Locked`] if the file is held by a writer that opened it with
+/// [`create_locked`](crate::create_locked), and with [`MjpegError::InvalidFrameSize`] for a
+/// drift of -3600s per hour or less.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{retime, ClockDrift};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     // The camera clock gains 3.2 seconds per hour
+///     retime("gate.avi", ClockDrift::per_hour(3.2))
+/// }
+/// ```
+pub fn retime<P: AsRef<Path>>(path: P, drift: ClockDrift) -> Result<()> {
+    drift.check()?;
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    lock_exclusive(&file)?;
+    scale_frame_rate(&mut file, drift.ratio)
+}
+
+/// Multiplies the frame rate of the video stream in the header of `file` by `factor`.
+fn scale_frame_rate<F: Read + Write + Seek>(file: &mut F, factor: f64) -> Result<()> {
+    let (avih, strh) = video_headers(file)?;
+
+    let mut data = [0u8; 8];
+    file.seek(SeekFrom::Start(strh + 20))?;
+    file.read_exact(&mut data)?;
+    let (scale, rate) = (le_u32(&data, 0) as u64, le_u32(&data, 4) as u64);
+    if scale == 0 || rate == 0 {
+        return Err(invalid("the video stream has no frame rate"));
+    }
+
+    // Refine the scale as far as u32 allows for the fraction of the new rate
+    let new_rate = rate as f64 * factor;
+    let refine = (u32::MAX as f64 / new_rate.max(scale as f64)).clamp(1.0, 1_000_000.0) as u64;
+    let (scale, rate) = (scale * refine, ((new_rate * refine as f64).round() as u64).max(1));
+    let divisor = gcd(scale, rate);
+    let (scale, rate) = ((scale / divisor) as u32, (rate / divisor) as u32);
+    let micro_sec_per_frame = (1_000_000.0 * scale as f64 / rate as f64).round() as u32;
+
+    file.seek(SeekFrom::Start(strh + 20))?;
+    file.write_all(&[scale.to_le_bytes(), rate.to_le_bytes()].concat())?;
+    file.seek(SeekFrom::Start(avih))?;
+    file.write_all(&micro_sec_per_frame.to_le_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes a copy of the video frames of `reader` whose playback duration matches the real
+/// time they cover, keeping the frame rate.
+///
+/// Frames are dropped from recordings that play too long and repeated in ones that play too
+/// short, spread evenly over the recording. Repeats are written as dropped frames (see
+/// [`MjpegWriter::add_dropped_frame`](crate::MjpegWriter::add_dropped_frame)), which players
+/// show as the previous frame without storing it again. As in [`remux`](crate::remux), other
+/// chunks are left out and a non-integer frame rate is rounded. Fails with
+/// [`MjpegError::InvalidFrameSize`] for a drift of -3600s per hour or less.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{resample, ClockDrift, MjpegReader};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = MjpegReader::new(File::open("gate.avi")?)?;
+///     let (_file, stats) = resample(&mut reader, ClockDrift::per_hour(-1.5), File::create("gate_fixed.avi")?)?;
+///     println!("{} frames repeated", stats.duplicated);
+///     Ok(())
+/// }
+/// ```
+pub fn resample<R: Read + Seek, W: Writer>(reader: &mut MjpegReader<R>, drift: ClockDrift, writer: W) -> Result<(W, ResampleStats)> {
+    drift.check()?;
+    let info = reader.info();
+    let fps = if info.scale == 1 { info.rate } else { info.fps().round() as u32 };
+    let mut writer = MjpegWriterBuilder::new(info.width, info.height, fps).unchecked_dimensions(true).build(writer)?;
+    let mut stats = ResampleStats::default();
+
+    let source_frames = reader.frame_count();
+    let frames = (source_frames as f64 / drift.ratio).round() as usize;
+    let mut previous = None;
+    for frame in 0..frames {
+        // The source frame shown at the same point of the recording
+        let source = ((frame as f64 * drift.ratio).round() as usize).min(source_frames - 1);
+        if previous == Some(source) {
+            writer.add_dropped_frame()?;
+            stats.duplicated += 1;
+        } else {
+            writer.add_frame(&reader.read_frame(source)?)?;
+        }
+        stats.dropped += (source - previous.map_or(0, |previous| previous + 1).min(source)) as u64;
+        previous = Some(source);
+        stats.frames += 1;
+    }
+    stats.dropped += (source_frames - previous.map_or(0, |previous| previous + 1)) as u64;
+    Ok((writer.finish()?, stats))
+}
+
+/// The file offsets of the `avih` payload and the `strh` payload of the first video stream.
+fn video_headers<R: Read + Seek>(reader: &mut R) -> Result<(u64, u64)> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let riff = read_chunk(reader, 0, file_len)?.ok_or_else(|| invalid("file is too short"))?;
+    if &riff.id != b"RIFF" || &read_list_type(reader, &riff)? != b"AVI " {
+        return Err(invalid("not a RIFF AVI file"));
+    }
+    let hdrl = read_chunk(reader, riff.data_offset() + 4, riff.end_within(file_len))?
+        .filter(|chunk| chunk.is_list())
+        .ok_or_else(|| invalid("missing hdrl list"))?;
+    if &read_list_type(reader, &hdrl)? != b"hdrl" {
+        return Err(invalid("missing hdrl list"));
+    }
+
+    let hdrl_end = hdrl.end().min(file_len);
+    let (mut avih, mut strh) = (None, None);
+    let mut offset = hdrl.data_offset() + 4;
+    while let Some(chunk) = read_chunk(reader, offset, hdrl_end)? {
+        if &chunk.id == b"avih" && chunk.size >= 40 {
+            avih = Some(chunk.data_offset());
+        } else if strh.is_none() && &chunk.id == b"LIST" && &read_list_type(reader, &chunk)? == b"strl" {
+            strh = read_chunk(reader, chunk.data_offset() + 4, chunk.end().min(hdrl_end))?
+                .filter(|header| &header.id == b"strh" && header.size >= 48)
+                .filter(|header| read_payload(reader, header).is_ok_and(|data| &data[..4] == b"vids"))
+                .map(|header| header.data_offset());
+        }
+        offset = chunk.end();
+    }
+    Ok((avih.ok_or_else(|| invalid("missing avih chunk"))?, strh.ok_or_else(|| invalid("missing video stream header"))?))
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
@@ -0,0 +1,89 @@
+use std::io::{Read, Seek, SeekFrom};
+use crate::{MjpegError, Result};
+
+/// A RIFF chunk header located in a file.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Chunk {
+    pub(crate) id: [u8; 4],
+    pub(crate) size: u32,
+    /// File offset of the chunk header
+    pub(crate) offset: u64,
+}
+
+impl Chunk {
+    pub(crate) fn data_offset(&self) -> u64 {
+        self.offset + 8
+    }
+
+    /// File offset of the next chunk (chunks are padded to even sizes)
+    pub(crate) fn end(&self) -> u64 {
+        self.data_offset() + self.size as u64 + (self.size & 1) as u64
+    }
+
+    /// Like `end()`, but a list with a zero size (not yet patched by finish()) runs to `limit`.
+    pub(crate) fn end_within(&self, limit: u64) -> u64 {
+        if self.is_list() && self.size == 0 {
+            limit
+        } else {
+            self.end().min(limit)
+        }
+    }
+
+    pub(crate) fn is_list(&self) -> bool {
+        &self.id == b"RIFF" || &self.id == b"LIST"
+    }
+}
+
+/// Reads the chunk header at `offset`, or `None` if it would extend past `end`.
+pub(crate) fn read_chunk<R: Read + Seek>(reader: &mut R, offset: u64, end: u64) -> Result<Option<Chunk>> {
+    if offset + 8 > end {
+        return Ok(None);
+    }
+    let mut header = [0u8; 8];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut header)?;
+    Ok(Some(Chunk {
+        id: fourcc(&header, 0),
+        size: le_u32(&header, 4),
+        offset,
+    }))
+}
+
+/// Reads the 4-byte list type following a `RIFF`/`LIST` chunk header.
+pub(crate) fn read_list_type<R: Read + Seek>(reader: &mut R, chunk: &Chunk) -> Result<[u8; 4]> {
+    let mut list_type = [0u8; 4];
+    reader.seek(SeekFrom::Start(chunk.data_offset()))?;
+    reader.read_exact(&mut list_type)?;
+    Ok(list_type)
+}
+
+/// Reads the whole payload of a chunk.
+pub(crate) fn read_payload<R: Read + Seek>(reader: &mut R, chunk: &Chunk) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; chunk.size as usize];
+    reader.seek(SeekFrom::Start(chunk.data_offset()))?;
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+pub(crate) fn le_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+pub(crate) fn le_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+pub(crate) fn fourcc(buf: &[u8], at: usize) -> [u8; 4] {
+    [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]]
+}
+
+/// Formats a fourcc for messages, escaping non-printable bytes.
+pub(crate) fn fourcc_str(id: &[u8; 4]) -> String {
+    id.iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { (b as char).to_string() } else { format!("\\x{:02x}", b) })
+        .collect()
+}
+
+pub(crate) fn invalid(msg: &str) -> MjpegError {
+    MjpegError::InvalidAvi(msg.to_string())
+}
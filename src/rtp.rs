@@ -0,0 +1,323 @@
+use std::io::{BufRead, Read};
+use crate::{MjpegError, Result};
+use crate::quality::{STANDARD_LUMA_TABLE, ZIGZAG};
+use crate::source::FrameSource;
+
+/// The chrominance quantization table of the JPEG standard (Annex K), which RFC 2435 scales for
+/// Q values below 128.
+const STANDARD_CHROMA_TABLE: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// The Huffman tables of the JPEG standard (Annex K) the scan data of RTP/JPEG is coded with, as
+/// table class and ID, code counts per length and values.
+const STANDARD_HUFFMAN_TABLES: [(u8, &[u8; 16], &[u8]); 4] = [
+    (0x00, &[0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+    (0x01, &[0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+    (0x10, &[0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D], &[
+        0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+        0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+        0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+        0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+        0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+        0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+        0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+        0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+        0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+        0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+        0xF9, 0xFA,
+    ]),
+    (0x11, &[0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77], &[
+        0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+        0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+        0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+        0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+        0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+        0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+        0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+        0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+        0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+        0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+        0xF9, 0xFA,
+    ]),
+];
+
+/// A [`FrameSource`] that reassembles the JPEG frames of an RTP/JPEG stream (RFC 2435) read
+/// from RTSP interleaved framing (`$`, channel, 16-bit length, packet), as sent over the TCP
+/// connection of an RTSP session.
+///
+/// Enabled by the `rtsp` feature. The JPEG headers RTP/JPEG leaves out are rebuilt for every
+/// frame: the quantization tables sent in band, or scaled from the standard tables by the Q
+/// value, the frame size, the restart interval and the standard Huffman tables. Packets of
+/// other channels, such as RTCP, and RTSP messages between packets are skipped. A frame with a
+/// lost packet is dropped and counted in [`lost_frames`](Self::lost_frames), so the recording
+/// continues with the next one. [`RtspSource`](crate::RtspSource) uses it after setting up a
+/// session; use it directly to record a captured stream.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameSource, MjpegAviWriter, MjpegWriter, RtpJpegSource};
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut source = RtpJpegSource::new(BufReader::new(File::open("capture.rtp")?), 0);
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 25)?;
+///     while let Some(frame) = source.next_frame()? {
+///         writer.add_frame(&frame)?;
+///     }
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+pub struct RtpJpegSource<R> {
+    reader: R,
+    channel: u8,
+    packet: Vec<u8>,
+    depacketizer: Depacketizer,
+}
+
+impl<R: BufRead> RtpJpegSource<R> {
+    /// Creates a source reading the RTP packets of interleaved channel `channel` from `reader`.
+    pub fn new(reader: R, channel: u8) -> Self {
+        RtpJpegSource { reader, channel, packet: Vec::new(), depacketizer: Depacketizer::default() }
+    }
+
+    /// The number of frames dropped because of lost or out-of-order packets.
+    pub fn lost_frames(&self) -> u64 {
+        self.depacketizer.lost
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next packet of the channel into `self.packet`, returning `false` at the end
+    /// of the stream.
+    fn next_packet(&mut self) -> Result<bool> {
+        loop {
+            let mut byte = [0u8; 1];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(false);
+            }
+            if byte[0] != b'$' {
+                // An RTSP message, such as the answer to a keep-alive request
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    if self.reader.read_line(&mut line)? == 0 {
+                        return Ok(false);
+                    }
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((_, value)) = line.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case("content-length")) {
+                        length = value.trim().parse().map_err(|_| MjpegError::Io(format!("invalid Content-Length in RTSP message: {}", value.trim())))?;
+                    }
+                }
+                std::io::copy(&mut self.reader.by_ref().take(length), &mut std::io::sink())?;
+                continue;
+            }
+            let mut header = [0u8; 3];
+            self.reader.read_exact(&mut header)?;
+            self.packet.resize(u16::from_be_bytes([header[1], header[2]]) as usize, 0);
+            self.reader.read_exact(&mut self.packet)?;
+            if header[0] == self.channel {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> FrameSource for RtpJpegSource<R> {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        while self.next_packet()? {
+            if let Some(frame) = self.depacketizer.push(&self.packet)? {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Reassembles JPEG frames from RTP/JPEG packets.
+#[derive(Default)]
+struct Depacketizer {
+    /// The headers and scan data of the frame being reassembled
+    frame: Vec<u8>,
+    /// The size of the rebuilt headers at the start of `frame`
+    header_len: usize,
+    /// The RTP timestamp of the frame being reassembled, `None` after a lost packet until the
+    /// next frame starts
+    timestamp: Option<u32>,
+    next_seq: u16,
+    /// The RTP timestamp of the last frame dropped, whose remaining packets are skipped
+    dropped: Option<u32>,
+    /// The quantization tables of Q values 128 to 254 sent in band, which later frames can omit
+    tables: Vec<(u8, Vec<u8>)>,
+    lost: u64,
+}
+
+impl Depacketizer {
+    /// Adds an RTP packet and returns the frame it completes, if any.
+    ///
+    /// Returns [`MjpegError::Codec`] for JPEG types other than the 4:2:2 and 4:2:0 ones RFC
+    /// 2435 defines, which no frame can be rebuilt for.
+    fn push(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(rtp) = parse_rtp(packet) else {
+            return Ok(None);
+        };
+        let payload = rtp.payload;
+        if payload.len() < 8 {
+            return Ok(None);
+        }
+        let offset = u32::from_be_bytes([0, payload[1], payload[2], payload[3]]) as usize;
+        let (kind, q, width, height) = (payload[4], payload[5], payload[6] as u16 * 8, payload[7] as u16 * 8);
+        let mut data = &payload[8..];
+        let mut restart_interval = 0;
+        if (64..128).contains(&kind) {
+            let Some(header) = data.get(..4) else { return Ok(None) };
+            restart_interval = u16::from_be_bytes([header[0], header[1]]);
+            data = &data[4..];
+        }
+
+        if offset == 0 {
+            if self.timestamp.is_some() {
+                // The last packet of the previous frame was lost
+                self.lost += 1;
+            }
+            if kind & 63 > 1 {
+                return Err(MjpegError::Codec(format!("unsupported RTP/JPEG type {}", kind)));
+            }
+            let tables = if q >= 128 {
+                let Some(header) = data.get(..4) else { return Ok(None) };
+                let (precision, len) = (header[1], u16::from_be_bytes([header[2], header[3]]) as usize);
+                let Some(tables) = data.get(4..4 + len) else { return Ok(None) };
+                data = &data[4 + len..];
+                if len > 0 && q < 255 {
+                    self.tables.retain(|(cached, _)| *cached != q);
+                    self.tables.push((q, [&[precision], tables].concat()));
+                }
+                match self.tables.iter().find(|(cached, _)| *cached == q) {
+                    Some((_, cached)) if len == 0 => cached.clone(),
+                    _ if len == 0 => {
+                        self.lost += 1;
+                        self.timestamp = None;
+                        self.dropped = Some(rtp.timestamp);
+                        return Ok(None);
+                    }
+                    _ => [&[precision], tables].concat(),
+                }
+            } else {
+                scaled_tables(q)
+            };
+            self.frame.clear();
+            write_headers(&mut self.frame, kind & 1, width, height, restart_interval, &tables);
+            self.header_len = self.frame.len();
+            self.timestamp = Some(rtp.timestamp);
+        } else if self.timestamp != Some(rtp.timestamp) || rtp.sequence != self.next_seq || offset != self.frame.len() - self.header_len {
+            if self.timestamp.take().is_some_and(|timestamp| timestamp != rtp.timestamp) {
+                // The last packet of the previous frame was lost too
+                self.lost += 1;
+            }
+            if self.dropped != Some(rtp.timestamp) {
+                self.dropped = Some(rtp.timestamp);
+                self.lost += 1;
+            }
+            return Ok(None);
+        }
+        self.frame.extend_from_slice(data);
+        self.next_seq = rtp.sequence.wrapping_add(1);
+        if !rtp.marker {
+            return Ok(None);
+        }
+        self.timestamp = None;
+        if !self.frame.ends_with(&[0xFF, 0xD9]) {
+            self.frame.extend_from_slice(&[0xFF, 0xD9]);
+        }
+        Ok(Some(std::mem::take(&mut self.frame)))
+    }
+}
+
+/// The fields of an RTP packet the depacketizer uses.
+struct RtpPacket<'a> {
+    marker: bool,
+    sequence: u16,
+    timestamp: u32,
+    payload: &'a [u8],
+}
+
+/// Parses an RTP packet (RFC 3550), skipping CSRCs, the header extension and padding.
+fn parse_rtp(packet: &[u8]) -> Option<RtpPacket<'_>> {
+    if packet.len() < 12 || packet[0] >> 6 != 2 {
+        return None;
+    }
+    let mut start = 12 + 4 * (packet[0] & 0x0F) as usize;
+    if packet[0] & 0x10 != 0 {
+        let extension = packet.get(start + 2..start + 4)?;
+        start += 4 + 4 * u16::from_be_bytes([extension[0], extension[1]]) as usize;
+    }
+    let padding = if packet[0] & 0x20 != 0 { *packet.last()? as usize } else { 0 };
+    Some(RtpPacket {
+        marker: packet[1] & 0x80 != 0,
+        sequence: u16::from_be_bytes([packet[2], packet[3]]),
+        timestamp: u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]),
+        payload: packet.get(start..packet.len().checked_sub(padding)?)?,
+    })
+}
+
+/// The luminance and chrominance tables of Q values 1 to 99, in zigzag order and prefixed with
+/// the precision byte of in-band tables (RFC 2435 Appendix A).
+fn scaled_tables(q: u8) -> Vec<u8> {
+    let factor = q.clamp(1, 99) as u32;
+    let scale = if factor < 50 { 5000 / factor } else { 200 - factor * 2 };
+    let mut tables = vec![0];
+    for table in [STANDARD_LUMA_TABLE, STANDARD_CHROMA_TABLE] {
+        tables.extend(ZIGZAG.iter().map(|&pos| ((table[pos] as u32 * scale + 50) / 100).clamp(1, 255) as u8));
+    }
+    tables
+}
+
+/// Writes the headers of a baseline JPEG frame up to its scan data (RFC 2435 Appendix B):
+/// `subsampling` 0 for 4:2:2 and 1 for 4:2:0, and `tables` the precision byte followed by the
+/// quantization tables in zigzag order.
+fn write_headers(out: &mut Vec<u8>, subsampling: u8, width: u16, height: u16, restart_interval: u16, tables: &[u8]) {
+    let segment = |out: &mut Vec<u8>, marker: u8, payload: &[u8]| {
+        out.extend_from_slice(&[0xFF, marker]);
+        out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        out.extend_from_slice(payload);
+    };
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let (precision, mut tables) = tables.split_first().map_or((0, &[][..]), |(&precision, tables)| (precision, tables));
+    let mut id = 0;
+    while !tables.is_empty() {
+        let wide = precision >> id & 1 != 0;
+        let len = if wide { 128 } else { 64 }.min(tables.len());
+        segment(out, 0xDB, &[&[(wide as u8) << 4 | id][..], &tables[..len]].concat());
+        tables = &tables[len..];
+        id += 1;
+    }
+    if restart_interval > 0 {
+        segment(out, 0xDD, &restart_interval.to_be_bytes());
+    }
+    let [h0, h1] = height.to_be_bytes();
+    let [w0, w1] = width.to_be_bytes();
+    let luma_sampling = if subsampling == 1 { 0x22 } else { 0x21 };
+    // The chroma components use table 1 if there is one
+    let chroma_table = (id > 1) as u8;
+    segment(out, 0xC0, &[8, h0, h1, w0, w1, 3, 0, luma_sampling, 0, 1, 0x11, chroma_table, 2, 0x11, chroma_table]);
+    for (class_id, counts, values) in STANDARD_HUFFMAN_TABLES {
+        segment(out, 0xC4, &[&[class_id][..], counts, values].concat());
+    }
+    segment(out, 0xDA, &[3, 0, 0x00, 1, 0x11, 2, 0x11, 0, 63, 0]);
+}
@@ -0,0 +1,236 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use crate::{MjpegError, Result};
+use crate::rtp::RtpJpegSource;
+use crate::source::FrameSource;
+
+/// The session timeout RTSP servers use if they don't announce one (RFC 2326, 12.37).
+const DEFAULT_SESSION_TIMEOUT: u64 = 60;
+
+/// A [`FrameSource`] that plays the Motion JPEG stream of an RTSP camera.
+///
+/// Enabled by the `rtsp` feature. A minimal RTSP 1.0 client: [`connect`](Self::connect) sends
+/// `DESCRIBE`, picks the first JPEG video stream of the SDP (payload type 26 or a
+/// `JPEG/90000` rtpmap), then `SETUP` and `PLAY` with RTP interleaved in the TCP connection,
+/// which passes firewalls and NAT and loses no packets to UDP. Frames are reassembled by
+/// [`RtpJpegSource`]. An `OPTIONS` request keeps the session alive every half session timeout
+/// while frames arrive. Authentication isn't supported, so URLs with credentials are rejected;
+/// most cameras can allow anonymous RTSP access.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FrameSource, MjpegAviWriter, MjpegWriter, RtspSource};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut camera = RtspSource::connect("rtsp://192.168.0.10/mjpeg")?;
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 1280, 720, 15)?;
+///     for _ in 0..15 * 60 {
+///         match camera.next_frame()? {
+///             Some(frame) => writer.add_frame(&frame)?,
+///             None => break,
+///         }
+///     }
+///     writer.finish()?;
+///     camera.teardown()
+/// }
+/// ```
+pub struct RtspSource {
+    connection: Connection,
+    source: RtpJpegSource<BufReader<TcpStream>>,
+    url: String,
+    session: String,
+    keepalive: Duration,
+    last_request: Instant,
+}
+
+impl RtspSource {
+    /// Connects to `url` (`rtsp://host[:port]/path`, port 554 by default) and starts playing
+    /// its JPEG video stream.
+    ///
+    /// Returns [`MjpegError::Io`] if the URL is invalid or holds credentials, or if the server
+    /// refuses a request, and [`MjpegError::Codec`] if the session has no JPEG video stream.
+    pub fn connect(url: &str) -> Result<Self> {
+        let invalid = || MjpegError::Io(format!("invalid RTSP URL: {}", url));
+        let rest = url.strip_prefix("rtsp://").ok_or_else(invalid)?;
+        let authority = &rest[..rest.find('/').unwrap_or(rest.len())];
+        if authority.is_empty() || authority.contains('@') {
+            return Err(invalid());
+        }
+        let address = if authority.rsplit_once(':').is_some_and(|(_, port)| !port.contains(']')) {
+            authority.to_string()
+        } else {
+            format!("{}:554", authority)
+        };
+        let mut connection = Connection::open(&address)?;
+
+        let describe = connection.request("DESCRIBE", url, &[("Accept", "application/sdp")])?;
+        let base = describe.header("Content-Base").unwrap_or(url).to_string();
+        let control = jpeg_control(&describe.body).ok_or_else(|| MjpegError::Codec(format!("no JPEG video stream in the session of {}", url)))?;
+        let track = match control {
+            Some(control) if control.contains("://") => control.to_string(),
+            Some(control) if control != "*" => format!("{}/{}", base.trim_end_matches('/'), control),
+            _ => base.clone(),
+        };
+
+        let setup = connection.request("SETUP", &track, &[("Transport", "RTP/AVP/TCP;unicast;interleaved=0-1")])?;
+        let session_header = setup.header("Session").ok_or_else(|| MjpegError::Io(format!("SETUP {} returned no Session", track)))?;
+        let mut params = session_header.split(';').map(str::trim);
+        let session = params.next().unwrap_or_default().to_string();
+        let timeout = params
+            .find_map(|param| param.strip_prefix("timeout="))
+            .and_then(|timeout| timeout.parse().ok())
+            .unwrap_or(DEFAULT_SESSION_TIMEOUT);
+        let channel = setup
+            .header("Transport")
+            .and_then(|transport| transport.split(';').find_map(|param| param.trim().strip_prefix("interleaved=")))
+            .and_then(|channels| channels.split('-').next()?.parse().ok())
+            .unwrap_or(0);
+
+        // The base URL controls the whole session, which some servers require for PLAY
+        connection.request("PLAY", &base, &[("Session", &session), ("Range", "npt=0.000-")])?;
+        let reader = connection.reader.take().expect("the reader is only taken once");
+        Ok(RtspSource {
+            connection,
+            source: RtpJpegSource::new(reader, channel),
+            url: base,
+            session,
+            keepalive: Duration::from_secs(timeout.max(2) / 2),
+            last_request: Instant::now(),
+        })
+    }
+
+    /// The number of frames dropped because of lost or out-of-order packets.
+    pub fn lost_frames(&self) -> u64 {
+        self.source.lost_frames()
+    }
+
+    /// Ends the session with `TEARDOWN` and closes the connection.
+    pub fn teardown(mut self) -> Result<()> {
+        let url = std::mem::take(&mut self.url);
+        self.connection.send("TEARDOWN", &url, &[("Session", &self.session)])
+    }
+}
+
+impl FrameSource for RtspSource {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.last_request.elapsed() >= self.keepalive {
+            // The answer is skipped by the RTP source between packets
+            self.connection.send("OPTIONS", &self.url, &[("Session", &self.session)])?;
+            self.last_request = Instant::now();
+        }
+        self.source.next_frame()
+    }
+}
+
+/// The control URL of the first JPEG video stream of an SDP session description: `Some(None)`
+/// if the stream has no `a=control` attribute, `None` if there is no such stream.
+fn jpeg_control(sdp: &str) -> Option<Option<&str>> {
+    let mut media: Option<(Vec<&str>, bool, Option<&str>)> = None;
+    let is_jpeg = |media: &Option<(Vec<&str>, bool, Option<&str>)>| media.as_ref().is_some_and(|(formats, jpeg, _)| *jpeg || formats.contains(&"26"));
+    for line in sdp.lines().map(str::trim) {
+        if let Some(description) = line.strip_prefix("m=") {
+            if is_jpeg(&media) {
+                break;
+            }
+            let mut fields = description.split_whitespace();
+            media = (fields.next() == Some("video")).then(|| (fields.skip(2).collect(), false, None));
+        } else if let Some((formats, jpeg, control)) = &mut media {
+            if let Some(map) = line.strip_prefix("a=rtpmap:") {
+                let (format, encoding) = map.split_once(' ').unwrap_or((map, ""));
+                *jpeg |= formats.contains(&format) && encoding.trim().eq_ignore_ascii_case("JPEG/90000");
+            } else if let Some(url) = line.strip_prefix("a=control:") {
+                *control = Some(url.trim());
+            }
+        }
+    }
+    match media {
+        Some((_, _, control)) if is_jpeg(&media) => Some(control),
+        _ => None,
+    }
+}
+
+/// The RTSP connection of a session.
+struct Connection {
+    stream: TcpStream,
+    /// The reading half, handed to the RTP source once playing
+    reader: Option<BufReader<TcpStream>>,
+    cseq: u32,
+}
+
+/// The answer to an RTSP request.
+struct Response {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+impl Connection {
+    fn open(address: &str) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Connection { stream, reader: Some(reader), cseq: 0 })
+    }
+
+    /// Sends a request without waiting for the answer.
+    fn send(&mut self, method: &str, url: &str, headers: &[(&str, &str)]) -> Result<()> {
+        self.cseq += 1;
+        let mut request = format!("{} {} RTSP/1.0\r\nCSeq: {}\r\nUser-Agent: mjpeg-avi-rs/{}\r\n", method, url, self.cseq, env!("CARGO_PKG_VERSION"));
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        self.stream.write_all(request.as_bytes())?;
+        Ok(())
+    }
+
+    /// Sends a request and reads the answer, failing unless it is a success.
+    fn request(&mut self, method: &str, url: &str, headers: &[(&str, &str)]) -> Result<Response> {
+        self.send(method, url, headers)?;
+        let reader = self.reader.as_mut().expect("requests are answered before playing");
+        let closed = || MjpegError::Io(format!("{} {} failed: connection closed", method, url));
+        let mut status = String::new();
+        loop {
+            // Skip RTP packets some servers send before answering
+            if reader.fill_buf()?.first() == Some(&b'$') {
+                let mut header = [0u8; 4];
+                reader.read_exact(&mut header)?;
+                std::io::copy(&mut reader.by_ref().take(u16::from_be_bytes([header[2], header[3]]) as u64), &mut std::io::sink())?;
+                continue;
+            }
+            if reader.read_line(&mut status)? == 0 {
+                return Err(closed());
+            }
+            if !status.trim().is_empty() {
+                break;
+            }
+            status.clear();
+        }
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(closed());
+            }
+            let Some((name, value)) = line.trim_end().split_once(':') else { break };
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        let mut response = Response { headers, body: String::new() };
+        let length: u64 = response.header("Content-Length").and_then(|length| length.parse().ok()).unwrap_or(0);
+        reader.take(length).read_to_string(&mut response.body)?;
+
+        let status = status.trim();
+        let success = status.split_whitespace().nth(1).is_some_and(|code| code.starts_with('2'));
+        if !status.starts_with("RTSP/") || !success {
+            return Err(MjpegError::Io(format!("{} {} failed: {}", method, url, status)));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,547 @@
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::{MjpegError, Result};
+use crate::range::http_error;
+use crate::sha256::{to_hex, Sha256};
+use crate::spill::{SpillOutput, SpillWriter};
+use crate::target::{StorageTarget, StoredSegment};
+use crate::timing::{civil_time, rfc3339};
+
+/// The payload hash sent instead of hashing the uploads, which S3 accepts over HTTPS.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+/// The SHA-256 of an empty payload.
+const EMPTY_PAYLOAD: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+/// The CRC-32 lookup table of the reflected polynomial 0xEDB88320.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// A [`StorageTarget`] that uploads segments to an S3-compatible object store.
+///
+/// Enabled by the `http` feature. Segments are recorded into a [`SpillWriter`], in memory up to
+/// 64MB by default and in a temporary file beyond, and uploaded with a single `PUT` by
+/// [`finalize`](StorageTarget::finalize). Requests are signed with AWS Signature Version 4 and
+/// use path-style URLs (`{endpoint}/{bucket}/{prefix}{name}`), which MinIO, Ceph and AWS all
+/// accept. [`open_read`](StorageTarget::open_read) downloads the whole object. As with
+/// [`HttpSource`](crate::HttpSource), enable one of reqwest's TLS features for HTTPS endpoints.
+///
+/// Segments recorded to local storage first can be uploaded later with
+/// [`upload_file`](Self::upload_file), in parts that resume after an interruption.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{catalog_target, S3Target};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut target = S3Target::new("https://s3.eu-west-1.amazonaws.com", "recordings", "eu-west-1", "AKIA...", "secret");
+///     target.prefix("cam1/");
+///     for entry in catalog_target(&mut target)? {
+///         println!("{} {:?}", entry.path.display(), entry.duration);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct S3Target {
+    client: reqwest::blocking::Client,
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    prefix: String,
+    memory_cap: usize,
+    part_size: usize,
+    resume_dir: Option<PathBuf>,
+}
+
+impl S3Target {
+    /// Creates a target for `bucket` at `endpoint`, e.g. `https://s3.us-east-1.amazonaws.com`
+    /// or `http://localhost:9000`, signing requests for `region` with the given access key.
+    pub fn new<E, B, R, A, S>(endpoint: E, bucket: B, region: R, access_key: A, secret_key: S) -> Self
+    where
+        E: Into<String>,
+        B: Into<String>,
+        R: Into<String>,
+        A: Into<String>,
+        S: Into<String>,
+    {
+        S3Target {
+            client: reqwest::blocking::Client::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            bucket: bucket.into(),
+            region: region.into(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            prefix: String::new(),
+            memory_cap: 64 << 20,
+            part_size: 8 << 20,
+            resume_dir: None,
+        }
+    }
+
+    /// Sends the requests with `client`, e.g. one with timeouts.
+    pub fn client(&mut self, client: reqwest::blocking::Client) -> &mut Self {
+        self.client = client;
+        self
+    }
+
+    /// Stores the segments under `prefix`, e.g. `cam1/`. [`list`](StorageTarget::list) only
+    /// reports objects under it, with names relative to it. Default: none.
+    pub fn prefix<P: Into<String>>(&mut self, prefix: P) -> &mut Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets the bytes of a segment kept in memory before it spills to a temporary file.
+    pub fn memory_cap(&mut self, bytes: usize) -> &mut Self {
+        self.memory_cap = bytes;
+        self
+    }
+
+    /// Sets the size of the parts of [`upload_file`](Self::upload_file). S3 requires at least
+    /// 5MB for every part but the last. Default: 8MB.
+    pub fn part_size(&mut self, bytes: usize) -> &mut Self {
+        self.part_size = bytes.max(1);
+        self
+    }
+
+    /// Keeps the progress of [`upload_file`](Self::upload_file) in `dir`: the upload ID and
+    /// the offset, size, ETag and CRC-32 of every uploaded part, saved after each part. An
+    /// upload that was interrupted, even by a restart of the process, then continues with the
+    /// missing parts when `upload_file()` is called again for the same name. An upload the
+    /// store no longer has, because it expired or was aborted, starts over. Default: none, so
+    /// an interrupted upload starts over.
+    pub fn resume_dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.resume_dir = Some(dir.into());
+        self
+    }
+
+    /// Uploads the finished segment at `path` as `name` with a multipart upload.
+    ///
+    /// The parts carry their CRC-32 checksum, which the store checks, and with a
+    /// [`resume_dir`](Self::resume_dir) the parts uploaded before an interruption are
+    /// skipped if the local file still has the same data there. Once all parts are uploaded,
+    /// the checksum of the assembled object reported by the store is compared with the one
+    /// of the local file, and a mismatch is returned as [`MjpegError::Io`]. The store must
+    /// support CRC-32 checksums, as AWS S3 and MinIO do.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mjpeg_avi_rs::S3Target;
+    ///
+    /// fn main() -> mjpeg_avi_rs::Result<()> {
+    ///     let mut target = S3Target::new("https://s3.eu-west-1.amazonaws.com", "recordings", "eu-west-1", "AKIA...", "secret");
+    ///     target.prefix("cam1/").resume_dir("/var/lib/recorder/uploads");
+    ///     // Called again after a failure or a reboot, it resumes where it stopped
+    ///     target.upload_file("0001.avi", "/var/recordings/cam1/0001.avi")
+    /// }
+    /// ```
+    pub fn upload_file<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<()> {
+        let key = format!("{}{}", self.prefix, name);
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let state_path = self.upload_state_path(&key);
+        let saved = match &state_path {
+            Some(path) => UploadState::load(path)?,
+            None => None,
+        };
+        let mut upload = match saved {
+            // Stores drop uploads that expire or are aborted, answering 404 NoSuchUpload
+            Some(upload) if upload.size == size && self.send_for_upload(reqwest::Method::GET, &key, &upload.upload_id)?.is_some() => upload,
+            saved => {
+                if let Some(stale) = saved {
+                    // The file changed since, or the store dropped the upload; its parts are of no use
+                    let _ = self.send_for_upload(reqwest::Method::DELETE, &key, &stale.upload_id);
+                    if let Some(path) = &state_path {
+                        fs::remove_file(path)?;
+                    }
+                }
+                let xml = self.send(reqwest::Method::POST, Some(&key), &[("uploads", "")], &[("x-amz-checksum-algorithm", "CRC32")], None)?.text().map_err(http_error)?;
+                let upload_id = xml_value(&xml, "UploadId").ok_or_else(|| MjpegError::Io(format!("no upload ID for {}", key)))?;
+                UploadState { upload_id, size, part_size: self.part_size as u64, parts: Vec::new() }
+            }
+        };
+
+        let mut buf = Vec::new();
+        for number in 1..=size.div_ceil(upload.part_size).max(1) as u32 {
+            let offset = (number - 1) as u64 * upload.part_size;
+            buf.resize(upload.part_size.min(size - offset) as usize, 0);
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut buf)?;
+            let crc = crc32(&buf);
+            if upload.parts.iter().any(|part| part.number == number && part.crc == crc) {
+                continue;
+            }
+            upload.parts.retain(|part| part.number != number);
+            let part_number = number.to_string();
+            let query = [("partNumber", part_number.as_str()), ("uploadId", upload.upload_id.as_str())];
+            let checksum = base64(&crc.to_be_bytes());
+            let response = self.send(reqwest::Method::PUT, Some(&key), &query, &[("x-amz-checksum-crc32", &checksum)], Some(buf.clone().into()))?;
+            let etag = response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .ok_or_else(|| MjpegError::Io(format!("no ETag for part {} of {}", number, key)))?;
+            upload.parts.push(UploadedPart { number, offset, len: buf.len() as u64, etag: etag.to_string(), crc });
+            if let Some(path) = &state_path {
+                upload.save(path)?;
+            }
+        }
+
+        upload.parts.sort_by_key(|part| part.number);
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for part in &upload.parts {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag><ChecksumCRC32>{}</ChecksumCRC32></Part>",
+                part.number,
+                part.etag.replace('&', "&amp;").replace('<', "&lt;"),
+                base64(&part.crc.to_be_bytes())
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let xml = self.send(reqwest::Method::POST, Some(&key), &[("uploadId", &upload.upload_id)], &[], Some(body.into()))?.text().map_err(http_error)?;
+        // Errors can come with 200 OK once the store started assembling the parts
+        if xml.contains("<Error>") {
+            return Err(MjpegError::Io(format!("completing the upload of {} failed: {}", key, xml_value(&xml, "Message").unwrap_or_default())));
+        }
+        let expected = upload.checksum();
+        match xml_value(&xml, "ChecksumCRC32") {
+            Some(checksum) if checksum == expected => {}
+            Some(checksum) => return Err(MjpegError::Io(format!("checksum of {} is {}, expected {}", key, checksum, expected))),
+            None => return Err(MjpegError::Io(format!("no checksum reported for {}", key))),
+        }
+        // Kept until now so a failed upload is resumed, or restarted if the store dropped it
+        if let Some(path) = &state_path {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Aborts an interrupted [`upload_file`](Self::upload_file) of `name` kept in the
+    /// [`resume_dir`](Self::resume_dir), so the store frees its parts. Does nothing if there
+    /// is none. The saved progress is removed once the store aborted the upload or answers
+    /// that it no longer has it, and kept if the request fails so the abort can be retried.
+    pub fn abort_upload(&mut self, name: &str) -> Result<()> {
+        let key = format!("{}{}", self.prefix, name);
+        let Some(path) = self.upload_state_path(&key) else { return Ok(()) };
+        if let Some(upload) = UploadState::load(&path)? {
+            self.send_for_upload(reqwest::Method::DELETE, &key, &upload.upload_id)?;
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// The file keeping the progress of the upload of `key`, if progress is kept.
+    fn upload_state_path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.resume_dir.as_ref()?;
+        Some(dir.join(format!("{}.upload", &to_hex(&sha256(format!("{}/{}", self.bucket, key).as_bytes()))[..32])))
+    }
+
+    /// Sends a signed request without a body for the multipart upload `upload_id` of `key`, such
+    /// as ListParts or AbortMultipartUpload, returning `None` if the store answers 404 because
+    /// the upload expired or was aborted (`NoSuchUpload`).
+    fn send_for_upload(&self, method: reqwest::Method, key: &str, upload_id: &str) -> Result<Option<reqwest::blocking::Response>> {
+        let (url, response) = self.request(method.clone(), Some(key), &[("uploadId", upload_id)], &[], None)?;
+        match response.status() {
+            status if status.is_success() => Ok(Some(response)),
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status => Err(MjpegError::Io(format!("{} {} failed: {}", method, url, status))),
+        }
+    }
+
+    /// Sends a signed request for the object `key`, or the bucket if it is `None`, with the
+    /// `x-amz-*` headers in `extra_headers`.
+    fn send(
+        &self,
+        method: reqwest::Method,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        extra_headers: &[(&str, &str)],
+        body: Option<reqwest::blocking::Body>,
+    ) -> Result<reqwest::blocking::Response> {
+        let (url, response) = self.request(method.clone(), key, query, extra_headers, body)?;
+        if !response.status().is_success() {
+            return Err(MjpegError::Io(format!("{} {} failed: {}", method, url, response.status())));
+        }
+        Ok(response)
+    }
+
+    /// Sends a signed request like [`send`](Self::send), returning the URL and the response
+    /// whatever its status.
+    fn request(
+        &self,
+        method: reqwest::Method,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        extra_headers: &[(&str, &str)],
+        body: Option<reqwest::blocking::Body>,
+    ) -> Result<(String, reqwest::blocking::Response)> {
+        let mut path = format!("/{}", uri_encode(&self.bucket, false));
+        if let Some(key) = key {
+            path.push('/');
+            path.push_str(&uri_encode(key, true));
+        }
+        let mut query: Vec<(String, String)> = query.iter().map(|(name, value)| (uri_encode(name, false), uri_encode(value, false))).collect();
+        query.sort();
+        let query = query.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&");
+        let url = if query.is_empty() { format!("{}{}", self.endpoint, path) } else { format!("{}{}?{}", self.endpoint, path, query) };
+
+        let host = self.endpoint.split_once("://").map_or(self.endpoint.as_str(), |(_, rest)| rest);
+        let payload_hash = if body.is_some() { UNSIGNED_PAYLOAD } else { EMPTY_PAYLOAD };
+        let amz_date = amz_date(SystemTime::now());
+        let mut headers = vec![("host", host), ("x-amz-content-sha256", payload_hash), ("x-amz-date", amz_date.as_str())];
+        headers.extend_from_slice(extra_headers);
+        headers.sort();
+        let authorization = self.authorization(method.as_str(), &path, &query, &headers, &amz_date);
+
+        let mut request = self
+            .client
+            .request(method, &url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header(reqwest::header::AUTHORIZATION, authorization);
+        for (name, value) in extra_headers {
+            request = request.header(*name, *value);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        let response = request.send().map_err(http_error)?;
+        Ok((url, response))
+    }
+
+    /// The `Authorization` header of a request with the canonical `path` and `query`, and
+    /// `headers` in lowercase, sorted by name and ending with the payload hash and the date.
+    pub(crate) fn authorization(&self, method: &str, path: &str, query: &str, headers: &[(&str, &str)], amz_date: &str) -> String {
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let payload_hash = headers.iter().find(|(name, _)| *name == "x-amz-content-sha256").map_or(UNSIGNED_PAYLOAD, |(_, value)| value);
+        let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, canonical_headers, signed_headers, payload_hash);
+
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, to_hex(&sha256(canonical_request.as_bytes())));
+        let mut key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = to_hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key, scope, signed_headers, signature)
+    }
+}
+
+impl StorageTarget for S3Target {
+    type Segment = SpillWriter;
+    type Reader = Cursor<Vec<u8>>;
+
+    fn open_segment(&mut self, _name: &str) -> Result<SpillWriter> {
+        Ok(SpillWriter::new(self.memory_cap))
+    }
+
+    fn finalize(&mut self, name: &str, segment: SpillWriter) -> Result<()> {
+        let body = match segment.into_output()? {
+            SpillOutput::Memory(data) => reqwest::blocking::Body::from(data),
+            SpillOutput::File(file) => reqwest::blocking::Body::from(file),
+        };
+        self.send(reqwest::Method::PUT, Some(&format!("{}{}", self.prefix, name)), &[], &[], Some(body))?;
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<()> {
+        self.send(reqwest::Method::DELETE, Some(&format!("{}{}", self.prefix, name)), &[], &[], None)?;
+        Ok(())
+    }
+
+    fn list(&mut self) -> Result<Vec<StoredSegment>> {
+        let mut segments = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let xml = self.send(reqwest::Method::GET, None, &query, &[], None)?.text().map_err(http_error)?;
+            for contents in xml.split("<Contents>").skip(1) {
+                let Some(key) = xml_value(contents, "Key") else { continue };
+                segments.push(StoredSegment {
+                    name: key.strip_prefix(&self.prefix).unwrap_or(&key).to_string(),
+                    size: xml_value(contents, "Size").and_then(|size| size.parse().ok()).unwrap_or(0),
+                    modified: xml_value(contents, "LastModified").and_then(|time| parse_time(&time)),
+                });
+            }
+            token = xml_value(&xml, "NextContinuationToken");
+            if xml_value(&xml, "IsTruncated").as_deref() != Some("true") || token.is_none() {
+                break;
+            }
+        }
+        segments.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(segments)
+    }
+
+    fn open_read(&mut self, name: &str) -> Result<Cursor<Vec<u8>>> {
+        let response = self.send(reqwest::Method::GET, Some(&format!("{}{}", self.prefix, name)), &[], &[], None)?;
+        Ok(Cursor::new(response.bytes().map_err(http_error)?.to_vec()))
+    }
+}
+
+/// The progress of a multipart upload of [`S3Target::upload_file`], saved in the resume
+/// directory as lines of text: `upload {id} {size} {part size}`, then
+/// `part {number} {offset} {len} {etag} {crc}` for every uploaded part.
+struct UploadState {
+    upload_id: String,
+    size: u64,
+    part_size: u64,
+    parts: Vec<UploadedPart>,
+}
+
+struct UploadedPart {
+    number: u32,
+    offset: u64,
+    len: u64,
+    etag: String,
+    crc: u32,
+}
+
+impl UploadState {
+    /// Loads the progress saved at `path`, or `None` if there is none or it is unreadable.
+    fn load(path: &Path) -> Result<Option<UploadState>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut lines = text.lines().map(|line| line.split(' ').collect::<Vec<_>>());
+        let Some(&["upload", upload_id, size, part_size]) = lines.next().as_deref() else { return Ok(None) };
+        let (Ok(size), Ok(part_size)) = (size.parse(), part_size.parse()) else { return Ok(None) };
+        let mut upload = UploadState { upload_id: upload_id.to_string(), size, part_size, parts: Vec::new() };
+        for line in lines {
+            if let &["part", number, offset, len, etag, crc] = line.as_slice() {
+                if let (Ok(number), Ok(offset), Ok(len), Ok(crc)) = (number.parse(), offset.parse(), len.parse(), u32::from_str_radix(crc, 16)) {
+                    upload.parts.push(UploadedPart { number, offset, len, etag: etag.to_string(), crc });
+                }
+            }
+        }
+        Ok(Some(upload))
+    }
+
+    /// Saves the progress to `path`, replacing it atomically.
+    fn save(&self, path: &Path) -> Result<()> {
+        let mut text = format!("upload {} {} {}\n", self.upload_id, self.size, self.part_size);
+        for part in &self.parts {
+            text.push_str(&format!("part {} {} {} {} {:08x}\n", part.number, part.offset, part.len, part.etag, part.crc));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// The checksum S3 reports for the assembled object: the CRC-32 of the parts' CRC-32s,
+    /// followed by the number of parts.
+    fn checksum(&self) -> String {
+        let crcs: Vec<u8> = self.parts.iter().flat_map(|part| part.crc.to_be_bytes()).collect();
+        format!("{}-{}", base64(&crc32(&crcs).to_be_bytes()), self.parts.len())
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, and slashes if `keep_slash` is set.
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Formats a time as the `x-amz-date` header, e.g. `20240131T120000Z`.
+fn amz_date(time: SystemTime) -> String {
+    let rfc3339 = rfc3339(time);
+    rfc3339[..19].chars().filter(|c| *c != '-' && *c != ':').chain(['Z']).collect()
+}
+
+/// Parses an S3 timestamp such as `2024-01-31T12:00:00.000Z`.
+fn parse_time(s: &str) -> Option<SystemTime> {
+    let field = |range: std::ops::Range<usize>| s.get(range)?.parse::<u32>().ok();
+    civil_time(field(0..4)? as i64, field(5..7)?, field(8..10)?, field(11..13)?, field(14..16)?, field(17..19)?, 0)
+}
+
+/// The unescaped text of the first `<tag>` element in `xml`.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(
+        xml[start..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (ISO-HDLC), the checksum of S3's `x-amz-checksum-crc32`.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| (crc >> 8) ^ CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize])
+}
+
+/// Base64 with padding (RFC 4648), as S3 expects checksums.
+pub(crate) fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            out.push(if i <= chunk.len() { ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char } else { '=' });
+        }
+    }
+    out
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut sha = Sha256::new();
+    sha.update(data);
+    sha.finish()
+}
+
+/// HMAC-SHA256 (RFC 2104).
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(&block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(&block.map(|b| b ^ 0x5c));
+    outer.update(&inner.finish());
+    outer.finish()
+}
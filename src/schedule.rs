@@ -0,0 +1,142 @@
+use std::time::{Duration, SystemTime};
+use jiff::civil::{Time, Weekday};
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+use crate::Result;
+use crate::naming::tz_error;
+
+/// What a recorder does according to a [`Schedule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingMode {
+    /// Not recording.
+    Off,
+    /// Recording every frame at the configured frame rate.
+    FullRate,
+    /// Recording one frame per interval.
+    Timelapse(Duration),
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    days: Vec<Weekday>,
+    start: Time,
+    end: Time,
+    mode: RecordingMode,
+}
+
+impl Window {
+    fn contains(&self, weekday: Weekday, time: Time) -> bool {
+        if self.start < self.end {
+            self.days.contains(&weekday) && self.start <= time && time < self.end
+        } else {
+            // Past midnight: the part after the start belongs to the listed day, the rest to the next
+            (self.days.contains(&weekday) && time >= self.start) || (self.days.contains(&weekday.wrapping_sub(1)) && time < self.end)
+        }
+    }
+}
+
+/// A weekly grid of recording modes in local time, such as full-rate recording during opening
+/// hours and a timelapse at night.
+///
+/// Windows are given in wall-clock time of the schedule's zone and follow it through DST
+/// changes. When windows overlap the one added last wins; outside all windows the default
+/// mode applies, [`RecordingMode::Off`] unless changed. Poll [`mode_at`](Self::mode_at), or
+/// sleep until [`next_change`](Self::next_change) to start, stop or reconfigure the recorder.
+/// Enabled by the `tz` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jiff::civil::{time, Weekday};
+/// use mjpeg_avi_rs::{RecordingMode, Schedule};
+/// use std::time::{Duration, SystemTime};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let weekdays = [Weekday::Monday, Weekday::Tuesday, Weekday::Wednesday, Weekday::Thursday, Weekday::Friday];
+///     let mut schedule = Schedule::in_zone("Asia/Tokyo")?;
+///     schedule
+///         .default_mode(RecordingMode::Timelapse(Duration::from_secs(10)))
+///         .window(&weekdays, time(9, 0, 0, 0), time(18, 0, 0, 0), RecordingMode::FullRate);
+///
+///     let now = SystemTime::now();
+///     println!("{:?} until {:?}", schedule.mode_at(now)?, schedule.next_change(now)?);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    tz: TimeZone,
+    windows: Vec<Window>,
+    default: RecordingMode,
+}
+
+impl Schedule {
+    /// Creates an empty schedule in `tz`.
+    pub fn new(tz: TimeZone) -> Self {
+        Schedule { tz, windows: Vec::new(), default: RecordingMode::Off }
+    }
+
+    /// Creates an empty schedule in the IANA time zone `name`, such as `America/New_York`.
+    pub fn in_zone(name: &str) -> Result<Self> {
+        Ok(Self::new(TimeZone::get(name).map_err(tz_error)?))
+    }
+
+    /// Sets the mode outside all windows.
+    pub fn default_mode(&mut self, mode: RecordingMode) -> &mut Self {
+        self.default = mode;
+        self
+    }
+
+    /// Adds a window from `start` to `end` on each of `days`.
+    ///
+    /// If `end` is not after `start`, the window runs past midnight into the next day.
+    pub fn window(&mut self, days: &[Weekday], start: Time, end: Time, mode: RecordingMode) -> &mut Self {
+        self.windows.push(Window { days: days.to_vec(), start, end, mode });
+        self
+    }
+
+    /// The mode at `time`.
+    pub fn mode_at(&self, time: SystemTime) -> Result<RecordingMode> {
+        let zoned = self.zoned(time)?;
+        Ok(self.mode_at_zoned(&zoned))
+    }
+
+    /// The first time after `time` at which the mode changes, or `None` if it never does.
+    pub fn next_change(&self, time: SystemTime) -> Result<Option<SystemTime>> {
+        let zoned = self.zoned(time)?;
+        let current = self.mode_at_zoned(&zoned);
+
+        // Every change happens at a window boundary within the next week
+        let mut boundaries = Vec::new();
+        for day in 0..8 {
+            let date = zoned.date().checked_add(jiff::Span::new().days(day)).map_err(tz_error)?;
+            for window in &self.windows {
+                for boundary in [window.start, window.end] {
+                    let boundary = date.to_datetime(boundary).to_zoned(self.tz.clone()).map_err(tz_error)?;
+                    if boundary.timestamp() > zoned.timestamp() {
+                        boundaries.push(boundary);
+                    }
+                }
+            }
+        }
+        boundaries.sort();
+        Ok(boundaries
+            .into_iter()
+            .find(|boundary| self.mode_at_zoned(boundary) != current)
+            .map(|boundary| SystemTime::from(boundary.timestamp())))
+    }
+
+    fn mode_at_zoned(&self, zoned: &Zoned) -> RecordingMode {
+        let (weekday, time) = (zoned.weekday(), zoned.time());
+        self.windows
+            .iter()
+            .rev()
+            .find(|window| window.contains(weekday, time))
+            .map_or(self.default, |window| window.mode)
+    }
+
+    fn zoned(&self, time: SystemTime) -> Result<Zoned> {
+        let timestamp = Timestamp::try_from(time).map_err(tz_error)?;
+        Ok(timestamp.to_zoned(self.tz.clone()))
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::builder::MjpegWriterBuilder;
+use crate::common::MAX_FRAME_COUNT;
+
+/// The bytes a frame adds besides its JPEG data: the chunk header, the `idx1` entry and half a
+/// padding byte on average.
+const FRAME_OVERHEAD: f64 = 8.0 + 16.0 + 0.5;
+
+/// Plans segments of a target playback duration that stay within the AVI size limits.
+///
+/// A segment of one hour at a high bitrate can be larger than the 2GB limit of a single-RIFF
+/// AVI file, and a writer that runs into it fails with
+/// [`MjpegError::FileSizeExceeded`](crate::MjpegError::FileSizeExceeded) in the middle of the
+/// segment. The planner keeps a rolling average of the frame sizes and, when a segment starts,
+/// predicts whether the target duration fits. If not, it shortens the segment, splitting the
+/// target duration evenly, e.g. into two segments of 30 minutes instead of 55 and 5 minutes.
+/// During the segment, [`should_rotate`](Self::should_rotate) also tells to rotate early if the
+/// frames turn out larger than predicted.
+///
+/// With [`open_dml`](MjpegWriterBuilder::open_dml) the files have no size limit, so segments
+/// keep the target duration unless [`max_bytes`](Self::max_bytes) sets one.
+///
+/// With [`split_on_events`](Self::split_on_events), segments also end exactly where an event,
+/// such as motion or a bookmarked incident, begins and ends, so each event lands in a file of
+/// its own for review. See [`begin_event`](Self::begin_event).
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriterBuilder, SegmentPlanner};
+/// use std::fs::File;
+/// use std::time::Duration;
+///
+/// # fn camera_frame() -> Vec<u8> { Vec::new() }
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let builder = MjpegWriterBuilder::new(1920, 1080, 30);
+///     let mut planner = SegmentPlanner::new(&builder, Duration::from_secs(3600));
+///     for segment in 0.. {
+///         planner.begin_segment();
+///         let mut writer = builder.build(File::create(format!("segment_{:04}.avi", segment))?)?;
+///         while !planner.should_rotate() {
+///             let frame = camera_frame();
+///             writer.add_frame(&frame)?;
+///             planner.add_frame(frame.len());
+///         }
+///         writer.finish()?;
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SegmentPlanner {
+    fps: u32,
+    target_frames: u64,
+    header_size: u64,
+    max_bytes: u64,
+    max_frames: u64,
+    headroom: f64,
+    /// The sizes of the latest frames
+    window: VecDeque<u32>,
+    window_frames: usize,
+    window_bytes: u64,
+    /// The frames planned for the current segment
+    planned: u64,
+    /// The frames and the predicted file size of the current segment
+    frames: u64,
+    bytes: u64,
+    split_on_events: bool,
+    in_event: bool,
+    /// An event began or ended since the segment began
+    event_boundary: bool,
+}
+
+impl SegmentPlanner {
+    /// Creates a planner for segments of `target` playback duration written by writers of
+    /// `builder`.
+    pub fn new(builder: &MjpegWriterBuilder, target: Duration) -> Self {
+        let fps = builder.fps();
+        let target_frames = ((target.as_secs_f64() * fps as f64).ceil() as u64).max(1);
+        let (max_bytes, max_frames) = if builder.is_open_dml() { (u64::MAX, u64::MAX) } else { (builder.max_riff_size(), MAX_FRAME_COUNT as u64) };
+        // The header and the idx1 chunk header
+        let header_size = builder.header_len() as u64 + 8;
+        SegmentPlanner {
+            fps,
+            target_frames,
+            header_size,
+            max_bytes,
+            max_frames,
+            headroom: 0.05,
+            window: VecDeque::new(),
+            window_frames: (fps as usize * 60).max(1),
+            window_bytes: 0,
+            planned: target_frames.min(max_frames),
+            frames: 0,
+            bytes: header_size,
+            split_on_events: false,
+            in_event: false,
+            event_boundary: false,
+        }
+    }
+
+    /// Sets the largest file size, e.g. 4GB - 1 for FAT32 with OpenDML. Default: 2GB, 4GB with
+    /// `allow_4gb`, or no limit with OpenDML.
+    pub fn max_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// Sets the fraction of the largest file size kept free for frames larger than the
+    /// average. Default: 0.05.
+    pub fn headroom(&mut self, fraction: f64) -> &mut Self {
+        self.headroom = fraction.clamp(0.0, 0.9);
+        self
+    }
+
+    /// Sets the number of latest frames averaged. Default: one minute of frames.
+    pub fn window(&mut self, frames: usize) -> &mut Self {
+        self.window_frames = frames.max(1);
+        while self.window.len() > self.window_frames {
+            self.window_bytes -= self.window.pop_front().unwrap_or(0) as u64;
+        }
+        self
+    }
+
+    /// Ends segments at the boundaries of events marked with [`begin_event`](Self::begin_event)
+    /// and [`end_event`](Self::end_event). An event is kept in one segment regardless of the
+    /// target duration and only split if it would not fit in a file. Default: false.
+    pub fn split_on_events(&mut self, enabled: bool) -> &mut Self {
+        self.split_on_events = enabled;
+        self
+    }
+
+    /// Marks that an event begins with the next frame, so with
+    /// [`split_on_events`](Self::split_on_events) [`should_rotate`](Self::should_rotate) ends
+    /// the current segment before it, unless the segment has no frames yet. Call it before
+    /// checking `should_rotate()`, and add the bookmark of the event after rotating, so it
+    /// lands on the first frame of the new segment.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriterBuilder, SegmentPlanner};
+    /// use std::fs::File;
+    /// use std::time::Duration;
+    ///
+    /// # fn camera_frame() -> Option<(Vec<u8>, bool)> { None }
+    /// fn main() -> mjpeg_avi_rs::Result<()> {
+    ///     let builder = MjpegWriterBuilder::new(1280, 720, 10);
+    ///     let mut planner = SegmentPlanner::new(&builder, Duration::from_secs(600));
+    ///     planner.split_on_events(true);
+    ///     let mut writer = builder.build(File::create("segment_0000.avi")?)?;
+    ///     planner.begin_segment();
+    ///     let mut segment = 0;
+    ///     // The frames with whether motion was detected in them
+    ///     while let Some((frame, motion)) = camera_frame() {
+    ///         let event_starts = motion && !planner.in_event();
+    ///         if event_starts {
+    ///             planner.begin_event();
+    ///         } else if !motion {
+    ///             planner.end_event();
+    ///         }
+    ///         if planner.should_rotate() {
+    ///             writer.finish()?;
+    ///             segment += 1;
+    ///             let kind = if planner.in_event() { "event" } else { "segment" };
+    ///             writer = builder.build(File::create(format!("{}_{:04}.avi", kind, segment))?)?;
+    ///             planner.begin_segment();
+    ///         }
+    ///         if event_starts {
+    ///             writer.add_bookmark("motion")?;
+    ///         }
+    ///         writer.add_frame(&frame)?;
+    ///         planner.add_frame(frame.len());
+    ///     }
+    ///     writer.finish()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn begin_event(&mut self) {
+        if !self.in_event {
+            self.in_event = true;
+            // Nothing to split off before the first frame of a segment
+            self.event_boundary = self.frames > 0;
+        }
+    }
+
+    /// Marks that the event ended with the last frame, so the next frame starts a new segment
+    /// with [`split_on_events`](Self::split_on_events).
+    pub fn end_event(&mut self) {
+        if self.in_event {
+            self.in_event = false;
+            self.event_boundary = self.frames > 0;
+        }
+    }
+
+    /// Returns true between [`begin_event`](Self::begin_event) and
+    /// [`end_event`](Self::end_event).
+    pub fn in_event(&self) -> bool {
+        self.in_event
+    }
+
+    /// Starts a segment and returns the number of frames planned for it.
+    pub fn begin_segment(&mut self) -> u64 {
+        let per_frame = self.average_frame_size().unwrap_or(0.0) + FRAME_OVERHEAD;
+        let capacity = ((self.budget() - self.header_size as f64) / per_frame).floor().max(1.0) as u64;
+        // Split the target duration evenly over the segments it needs
+        let segments = self.target_frames.div_ceil(capacity.min(self.max_frames));
+        self.planned = self.target_frames.div_ceil(segments);
+        self.frames = 0;
+        self.bytes = self.header_size;
+        self.event_boundary = false;
+        self.planned
+    }
+
+    /// Records a frame of `size` bytes written to the current segment, 0 for dropped frames.
+    pub fn add_frame(&mut self, size: usize) {
+        let size = size.min(u32::MAX as usize) as u32;
+        self.window.push_back(size);
+        self.window_bytes += size as u64;
+        if self.window.len() > self.window_frames {
+            self.window_bytes -= self.window.pop_front().unwrap_or(0) as u64;
+        }
+        self.frames += 1;
+        self.bytes += FRAME_OVERHEAD as u64 + size as u64 + (size & 1) as u64;
+    }
+
+    /// Returns true if the current segment should end before the next frame: it has the
+    /// planned frames, or a frame of the average size would leave less than the headroom.
+    /// With [`split_on_events`](Self::split_on_events), also if an event began or ended since
+    /// the segment began, but not for the planned frames during an event.
+    pub fn should_rotate(&self) -> bool {
+        let next = self.average_frame_size().unwrap_or(0.0) + FRAME_OVERHEAD;
+        let full = self.frames >= self.max_frames || self.bytes as f64 + next > self.budget();
+        if self.split_on_events && self.frames > 0 {
+            if self.event_boundary {
+                return true;
+            }
+            if self.in_event {
+                return full;
+            }
+        }
+        self.frames >= self.planned || full
+    }
+
+    /// The number of frames planned for the current segment.
+    pub fn planned_frames(&self) -> u64 {
+        self.planned
+    }
+
+    /// The playback duration planned for the current segment.
+    pub fn planned_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.planned as f64 / self.fps.max(1) as f64)
+    }
+
+    /// The average size of the latest frames, `None` before the first frame.
+    pub fn average_frame_size(&self) -> Option<f64> {
+        (!self.window.is_empty()).then(|| self.window_bytes as f64 / self.window.len() as f64)
+    }
+
+    /// The file size the segments are planned to stay under.
+    fn budget(&self) -> f64 {
+        self.max_bytes as f64 * (1.0 - self.headroom)
+    }
+}
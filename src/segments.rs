@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::time::Duration;
+use crate::{MjpegError, Result};
+use crate::frame_map::frame_timestamp;
+use crate::reader::MjpegReader;
+
+/// Reads a series of segment files as one continuous recording.
+///
+/// Frames are numbered across all segments, and their timestamps continue from the end of
+/// the previous segment, so review tools can seek and step through a recording that was split
+/// into several files as if it were one. Each segment may have its own frame rate.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::SegmentSetReader;
+/// use std::time::Duration;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = SegmentSetReader::open(["cam_000.avi", "cam_001.avi", "cam_002.avi"])?;
+///     println!("{} frames, {:?}", reader.frame_count(), reader.duration());
+///     if let Some(frame) = reader.frame_at(Duration::from_secs(90)) {
+///         std::fs::write("at_90s.jpg", reader.read_frame(frame)?)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct SegmentSetReader<R: Read + Seek> {
+    segments: Vec<MjpegReader<R>>,
+    /// The global number of the first frame of each segment
+    first_frames: Vec<usize>,
+    /// The start time of each segment
+    start_times: Vec<Duration>,
+    frame_count: usize,
+    duration: Duration,
+}
+
+impl SegmentSetReader<File> {
+    /// Opens the segment files in playback order.
+    pub fn open<P: AsRef<Path>, I: IntoIterator<Item = P>>(paths: I) -> Result<Self> {
+        let segments = paths
+            .into_iter()
+            .map(|path| MjpegReader::new(File::open(path)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(segments))
+    }
+}
+
+impl<R: Read + Seek> SegmentSetReader<R> {
+    /// Combines readers of the segments in playback order.
+    pub fn new(segments: Vec<MjpegReader<R>>) -> Self {
+        let mut first_frames = Vec::with_capacity(segments.len());
+        let mut start_times = Vec::with_capacity(segments.len());
+        let mut frame_count = 0;
+        let mut duration = Duration::ZERO;
+        for segment in &segments {
+            first_frames.push(frame_count);
+            start_times.push(duration);
+            frame_count += segment.frame_count();
+            duration += frame_timestamp(segment.frame_count() as u64, segment.info().scale, segment.info().rate);
+        }
+        SegmentSetReader { segments, first_frames, start_times, frame_count, duration }
+    }
+
+    /// The number of frames in all segments.
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// The total duration of all segments.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The segment readers.
+    pub fn segments(&self) -> &[MjpegReader<R>] {
+        &self.segments
+    }
+
+    /// The segment that contains frame `frame` and the frame's number within it.
+    pub fn locate(&self, frame: usize) -> Option<(usize, usize)> {
+        if frame >= self.frame_count {
+            return None;
+        }
+        // Empty segments share their first frame number with the next segment
+        let segment = self.first_frames.partition_point(|&first| first <= frame) - 1;
+        Some((segment, frame - self.first_frames[segment]))
+    }
+
+    /// The timestamp of frame `frame` from the start of the first segment.
+    pub fn timestamp(&self, frame: usize) -> Option<Duration> {
+        let (segment, local) = self.locate(frame)?;
+        let info = self.segments[segment].info();
+        Some(self.start_times[segment] + frame_timestamp(local as u64, info.scale, info.rate))
+    }
+
+    /// The frame shown at `time` from the start of the first segment, or `None` after the end.
+    pub fn frame_at(&self, time: Duration) -> Option<usize> {
+        if time >= self.duration {
+            return None;
+        }
+        let segment = self.start_times.partition_point(|&start| start <= time) - 1;
+        let reader = &self.segments[segment];
+        let info = reader.info();
+        if info.rate == 0 || reader.frame_count() == 0 {
+            return Some(self.first_frames[segment]);
+        }
+        let offset = (time - self.start_times[segment]).as_nanos();
+        let local = offset * info.rate as u128 / (info.scale as u128 * 1_000_000_000);
+        Some(self.first_frames[segment] + (local as usize).min(reader.frame_count() - 1))
+    }
+
+    /// Reads the JPEG data of frame `frame`.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`] if `frame` is not less than `frame_count()`.
+    pub fn read_frame(&mut self, frame: usize) -> Result<Vec<u8>> {
+        let (segment, local) = self.locate(frame).ok_or(MjpegError::FrameIndexOutOfRange)?;
+        self.segments[segment].read_frame(local)
+    }
+
+    /// Consumes the reader, returning the segment readers.
+    pub fn into_segments(self) -> Vec<MjpegReader<R>> {
+        self.segments
+    }
+}
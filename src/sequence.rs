@@ -0,0 +1,152 @@
+use std::io::{Read, Seek};
+use crate::{MjpegError, Result};
+use crate::reader::MjpegReader;
+use crate::testpattern::read_counter;
+
+/// A break in the frame counter sequence found by a [`SequenceValidator`]. `frame` is the
+/// frame number in the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SequenceIssue {
+    /// Frames are missing: the counter skipped from `expected` to `found`.
+    Gap { frame: usize, expected: u32, found: u32 },
+    /// The frame repeats the counter of the frame before it.
+    Repeat { frame: usize, counter: u32 },
+    /// The counter went back from `expected` to `found`, e.g. frames written out of order.
+    Backwards { frame: usize, expected: u32, found: u32 },
+    /// The frame is a dropped frame, an empty chunk standing for a frame the capture missed.
+    Dropped { frame: usize },
+    /// The frame can't be decoded or its counter can't be read.
+    Unreadable { frame: usize },
+}
+
+/// The result of validating the frame counters of a recording, see [`SequenceValidator`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceReport {
+    /// The number of frames checked.
+    pub frames: u64,
+    /// The counter of the first readable frame.
+    pub first_counter: Option<u32>,
+    /// The number of counter values skipped, i.e. the frames lost on the way to the file.
+    pub missing: u64,
+    /// The number of frames repeating the counter of the frame before them.
+    pub repeated: u64,
+    /// The number of dropped frames.
+    pub dropped: u64,
+    /// The breaks in the sequence, in order.
+    pub issues: Vec<SequenceIssue>,
+}
+
+impl SequenceReport {
+    /// Returns true if every frame is there exactly once and in order.
+    pub fn is_intact(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks that the frame counters of a [`TestPattern`](crate::TestPattern) recording count up
+/// by one from frame to frame, as an end-to-end check of a capture pipeline.
+///
+/// Play a test pattern into the camera input, or feed its frames to the pipeline, record, and
+/// validate the recording: each frame is decoded and the counter cells are read, so frames
+/// lost, duplicated or reordered anywhere between the source and the file show up as
+/// [`SequenceIssue`]s. [`validate_sequence`] checks a finished recording; feed frames to
+/// [`check_frame`](Self::check_frame) to check them as they arrive. The counter may start
+/// anywhere. Enabled by the `testpattern` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{validate_sequence, MjpegReader};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = MjpegReader::new(File::open("capture.avi")?)?;
+///     let report = validate_sequence(&mut reader)?;
+///     if !report.is_intact() {
+///         println!("{} frames missing, {} repeated: {:?}", report.missing, report.repeated, report.issues);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SequenceValidator {
+    report: SequenceReport,
+    /// The counter expected in the next frame
+    expected: Option<u32>,
+}
+
+impl SequenceValidator {
+    /// Creates a validator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the next frame of the recording.
+    pub fn check_frame(&mut self, jpeg: &[u8]) {
+        let frame = self.next_frame();
+        let Some(counter) = read_counter(jpeg) else {
+            self.report.issues.push(SequenceIssue::Unreadable { frame });
+            self.expected = self.expected.map(|expected| expected.wrapping_add(1));
+            return;
+        };
+        self.report.first_counter.get_or_insert(counter);
+        match self.expected {
+            Some(expected) if counter == expected => {}
+            Some(expected) if counter.wrapping_add(1) == expected => {
+                self.report.repeated += 1;
+                self.report.issues.push(SequenceIssue::Repeat { frame, counter });
+            }
+            Some(expected) if counter > expected => {
+                self.report.missing += (counter - expected) as u64;
+                self.report.issues.push(SequenceIssue::Gap { frame, expected, found: counter });
+            }
+            Some(expected) => self.report.issues.push(SequenceIssue::Backwards { frame, expected, found: counter }),
+            None => {}
+        }
+        self.expected = Some(counter.wrapping_add(1));
+    }
+
+    /// Checks the next frame of the recording, which is a dropped frame.
+    pub fn check_dropped_frame(&mut self) {
+        let frame = self.next_frame();
+        self.report.dropped += 1;
+        self.report.issues.push(SequenceIssue::Dropped { frame });
+        self.expected = self.expected.map(|expected| expected.wrapping_add(1));
+    }
+
+    /// The results so far.
+    pub fn report(&self) -> &SequenceReport {
+        &self.report
+    }
+
+    /// Ends the validation and returns the results.
+    pub fn into_report(self) -> SequenceReport {
+        self.report
+    }
+
+    fn next_frame(&mut self) -> usize {
+        self.report.frames += 1;
+        self.report.frames as usize - 1
+    }
+}
+
+/// Validates the frame counters of all frames of `reader`, see [`SequenceValidator`].
+///
+/// Frames whose data fails the reader's checks count as unreadable. Fails only if the file
+/// can't be read.
+pub fn validate_sequence<R: Read + Seek>(reader: &mut MjpegReader<R>) -> Result<SequenceReport> {
+    let mut validator = SequenceValidator::new();
+    let mut jpeg = Vec::new();
+    for frame in 0..reader.frame_count() {
+        if reader.frame_entry(frame).is_some_and(|entry| entry.size == 0) {
+            validator.check_dropped_frame();
+            continue;
+        }
+        match reader.read_frame_to(frame, &mut jpeg) {
+            Ok(()) => validator.check_frame(&jpeg),
+            Err(MjpegError::CorruptFrame(_)) => validator.check_frame(&[]),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(validator.into_report())
+}
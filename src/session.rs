@@ -0,0 +1,293 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::{MjpegError, Result};
+use crate::catalog::{escape, unescape};
+use crate::frame_map::frame_timestamp;
+use crate::lock::{create_locked, lock_exclusive};
+use crate::reader::MjpegReader;
+use crate::remux::remux;
+
+const LOCK_FILE: &str = "session.lock";
+const STATE_FILE: &str = "session.state";
+const MANIFEST_FILE: &str = "session.segments";
+const STATE_HEADER: &str = "mjpeg-avi-rs session 1";
+
+/// A finished segment of a [`RecordingSession`], as listed in its manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSegment {
+    /// The path of the segment file.
+    pub path: PathBuf,
+    /// The number of video frames.
+    pub frames: usize,
+    /// The duration of the segment.
+    pub duration: Duration,
+    /// The segment was left unfinished by a recorder that stopped and has been repaired.
+    pub repaired: bool,
+}
+
+/// The state of the session in a directory, see [`session_status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// No session was started, or the last one was closed.
+    Idle,
+    /// A recorder holds the session.
+    Active {
+        /// The process id of the recorder.
+        pid: u32,
+    },
+    /// The recorder stopped without closing the session, e.g. because it crashed.
+    Abandoned {
+        /// The process id of the recorder.
+        pid: u32,
+        /// The segment it was writing, which [`RecordingSession::open`] repairs.
+        segment: Option<PathBuf>,
+    },
+}
+
+/// A recording session in a directory, with on-disk state that lets supervisor processes
+/// detect abandoned sessions and lets the next recorder resume them cleanly.
+///
+/// The session keeps three files next to its segments:
+///
+/// * `session.lock`, holding an exclusive advisory lock and the process id of the recorder
+///   while the session is open. The operating system releases the lock when the process
+///   exits, however it exits.
+/// * `session.state`, a small text file that is replaced atomically and names the segment
+///   being written.
+/// * `session.segments`, a manifest that gets one tab-separated line per finished segment:
+///   frames, duration in nanoseconds, 1 if it was repaired and the file name.
+///
+/// Segments are created with [`begin_segment`](Self::begin_segment), which locks them as
+/// [`create_locked`](crate::create_locked) does, and listed in the manifest by
+/// [`end_segment`](Self::end_segment) once their writer is finished. Opening a session whose
+/// recorder stopped without closing it repairs the segment it was writing by remuxing its
+/// intact frames into a finished file, and recording continues in a new segment.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, RecordingSession};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut session = RecordingSession::open("/var/recordings/cam1")?;
+///     if let Some(segment) = session.repaired() {
+///         println!("repaired {} after a crash", segment.path.display());
+///     }
+///     for hour in 0..24 {
+///         let file = session.begin_segment(&format!("hour_{:02}.avi", hour))?;
+///         let mut writer = MjpegWriter::new(file, 640, 480, 10)?;
+///         writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///         writer.finish()?;
+///         session.end_segment()?;
+///     }
+///     session.close()
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RecordingSession {
+    dir: PathBuf,
+    /// Keeps the session locked until it is dropped
+    _lock: File,
+    /// The file name of the segment being written
+    current: Option<String>,
+    segments: Vec<SessionSegment>,
+    repaired: Option<SessionSegment>,
+}
+
+impl RecordingSession {
+    /// Opens the session in `dir`, creating the directory if needed.
+    ///
+    /// Fails with [`MjpegError::Locked`] if another recorder holds the session. If the previous
+    /// recorder stopped while writing a segment, the segment is repaired, see
+    /// [`repaired`](Self::repaired).
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut lock = create_locked(dir.join(LOCK_FILE))?;
+        writeln!(lock, "{}", std::process::id())?;
+        lock.sync_all()?;
+
+        let state = read_state(&dir)?;
+        let mut session = RecordingSession { segments: read_manifest(&dir)?, dir, _lock: lock, current: None, repaired: None };
+        if let Some((true, _, Some(name))) = state {
+            session.repaired = session.repair(&name)?;
+        }
+        session.write_state(true)?;
+        Ok(session)
+    }
+
+    /// The segment left unfinished by the previous recorder and repaired by [`open`](Self::open).
+    pub fn repaired(&self) -> Option<&SessionSegment> {
+        self.repaired.as_ref()
+    }
+
+    /// The finished segments of the session, in the order they were recorded.
+    pub fn segments(&self) -> &[SessionSegment] {
+        &self.segments
+    }
+
+    /// Creates the segment file `name` in the session directory and records it as the segment
+    /// being written. A segment still open is ended first, see [`end_segment`](Self::end_segment).
+    pub fn begin_segment(&mut self, name: &str) -> Result<File> {
+        self.end_segment()?;
+        let file = create_locked(self.dir.join(name))?;
+        self.current = Some(name.to_string());
+        self.write_state(true)?;
+        Ok(file)
+    }
+
+    /// Lists the segment being written in the manifest, after its writer is finished and the
+    /// file is closed. Returns `None` if no segment is open.
+    pub fn end_segment(&mut self) -> Result<Option<SessionSegment>> {
+        let Some(name) = self.current.take() else {
+            return Ok(None);
+        };
+        let reader = MjpegReader::new(File::open(self.dir.join(&name))?)?;
+        let segment = self.add_segment(&name, &reader, false)?;
+        self.write_state(true)?;
+        Ok(Some(segment))
+    }
+
+    /// Ends the segment being written and marks the session as closed.
+    pub fn close(mut self) -> Result<()> {
+        self.end_segment()?;
+        self.write_state(false)
+    }
+
+    /// Replaces the unfinished segment `name` with a finished file of its intact frames.
+    /// Returns `None` if the recorder stopped before its header was written.
+    fn repair(&mut self, name: &str) -> Result<Option<SessionSegment>> {
+        let path = self.dir.join(name);
+        let mut reader = match File::open(&path).map_err(MjpegError::from).and_then(MjpegReader::new) {
+            Ok(reader) => reader,
+            Err(MjpegError::InvalidAvi(_)) => {
+                fs::remove_file(&path)?;
+                return Ok(None);
+            }
+            Err(_) if !path.exists() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        reader.skip_corrupt_frames()?;
+        let temp = self.dir.join(format!("{}.repair", name));
+        remux(&mut reader, File::create(&temp)?)?.sync_all()?;
+        fs::rename(&temp, &path)?;
+        self.add_segment(name, &MjpegReader::new(File::open(&path)?)?, true).map(Some)
+    }
+
+    fn add_segment(&mut self, name: &str, reader: &MjpegReader<File>, repaired: bool) -> Result<SessionSegment> {
+        let info = reader.info();
+        let segment = SessionSegment {
+            path: self.dir.join(name),
+            frames: reader.frame_count(),
+            duration: frame_timestamp(reader.frame_count() as u64, info.scale, info.rate),
+            repaired,
+        };
+        let mut manifest = OpenOptions::new().create(true).append(true).open(self.dir.join(MANIFEST_FILE))?;
+        writeln!(manifest, "{}\t{}\t{}\t{}", segment.frames, segment.duration.as_nanos(), repaired as u8, escape(name))?;
+        manifest.sync_all()?;
+        self.segments.push(segment.clone());
+        Ok(segment)
+    }
+
+    /// Replaces the state file, so readers never see a partial state.
+    fn write_state(&self, recording: bool) -> Result<()> {
+        let mut state = format!("{}\nstate\t{}\npid\t{}\n", STATE_HEADER, if recording { "recording" } else { "closed" }, std::process::id());
+        if let Some(name) = &self.current {
+            state.push_str(&format!("segment\t{}\n", escape(name)));
+        }
+        let temp = self.dir.join(format!("{}.tmp", STATE_FILE));
+        let mut file = File::create(&temp)?;
+        file.write_all(state.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, self.dir.join(STATE_FILE))?;
+        Ok(())
+    }
+}
+
+/// Reports whether the session in `dir` is idle, held by a recorder or abandoned, for
+/// supervisor processes.
+///
+/// An abandoned session is repaired and resumed by opening it with
+/// [`RecordingSession::open`]. The check briefly takes the session lock, so a recorder
+/// opening the session at the same moment may fail with [`MjpegError::Locked`] and should
+/// retry.
+pub fn session_status<P: AsRef<Path>>(dir: P) -> Result<SessionStatus> {
+    let dir = dir.as_ref();
+    let state = read_state(dir)?;
+    let lock = match File::open(dir.join(LOCK_FILE)) {
+        Ok(lock) => lock,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(SessionStatus::Idle),
+        Err(err) => return Err(err.into()),
+    };
+    let held = match lock_exclusive(&lock) {
+        Ok(()) => false,
+        Err(MjpegError::Locked) => true,
+        Err(err) => return Err(err),
+    };
+    drop(lock);
+
+    Ok(match state {
+        // A recorder that has just taken the lock may not have written its state yet
+        _ if held => SessionStatus::Active { pid: read_state(dir)?.map_or(0, |(_, pid, _)| pid) },
+        Some((true, pid, segment)) => SessionStatus::Abandoned { pid, segment: segment.map(|name| dir.join(name)) },
+        _ => SessionStatus::Idle,
+    })
+}
+
+/// Reads the state file: whether the session is recording, the process id of the recorder
+/// and the segment being written.
+fn read_state(dir: &Path) -> Result<Option<(bool, u32, Option<String>)>> {
+    let file = match File::open(dir.join(STATE_FILE)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut lines = BufReader::new(file).lines();
+    if lines.next().transpose()?.as_deref() != Some(STATE_HEADER) {
+        return Err(invalid_state());
+    }
+    let (mut recording, mut pid, mut segment) = (false, 0, None);
+    for line in lines {
+        let line = line?;
+        match line.split_once('\t').ok_or_else(invalid_state)? {
+            ("state", state) => recording = state == "recording",
+            ("pid", value) => pid = value.parse().map_err(|_| invalid_state())?,
+            ("segment", name) => segment = Some(unescape(name)),
+            _ => {}
+        }
+    }
+    Ok(Some((recording, pid, segment)))
+}
+
+fn read_manifest(dir: &Path) -> Result<Vec<SessionSegment>> {
+    let file = match File::open(dir.join(MANIFEST_FILE)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut segments = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        // A line cut short by a crash ends the manifest
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [frames, duration, repaired, name] = fields.as_slice() else {
+            break;
+        };
+        let (Ok(frames), Ok(duration)) = (frames.parse(), duration.parse()) else {
+            break;
+        };
+        segments.push(SessionSegment {
+            path: dir.join(unescape(name)),
+            frames,
+            duration: Duration::from_nanos(duration),
+            repaired: *repaired == "1",
+        });
+    }
+    Ok(segments)
+}
+
+fn invalid_state() -> MjpegError {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid session state").into()
+}
@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+use crate::{MjpegError, Result};
+use crate::mjpeg_async::MjpegAviWriterAsync;
+use crate::pacing::{MissedTickPolicy, PacingStats, Ticker};
+use crate::source::AsyncFrameSource;
+use crate::writer::AsyncWriter;
+
+/// Completes when the process receives ctrl-c, or `SIGTERM` on Unix.
+///
+/// Enabled by the `signal` feature.
+pub async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Finishes `writer`, giving up after `timeout`.
+///
+/// Returns [`MjpegError::Io`] if the timeout elapses. Enabled by the `signal` feature.
+pub async fn finish_with_timeout<W, M>(writer: M, timeout: Duration) -> Result<W>
+where
+    W: AsyncWriter,
+    M: MjpegAviWriterAsync<W>,
+{
+    tokio::time::timeout(timeout, writer.finish())
+        .await
+        .map_err(|_| MjpegError::Io(format!("finishing the writer timed out after {:?}", timeout)))?
+}
+
+/// Records from `source` like [`pull_at_fps_async`](crate::pull_at_fps_async) until the source
+/// ends or the process receives ctrl-c or `SIGTERM`, then finishes the writer.
+///
+/// A frame being written when the signal arrives is completed first, and `finish()` always
+/// runs, bounded by `finish_timeout`, so an unattended recorder that is shut down leaves a
+/// playable file. If the source fails, the writer is finished before the error is returned.
+/// Enabled by the `signal` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{record_until_shutdown, MissedTickPolicy, MjpegAsyncWriter};
+/// use std::time::Duration;
+///
+/// async fn record() -> mjpeg_avi_rs::Result<()> {
+///     let file = tokio::fs::File::create("output.avi").await?;
+///     let writer = MjpegAsyncWriter::new(file, 640, 480, 10).await?;
+///     let (_file, stats) = record_until_shutdown(writer, 10, MissedTickPolicy::Skip, || async {
+///         // Grab a JPEG from the camera here
+///         Ok(Some(vec![0xFF, 0xD8, 0xFF, 0xD9]))
+///     }, Duration::from_secs(5)).await?;
+///     println!("recorded {} frames", stats.frames);
+///     Ok(())
+/// }
+/// ```
+pub async fn record_until_shutdown<W, M, S>(
+    mut writer: M,
+    fps: u32,
+    policy: MissedTickPolicy,
+    mut source: S,
+    finish_timeout: Duration,
+) -> Result<(W, PacingStats)>
+where
+    W: AsyncWriter,
+    M: MjpegAviWriterAsync<W>,
+    S: AsyncFrameSource,
+{
+    let mut ticker = Ticker::new(fps, policy)?;
+    let mut stats = PacingStats::default();
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    let result = loop {
+        let next = tokio::select! {
+            biased;
+            result = &mut shutdown => break result,
+            next = async {
+                tokio::time::sleep_until(tokio::time::Instant::from_std(ticker.next)).await;
+                source.next_frame().await
+            } => next,
+        };
+
+        // Not raced with the signal, so a frame is never cut off halfway
+        let frame = match next {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break Ok(()),
+            Err(err) => break Err(err),
+        };
+        if let Err(err) = writer.add_frame(&frame).await {
+            break Err(err);
+        }
+        stats.frames += 1;
+        stats.missed_ticks += ticker.advance(Instant::now());
+    };
+
+    let finished = finish_with_timeout(writer, finish_timeout).await;
+    result?;
+    Ok((finished?, stats))
+}
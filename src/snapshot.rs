@@ -0,0 +1,130 @@
+use std::io::Read;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use crate::{MjpegError, Result};
+use crate::range::{http_error, RequestOptions};
+use crate::source::FrameSource;
+
+/// A [`FrameSource`] that fetches a camera's JPEG snapshot URL for every frame.
+///
+/// A fallback for cameras without a reliable stream endpoint, such as the snapshot URI of an
+/// ONVIF camera: poll it with [`pull_at_fps`](crate::pull_at_fps), whose drift-free schedule
+/// keeps the frame rate even when single requests are slow. Requests are conditional
+/// (`If-None-Match` / `If-Modified-Since`), so a camera that hasn't updated its image can answer
+/// `304 Not Modified`, and the previous frame is repeated to keep the timeline. Enabled by the
+/// `http` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{pull_at_fps, MissedTickPolicy, MjpegAviWriter, MjpegWriter, SnapshotSource};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut camera = SnapshotSource::new("http://192.168.0.10/onvif/snapshot.jpg");
+///     camera.basic_auth("admin", Some("password"));
+///     let mut writer = MjpegWriter::new(File::create("output.avi")?, 1280, 720, 2)?;
+///     pull_at_fps(&mut writer, 2, MissedTickPolicy::Skip, camera)?;
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+pub struct SnapshotSource {
+    client: reqwest::blocking::Client,
+    url: String,
+    options: RequestOptions,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    last_frame: Option<Vec<u8>>,
+    not_modified: u64,
+}
+
+impl SnapshotSource {
+    /// Creates a source polling `url` with a default client.
+    pub fn new<U: Into<String>>(url: U) -> Self {
+        Self::with_client(reqwest::blocking::Client::new(), url)
+    }
+
+    /// Creates a source polling `url` with `client`, e.g. one with timeouts.
+    pub fn with_client<U: Into<String>>(client: reqwest::blocking::Client, url: U) -> Self {
+        SnapshotSource {
+            client,
+            url: url.into(),
+            options: RequestOptions::default(),
+            etag: None,
+            last_modified: None,
+            last_frame: None,
+            not_modified: 0,
+        }
+    }
+
+    /// Sends HTTP Basic credentials with every request.
+    pub fn basic_auth<U: Into<String>, P: Into<String>>(&mut self, username: U, password: Option<P>) -> &mut Self {
+        self.options.basic_auth = Some((username.into(), password.map(Into::into)));
+        self
+    }
+
+    /// Adds a header sent with every request, such as an API token.
+    pub fn header<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) -> &mut Self {
+        self.options.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The number of polls answered with `304 Not Modified`, which repeated the previous frame.
+    pub fn not_modified(&self) -> u64 {
+        self.not_modified
+    }
+
+    /// Fetches the current snapshot.
+    pub fn fetch(&mut self) -> Result<Vec<u8>> {
+        let mut frame = Vec::new();
+        self.fetch_into(&mut frame)?;
+        Ok(frame)
+    }
+
+    /// Fetches the current snapshot into `frame`, replacing its contents.
+    fn fetch_into(&mut self, frame: &mut Vec<u8>) -> Result<()> {
+        frame.clear();
+        let mut request = self.options.apply(self.client.get(&self.url));
+        if self.last_frame.is_some() {
+            if let Some(etag) = &self.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &self.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let mut response = request.send().map_err(http_error)?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(last_frame) = &self.last_frame {
+                self.not_modified += 1;
+                frame.extend_from_slice(last_frame);
+                return Ok(());
+            }
+        }
+        if !response.status().is_success() {
+            return Err(MjpegError::Io(format!("GET {} failed: {}", self.url, response.status())));
+        }
+
+        let header = |name| Some(response.headers().get(name)?.to_str().ok()?.to_string());
+        self.etag = header(ETAG);
+        self.last_modified = header(LAST_MODIFIED);
+        response.read_to_end(frame)?;
+        let last_frame = self.last_frame.get_or_insert_with(Vec::new);
+        last_frame.clear();
+        last_frame.extend_from_slice(frame);
+        Ok(())
+    }
+}
+
+impl FrameSource for SnapshotSource {
+    /// Fetches the current snapshot; the source never ends.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        self.fetch().map(Some)
+    }
+
+    fn next_frame_into(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        self.fetch_into(buf).map(|()| true)
+    }
+}
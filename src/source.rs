@@ -0,0 +1,126 @@
+use std::sync::mpsc::{Receiver, RecvError};
+use crate::Result;
+use crate::buffers::PooledFrame;
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+
+/// A source of JPEG frames, such as a camera, a network stream or a directory of images.
+///
+/// [`pull_at_fps`](crate::pull_at_fps) takes any source, so the recording loop doesn't depend
+/// on where the frames come from. It is implemented for closures returning
+/// `Result<Option<Vec<u8>>>` and for channel receivers of `Vec<u8>` or
+/// [`PooledFrame`](crate::PooledFrame), which end when all senders are dropped.
+pub trait FrameSource {
+    /// Returns the next frame, or `None` when the source has ended.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// Reads the next frame into `buf`, replacing its contents, and returns `false` when the
+    /// source has ended.
+    ///
+    /// The recording loops call this with one buffer for all frames. The default moves the
+    /// frame returned by [`next_frame`](Self::next_frame) into `buf`; sources that can fill
+    /// or swap buffers implement it to avoid allocating a buffer per frame.
+    fn next_frame_into(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        match self.next_frame()? {
+            Some(frame) => {
+                *buf = frame;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl<F: FnMut() -> Result<Option<Vec<u8>>>> FrameSource for F {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        self()
+    }
+}
+
+impl FrameSource for Receiver<Vec<u8>> {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.recv() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(RecvError) => Ok(None),
+        }
+    }
+}
+
+impl FrameSource for Receiver<PooledFrame> {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.recv() {
+            Ok(frame) => Ok(Some(frame.into_vec())),
+            Err(RecvError) => Ok(None),
+        }
+    }
+
+    /// Swaps `buf` with the received frame, whose pool gets the previous buffer.
+    fn next_frame_into(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        match self.recv() {
+            Ok(mut frame) => {
+                std::mem::swap(buf, &mut frame);
+                Ok(true)
+            }
+            Err(RecvError) => Ok(false),
+        }
+    }
+}
+
+/// Asynchronous version of [`FrameSource`].
+///
+/// It is implemented for closures returning a future of `Result<Option<Vec<u8>>>` and for
+/// tokio channel receivers of `Vec<u8>` or [`PooledFrame`](crate::PooledFrame).
+#[cfg(feature = "tokio")]
+pub trait AsyncFrameSource {
+    /// Returns the next frame, or `None` when the source has ended.
+    fn next_frame(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>>>;
+
+    /// Reads the next frame into `buf`, see [`FrameSource::next_frame_into`].
+    fn next_frame_into(&mut self, buf: &mut Vec<u8>) -> impl Future<Output = Result<bool>> {
+        async move {
+            match self.next_frame().await? {
+                Some(frame) => {
+                    *buf = frame;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<F, Fut> AsyncFrameSource for F
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<Vec<u8>>>>,
+{
+    fn next_frame(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>>> {
+        self()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncFrameSource for tokio::sync::mpsc::Receiver<Vec<u8>> {
+    async fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.recv().await)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncFrameSource for tokio::sync::mpsc::Receiver<PooledFrame> {
+    async fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.recv().await.map(PooledFrame::into_vec))
+    }
+
+    async fn next_frame_into(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        match self.recv().await {
+            Some(mut frame) => {
+                std::mem::swap(buf, &mut frame);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{MjpegError, Result};
+use crate::builder::MjpegWriterBuilder;
+use crate::mjpeg_sync::{MjpegAviWriter, MjpegWriter};
+use crate::writer::Writer;
+
+/// Records full-rate video and, in the same pass, a fast-scrub proxy file holding every
+/// `speedup`th frame, such as every 32nd frame for a review file that plays at 32x speed.
+///
+/// The proxy frames are copied unchanged and the proxy plays at the fps of the full file, so
+/// an hour of recording becomes a file of under two minutes, at 1/`speedup` of the size,
+/// without a post-processing pass. The proxy holds video stream 0 only; audio, data and the
+/// other streams go to the full file through [`full_mut`](Self::full_mut), and frames added
+/// there count towards the selection as well. Frame `n` of the proxy is frame `n * speedup` of
+/// the full file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegWriterBuilder, SpeedupRecorder};
+/// use std::fs::File;
+///
+/// # fn camera_frame() -> Option<Vec<u8>> { None }
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let builder = MjpegWriterBuilder::new(1280, 720, 30);
+///     let mut recorder = SpeedupRecorder::new(&builder, 32, File::create("full.avi")?, File::create("x32.avi")?)?;
+///     while let Some(frame) = camera_frame() {
+///         recorder.add_frame(&frame)?;
+///     }
+///     recorder.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[must_use = "The recorder must be finalized using .finish() to produce valid AVI files"]
+pub struct SpeedupRecorder<W: Writer, P: Writer> {
+    full: MjpegWriter<W>,
+    proxy: MjpegWriter<P>,
+    speedup: u32,
+}
+
+impl<W: Writer, P: Writer> SpeedupRecorder<W, P> {
+    /// Creates the full file with `builder` and a proxy file of the same frame size and fps.
+    ///
+    /// Returns [`MjpegError::InvalidFrameSize`] if `speedup` is 0.
+    pub fn new(builder: &MjpegWriterBuilder, speedup: u32, full: W, proxy: P) -> Result<Self> {
+        if speedup == 0 {
+            return Err(MjpegError::InvalidFrameSize);
+        }
+        let full = builder.build(full)?;
+        // The sizes were checked, or deliberately not, when building the full file
+        let proxy = MjpegWriterBuilder::new(builder.width(), builder.height(), builder.fps()).unchecked_dimensions(true).build(proxy)?;
+        Ok(SpeedupRecorder { full, proxy, speedup })
+    }
+
+    /// Adds a JPEG frame to the full file, and to the proxy if it is one of every `speedup` frames.
+    pub fn add_frame(&mut self, jpeg_binary: &[u8]) -> Result<()> {
+        self.add_frame_vectored(&[jpeg_binary])
+    }
+
+    /// Adds a JPEG frame from a slice of buffers, see [`add_frame`](Self::add_frame).
+    pub fn add_frame_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        let sampled = self.is_sampled();
+        self.full.add_frame_vectored(bufs)?;
+        if sampled {
+            self.proxy.add_frame_vectored(bufs)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a dropped frame to the full file, and to the proxy if it is one of every `speedup`
+    /// frames, so the proxy keeps its timing.
+    pub fn add_dropped_frame(&mut self) -> Result<()> {
+        let sampled = self.is_sampled();
+        self.full.add_dropped_frame()?;
+        if sampled {
+            self.proxy.add_dropped_frame()?;
+        }
+        Ok(())
+    }
+
+    /// The full-rate writer, for audio, data, bookmarks and the other streams.
+    pub fn full_mut(&mut self) -> &mut MjpegWriter<W> {
+        &mut self.full
+    }
+
+    /// Finalizes both files, returning the full and the proxy writer.
+    pub fn finish(self) -> Result<(W, P)> {
+        let full = self.full.finish()?;
+        let proxy = self.proxy.finish()?;
+        Ok((full, proxy))
+    }
+
+    /// Returns true if the next frame of the full file goes into the proxy.
+    fn is_sampled(&self) -> bool {
+        self.full.frame_count().is_multiple_of(self.speedup)
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A target that keeps the output in memory up to a cap and moves it to a temporary file
+/// beyond that.
+///
+/// Use it for request-scoped video generation in web services: most outputs stay small and
+/// never touch the disk, while a long one doesn't exhaust the memory. The temporary file is
+/// deleted when it is closed. After finishing the AVI writer, take the result with
+/// [`into_output`](Self::into_output).
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, SpillOutput, SpillWriter};
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut writer = MjpegWriter::new(SpillWriter::new(4 << 20), 640, 480, 30)?;
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     match writer.finish()?.into_output()? {
+///         SpillOutput::Memory(bytes) => println!("{} bytes in memory", bytes.len()),
+///         SpillOutput::File(file) => println!("{} bytes on disk", file.metadata()?.len()),
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SpillWriter {
+    cap: usize,
+    dir: PathBuf,
+    target: Target,
+}
+
+#[derive(Debug)]
+enum Target {
+    Memory(Cursor<Vec<u8>>),
+    File(File),
+}
+
+/// The output of a [`SpillWriter`].
+#[derive(Debug)]
+pub enum SpillOutput {
+    /// The output fit in memory.
+    Memory(Vec<u8>),
+    /// The output was spilled to a temporary file, positioned at the start. The file is deleted
+    /// when it is closed.
+    File(File),
+}
+
+impl SpillWriter {
+    /// Creates a writer that keeps up to `cap` bytes in memory and spills to the system's
+    /// temporary directory.
+    pub fn new(cap: usize) -> Self {
+        SpillWriter { cap, dir: std::env::temp_dir(), target: Target::Memory(Cursor::new(Vec::new())) }
+    }
+
+    /// Sets the directory of the temporary file. Takes effect if the writer hasn't spilled yet.
+    pub fn spill_dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Returns true if the output has been moved to a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.target, Target::File(_))
+    }
+
+    /// Returns the output: the bytes if they fit in memory, the temporary file otherwise.
+    pub fn into_output(self) -> io::Result<SpillOutput> {
+        match self.target {
+            Target::Memory(cursor) => Ok(SpillOutput::Memory(cursor.into_inner())),
+            Target::File(mut file) => {
+                file.flush()?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(SpillOutput::File(file))
+            }
+        }
+    }
+
+    /// Moves the buffered output to a temporary file, keeping the position.
+    fn spill(&mut self) -> io::Result<()> {
+        let Target::Memory(cursor) = &self.target else { return Ok(()) };
+        let mut file = create_temp_file(&self.dir)?;
+        file.write_all(cursor.get_ref())?;
+        file.seek(SeekFrom::Start(cursor.position()))?;
+        self.target = Target::File(file);
+        Ok(())
+    }
+}
+
+impl Write for SpillWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Target::Memory(cursor) = &self.target {
+            if cursor.position().saturating_add(buf.len() as u64) > self.cap as u64 {
+                self.spill()?;
+            }
+        }
+        match &mut self.target {
+            Target::Memory(cursor) => cursor.write(buf),
+            Target::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.target {
+            Target::Memory(_) => Ok(()),
+            Target::File(file) => file.flush(),
+        }
+    }
+}
+
+impl Seek for SpillWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.target {
+            Target::Memory(cursor) => cursor.seek(pos),
+            Target::File(file) => file.seek(pos),
+        }
+    }
+}
+
+impl Read for SpillWriter {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.target {
+            Target::Memory(cursor) => cursor.read(buf),
+            Target::File(file) => file.read(buf),
+        }
+    }
+}
+
+/// Creates a new file in `dir` that is deleted when it is closed.
+fn create_temp_file(dir: &std::path::Path) -> io::Result<File> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    loop {
+        let name = format!("mjpeg-avi-spill-{}-{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = dir.join(name);
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+            options.custom_flags(FILE_FLAG_DELETE_ON_CLOSE);
+        }
+        match options.open(&path) {
+            Ok(file) => {
+                // Unlinking an open file keeps it readable until it is closed
+                #[cfg(not(windows))]
+                std::fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
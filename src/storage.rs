@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// What to do with a finished AVI file before moving on to the next one.
+///
+/// A 24/7 recorder writes far more data than it ever reads back. Applying a policy after
+/// each file is finished makes the data durable and, with the `fadvise` feature, tells the
+/// kernel to drop the file's pages from the page cache so the recording doesn't evict the
+/// rest of the system's cache.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, StoragePolicy};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let file = File::create("segment.avi")?;
+///     let mut writer = MjpegWriter::new(file, 320, 240, 30)?;
+///     writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///     let file = writer.finish()?;
+///
+///     StoragePolicy::new().sync_file(true).sync_directory(true).apply(&file, "segment.avi")?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StoragePolicy {
+    sync_file: bool,
+    sync_directory: bool,
+    #[cfg(feature = "fadvise")]
+    drop_page_cache: bool,
+}
+
+impl StoragePolicy {
+    /// Creates a policy that does nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flushes the file's data and metadata to the device (`fsync`).
+    pub fn sync_file(&mut self, sync: bool) -> &mut Self {
+        self.sync_file = sync;
+        self
+    }
+
+    /// Flushes the parent directory so the file's directory entry is durable (Unix only).
+    pub fn sync_directory(&mut self, sync: bool) -> &mut Self {
+        self.sync_directory = sync;
+        self
+    }
+
+    /// Advises the kernel to drop the file's cached pages (`posix_fadvise(POSIX_FADV_DONTNEED)`).
+    ///
+    /// Only clean pages can be dropped, so the file is synced first even if
+    /// [`sync_file`](StoragePolicy::sync_file) is off. This is a no-op on platforms without
+    /// `posix_fadvise`.
+    #[cfg(feature = "fadvise")]
+    pub fn drop_page_cache(&mut self, drop: bool) -> &mut Self {
+        self.drop_page_cache = drop;
+        self
+    }
+
+    /// Applies the policy to a finished file located at `path`.
+    pub fn apply<P: AsRef<Path>>(&self, file: &File, path: P) -> io::Result<()> {
+        #[cfg(feature = "fadvise")]
+        let sync_file = self.sync_file || self.drop_page_cache;
+        #[cfg(not(feature = "fadvise"))]
+        let sync_file = self.sync_file;
+
+        if sync_file {
+            file.sync_all()?;
+        }
+
+        #[cfg(feature = "fadvise")]
+        if self.drop_page_cache {
+            fadvise_dontneed(file)?;
+        }
+
+        #[cfg(unix)]
+        if self.sync_directory {
+            let parent = match path.as_ref().parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            File::open(parent)?.sync_all()?;
+        }
+        #[cfg(not(unix))]
+        let _ = path;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "fadvise", any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn fadvise_dontneed(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // posix_fadvise returns the error number instead of setting errno
+    let ret = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "fadvise", not(any(target_os = "linux", target_os = "android", target_os = "freebsd"))))]
+fn fadvise_dontneed(_file: &File) -> io::Result<()> {
+    Ok(())
+}
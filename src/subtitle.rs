@@ -0,0 +1,171 @@
+use std::fmt;
+use std::time::Duration;
+use crate::Result;
+use crate::bookmark::Bookmark;
+
+#[cfg(feature = "annotations")]
+use crate::reader::AviInfo;
+
+/// A subtitle shown from `start` to `end`, see [`SubtitleTrack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleCue {
+    /// When the cue appears.
+    pub start: Duration,
+    /// When the cue disappears.
+    pub end: Duration,
+    /// The text, one or more lines.
+    pub text: String,
+}
+
+/// Event markers of a recording as an SRT or WebVTT subtitle file, so any player shows them
+/// next to the video.
+///
+/// Cues come from [bookmarks](Self::add_bookmarks), from an
+/// [annotation sidecar](Self::add_annotations) with the `annotations` feature, or are added
+/// directly. Save the output next to the AVI file with the same name, e.g. `output.srt` for
+/// `output.avi`, and players pick it up automatically.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegReader, SubtitleTrack};
+/// use std::fs::File;
+/// use std::time::Duration;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut reader = MjpegReader::new(File::open("output.avi")?)?;
+///     let mut srt = String::new();
+///     SubtitleTrack::new().add_bookmarks(&reader.bookmarks()?, Duration::from_secs(3)).write_srt(&mut srt)?;
+///     std::fs::write("output.srt", srt)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubtitleTrack {
+    cues: Vec<SubtitleCue>,
+}
+
+impl SubtitleTrack {
+    /// Creates an empty track.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cues in the order they were added.
+    pub fn cues(&self) -> &[SubtitleCue] {
+        &self.cues
+    }
+
+    /// Adds a cue.
+    pub fn add_cue<S: Into<String>>(&mut self, start: Duration, end: Duration, text: S) -> &mut Self {
+        self.cues.push(SubtitleCue { start, end, text: text.into() });
+        self
+    }
+
+    /// Adds a cue showing the label of each bookmark for `display`, or until the next bookmark.
+    pub fn add_bookmarks(&mut self, bookmarks: &[Bookmark], display: Duration) -> &mut Self {
+        let mut bookmarks: Vec<&Bookmark> = bookmarks.iter().collect();
+        bookmarks.sort_by_key(|bookmark| bookmark.frame);
+        for (i, bookmark) in bookmarks.iter().enumerate() {
+            let mut end = bookmark.timestamp + display;
+            if let Some(next) = bookmarks.get(i + 1).filter(|next| next.timestamp > bookmark.timestamp) {
+                end = end.min(next.timestamp);
+            }
+            self.add_cue(bookmark.timestamp, end, bookmark.label.as_str());
+        }
+        self
+    }
+
+    /// Adds the annotations of an [`AnnotationSink`](crate::AnnotationSink) sidecar, timed
+    /// with the frame rate in `info`.
+    ///
+    /// An annotation that is a string is shown as is, one that is an object with a `label`
+    /// string shows the label, and others show their JSON. Runs of consecutive frames with the
+    /// same text become one cue, which ends at the frame after the run. Enabled by the
+    /// `annotations` feature.
+    #[cfg(feature = "annotations")]
+    pub fn add_annotations<B: std::io::BufRead>(&mut self, ndjson: B, info: &AviInfo) -> Result<&mut Self> {
+        use crate::frame_map::frame_timestamp;
+        use serde_json::Value;
+
+        let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        // The first frame, the frame after the last one and the text of the current run
+        let mut run: Option<(u64, u64, String)> = None;
+        let mut runs = Vec::new();
+        for (number, line) in ndjson.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: Value = serde_json::from_str(&line).map_err(|err| invalid(format!("line {}: {}", number + 1, err)))?;
+            let frame = value["frame"].as_u64().ok_or_else(|| invalid(format!("line {}: no frame number", number + 1)))?;
+            let text = match &value["annotation"] {
+                Value::String(text) => text.clone(),
+                annotation => match annotation["label"].as_str() {
+                    Some(label) => label.to_string(),
+                    None => annotation.to_string(),
+                },
+            };
+            match &mut run {
+                Some((_, end, run_text)) if *end == frame && *run_text == text => *end += 1,
+                _ => runs.extend(run.replace((frame, frame + 1, text))),
+            }
+        }
+        runs.extend(run);
+        for (start, end, text) in runs {
+            self.add_cue(frame_timestamp(start, info.scale, info.rate), frame_timestamp(end, info.scale, info.rate), text);
+        }
+        Ok(self)
+    }
+
+    /// Writes the cues in SubRip (`.srt`) format, ordered by their start.
+    pub fn write_srt<O: fmt::Write>(&self, out: &mut O) -> Result<()> {
+        for (i, cue) in self.sorted().into_iter().enumerate() {
+            writeln!(out, "{}", i + 1)?;
+            write_time(out, cue.start, ',')?;
+            out.write_str(" --> ")?;
+            write_time(out, cue.end, ',')?;
+            out.write_char('\n')?;
+            for line in text_lines(&cue.text) {
+                writeln!(out, "{}", line)?;
+            }
+            out.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    /// Writes the cues in WebVTT (`.vtt`) format, ordered by their start.
+    pub fn write_webvtt<O: fmt::Write>(&self, out: &mut O) -> Result<()> {
+        out.write_str("WEBVTT\n\n")?;
+        for cue in self.sorted() {
+            write_time(out, cue.start, '.')?;
+            out.write_str(" --> ")?;
+            write_time(out, cue.end, '.')?;
+            out.write_char('\n')?;
+            for line in text_lines(&cue.text) {
+                // Cue text is HTML-like and must not contain the timing arrow
+                let line = line.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+                writeln!(out, "{}", line)?;
+            }
+            out.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    fn sorted(&self) -> Vec<&SubtitleCue> {
+        let mut cues: Vec<&SubtitleCue> = self.cues.iter().collect();
+        cues.sort_by_key(|cue| cue.start);
+        cues
+    }
+}
+
+/// Writes `HH:MM:SS,mmm`, with `separator` before the milliseconds.
+fn write_time<O: fmt::Write>(out: &mut O, time: Duration, separator: char) -> fmt::Result {
+    let secs = time.as_secs();
+    write!(out, "{:02}:{:02}:{:02}{}{:03}", secs / 3600, secs / 60 % 60, secs % 60, separator, time.subsec_millis())
+}
+
+/// The lines of a cue text; an empty line would end the cue.
+fn text_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(str::trim_end).filter(|line| !line.is_empty())
+}
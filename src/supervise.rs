@@ -0,0 +1,131 @@
+use std::future::Future;
+use std::time::Duration;
+use crate::{MjpegError, Result};
+use crate::source::AsyncFrameSource;
+
+/// How often a [`SupervisedSource`] restarts a failing source before giving up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// The number of restarts in a row, without a frame in between, before the error is
+    /// returned.
+    pub max_restarts: u32,
+    /// The time to wait before the first restart.
+    pub backoff: Duration,
+    /// The longest time to wait; the wait doubles with each restart in a row up to this.
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    /// 5 restarts, waiting 1 second, then 2, 4, ... up to 30 seconds.
+    fn default() -> Self {
+        RestartPolicy { max_restarts: 5, backoff: Duration::from_secs(1), max_backoff: Duration::from_secs(30) }
+    }
+}
+
+/// Statistics of a [`SupervisedSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RestartStats {
+    /// The number of times the source was restarted.
+    pub restarts: u64,
+    /// The number of restarts since the last frame.
+    pub consecutive: u32,
+}
+
+/// Restarts a flaky frame source, such as a network camera, when it fails, so long-running
+/// recorders survive without an external supervisor.
+///
+/// The source is created by `connect`, an async closure that opens the camera. When the source
+/// returns an error, it is dropped and `connect` is called again after the backoff of the
+/// [`RestartPolicy`]; failed connects count as restarts too. Once the policy's restarts in a
+/// row are used up, the error is returned to the recording loop, which finishes the writer.
+/// The source is a regular [`AsyncFrameSource`] for
+/// [`pull_at_fps_async`](crate::pull_at_fps_async) and `record_until_shutdown`. Enabled by the
+/// `tokio` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{pull_at_fps_async, MissedTickPolicy, MjpegAsyncWriter, MjpegAviWriterAsync, RestartPolicy, SupervisedSource};
+///
+/// # async fn open_camera() -> mjpeg_avi_rs::Result<tokio::sync::mpsc::Receiver<Vec<u8>>> { unimplemented!() }
+/// async fn record() -> mjpeg_avi_rs::Result<()> {
+///     let file = tokio::fs::File::create("output.avi").await?;
+///     let mut writer = MjpegAsyncWriter::new(file, 640, 480, 10).await?;
+///     let source = SupervisedSource::new(open_camera, RestartPolicy::default());
+///     let result = pull_at_fps_async(&mut writer, 10, MissedTickPolicy::Skip, source).await;
+///     writer.finish().await?;
+///     result.map(|_| ())
+/// }
+/// ```
+pub struct SupervisedSource<S, F> {
+    connect: F,
+    policy: RestartPolicy,
+    source: Option<S>,
+    stats: RestartStats,
+}
+
+impl<S, F, Fut> SupervisedSource<S, F>
+where
+    S: AsyncFrameSource,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<S>>,
+{
+    /// Creates a supervisor that opens the source with `connect` on the first frame.
+    pub fn new(connect: F, policy: RestartPolicy) -> Self {
+        SupervisedSource { connect, policy, source: None, stats: RestartStats::default() }
+    }
+
+    /// The restarts so far.
+    pub fn stats(&self) -> RestartStats {
+        self.stats
+    }
+
+    /// Counts a restart after `err` and waits out the backoff, or returns `err` if the restarts
+    /// in a row are used up.
+    async fn restart(&mut self, err: MjpegError) -> Result<()> {
+        self.source = None;
+        if self.stats.consecutive >= self.policy.max_restarts {
+            return Err(err);
+        }
+        let backoff = self.policy.backoff.saturating_mul(1 << self.stats.consecutive.min(16)).min(self.policy.max_backoff);
+        self.stats.restarts += 1;
+        self.stats.consecutive += 1;
+        tokio::time::sleep(backoff).await;
+        Ok(())
+    }
+}
+
+impl<S, F, Fut> AsyncFrameSource for SupervisedSource<S, F>
+where
+    S: AsyncFrameSource,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<S>>,
+{
+    async fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut frame = Vec::new();
+        Ok(self.next_frame_into(&mut frame).await?.then_some(frame))
+    }
+
+    async fn next_frame_into(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        loop {
+            let source = match &mut self.source {
+                Some(source) => source,
+                None => match (self.connect)().await {
+                    Ok(source) => self.source.insert(source),
+                    Err(err) => {
+                        self.restart(err).await?;
+                        continue;
+                    }
+                },
+            };
+            match source.next_frame_into(buf).await {
+                Ok(more) => {
+                    self.stats.consecutive = 0;
+                    return Ok(more);
+                }
+                Err(err) => self.restart(err).await?,
+            }
+        }
+    }
+}
+
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use crate::Result;
+use crate::lock::create_locked;
+use crate::storage::StoragePolicy;
+use crate::writer::Writer;
+
+/// The finished segments of a [`MemoryTarget`] by name: their bytes and when they were finalized.
+type MemorySegments = BTreeMap<String, (Vec<u8>, SystemTime)>;
+
+/// A finished segment listed by [`StorageTarget::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredSegment {
+    /// The name the segment was opened with.
+    pub name: String,
+    /// The size in bytes.
+    pub size: u64,
+    /// When the segment was last written, if the storage knows.
+    pub modified: Option<SystemTime>,
+}
+
+/// Storage for recorded segments, so a recording stack can run against storage other than
+/// a local file system, such as object stores or memory.
+///
+/// A segment is recorded into the writer returned by [`open_segment`](Self::open_segment) and
+/// handed back to [`finalize`](Self::finalize) after the AVI writer is finished, which makes it
+/// durable or uploads it. Segments are addressed by name, e.g. `cam1/0001.avi`.
+/// [`catalog_target`](crate::catalog_target) catalogs the segments of any target.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{FileTarget, MjpegAviWriter, MjpegWriterBuilder, StorageTarget};
+///
+/// fn record<T: StorageTarget>(target: &mut T, frames: &[Vec<u8>]) -> mjpeg_avi_rs::Result<()> {
+///     let builder = MjpegWriterBuilder::new(640, 480, 10);
+///     let mut writer = builder.build(target.open_segment("0001.avi")?)?;
+///     for frame in frames {
+///         writer.add_frame(frame)?;
+///     }
+///     let segment = writer.finish()?;
+///     target.finalize("0001.avi", segment)
+/// }
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     record(&mut FileTarget::new("/var/recordings/cam1")?, &[std::fs::read("frame.jpg")?])
+/// }
+/// ```
+pub trait StorageTarget {
+    /// The writer a segment is recorded into.
+    type Segment: Writer;
+    /// The reader of a finished segment.
+    type Reader: Read + Seek;
+
+    /// Creates the segment `name`, replacing a finished segment of the same name.
+    fn open_segment(&mut self, name: &str) -> Result<Self::Segment>;
+
+    /// Stores the segment `name` after its AVI writer is finished; `segment` is the writer
+    /// returned by `finish()`.
+    fn finalize(&mut self, name: &str, segment: Self::Segment) -> Result<()>;
+
+    /// Deletes the segment `name`, e.g. when retention expires it.
+    fn delete(&mut self, name: &str) -> Result<()>;
+
+    /// Lists the finished segments, sorted by name.
+    fn list(&mut self) -> Result<Vec<StoredSegment>>;
+
+    /// Opens the finished segment `name` for reading.
+    fn open_read(&mut self, name: &str) -> Result<Self::Reader>;
+}
+
+/// A [`StorageTarget`] that stores segments as files in a directory.
+///
+/// Segments are created with [`create_locked`](crate::create_locked) and finished with a
+/// [`StoragePolicy`]. [`list`](StorageTarget::list) reports the `.avi` files of the
+/// directory, including recordings in progress, as [`catalog`](crate::catalog) does.
+#[derive(Debug, Clone)]
+pub struct FileTarget {
+    dir: PathBuf,
+    policy: StoragePolicy,
+}
+
+impl FileTarget {
+    /// Creates a target for `dir`, creating the directory if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(FileTarget { dir: dir.as_ref().to_path_buf(), policy: StoragePolicy::new() })
+    }
+
+    /// Sets the policy applied to finished segments. Default: [`StoragePolicy::new`], which does
+    /// nothing.
+    pub fn policy(&mut self, policy: StoragePolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The path of the segment `name`.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl StorageTarget for FileTarget {
+    type Segment = File;
+    type Reader = File;
+
+    fn open_segment(&mut self, name: &str) -> Result<File> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        create_locked(path)
+    }
+
+    fn finalize(&mut self, name: &str, segment: File) -> Result<()> {
+        self.policy.apply(&segment, self.path(name))?;
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<()> {
+        fs::remove_file(self.path(name))?;
+        Ok(())
+    }
+
+    fn list(&mut self) -> Result<Vec<StoredSegment>> {
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type()?.is_file() || !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("avi")) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            segments.push(StoredSegment {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        segments.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(segments)
+    }
+
+    fn open_read(&mut self, name: &str) -> Result<File> {
+        Ok(File::open(self.path(name))?)
+    }
+}
+
+/// A [`StorageTarget`] that keeps segments in memory, for tests and short-lived previews.
+///
+/// Clones share the segments. Segments being recorded are listed once they are finalized.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTarget {
+    segments: Arc<Mutex<MemorySegments>>,
+}
+
+impl MemoryTarget {
+    /// Creates an empty target.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bytes of the finished segment `name`.
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.lock().get(name).map(|(data, _)| data.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemorySegments> {
+        // The map stays consistent even if a holder panicked
+        self.segments.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl StorageTarget for MemoryTarget {
+    type Segment = Cursor<Vec<u8>>;
+    type Reader = Cursor<Vec<u8>>;
+
+    fn open_segment(&mut self, _name: &str) -> Result<Cursor<Vec<u8>>> {
+        Ok(Cursor::new(Vec::new()))
+    }
+
+    fn finalize(&mut self, name: &str, segment: Cursor<Vec<u8>>) -> Result<()> {
+        self.lock().insert(name.to_string(), (segment.into_inner(), SystemTime::now()));
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<()> {
+        self.lock().remove(name).map(|_| ()).ok_or_else(|| not_found(name))
+    }
+
+    fn list(&mut self) -> Result<Vec<StoredSegment>> {
+        Ok(self
+            .lock()
+            .iter()
+            .map(|(name, (data, modified))| StoredSegment { name: name.clone(), size: data.len() as u64, modified: Some(*modified) })
+            .collect())
+    }
+
+    fn open_read(&mut self, name: &str) -> Result<Cursor<Vec<u8>>> {
+        self.get(name).map(Cursor::new).ok_or_else(|| not_found(name))
+    }
+}
+
+fn not_found(name: &str) -> crate::MjpegError {
+    io::Error::new(io::ErrorKind::NotFound, format!("no segment named {}", name)).into()
+}
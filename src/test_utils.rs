@@ -0,0 +1,369 @@
+//! Helpers for testing applications and features built on this crate.
+//!
+//! Enabled by the `test-utils` feature.
+//!
+//! The [`ffprobe`] functions check generated files against FFmpeg, a widely used reference
+//! decoder. They return `Ok(None)` when `ffprobe`/`ffmpeg` are not installed, so tests using
+//! them can skip instead of failing on machines without FFmpeg. [`MockClock`] runs the
+//! time-based parts of the crate deterministically, and [`MockStorage`] stands in for cloud
+//! storage with injected faults.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use crate::{MjpegError, Result};
+use crate::clock::{Clock, SystemClock};
+use crate::target::{MemoryTarget, StorageTarget, StoredSegment};
+
+/// A [`Clock`] that only moves when told to.
+///
+/// `sleep` returns immediately after advancing the clock by the requested duration, so loops
+/// paced by the clock run instantly and see exactly the time they asked for. Clones share the
+/// same time, so a test can keep one to advance or inspect the clock given to the code under
+/// test.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    start_system_time: SystemTime,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    /// Creates a clock starting at the current time.
+    pub fn new() -> Self {
+        MockClock {
+            start: Instant::now(),
+            start_system_time: SystemTime::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// The time the clock has moved since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.start_system_time + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// An operation of a [`StorageTarget`], to inject faults into with [`MockStorage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StorageOp {
+    /// [`StorageTarget::open_segment`]
+    OpenSegment,
+    /// [`StorageTarget::finalize`]
+    Finalize,
+    /// [`StorageTarget::delete`]
+    Delete,
+    /// [`StorageTarget::list`]
+    List,
+    /// [`StorageTarget::open_read`]
+    OpenRead,
+}
+
+/// A fault injected by [`MockStorage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// The operation fails as if the store answered `500 Internal Server Error`.
+    ServerError,
+    /// Only the first bytes of the segment are stored before `finalize` fails, as if the
+    /// connection dropped during the upload. Other operations fail as with `ServerError`.
+    PartialUpload(usize),
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    latency: Duration,
+    faults: BTreeMap<StorageOp, VecDeque<Fault>>,
+    calls: BTreeMap<StorageOp, u64>,
+}
+
+/// An in-memory [`StorageTarget`] with injected latency and failures, so the cloud recording
+/// path of an application, such as its retries and what it does with partial uploads, can be
+/// tested without a network or an object store.
+///
+/// Segments are kept as by [`MemoryTarget`]. Every operation first waits the latency on the
+/// clock, then takes the next fault queued for it with [`inject`](Self::inject), if any.
+/// Faults are consumed in order, so a test can make the third upload fail by queuing it after
+/// the others. Clones share the segments, the faults and the call counts, so a test keeps one
+/// to set up faults and check the results while another is used by the code under test.
+///
+/// # Examples
+///
+/// ```
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriterBuilder, StorageTarget};
+/// use mjpeg_avi_rs::test_utils::{Fault, MockClock, MockStorage, StorageOp};
+/// use std::time::Duration;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let clock = MockClock::new();
+///     let mut storage = MockStorage::with_clock(clock.clone());
+///     storage.set_latency(Duration::from_millis(200));
+///     storage.inject(StorageOp::Finalize, Fault::ServerError);
+///
+///     let builder = MjpegWriterBuilder::new(320, 240, 10);
+///     let mut writer = builder.build(storage.open_segment("0001.avi")?)?;
+///     writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///     let segment = writer.finish()?;
+///     assert!(storage.finalize("0001.avi", segment.clone()).is_err());
+///     // The retry goes through
+///     storage.finalize("0001.avi", segment)?;
+///     assert_eq!(storage.calls(StorageOp::Finalize), 2);
+///     assert_eq!(clock.elapsed(), Duration::from_millis(600));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockStorage<C: Clock = SystemClock> {
+    target: MemoryTarget,
+    clock: C,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockStorage {
+    /// Creates an empty store without latency or faults.
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for MockStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> MockStorage<C> {
+    /// Creates an empty store that waits on `clock`, e.g. a [`MockClock`].
+    pub fn with_clock(clock: C) -> Self {
+        MockStorage { target: MemoryTarget::new(), clock, state: Arc::default() }
+    }
+
+    /// Sets the time every operation takes. Default: none.
+    pub fn set_latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    /// Queues `fault` for the next call of `op` that has no fault queued before it.
+    pub fn inject(&self, op: StorageOp, fault: Fault) {
+        self.lock().faults.entry(op).or_default().push_back(fault);
+    }
+
+    /// Makes the next `times` calls of `op` fail with [`Fault::ServerError`].
+    pub fn fail(&self, op: StorageOp, times: usize) {
+        for _ in 0..times {
+            self.inject(op, Fault::ServerError);
+        }
+    }
+
+    /// Drops the faults that have not been used yet.
+    pub fn clear_faults(&self) {
+        self.lock().faults.clear();
+    }
+
+    /// The number of calls of `op` so far, including the failed ones.
+    pub fn calls(&self, op: StorageOp) -> u64 {
+        self.lock().calls.get(&op).copied().unwrap_or(0)
+    }
+
+    /// The bytes of the stored segment `name`, which are cut short after a
+    /// [`Fault::PartialUpload`].
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.target.get(name)
+    }
+
+    /// Waits the latency, counts the call and takes its fault.
+    fn begin(&self, op: StorageOp) -> Option<Fault> {
+        let latency = self.lock().latency;
+        if !latency.is_zero() {
+            self.clock.sleep(latency);
+        }
+        let mut state = self.lock();
+        *state.calls.entry(op).or_default() += 1;
+        state.faults.get_mut(&op).and_then(VecDeque::pop_front)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<C: Clock> StorageTarget for MockStorage<C> {
+    type Segment = Cursor<Vec<u8>>;
+    type Reader = Cursor<Vec<u8>>;
+
+    fn open_segment(&mut self, name: &str) -> Result<Cursor<Vec<u8>>> {
+        match self.begin(StorageOp::OpenSegment) {
+            Some(_) => Err(server_error(StorageOp::OpenSegment, name)),
+            None => self.target.open_segment(name),
+        }
+    }
+
+    fn finalize(&mut self, name: &str, segment: Cursor<Vec<u8>>) -> Result<()> {
+        match self.begin(StorageOp::Finalize) {
+            Some(Fault::PartialUpload(bytes)) => {
+                let mut data = segment.into_inner();
+                data.truncate(bytes);
+                self.target.finalize(name, Cursor::new(data))?;
+                Err(MjpegError::Io(format!("upload of {} interrupted after {} bytes", name, bytes)))
+            }
+            Some(Fault::ServerError) => Err(server_error(StorageOp::Finalize, name)),
+            None => self.target.finalize(name, segment),
+        }
+    }
+
+    fn delete(&mut self, name: &str) -> Result<()> {
+        match self.begin(StorageOp::Delete) {
+            Some(_) => Err(server_error(StorageOp::Delete, name)),
+            None => self.target.delete(name),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<StoredSegment>> {
+        match self.begin(StorageOp::List) {
+            Some(_) => Err(server_error(StorageOp::List, "")),
+            None => self.target.list(),
+        }
+    }
+
+    fn open_read(&mut self, name: &str) -> Result<Cursor<Vec<u8>>> {
+        match self.begin(StorageOp::OpenRead) {
+            Some(_) => Err(server_error(StorageOp::OpenRead, name)),
+            None => self.target.open_read(name),
+        }
+    }
+}
+
+fn server_error(op: StorageOp, name: &str) -> MjpegError {
+    MjpegError::Io(format!("{:?} {} failed: 500 Internal Server Error", op, name))
+}
+
+/// Playability checks using the FFmpeg command line tools.
+pub mod ffprobe {
+    use std::path::Path;
+    use std::process::Command;
+    use crate::{MjpegError, Result};
+
+    /// Stream properties of the first video stream reported by `ffprobe`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ProbeInfo {
+        /// The codec name, `"mjpeg"` for files produced by this crate.
+        pub codec_name: String,
+        /// The width of the video frames.
+        pub width: u32,
+        /// The height of the video frames.
+        pub height: u32,
+        /// The number of frames actually decoded by `ffprobe -count_frames`.
+        pub frames: u64,
+        /// The frame rate as a `(numerator, denominator)` pair.
+        pub frame_rate: (u32, u32),
+        /// The container duration in seconds, if reported.
+        pub duration: Option<f64>,
+    }
+
+    impl ProbeInfo {
+        /// The frame rate in frames per second.
+        pub fn fps(&self) -> f64 {
+            self.frame_rate.0 as f64 / self.frame_rate.1.max(1) as f64
+        }
+
+        /// Panics with a descriptive message unless the stream matches the expected values.
+        pub fn assert_matches(&self, width: u32, height: u32, fps: u32, frames: u64) {
+            assert_eq!(self.codec_name, "mjpeg", "unexpected codec: {:?}", self);
+            assert_eq!((self.width, self.height), (width, height), "unexpected dimensions: {:?}", self);
+            assert!((self.fps() - fps as f64).abs() < 1e-6, "unexpected frame rate: {:?}", self);
+            assert_eq!(self.frames, frames, "unexpected frame count: {:?}", self);
+            if let Some(duration) = self.duration {
+                let expected = frames as f64 / fps as f64;
+                assert!((duration - expected).abs() <= 1.0 / fps as f64, "unexpected duration: {:?}", self);
+            }
+        }
+    }
+
+    /// Returns true if `ffprobe` can be run.
+    pub fn available() -> bool {
+        Command::new("ffprobe").arg("-version").output().is_ok_and(|o| o.status.success())
+    }
+
+    /// Probes the first video stream of `path`, decoding every frame to count them.
+    ///
+    /// Returns `Ok(None)` if `ffprobe` is not installed.
+    pub fn probe<P: AsRef<Path>>(path: P) -> Result<Option<ProbeInfo>> {
+        if !available() {
+            return Ok(None);
+        }
+
+        let output = Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-count_frames"])
+            .args(["-show_entries", "stream=codec_name,width,height,r_frame_rate,nb_read_frames:format=duration"])
+            .args(["-of", "default=noprint_wrappers=1"])
+            .arg(path.as_ref())
+            .output()?;
+        if !output.status.success() {
+            return Err(MjpegError::Io(format!("ffprobe failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let field = |name: &str| {
+            stdout
+                .lines()
+                .find_map(|line| line.strip_prefix(name).and_then(|l| l.strip_prefix('=')))
+                .map(str::trim)
+                .ok_or_else(|| MjpegError::Io(format!("ffprobe didn't report {}", name)))
+        };
+        let number = |name: &str| -> Result<u64> {
+            field(name)?.parse().map_err(|_| MjpegError::Io(format!("ffprobe reported an invalid {}", name)))
+        };
+
+        let (num, den) = field("r_frame_rate")?.split_once('/').unwrap_or(("0", "1"));
+        Ok(Some(ProbeInfo {
+            codec_name: field("codec_name")?.to_string(),
+            width: number("width")? as u32,
+            height: number("height")? as u32,
+            frames: number("nb_read_frames")?,
+            frame_rate: (num.parse().unwrap_or(0), den.parse().unwrap_or(1)),
+            duration: field("duration").ok().and_then(|d| d.parse().ok()),
+        }))
+    }
+
+    /// Decodes the whole file with `ffmpeg` and returns the decoder's error output.
+    ///
+    /// An empty string means the file decoded cleanly. Returns `Ok(None)` if `ffmpeg` is not installed.
+    pub fn decode_errors<P: AsRef<Path>>(path: P) -> Result<Option<String>> {
+        let output = match Command::new("ffmpeg")
+            .args(["-v", "error", "-nostdin", "-i"])
+            .arg(path.as_ref())
+            .args(["-f", "null", "-"])
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+    }
+}
@@ -0,0 +1,246 @@
+use image::{GrayImage, ImageFormat, Rgb, RgbImage};
+use image::codecs::jpeg::JpegEncoder;
+use crate::{MjpegError, Result};
+use crate::source::FrameSource;
+use crate::timecode::Timecode;
+
+/// The 75% color bars, left to right: white, yellow, cyan, green, magenta, red, blue.
+const BARS: [[u8; 3]; 7] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+];
+/// The strip under the bars: blue, black, magenta, black, cyan, black, white.
+const CASTELLATIONS: [[u8; 3]; 7] = [
+    [0, 0, 191],
+    [19, 19, 19],
+    [191, 0, 191],
+    [19, 19, 19],
+    [0, 191, 191],
+    [19, 19, 19],
+    [191, 191, 191],
+];
+/// The bits of the frame counter, in two rows.
+const COUNTER_BITS: u32 = 32;
+/// The cells of a counter row: a black guard cell, 16 bits and a white guard cell.
+const ROW_CELLS: u32 = COUNTER_BITS / 2 + 2;
+/// 5x7 glyphs of the digits and `:`, one byte per row, bit 4 is the leftmost pixel.
+const GLYPHS: [[u8; 7]; 11] = [
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+];
+
+/// Generates the frames of a reference video: color bars with a moving circle, the timecode
+/// and frame number burned in, and the frame number as a binary counter.
+///
+/// Record a test pattern to check a playback stack against this crate's output: the bars
+/// show color conversion errors, the circle sweeps the width once per second so stutter is
+/// easy to see, and the burned-in timecode tells which frame is shown. The counter strip at
+/// the bottom holds the frame number as 32 black or white cells in two rows, which survives
+/// JPEG compression and is read back by [`SequenceValidator`](crate::SequenceValidator) to
+/// find frames lost, repeated or reordered in a capture pipeline. Patterns of at least 160x120 keep the text and the cells readable.
+///
+/// The pattern is a [`FrameSource`] of frames 0, 1, 2, ..., endless unless limited with
+/// [`frames`](Self::frames). Enabled by the `testpattern` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, TestPattern};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let pattern = TestPattern::new(1280, 720, 30);
+///     let mut writer = MjpegWriter::new(File::create("reference.avi")?, 1280, 720, 30)?;
+///     for frame in 0..30 * 60 {
+///         writer.add_frame(&pattern.encode(frame)?)?;
+///     }
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestPattern {
+    width: u32,
+    height: u32,
+    fps: u32,
+    quality: u8,
+    next: u64,
+    limit: Option<u64>,
+}
+
+impl TestPattern {
+    /// Creates a pattern of `width` x `height` frames at `fps`.
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        TestPattern { width, height, fps: fps.max(1), quality: 85, next: 0, limit: None }
+    }
+
+    /// Sets the JPEG quality, 1 to 100. Default: 85.
+    pub fn quality(&mut self, quality: u8) -> &mut Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Ends the frame source after `count` frames. Default: endless.
+    pub fn frames(&mut self, count: u64) -> &mut Self {
+        self.limit = Some(count);
+        self
+    }
+
+    /// Draws frame number `frame`.
+    pub fn render(&self, frame: u64) -> RgbImage {
+        let (width, height) = (self.width, self.height);
+        let mut image = RgbImage::new(width, height);
+        let bars_end = height * 2 / 3;
+        let strip_end = height * 3 / 4;
+        let text_end = height * 7 / 8;
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let bar = (x * 7 / width.max(1)) as usize;
+            *pixel = Rgb(match y {
+                y if y < bars_end => BARS[bar],
+                y if y < strip_end => CASTELLATIONS[bar],
+                _ => [0, 0, 0],
+            });
+        }
+
+        // A circle sweeping the bars once per second
+        let radius = (height / 12).max(2) as i64;
+        let travel = (width as i64 - 2 * radius).max(1);
+        let center_x = radius + (frame % self.fps as u64) as i64 * travel / self.fps as i64;
+        let center_y = (bars_end / 2) as i64;
+        for y in (center_y - radius).max(0)..(center_y + radius + 1).min(bars_end as i64) {
+            for x in (center_x - radius).max(0)..(center_x + radius + 1).min(width as i64) {
+                if (x - center_x).pow(2) + (y - center_y).pow(2) <= radius * radius {
+                    image.put_pixel(x as u32, y as u32, Rgb([235, 235, 235]));
+                }
+            }
+        }
+
+        // The timecode and the frame number, scaled to the text band
+        let text = format!("{} {}", Timecode::from_frames(frame, self.fps), frame);
+        let scale = ((text_end - strip_end) / 9).min(width / (text.len() as u32 * 6)).max(1);
+        let left = width.saturating_sub(text.len() as u32 * 6 * scale) / 2;
+        let top = strip_end + (text_end - strip_end).saturating_sub(7 * scale) / 2;
+        for (i, c) in text.chars().enumerate() {
+            let glyph = match c {
+                '0'..='9' => GLYPHS[c as usize - '0' as usize],
+                ':' => GLYPHS[10],
+                _ => continue,
+            };
+            let glyph_left = left + i as u32 * 6 * scale;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0x10 >> column) != 0 {
+                        fill(&mut image, glyph_left + column * scale, top + row as u32 * scale, scale, scale, [235, 235, 235]);
+                    }
+                }
+            }
+        }
+
+        // The frame number as binary cells, most significant bit first
+        for row in 0..2 {
+            for column in 0..ROW_CELLS {
+                let value = match column {
+                    0 => 0,
+                    column if column == ROW_CELLS - 1 => 1,
+                    column => (frame as u32 >> (COUNTER_BITS - row * 16 - column)) & 1,
+                };
+                let (x, y, cell_width, cell_height) = counter_cell(width, height, row, column);
+                fill(&mut image, x, y, cell_width, cell_height, if value == 1 { [255, 255, 255] } else { [0, 0, 0] });
+            }
+        }
+        image
+    }
+
+    /// Draws frame number `frame` and encodes it as JPEG.
+    pub fn encode(&self, frame: u64) -> Result<Vec<u8>> {
+        let image = self.render(frame);
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, self.quality)
+            .encode_image(&image)
+            .map_err(|err| MjpegError::Codec(err.to_string()))?;
+        Ok(jpeg)
+    }
+}
+
+impl FrameSource for TestPattern {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.limit.is_some_and(|limit| self.next >= limit) {
+            return Ok(None);
+        }
+        let jpeg = self.encode(self.next)?;
+        self.next += 1;
+        Ok(Some(jpeg))
+    }
+}
+
+/// The rectangle of the counter cell in `row` and `column` of a frame of `width` x
+/// `height`: x, y, width, height.
+///
+/// The two rows fill the bottom eighth of the frame, each with a black guard cell, 16 bits
+/// from the most significant and a white guard cell. The guard cells tell counters from
+/// frames of other sources.
+fn counter_cell(width: u32, height: u32, row: u32, column: u32) -> (u32, u32, u32, u32) {
+    let cell_width = (width / ROW_CELLS).max(1);
+    let top = height * 7 / 8;
+    let cell_height = ((height - top) / 2).max(1);
+    let left = width.saturating_sub(cell_width * ROW_CELLS) / 2;
+    (left + column * cell_width, top + row * cell_height, cell_width, cell_height)
+}
+
+/// Reads the frame counter of a test pattern frame, `None` if the frame can't be decoded, a
+/// cell is neither clearly black nor clearly white, or a guard cell is wrong.
+pub(crate) fn read_counter(jpeg: &[u8]) -> Option<u32> {
+    let image = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg).ok()?.to_luma8();
+    let mut counter = 0u32;
+    for row in 0..2 {
+        for column in 0..ROW_CELLS {
+            let (x, y, width, height) = counter_cell(image.width(), image.height(), row, column);
+            // The middle of the cell, away from the ringing at its edges
+            let value = match mean_luma(&image, x + width / 4, y + height / 4, width.div_ceil(2), height.div_ceil(2))? {
+                0..=95 => 0,
+                160.. => 1,
+                _ => return None,
+            };
+            match column {
+                0 if value != 0 => return None,
+                column if column == ROW_CELLS - 1 && value != 1 => return None,
+                column if column > 0 && column < ROW_CELLS - 1 => counter = counter << 1 | value,
+                _ => {}
+            }
+        }
+    }
+    Some(counter)
+}
+
+fn mean_luma(image: &GrayImage, x: u32, y: u32, width: u32, height: u32) -> Option<u32> {
+    let (mut sum, mut count) = (0u32, 0u32);
+    for y in y..(y + height).min(image.height()) {
+        for x in x..(x + width).min(image.width()) {
+            sum += image.get_pixel(x, y)[0] as u32;
+            count += 1;
+        }
+    }
+    sum.checked_div(count)
+}
+
+fn fill(image: &mut RgbImage, x: u32, y: u32, width: u32, height: u32, color: [u8; 3]) {
+    for y in y..(y + height).min(image.height()) {
+        for x in x..(x + width).min(image.width()) {
+            image.put_pixel(x, y, Rgb(color));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::{self, IoSlice, Read, SeekFrom, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::Result;
+use crate::clock::{Clock, SystemClock};
+use crate::writer::Writer;
+
+/// A bandwidth limit shared between a [`RateLimited`] stream and the code controlling it.
+///
+/// Clones share the limit, so it can be changed at runtime, e.g. when the uplink is needed for
+/// live video. Zero means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit(Arc<AtomicU64>);
+
+impl RateLimit {
+    /// Creates a limit of `bytes_per_sec`.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimit(Arc::new(AtomicU64::new(bytes_per_sec)))
+    }
+
+    /// The limit in bytes per second, zero if unlimited.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Changes the limit. Streams pick up the new value with their next write or read.
+    pub fn set(&self, bytes_per_sec: u64) {
+        self.0.store(bytes_per_sec, Ordering::Relaxed)
+    }
+}
+
+/// Wraps a writer or reader and limits its throughput with a token bucket.
+///
+/// Wrap the output of the AVI writer to shape a recording streamed to a network target, or
+/// the reader of a finished file to shape an upload, so it doesn't saturate a constrained
+/// uplink. Up to one second's worth of bytes can be sent in a burst after an idle period.
+/// Seeks are not limited.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{RateLimit, RateLimited};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let limit = RateLimit::new(512 * 1024);
+///     let mut upload = RateLimited::new(File::open("output.avi")?, limit.clone());
+///     // Hand `upload` to the HTTP client as the request body; raise the limit at night
+///     limit.set(4 * 1024 * 1024);
+///     std::io::copy(&mut upload, &mut std::io::sink())?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RateLimited<T, C: Clock = SystemClock> {
+    inner: T,
+    limit: RateLimit,
+    clock: C,
+    /// Bytes that may be sent without waiting; negative while paying off a large write
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl<T> RateLimited<T> {
+    /// Wraps `inner`, limiting it to `limit`.
+    pub fn new(inner: T, limit: RateLimit) -> Self {
+        Self::with_clock(inner, limit, SystemClock)
+    }
+}
+
+impl<T, C: Clock> RateLimited<T, C> {
+    /// Wraps `inner`, waiting through `clock`.
+    pub fn with_clock(inner: T, limit: RateLimit, clock: C) -> Self {
+        let refilled = clock.now();
+        RateLimited { inner, limit, clock, tokens: 0.0, refilled }
+    }
+
+    /// The limit of this stream.
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// A reference to the wrapped stream.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Unwraps the stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Takes `len` bytes from the bucket, waiting until the bucket is no longer in debt.
+    fn acquire(&mut self, len: usize) {
+        let rate = self.limit.get();
+        let now = self.clock.now();
+        if rate == 0 {
+            self.tokens = 0.0;
+            self.refilled = now;
+            return;
+        }
+
+        let rate = rate as f64;
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate) - len as f64;
+        self.refilled = now;
+        if self.tokens < 0.0 {
+            let wait = Duration::from_secs_f64(-self.tokens / rate);
+            self.clock.sleep(wait);
+            self.tokens = 0.0;
+            self.refilled = now + wait;
+        }
+    }
+}
+
+impl<W: Writer, C: Clock> Writer for RateLimited<W, C> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.acquire(buf.len());
+        self.inner.write_all(buf)
+    }
+
+    fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        self.acquire(bufs.iter().map(|buf| buf.len()).sum());
+        self.inner.write_all_vectored(bufs)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<W: Write, C: Clock> Write for RateLimited<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.acquire(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read, C: Clock> Read for RateLimited<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.acquire(n);
+        Ok(n)
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::MjpegError;
+use crate::metadata::AviMetadata;
+use crate::riff::invalid;
+
+/// The fourcc of the data stream holding the timecodes of the frames.
+pub(crate) const TIMECODE_FOURCC: [u8; 4] = *b"tmcd";
+
+/// A non-drop-frame SMPTE timecode, `HH:MM:SS:FF`.
+///
+/// Parsed from and displayed as `01:00:00:00`; `;` is accepted as the last separator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Timecode {
+    /// Hours, 0 to 23.
+    pub hours: u32,
+    /// Minutes, 0 to 59.
+    pub minutes: u32,
+    /// Seconds, 0 to 59.
+    pub seconds: u32,
+    /// Frames, below the frame rate.
+    pub frames: u32,
+}
+
+impl Timecode {
+    /// Creates a timecode.
+    pub fn new(hours: u32, minutes: u32, seconds: u32, frames: u32) -> Self {
+        Timecode { hours, minutes, seconds, frames }
+    }
+
+    /// The timecode `frames` frames after midnight at `fps`, wrapping around after 24 hours.
+    pub fn from_frames(frames: u64, fps: u32) -> Self {
+        let fps = fps.max(1) as u64;
+        let secs = frames / fps % 86400;
+        Timecode {
+            hours: (secs / 3600) as u32,
+            minutes: (secs / 60 % 60) as u32,
+            seconds: (secs % 60) as u32,
+            frames: (frames % fps) as u32,
+        }
+    }
+
+    /// The number of frames after midnight at `fps`.
+    pub fn to_frames(&self, fps: u32) -> u64 {
+        let secs = self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64;
+        secs * fps as u64 + self.frames as u64
+    }
+
+    /// The UTC time of day of `time` as a timecode at `fps`.
+    pub fn from_time(time: SystemTime, fps: u32) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let frames = since_epoch.as_secs() % 86400 * fps as u64 + since_epoch.subsec_nanos() as u64 * fps as u64 / 1_000_000_000;
+        Self::from_frames(frames, fps)
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds, self.frames)
+    }
+}
+
+impl FromStr for Timecode {
+    type Err = MjpegError;
+
+    fn from_str(s: &str) -> Result<Self, MjpegError> {
+        let fields: Vec<u32> = s.trim_end_matches('\0').split([':', ';']).map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid("invalid timecode"))?;
+        match fields[..] {
+            [hours, minutes, seconds, frames] if hours < 24 && minutes < 60 && seconds < 60 => Ok(Timecode { hours, minutes, seconds, frames }),
+            _ => Err(invalid("invalid timecode")),
+        }
+    }
+}
+
+/// Where the timecodes of a recording come from, see
+/// [`MjpegWriterBuilder::timecode`](crate::MjpegWriterBuilder::timecode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimecodeSource {
+    /// The first frame has the given timecode and every frame counts up by one frame, as
+    /// from a camera's record-run timecode.
+    Smpte(Timecode),
+    /// Every frame has the UTC time of day when it was written.
+    WallClock,
+}
+
+/// `metadata` with the start timecode of `source` as the `ISMP` tag, unless it has one.
+pub(crate) fn with_start_timecode(metadata: &AviMetadata, source: Option<TimecodeSource>) -> AviMetadata {
+    let mut metadata = metadata.clone();
+    if let Some(TimecodeSource::Smpte(start)) = source {
+        if !metadata.other.iter().any(|(id, _)| id == b"ISMP") {
+            metadata.other.push((*b"ISMP", start.to_string()));
+        }
+    }
+    metadata
+}
@@ -0,0 +1,190 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+use crate::Result;
+use crate::import::find_segment;
+use crate::timing::civil_time;
+
+#[cfg(feature = "regex")]
+use crate::MjpegError;
+
+/// How [`JpegImporter`](crate::JpegImporter) finds the capture time of an image.
+///
+/// Strategies are tried in the order they were added until one returns a time, so a reliable
+/// source can fall back to a rough one. Closures taking the path and the path relative to the
+/// input directory are strategies too.
+pub trait TimestampStrategy {
+    /// The capture time of the image at `path`, `None` if this strategy can't tell.
+    ///
+    /// `relative` is the path below the input directory.
+    fn timestamp(&self, path: &Path, relative: &Path) -> Result<Option<SystemTime>>;
+}
+
+impl<F: Fn(&Path, &Path) -> Result<Option<SystemTime>>> TimestampStrategy for F {
+    fn timestamp(&self, path: &Path, relative: &Path) -> Result<Option<SystemTime>> {
+        self(path, relative)
+    }
+}
+
+/// Reads the time from the digits of the relative path, in the order year, month, day, hour,
+/// minute and second, followed by the fraction of the second.
+///
+/// `2024/05/01/12/34/56.123.jpg`, `2024-05-01/123456_123.jpg` and `20240501_123456.jpg` all mean
+/// 12:34:56.123 on 2024-05-01 (UTC). Directory names with other digits, such as `cam1`, must be
+/// above the input directory. This is the default strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathDigits;
+
+impl TimestampStrategy for PathDigits {
+    fn timestamp(&self, _path: &Path, relative: &Path) -> Result<Option<SystemTime>> {
+        let digits: Vec<u32> = relative
+            .with_extension("")
+            .to_string_lossy()
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .collect();
+        if digits.len() < 14 {
+            return Ok(None);
+        }
+        let number = |digits: &[u32]| digits.iter().fold(0, |n, d| n * 10 + d);
+        Ok(civil_time(
+            number(&digits[0..4]) as i64,
+            number(&digits[4..6]),
+            number(&digits[6..8]),
+            number(&digits[8..10]),
+            number(&digits[10..12]),
+            number(&digits[12..14]),
+            fraction_nanos(&digits[14..]),
+        ))
+    }
+}
+
+/// Reads the EXIF `DateTimeOriginal` tag written by most cameras, with `SubSecTimeOriginal`
+/// if present.
+///
+/// EXIF has no time zone, so the time is taken as UTC like the other strategies.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExifTimestamp;
+
+impl TimestampStrategy for ExifTimestamp {
+    fn timestamp(&self, path: &Path, _relative: &Path) -> Result<Option<SystemTime>> {
+        // The EXIF segment comes right after the SOI marker and is limited to 64 KiB
+        let mut head = Vec::new();
+        File::open(path)?.take(70 * 1024).read_to_end(&mut head)?;
+        Ok(exif_date_time_original(&head))
+    }
+}
+
+/// Takes the modification time of the file, the last resort for images that were not copied
+/// without preserving it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModifiedTime;
+
+impl TimestampStrategy for ModifiedTime {
+    fn timestamp(&self, path: &Path, _relative: &Path) -> Result<Option<SystemTime>> {
+        Ok(std::fs::metadata(path)?.modified().ok())
+    }
+}
+
+/// Matches a regular expression against the relative path, using `/` as the separator.
+///
+/// The named groups `year`, `month` and `day` are required; `hour`, `minute`, `second` and
+/// `fraction` (digits of the fraction of a second) default to zero. Enabled by the `regex`
+/// feature.
+///
+/// ```
+/// # fn main() -> mjpeg_avi_rs::Result<()> {
+/// let strategy = mjpeg_avi_rs::FilenameRegex::new(
+///     r"IMG_(?<year>\d{4})(?<month>\d\d)(?<day>\d\d)_(?<hour>\d\d)(?<minute>\d\d)(?<second>\d\d)",
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct FilenameRegex(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl FilenameRegex {
+    /// Compiles `pattern`.
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = regex::Regex::new(pattern).map_err(|err| MjpegError::Io(err.to_string()))?;
+        Ok(FilenameRegex(regex))
+    }
+}
+
+#[cfg(feature = "regex")]
+impl TimestampStrategy for FilenameRegex {
+    fn timestamp(&self, _path: &Path, relative: &Path) -> Result<Option<SystemTime>> {
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let Some(captures) = self.0.captures(&relative) else {
+            return Ok(None);
+        };
+        let field = |name: &str| captures.name(name).map(|m| m.as_str().parse::<u32>().ok());
+        let (Some(Some(year)), Some(Some(month)), Some(Some(day))) = (field("year"), field("month"), field("day")) else {
+            return Ok(None);
+        };
+        let time = |name: &str| field(name).unwrap_or(Some(0));
+        let (Some(hour), Some(minute), Some(second)) = (time("hour"), time("minute"), time("second")) else {
+            return Ok(None);
+        };
+        let fraction: Vec<u32> = captures
+            .name("fraction")
+            .map_or("", |m| m.as_str())
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .collect();
+        Ok(civil_time(year as i64, month, day, hour, minute, second, fraction_nanos(&fraction)))
+    }
+}
+
+/// Converts the digits of a fraction of a second to nanoseconds, ignoring digits beyond them.
+fn fraction_nanos(digits: &[u32]) -> u32 {
+    let digits = &digits[..digits.len().min(9)];
+    digits.iter().fold(0, |n, d| n * 10 + d) * 10u32.pow(9 - digits.len() as u32)
+}
+
+/// Reads `DateTimeOriginal` and `SubSecTimeOriginal` from the EXIF segment of a JPEG image.
+pub(crate) fn exif_date_time_original(jpeg: &[u8]) -> Option<SystemTime> {
+    let tiff = find_segment(jpeg, |marker, payload| {
+        (marker == 0xE1 && payload.starts_with(b"Exif\0\0")).then(|| &payload[6..])
+    })?;
+    let big_endian = match tiff.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| {
+        let bytes = [*tiff.get(pos)?, *tiff.get(pos + 1)?];
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let u32_at = |pos: usize| {
+        let bytes: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+    // The position of the 12-byte entry of `tag` in the IFD at `ifd`
+    let find_entry = |ifd: usize, tag: u16| {
+        let count = u16_at(ifd)? as usize;
+        (0..count).map(|i| ifd + 2 + i * 12).find(|&entry| u16_at(entry) == Some(tag))
+    };
+    // The value of an ASCII entry, stored in the entry itself if it fits in 4 bytes
+    let ascii = |entry: usize| {
+        let count = u32_at(entry + 4)? as usize;
+        let start = if count <= 4 { entry + 8 } else { u32_at(entry + 8)? as usize };
+        let value = tiff.get(start..start + count)?;
+        std::str::from_utf8(value).ok().map(|s| s.trim_end_matches(['\0', ' ']))
+    };
+
+    let exif_ifd = u32_at(find_entry(u32_at(4)? as usize, 0x8769)? + 8)? as usize;
+    // "YYYY:MM:DD HH:MM:SS"
+    let date_time = ascii(find_entry(exif_ifd, 0x9003)?)?;
+    let field = |range: std::ops::Range<usize>| date_time.get(range)?.parse::<u32>().ok();
+    let sub_sec: Vec<u32> = find_entry(exif_ifd, 0x9291)
+        .and_then(ascii)
+        .unwrap_or("")
+        .chars()
+        .map_while(|c| c.to_digit(10))
+        .collect();
+    civil_time(field(0..4)? as i64, field(5..7)?, field(8..10)?, field(11..13)?, field(14..16)?, field(17..19)?, fraction_nanos(&sub_sec))
+}
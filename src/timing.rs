@@ -0,0 +1,130 @@
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::observer::FrameObserver;
+
+/// The timing of one frame, as recorded by [`TimingLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// The number of the frame, starting at 0.
+    pub frame: u64,
+    /// The presentation time of the frame in the file.
+    pub pts: Duration,
+    /// The wall-clock time the frame was captured.
+    pub source_time: SystemTime,
+}
+
+/// An observer that records the wall-clock time of every frame, for a timing sidecar file.
+///
+/// In an AVI file, frame `n` is always shown at `n / fps`. Editing tools that care about the
+/// original capture times can import the mapping written by [`write_csv`](Self::write_csv)
+/// next to the recording. By default, a frame's source time is the time it was written; call
+/// [`set_capture_time`](Self::set_capture_time) before adding a frame to record the camera's
+/// timestamp instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, ObservedWriter, TimingLog};
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut timing = TimingLog::new(30);
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 320, 240, 30)?;
+///     let mut writer = ObservedWriter::new(writer, &mut timing);
+///     writer.add_frame(&std::fs::read("frame.jpg")?)?;
+///     writer.finish()?;
+///     timing.write_csv(File::create("output.timing.csv")?)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimingLog {
+    fps: u32,
+    frames: Vec<FrameTiming>,
+    next_capture_time: Option<SystemTime>,
+}
+
+impl TimingLog {
+    /// Creates a log for a file played at `fps`.
+    pub fn new(fps: u32) -> Self {
+        TimingLog { fps, frames: Vec::new(), next_capture_time: None }
+    }
+
+    /// Sets the source time of the next frame.
+    pub fn set_capture_time(&mut self, time: SystemTime) {
+        self.next_capture_time = Some(time);
+    }
+
+    /// The frames recorded so far.
+    pub fn frames(&self) -> &[FrameTiming] {
+        &self.frames
+    }
+
+    /// Writes the log as CSV with the columns `frame`, `pts` (seconds) and `source_time` (RFC 3339, UTC).
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "frame,pts,source_time")?;
+        for timing in &self.frames {
+            writeln!(out, "{},{:.6},{}", timing.frame, timing.pts.as_secs_f64(), rfc3339(timing.source_time))?;
+        }
+        out.flush()
+    }
+}
+
+impl FrameObserver for TimingLog {
+    fn on_frame(&mut self, _bufs: &[&[u8]]) {
+        let frame = self.frames.len() as u64;
+        let pts = if self.fps == 0 { Duration::ZERO } else { Duration::from_secs(frame) / self.fps };
+        let source_time = self.next_capture_time.take().unwrap_or_else(SystemTime::now);
+        self.frames.push(FrameTiming { frame, pts, source_time });
+    }
+}
+
+/// Formats a time as RFC 3339 in UTC with microseconds, e.g. `2024-01-31T12:00:00.000000Z`.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Converts a UTC civil date and time to a `SystemTime`, `None` if a field is out of range or
+/// the time is before 1970.
+pub(crate) fn civil_time(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32, nanos: u32) -> Option<SystemTime> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || nanos >= 1_000_000_000 {
+        return None;
+    }
+
+    // Days since 1970-01-01 from the civil date (Howard Hinnant's algorithm)
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
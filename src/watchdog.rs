@@ -0,0 +1,113 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::{MjpegError, Result};
+use crate::observer::FrameObserver;
+use crate::clock::{Clock, SystemClock};
+
+/// An observer that detects a stalled frame flow, such as a dead camera.
+///
+/// Attach a clone to an [`ObservedWriter`](crate::ObservedWriter) and keep another one to
+/// watch it: [`check`](Self::check) returns [`MjpegError::Stalled`] when no frame has been
+/// written for the timeout, and [`wait_for_stall`](Self::wait_for_stall) blocks a monitoring
+/// thread until that happens, so a headless recorder can finish the current file and raise an
+/// alarm instead of writing empty hours. The timeout starts when the watchdog is created.
+/// Time is read through a [`Clock`], the system clock by default.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, ObservedWriter, Watchdog};
+/// use std::fs::File;
+/// use std::time::Duration;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let watchdog = Watchdog::new(Duration::from_secs(10));
+///     let writer = MjpegWriter::new(File::create("output.avi")?, 640, 480, 10)?;
+///     let mut writer = ObservedWriter::new(writer, watchdog.clone());
+///     loop {
+///         if watchdog.check().is_err() {
+///             // No frame for 10 seconds: keep what was recorded and report the camera
+///             break;
+///         }
+///         // Grab a JPEG from the camera with a timeout here
+///         writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///     }
+///     writer.finish()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Watchdog<C: Clock = SystemClock> {
+    timeout: Duration,
+    clock: C,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    last_frame: Instant,
+    frames: u64,
+}
+
+impl Watchdog {
+    /// Creates a watchdog that reports a stall after `timeout` without frames.
+    pub fn new(timeout: Duration) -> Self {
+        Self::with_clock(timeout, SystemClock)
+    }
+}
+
+impl<C: Clock> Watchdog<C> {
+    /// Creates a watchdog that reads the time from `clock`. Clones share the clock.
+    pub fn with_clock(timeout: Duration, clock: C) -> Self {
+        let last_frame = clock.now();
+        Watchdog {
+            timeout,
+            clock,
+            state: Arc::new(Mutex::new(State { last_frame, frames: 0 })),
+        }
+    }
+
+    /// The number of frames written so far.
+    pub fn frames(&self) -> u64 {
+        self.lock().frames
+    }
+
+    /// The time since the last frame, or since the watchdog was created if there was none.
+    pub fn idle(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.lock().last_frame)
+    }
+
+    /// Returns [`MjpegError::Stalled`] with the idle time if it has reached the timeout.
+    pub fn check(&self) -> Result<()> {
+        let idle = self.idle();
+        if idle >= self.timeout {
+            return Err(MjpegError::Stalled(idle));
+        }
+        Ok(())
+    }
+
+    /// Blocks until no frame has been written for the timeout and returns the idle time.
+    pub fn wait_for_stall(&self) -> Duration {
+        loop {
+            let deadline = self.lock().last_frame + self.timeout;
+            let now = self.clock.now();
+            if now >= deadline {
+                return self.idle();
+            }
+            self.clock.sleep(deadline - now);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<C: Clock> FrameObserver for Watchdog<C> {
+    fn on_frame(&mut self, _bufs: &[&[u8]]) {
+        let now = self.clock.now();
+        let mut state = self.lock();
+        state.last_frame = now;
+        state.frames += 1;
+    }
+}
@@ -1,13 +1,15 @@
-use std::io::{IoSlice, SeekFrom};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, IoSlice, Seek, SeekFrom, Write};
 use crate::{MjpegError, Result};
 
-#[cfg(any(feature = "async", feature = "tokio"))]
+#[cfg(any(feature = "async", feature = "tokio", feature = "embedded-io"))]
 use std::future::Future;
 
 /// A trait for synchronous writers that support `Write` and `Seek` operations.
 ///
 /// This trait is an abstraction over `std::io::Write` and `std::io::Seek`,
 /// allowing the AVI writer to work with different output types like files or in-memory buffers.
+/// It is implemented for the common std types; wrap other std writers in [`StdWriter`].
 pub trait Writer {
     /// Writes a buffer into this writer, returning how many bytes were written.
     fn write_all(&mut self, buf: &[u8]) -> Result<()>;
@@ -29,27 +31,180 @@ pub trait AsyncWriter: Send {
     fn write_all(&mut self, buf: &[u8]) -> impl Future<Output = Result<()>> + Send;
 
     /// Asynchronously writes a slice of buffers into this writer.
+    #[allow(clippy::needless_lifetimes)]
     fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> impl Future<Output = Result<()>> + Send;
 
     /// Asynchronously seeks to an offset, in bytes, in a stream.
     fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = Result<u64>> + Send;
 }
 
-// Implement Writer for std::io types
-impl<W: std::io::Write + std::io::Seek> Writer for W {
+/// A variant of `AsyncWriter` whose futures don't need to be `Send`.
+///
+/// Use it on single-threaded executors like wasm, embassy or a tokio `LocalSet`, where writers
+/// hold `!Send` handles. It is implemented for the same types as `AsyncWriter`, without the
+/// `Send` requirement, and for `embedded-io-async` writers wrapped in `EmbeddedIoWriter`;
+/// see [`MjpegLocalWriter`](crate::MjpegLocalWriter).
+#[cfg(any(feature = "async", feature = "tokio", feature = "embedded-io"))]
+pub trait LocalAsyncWriter {
+    /// Asynchronously writes a buffer into this writer.
+    fn write_all(&mut self, buf: &[u8]) -> impl Future<Output = Result<()>>;
+
+    /// Asynchronously writes a slice of buffers into this writer.
+    #[allow(clippy::needless_lifetimes)]
+    fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> impl Future<Output = Result<()>>;
+
+    /// Asynchronously seeks to an offset, in bytes, in a stream.
+    fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = Result<u64>>;
+}
+
+/// Adapts any `std::io::Write + std::io::Seek` type to [`Writer`].
+///
+/// `Writer` is implemented directly for the common std types ([`File`], `Cursor`s over
+/// vectors and slices, [`BufWriter`]) and for [`AlignedFile`](crate::AlignedFile); wrap other
+/// std writers in `StdWriter`. Types that don't implement the std traits, like FFI handles or
+/// special devices, can implement `Writer` themselves.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter, StdWriter};
+/// use std::io::{Seek, SeekFrom, Write};
+///
+/// fn record<W: Write + Seek>(output: W) -> mjpeg_avi_rs::Result<W> {
+///     let mut writer = MjpegWriter::new(StdWriter::new(output), 320, 240, 30)?;
+///     writer.add_frame(&[0xFF, 0xD8, 0xFF, 0xD9])?;
+///     Ok(writer.finish()?.into_inner())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct StdWriter<W>(W);
+
+impl<W> StdWriter<W> {
+    /// Wraps a std writer.
+    pub fn new(writer: W) -> Self {
+        StdWriter(writer)
+    }
+
+    /// A reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.0
+    }
+
+    /// A mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.0
+    }
+
+    /// Unwraps the std writer.
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+impl<W: Write + Seek> Writer for StdWriter<W> {
     fn write_all(&mut self, buf: &[u8]) -> Result<()> {
-        std::io::Write::write_all(self, buf).map_err(MjpegError::from)
+        std_write_all(&mut self.0, buf)
     }
 
     fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
-        std::io::Write::write_vectored(self, bufs)
-            .map(|_| ()) // Convert usize to ()
-            .map_err(MjpegError::from)
+        std_write_all_vectored(&mut self.0, bufs)
     }
 
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-        std::io::Seek::seek(self, pos).map_err(MjpegError::from)
+        std_seek(&mut self.0, pos)
+    }
+}
+
+/// Implements `Writer` for std types by delegating to their `Write` and `Seek` impls.
+macro_rules! impl_writer_for_std {
+    ($( [$($generics:tt)*] $ty:ty ),* $(,)?) => {
+        $(
+            impl<$($generics)*> Writer for $ty {
+                fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+                    std_write_all(self, buf)
+                }
+
+                fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+                    std_write_all_vectored(self, bufs)
+                }
+
+                fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+                    std_seek(self, pos)
+                }
+            }
+        )*
+    };
+}
+
+impl_writer_for_std! {
+    [] File,
+    [] &File,
+    [] Cursor<Vec<u8>>,
+    [] Cursor<&mut Vec<u8>>,
+    [] Cursor<&mut [u8]>,
+    [] Cursor<Box<[u8]>>,
+    [W: Write + Seek] BufWriter<W>,
+    [] crate::aligned::AlignedFile,
+    [] crate::estimate::NullWriter,
+    [] crate::spill::SpillWriter,
+}
+
+impl<W: Writer + ?Sized> Writer for &mut W {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        (**self).write_all(buf)
+    }
+
+    fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        (**self).write_all_vectored(bufs)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (**self).seek(pos)
+    }
+}
+
+impl<W: Writer + ?Sized> Writer for Box<W> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        (**self).write_all(buf)
+    }
+
+    fn write_all_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        (**self).write_all_vectored(bufs)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (**self).seek(pos)
+    }
+}
+
+fn std_write_all<W: Write + ?Sized>(writer: &mut W, buf: &[u8]) -> Result<()> {
+    writer.write_all(buf).map_err(MjpegError::from)
+}
+
+fn std_write_all_vectored<W: Write + ?Sized>(writer: &mut W, mut bufs: &[IoSlice<'_>]) -> Result<()> {
+    // write_vectored may write only part of the buffers, so continue from where it stopped
+    while !bufs.is_empty() {
+        let mut n = writer.write_vectored(bufs)?;
+        if n == 0 && bufs.iter().any(|b| !b.is_empty()) {
+            return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+        }
+
+        let mut i = 0;
+        while i < bufs.len() && n >= bufs[i].len() {
+            n -= bufs[i].len();
+            i += 1;
+        }
+        if n > 0 {
+            writer.write_all(&bufs[i][n..])?;
+            i += 1;
+        }
+        bufs = &bufs[i..];
     }
+    Ok(())
+}
+
+fn std_seek<W: Seek + ?Sized>(writer: &mut W, pos: SeekFrom) -> Result<u64> {
+    writer.seek(pos).map_err(MjpegError::from)
 }
 
 // Implement AsyncWriter for futures types
@@ -59,6 +214,7 @@ impl<W: futures::io::AsyncWrite + futures::io::AsyncSeek + Unpin + Send> AsyncWr
         futures::io::AsyncWriteExt::write_all(self, buf).await.map_err(MjpegError::from)
     }
 
+    #[allow(clippy::needless_lifetimes)]
     async fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> Result<()> {
         for buf in bufs {
             futures::io::AsyncWriteExt::write_all(self, buf).await?;
@@ -78,6 +234,7 @@ impl AsyncWriter for tokio::fs::File {
         tokio::io::AsyncWriteExt::write_all(self, buf).await.map_err(MjpegError::from)
     }
 
+    #[allow(clippy::needless_lifetimes)]
     async fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> Result<()> {
         tokio::io::AsyncWriteExt::write_vectored(self, bufs).await.map(|_| ()).map_err(MjpegError::from)
     }
@@ -85,4 +242,107 @@ impl AsyncWriter for tokio::fs::File {
     async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         tokio::io::AsyncSeekExt::seek(self, pos).await.map_err(MjpegError::from)
     }
-}
\ No newline at end of file
+}
+#[cfg(feature = "async")]
+impl<W: futures::io::AsyncWrite + futures::io::AsyncSeek + Unpin> LocalAsyncWriter for W {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        futures::io::AsyncWriteExt::write_all(self, buf).await.map_err(MjpegError::from)
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    async fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> Result<()> {
+        for buf in bufs {
+            futures::io::AsyncWriteExt::write_all(self, buf).await?;
+        }
+        Ok(())
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        futures::io::AsyncSeekExt::seek(self, pos).await.map_err(MjpegError::from)
+    }
+}
+
+// With `async`, the blanket impl above could cover it if tokio ever implements the futures traits
+#[cfg(all(feature = "tokio", not(feature = "async")))]
+impl LocalAsyncWriter for tokio::fs::File {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        AsyncWriter::write_all(self, buf).await
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    async fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> Result<()> {
+        AsyncWriter::write_all_vectored(self, bufs).await
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        AsyncWriter::seek(self, pos).await
+    }
+}
+
+/// Adapts an `embedded-io-async` writer, such as an SD card or flash file on Embassy, to [`LocalAsyncWriter`].
+///
+/// Enabled by the `embedded-io` feature. Errors are reported as [`MjpegError::Io`] with the
+/// error kind.
+///
+/// # Examples
+///
+/// ```no_run
+/// use embedded_io_async::{Seek, Write};
+/// use mjpeg_avi_rs::{EmbeddedIoWriter, MjpegAviWriterLocal, MjpegLocalWriter};
+///
+/// async fn record<F: Write + Seek>(file: F, frame: &[u8]) -> mjpeg_avi_rs::Result<F> {
+///     let mut writer = MjpegLocalWriter::new(EmbeddedIoWriter::new(file), 640, 480, 15).await?;
+///     writer.add_frame(frame).await?;
+///     Ok(writer.finish().await?.into_inner())
+/// }
+/// ```
+#[cfg(feature = "embedded-io")]
+#[derive(Debug, Default)]
+pub struct EmbeddedIoWriter<W>(W);
+
+#[cfg(feature = "embedded-io")]
+impl<W> EmbeddedIoWriter<W> {
+    /// Wraps an `embedded-io-async` writer.
+    pub fn new(writer: W) -> Self {
+        EmbeddedIoWriter(writer)
+    }
+
+    /// A mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.0
+    }
+
+    /// Unwraps the `embedded-io-async` writer.
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<W: embedded_io_async::Write + embedded_io_async::Seek> LocalAsyncWriter for EmbeddedIoWriter<W> {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.0.write_all(buf).await.map_err(embedded_io_error)
+    }
+
+    #[allow(clippy::needless_lifetimes)]
+    async fn write_all_vectored<'a, 'b>(&'a mut self, bufs: &'b [IoSlice<'b>]) -> Result<()> {
+        for buf in bufs {
+            self.0.write_all(buf).await.map_err(embedded_io_error)?;
+        }
+        Ok(())
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => embedded_io_async::SeekFrom::Start(offset),
+            SeekFrom::End(offset) => embedded_io_async::SeekFrom::End(offset),
+            SeekFrom::Current(offset) => embedded_io_async::SeekFrom::Current(offset),
+        };
+        self.0.seek(pos).await.map_err(embedded_io_error)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+fn embedded_io_error<E: embedded_io_async::Error>(err: E) -> MjpegError {
+    MjpegError::Io(format!("{:?}", err.kind()))
+}
@@ -0,0 +1,242 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use ruzstd::decoding::FrameDecoder;
+use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+use crate::{MjpegError, Result};
+use crate::reader::MjpegReader;
+use crate::riff::le_u32;
+
+/// The magic of the skippable frame holding the frame table.
+const FRAME_TABLE_MAGIC: u32 = 0x184D_2A50;
+/// The magic of the skippable frame holding the seek table of the Zstandard seekable format.
+const SEEK_TABLE_MAGIC: u32 = 0x184D_2A5E;
+/// The magic ending the seek table.
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+const FRAME_TABLE_ID: [u8; 4] = *b"MJZF";
+const FRAME_TABLE_VERSION: u8 = 1;
+/// The size of the seek table footer: frame count, descriptor and magic.
+const FOOTER_SIZE: u64 = 9;
+
+/// Statistics of a [`pack_zstd`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ZstdStats {
+    /// The number of video frames packed.
+    pub frames: u64,
+    /// The size of the source file.
+    pub avi_bytes: u64,
+    /// The size of the archive, tables included.
+    pub archive_bytes: u64,
+}
+
+impl ZstdStats {
+    /// The fraction of the source size the archive saves.
+    pub fn saving_ratio(&self) -> f64 {
+        if self.avi_bytes == 0 {
+            return 0.0;
+        }
+        1.0 - self.archive_bytes as f64 / self.avi_bytes as f64
+    }
+}
+
+/// Packs a finished AVI file into a seekable Zstandard archive for cold storage.
+///
+/// Enabled by the `zstd` feature. JPEG frames of static scenes still compress by 10-30%, and
+/// the file is kept byte for byte: [`unpack_zstd`] restores it exactly, including chunks this
+/// crate doesn't interpret, and [`ZstdArchive`] reads single frames without unpacking the rest.
+///
+/// The archive is a sequence of Zstandard frames whose decompressed contents, concatenated,
+/// are the source file, so `zstd -d` restores it too. The file is cut before the chunk of every
+/// video frame: the first Zstandard frame holds the headers, each following one a frame chunk
+/// and what follows it up to the next one, such as audio, bookmarks or the index. Two skippable
+/// frames follow, which decoders ignore. The frame table, magic `0x184D2A50`, holds `MJZF`, a
+/// version byte (1) and the frame count as `u32`, then per video frame the number of the
+/// Zstandard frame holding it and the offset and length of the JPEG data in its decompressed
+/// contents as `u32`. The seek table of the Zstandard seekable format, magic `0x184D2A5E`,
+/// holds the compressed and decompressed size of every Zstandard frame as `u32`, then the
+/// number of Zstandard frames as `u32`, a descriptor byte (0) and the magic `0x8F92EAB1`. All
+/// integers are little-endian.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let stats = mjpeg_avi_rs::pack_zstd(File::open("archive.avi")?, File::create("archive.avi.zst")?)?;
+///     println!("saved {:.0}%", stats.saving_ratio() * 100.0);
+///     Ok(())
+/// }
+/// ```
+pub fn pack_zstd<R: Read + Seek, O: Write>(source: R, mut out: O) -> Result<ZstdStats> {
+    let reader = MjpegReader::new(source)?;
+    let frame_map = reader.frame_map();
+    let mut source = reader.into_inner();
+    let avi_bytes = source.seek(SeekFrom::End(0))?;
+
+    // Cut before every frame chunk; frames sharing a chunk share a Zstandard frame
+    let mut cuts: Vec<u64> = std::iter::once(0).chain(frame_map.iter().map(|entry| entry.offset - 8)).collect();
+    cuts.sort_unstable();
+    cuts.dedup();
+    cuts.push(avi_bytes);
+
+    let mut stats = ZstdStats { frames: frame_map.len() as u64, avi_bytes, archive_bytes: 0 };
+    let mut seek_table = Vec::with_capacity((cuts.len() - 1) * 8);
+    let mut buf = Vec::new();
+    source.seek(SeekFrom::Start(0))?;
+    for range in cuts.windows(2) {
+        let len = u32::try_from(range[1] - range[0]).map_err(|_| MjpegError::FrameSizeExceeded)?;
+        buf.resize(len as usize, 0);
+        source.read_exact(&mut buf)?;
+        let compressed = compress_to_vec(buf.as_slice(), CompressionLevel::Fastest);
+        out.write_all(&compressed)?;
+        seek_table.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        seek_table.extend_from_slice(&len.to_le_bytes());
+        stats.archive_bytes += compressed.len() as u64;
+    }
+
+    let mut frame_table = FRAME_TABLE_ID.to_vec();
+    frame_table.push(FRAME_TABLE_VERSION);
+    frame_table.extend_from_slice(&(frame_map.len() as u32).to_le_bytes());
+    for entry in &frame_map {
+        let frame = cuts.partition_point(|&cut| cut <= entry.offset - 8) - 1;
+        for value in [frame as u32, (entry.offset - cuts[frame]) as u32, entry.len] {
+            frame_table.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    seek_table.extend_from_slice(&(cuts.len() as u32 - 1).to_le_bytes());
+    seek_table.push(0);
+    seek_table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+    for (magic, table) in [(FRAME_TABLE_MAGIC, frame_table), (SEEK_TABLE_MAGIC, seek_table)] {
+        out.write_all(&magic.to_le_bytes())?;
+        out.write_all(&(table.len() as u32).to_le_bytes())?;
+        out.write_all(&table)?;
+        stats.archive_bytes += 8 + table.len() as u64;
+    }
+    out.flush()?;
+    Ok(stats)
+}
+
+/// Restores the AVI file packed by [`pack_zstd`], byte for byte.
+pub fn unpack_zstd<I: Read + Seek, O: Write>(input: I, mut out: O) -> Result<O> {
+    let mut archive = ZstdArchive::new(input)?;
+    for i in 0..archive.blocks.len() {
+        out.write_all(&archive.read_block(i)?)?;
+    }
+    out.flush()?;
+    Ok(out)
+}
+
+/// Reads the frames of an archive written by [`pack_zstd`] without unpacking it, decompressing
+/// only the Zstandard frame holding each one.
+///
+/// Enabled by the `zstd` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mjpeg_avi_rs::ZstdArchive;
+/// use std::fs::File;
+///
+/// fn main() -> mjpeg_avi_rs::Result<()> {
+///     let mut archive = ZstdArchive::new(File::open("archive.avi.zst")?)?;
+///     let middle = archive.read_frame(archive.frame_count() / 2)?;
+///     std::fs::write("middle.jpg", middle)?;
+///     Ok(())
+/// }
+/// ```
+pub struct ZstdArchive<R> {
+    input: R,
+    /// Per Zstandard frame: archive offset, compressed size and decompressed size
+    blocks: Vec<(u64, u32, u32)>,
+    /// Per video frame: Zstandard frame, offset and length of the JPEG data in it
+    frames: Vec<(u32, u32, u32)>,
+    decoder: FrameDecoder,
+}
+
+impl<R: Read + Seek> ZstdArchive<R> {
+    /// Opens an archive, reading its frame and seek tables.
+    ///
+    /// Returns [`MjpegError::InvalidAvi`] if `input` isn't an archive of [`pack_zstd`].
+    pub fn new(mut input: R) -> Result<Self> {
+        let invalid = || MjpegError::InvalidAvi("not a zstd archive of a supported version".to_string());
+        let end = input.seek(SeekFrom::End(0))?;
+        let footer = read_at(&mut input, end.checked_sub(FOOTER_SIZE).ok_or_else(invalid)?, FOOTER_SIZE as usize)?;
+        let count = le_u32(&footer, 0) as u64;
+        if le_u32(&footer, 5) != SEEKABLE_MAGIC || footer[4] & 0x7F != 0 {
+            return Err(invalid());
+        }
+        // Seek tables with checksums have 12 bytes per entry
+        let entry_size = if footer[4] & 0x80 != 0 { 12 } else { 8 };
+        let seek_table_start = end.checked_sub(FOOTER_SIZE + 8 + count * entry_size).ok_or_else(invalid)?;
+        let seek_table = read_at(&mut input, seek_table_start, (8 + count * entry_size) as usize)?;
+        if le_u32(&seek_table, 0) != SEEK_TABLE_MAGIC || le_u32(&seek_table, 4) as u64 != count * entry_size + FOOTER_SIZE {
+            return Err(invalid());
+        }
+        let mut blocks = Vec::with_capacity(count as usize);
+        let mut offset = 0;
+        for entry in seek_table[8..].chunks_exact(entry_size as usize) {
+            let compressed = le_u32(entry, 0);
+            blocks.push((offset, compressed, le_u32(entry, 4)));
+            offset += compressed as u64;
+        }
+
+        let header = read_at(&mut input, offset, 17).map_err(|_| invalid())?;
+        let frame_count = le_u32(&header, 13) as u64;
+        if le_u32(&header, 0) != FRAME_TABLE_MAGIC || header[8..12] != FRAME_TABLE_ID || header[12] != FRAME_TABLE_VERSION {
+            return Err(invalid());
+        }
+        if le_u32(&header, 4) as u64 != 9 + frame_count * 12 || offset + 17 + frame_count * 12 != seek_table_start {
+            return Err(invalid());
+        }
+        let table = read_at(&mut input, offset + 17, frame_count as usize * 12)?;
+        let frames: Vec<(u32, u32, u32)> = table.chunks_exact(12).map(|entry| (le_u32(entry, 0), le_u32(entry, 4), le_u32(entry, 8))).collect();
+        if frames.iter().any(|&(block, start, len)| blocks.get(block as usize).is_none_or(|&(_, _, size)| start as u64 + len as u64 > size as u64)) {
+            return Err(invalid());
+        }
+        Ok(ZstdArchive { input, blocks, frames, decoder: FrameDecoder::new() })
+    }
+
+    /// The number of video frames in the archive.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The size of the packed AVI file.
+    pub fn avi_size(&self) -> u64 {
+        self.blocks.iter().map(|&(_, _, size)| size as u64).sum()
+    }
+
+    /// Reads the JPEG data of frame `index`.
+    ///
+    /// Returns [`MjpegError::FrameIndexOutOfRange`] if `index` is not less than `frame_count()`.
+    pub fn read_frame(&mut self, index: usize) -> Result<Vec<u8>> {
+        let (block, offset, len) = *self.frames.get(index).ok_or(MjpegError::FrameIndexOutOfRange)?;
+        let mut data = self.read_block(block as usize)?;
+        data.truncate((offset + len) as usize);
+        data.drain(..offset as usize);
+        Ok(data)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Decompresses Zstandard frame `index`.
+    fn read_block(&mut self, index: usize) -> Result<Vec<u8>> {
+        let (offset, compressed, size) = self.blocks[index];
+        let input = read_at(&mut self.input, offset, compressed as usize)?;
+        let mut data = vec![0u8; size as usize];
+        let written = self.decoder.decode_all(&input, &mut data).map_err(|err| MjpegError::Codec(err.to_string()))?;
+        if written != data.len() {
+            return Err(MjpegError::Codec(format!("zstd frame {} has {} bytes, expected {}", index, written, data.len())));
+        }
+        Ok(data)
+    }
+}
+
+fn read_at<R: Read + Seek>(input: &mut R, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    input.seek(SeekFrom::Start(offset))?;
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
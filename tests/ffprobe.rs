@@ -0,0 +1,65 @@
+//! Playability checks against FFmpeg.
+//!
+//! Run with `cargo test --features test-utils`. The tests are skipped when FFmpeg is not installed.
+#![cfg(feature = "test-utils")]
+
+use mjpeg_avi_rs::test_utils::ffprobe;
+use mjpeg_avi_rs::{MjpegAviWriter, MjpegWriter};
+
+fn create_test_jpeg(width: u32, height: u32, circle_x: u32) -> Vec<u8> {
+    use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb, RgbImage};
+
+    let mut img: RgbImage = ImageBuffer::from_pixel(width, height, Rgb([255, 255, 255]));
+    let center_y = height / 2;
+    let radius = 20i32;
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as i32 - circle_x as i32;
+            let dy = y as i32 - center_y as i32;
+            if dx * dx + dy * dy <= radius * radius {
+                img.put_pixel(x, y, Rgb([255, 0, 0]));
+            }
+        }
+    }
+
+    let mut buffer = Vec::new();
+    DynamicImage::ImageRgb8(img)
+        .write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Jpeg)
+        .unwrap();
+    buffer
+}
+
+fn write_test_file(name: &str, width: u32, height: u32, fps: u32, frames: u32) -> std::path::PathBuf {
+    let dir = std::path::Path::new("target/test_output");
+    std::fs::create_dir_all(dir).unwrap();
+    let path = dir.join(name);
+
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = MjpegWriter::new(file, width, height, fps).unwrap();
+    for frame in 0..frames {
+        let circle_x = 20 + frame * (width - 40) / frames.max(2);
+        writer.add_frame(&create_test_jpeg(width, height, circle_x)).unwrap();
+    }
+    writer.finish().unwrap();
+    path
+}
+
+#[test]
+fn test_ffprobe_matches_header() {
+    let path = write_test_file("ffprobe_test.avi", 320, 240, 25, 50);
+    let Some(info) = ffprobe::probe(&path).unwrap() else {
+        println!("ffprobe not found, skipping");
+        return;
+    };
+    info.assert_matches(320, 240, 25, 50);
+}
+
+#[test]
+fn test_ffmpeg_decodes_cleanly() {
+    let path = write_test_file("ffmpeg_decode_test.avi", 160, 120, 10, 10);
+    let Some(errors) = ffprobe::decode_errors(&path).unwrap() else {
+        println!("ffmpeg not found, skipping");
+        return;
+    };
+    assert!(errors.is_empty(), "ffmpeg reported errors: {}", errors);
+}
@@ -0,0 +1,123 @@
+//! Reading a file over HTTP range requests from a minimal local server.
+//!
+//! Run with `cargo test --features http`.
+#![cfg(feature = "http")]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use mjpeg_avi_rs::{HttpSource, MjpegAviWriter, MjpegReader, MjpegWriter, RangeReader};
+
+/// Serves `data` on a local port, answering HEAD and ranged GET requests. Returns the URL and a request counter.
+fn serve(data: Vec<u8>) -> (String, Arc<AtomicUsize>) {
+    serve_with_auth(data, None)
+}
+
+/// Like `serve`, but answers 401 to requests without the `Authorization` header value `auth`.
+fn serve_with_auth(data: Vec<u8>, auth: Option<&'static str>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/test.avi", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                    break;
+                }
+                let mut range = None;
+                let mut authorized = auth.is_none();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = value.split_once('-').unwrap();
+                        range = Some((start.parse::<usize>().unwrap(), end.parse::<usize>().unwrap()));
+                    }
+                    if let Some(value) = line.strip_prefix("authorization: ").or_else(|| line.strip_prefix("Authorization: ")) {
+                        authorized |= Some(value) == auth;
+                    }
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let response = if !authorized {
+                    b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".to_vec()
+                } else if request_line.starts_with("HEAD") {
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", data.len()).into_bytes()
+                } else if let Some((start, end)) = range.filter(|&(start, _)| start < data.len()) {
+                    let end = end.min(data.len() - 1);
+                    let mut response = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        end + 1 - start,
+                        start,
+                        end,
+                        data.len()
+                    )
+                    .into_bytes();
+                    response.extend_from_slice(&data[start..=end]);
+                    response
+                } else {
+                    b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\n\r\n".to_vec()
+                };
+                if stream.write_all(&response).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    (url, requests)
+}
+
+#[test]
+fn test_read_frames_over_http() {
+    let frames: Vec<Vec<u8>> = (0..5u8).map(|i| vec![0xFF, 0xD8, i, i, i, 0xFF, 0xD9]).collect();
+    let mut writer = MjpegWriter::new(std::io::Cursor::new(Vec::new()), 64, 48, 10).unwrap();
+    for frame in &frames {
+        writer.add_frame(frame).unwrap();
+    }
+    let data = writer.finish().unwrap().into_inner();
+    let (url, requests) = serve(data.clone());
+
+    let mut reader = MjpegReader::new(RangeReader::new(HttpSource::new(url.as_str())).unwrap()).unwrap();
+    assert_eq!(reader.frame_count(), 5);
+    assert_eq!((reader.info().width, reader.info().height), (64, 48));
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(&reader.read_frame(i).unwrap()[..frame.len()], &frame[..]);
+    }
+    // The whole file fits in one block: one HEAD and one GET
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_missing_file_over_http() {
+    let (url, _) = serve(Vec::new());
+    let mut source = HttpSource::new(url);
+    let mut buf = [0u8; 4];
+    assert!(mjpeg_avi_rs::RangeSource::read_at(&mut source, 0, &mut buf).is_err());
+}
+
+#[test]
+fn test_basic_auth_over_http() {
+    let mut writer = MjpegWriter::new(std::io::Cursor::new(Vec::new()), 64, 48, 10).unwrap();
+    writer.add_frame(&[0xFF, 0xD8, 1, 0xFF, 0xD9]).unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    // "camera:secret" in Base64
+    let (url, _) = serve_with_auth(data, Some("Basic Y2FtZXJhOnNlY3JldA=="));
+
+    assert!(RangeReader::new(HttpSource::new(url.as_str())).is_err());
+
+    let mut source = HttpSource::new(url.as_str());
+    source.basic_auth("camera", Some("secret")).header("X-Client", "test");
+    let mut reader = MjpegReader::new(RangeReader::new(source).unwrap()).unwrap();
+    assert_eq!(reader.frame_count(), 1);
+    assert_eq!(&reader.read_frame(0).unwrap()[..5], &[0xFF, 0xD8, 1, 0xFF, 0xD9]);
+}
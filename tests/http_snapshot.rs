@@ -0,0 +1,81 @@
+//! Polling a snapshot URL from a minimal local server.
+//!
+//! Run with `cargo test --features http`.
+#![cfg(feature = "http")]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use mjpeg_avi_rs::{pull_at_fps, MissedTickPolicy, MjpegAviWriter, MjpegReader, MjpegWriter, SnapshotSource};
+
+/// Serves `snapshots` in turn with an ETag each, answering 304 when the client already has the current one.
+fn serve(snapshots: Vec<Vec<u8>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/snapshot.jpg", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        let mut requests = 0;
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                    break;
+                }
+                let mut if_none_match = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("if-none-match: ") {
+                        if_none_match = Some(value.to_string());
+                    }
+                }
+
+                // The image changes on every other request
+                let current = (requests / 2).min(snapshots.len() - 1);
+                requests += 1;
+                let etag = format!("\"{}\"", current);
+                let response = if if_none_match.as_deref() == Some(etag.as_str()) {
+                    b"HTTP/1.1 304 Not Modified\r\n\r\n".to_vec()
+                } else {
+                    let mut response = format!("HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\n\r\n", etag, snapshots[current].len()).into_bytes();
+                    response.extend_from_slice(&snapshots[current]);
+                    response
+                };
+                if stream.write_all(&response).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    url
+}
+
+#[test]
+fn test_poll_snapshots() {
+    let snapshots: Vec<Vec<u8>> = (0..3u8).map(|i| vec![0xFF, 0xD8, i, 0xFF, 0xD9]).collect();
+    let mut camera = SnapshotSource::new(serve(snapshots.clone()));
+
+    let mut writer = MjpegWriter::new(std::io::Cursor::new(Vec::new()), 64, 48, 100).unwrap();
+    let mut remaining = 6;
+    let stats = pull_at_fps(&mut writer, 100, MissedTickPolicy::Burst, || {
+        remaining -= 1;
+        if remaining < 0 {
+            return Ok(None);
+        }
+        camera.fetch().map(Some)
+    })
+    .unwrap();
+    assert_eq!(stats.frames, 6);
+    assert_eq!(camera.not_modified(), 3);
+
+    let data = writer.finish().unwrap().into_inner();
+    let mut reader = MjpegReader::new(std::io::Cursor::new(data)).unwrap();
+    for frame in 0..6 {
+        assert_eq!(&reader.read_frame(frame).unwrap()[..5], &snapshots[frame / 2][..]);
+    }
+}
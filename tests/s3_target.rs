@@ -0,0 +1,311 @@
+//! Recording into, listing and cataloging an S3 bucket served by a minimal local server.
+//!
+//! Run with `cargo test --features http`.
+#![cfg(feature = "http")]
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use mjpeg_avi_rs::{catalog_target, MjpegAviWriter, MjpegReader, MjpegWriterBuilder, S3Target, StorageTarget};
+
+#[derive(Default)]
+struct Bucket {
+    objects: BTreeMap<String, Vec<u8>>,
+    /// The parts of the multipart uploads by upload ID: part number, data and CRC-32
+    uploads: BTreeMap<String, BTreeMap<u32, (Vec<u8>, u32)>>,
+    /// The number of parts received
+    parts: usize,
+    /// Fail the upload of parts with this number
+    fail_part: Option<u32>,
+    /// Answer CompleteMultipartUpload with an error in a 200 OK, as S3 may once it started
+    fail_complete: bool,
+}
+
+type Objects = Arc<Mutex<Bucket>>;
+
+/// Serves a bucket named `bucket` with path-style PUT, GET, DELETE, ListObjectsV2 and
+/// multipart upload requests with CRC-32 checksums, answering 403 to requests without a SigV4
+/// `Authorization` header and 404 to those for unknown uploads. Returns the endpoint and the
+/// bucket.
+fn serve() -> (String, Objects) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let objects: Objects = Arc::default();
+    let stored = objects.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+            let stored = stored.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    let mut parts = request_line.split_whitespace();
+                    let (method, target) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+                    let (mut length, mut signed, mut checksum) = (0, false, None);
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end().to_ascii_lowercase();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some(value) = line.strip_prefix("content-length: ") {
+                            length = value.parse().unwrap();
+                        }
+                        if let Some(value) = line.strip_prefix("authorization: ") {
+                            signed = value.starts_with("aws4-hmac-sha256 credential=key/") && value.contains("/test-region/s3/aws4_request");
+                        }
+                        if let Some(value) = line.strip_prefix("x-amz-checksum-crc32: ") {
+                            checksum = Some(value.to_string());
+                        }
+                    }
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+
+                    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+                    let key = path.strip_prefix("/bucket/").map(percent_decode);
+                    let param = |name: &str| query.split('&').find_map(|param| param.strip_prefix(name)?.strip_prefix('=')).map(percent_decode);
+                    let mut bucket = stored.lock().unwrap();
+                    let mut etag = None;
+                    let (status, body) = match (method.as_str(), key) {
+                        _ if !signed => ("403 Forbidden", Vec::new()),
+                        ("POST", Some(key)) if param("uploads").is_some() => {
+                            let upload_id = format!("upload-{}-{}", key.len(), bucket.uploads.len());
+                            bucket.uploads.insert(upload_id.clone(), BTreeMap::new());
+                            ("200 OK", format!("<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>", upload_id).into_bytes())
+                        }
+                        ("PUT", Some(_)) if param("uploadId").is_some() => {
+                            let number: u32 = param("partNumber").unwrap().parse().unwrap();
+                            let crc = crc32(&body);
+                            bucket.parts += 1;
+                            if bucket.fail_part == Some(number) {
+                                ("500 Internal Server Error", Vec::new())
+                            } else if checksum != Some(base64(&crc.to_be_bytes()).to_ascii_lowercase()) {
+                                ("400 Bad Request", b"<Error><Code>BadDigest</Code></Error>".to_vec())
+                            } else {
+                                etag = Some(format!("\"etag-{}\"", number));
+                                match bucket.uploads.get_mut(&param("uploadId").unwrap()) {
+                                    Some(parts) => {
+                                        parts.insert(number, (body, crc));
+                                        ("200 OK", Vec::new())
+                                    }
+                                    None => ("404 Not Found", Vec::new()),
+                                }
+                            }
+                        }
+                        ("POST", Some(_)) if bucket.fail_complete => {
+                            ("200 OK", b"<Error><Code>InternalError</Code><Message>We encountered an internal error.</Message></Error>".to_vec())
+                        }
+                        ("POST", Some(key)) => match bucket.uploads.remove(&param("uploadId").unwrap_or_default()) {
+                            Some(parts) => {
+                                let crcs: Vec<u8> = parts.values().flat_map(|(_, crc)| crc.to_be_bytes()).collect();
+                                let checksum = format!("{}-{}", base64(&crc32(&crcs).to_be_bytes()), parts.len());
+                                bucket.objects.insert(key, parts.into_values().flat_map(|(data, _)| data).collect());
+                                ("200 OK", format!("<CompleteMultipartUploadResult><ChecksumCRC32>{}</ChecksumCRC32></CompleteMultipartUploadResult>", checksum).into_bytes())
+                            }
+                            None => ("404 Not Found", Vec::new()),
+                        },
+                        ("GET", Some(_)) if param("uploadId").is_some() => match bucket.uploads.get(&param("uploadId").unwrap()) {
+                            Some(parts) => ("200 OK", format!("<ListPartsResult><PartCount>{}</PartCount></ListPartsResult>", parts.len()).into_bytes()),
+                            None => ("404 Not Found", b"<Error><Code>NoSuchUpload</Code></Error>".to_vec()),
+                        },
+                        ("DELETE", Some(_)) if param("uploadId").is_some() => match bucket.uploads.remove(&param("uploadId").unwrap()) {
+                            Some(_) => ("204 No Content", Vec::new()),
+                            None => ("404 Not Found", b"<Error><Code>NoSuchUpload</Code></Error>".to_vec()),
+                        },
+                        ("PUT", Some(key)) => {
+                            bucket.objects.insert(key, body);
+                            ("200 OK", Vec::new())
+                        }
+                        ("GET", Some(key)) => match bucket.objects.get(&key) {
+                            Some(data) => ("200 OK", data.clone()),
+                            None => ("404 Not Found", Vec::new()),
+                        },
+                        ("DELETE", Some(key)) => {
+                            bucket.objects.remove(&key);
+                            ("204 No Content", Vec::new())
+                        }
+                        ("GET", None) => {
+                            let prefix = param("prefix").unwrap_or_default();
+                            let mut xml = String::from("<ListBucketResult><IsTruncated>false</IsTruncated>");
+                            for (key, data) in bucket.objects.iter().filter(|(key, _)| key.starts_with(&prefix)) {
+                                xml.push_str(&format!(
+                                    "<Contents><Key>{}</Key><LastModified>2024-05-01T12:00:00.000Z</LastModified><Size>{}</Size></Contents>",
+                                    key.replace('&', "&amp;"),
+                                    data.len()
+                                ));
+                            }
+                            xml.push_str("</ListBucketResult>");
+                            ("200 OK", xml.into_bytes())
+                        }
+                        _ => ("400 Bad Request", Vec::new()),
+                    };
+                    drop(bucket);
+                    let etag = etag.map_or(String::new(), |etag| format!("ETag: {}\r\n", etag));
+                    let mut response = format!("HTTP/1.1 {}\r\n{}Content-Length: {}\r\n\r\n", status, etag, body.len()).into_bytes();
+                    response.extend_from_slice(&body);
+                    if stream.write_all(&response).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (endpoint, objects)
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+            out.push(u8::from_str_radix(&hex, 16).unwrap());
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            out.push(if i <= chunk.len() { ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char } else { '=' });
+        }
+    }
+    out
+}
+
+fn jpeg() -> Vec<u8> {
+    let mut frame = vec![0xFF, 0xD8];
+    frame.extend_from_slice(&[0x55; 100]);
+    frame.extend_from_slice(&[0xFF, 0xD9]);
+    frame
+}
+
+#[test]
+fn test_s3_target() {
+    let (endpoint, objects) = serve();
+    let mut target = S3Target::new(endpoint.as_str(), "bucket", "test-region", "key", "secret");
+    target.prefix("cam1/");
+
+    let builder = MjpegWriterBuilder::new(320, 240, 10);
+    for name in ["0002.avi", "0001 & more.avi"] {
+        let mut writer = builder.build(target.open_segment(name).unwrap()).unwrap();
+        for _ in 0..20 {
+            writer.add_frame(&jpeg()).unwrap();
+        }
+        target.finalize(name, writer.finish().unwrap()).unwrap();
+    }
+    assert!(objects.lock().unwrap().objects.contains_key("cam1/0002.avi"));
+
+    let segments = target.list().unwrap();
+    assert_eq!(segments.iter().map(|segment| segment.name.as_str()).collect::<Vec<_>>(), ["0001 & more.avi", "0002.avi"]);
+    assert_eq!(segments[1].size, objects.lock().unwrap().objects["cam1/0002.avi"].len() as u64);
+    assert!(segments[0].modified.is_some());
+
+    let reader = MjpegReader::new(target.open_read("0002.avi").unwrap()).unwrap();
+    assert_eq!(reader.frame_count(), 20);
+    let entries = catalog_target(&mut target).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].duration, std::time::Duration::from_secs(2));
+
+    target.delete("0002.avi").unwrap();
+    assert_eq!(target.list().unwrap().len(), 1);
+    assert!(target.open_read("0002.avi").is_err());
+
+    // Unsigned requests are refused, so errors are reported
+    let mut wrong = S3Target::new(endpoint.as_str(), "bucket", "other-region", "key", "secret");
+    assert!(wrong.list().is_err());
+}
+
+#[test]
+fn test_s3_resumed_upload() {
+    let (endpoint, bucket) = serve();
+    let dir = std::env::temp_dir().join(format!("mjpeg_avi_rs_s3_upload_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let resume_dir = dir.join("uploads");
+    let mut target = S3Target::new(endpoint.as_str(), "bucket", "test-region", "key", "secret");
+    target.prefix("cam1/").part_size(1000).resume_dir(&resume_dir);
+
+    let mut writer = MjpegWriterBuilder::new(320, 240, 10).build(Cursor::new(Vec::new())).unwrap();
+    for _ in 0..40 {
+        writer.add_frame(&jpeg()).unwrap();
+    }
+    let data = writer.finish().unwrap().into_inner();
+    let path = dir.join("0001.avi");
+    std::fs::write(&path, &data).unwrap();
+    let parts = data.len().div_ceil(1000);
+    assert!(parts > 4);
+
+    // The upload is interrupted at part 4, and resumes with it after the process "restarts"
+    bucket.lock().unwrap().fail_part = Some(4);
+    assert!(target.upload_file("0001.avi", &path).is_err());
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 1);
+    assert_eq!(bucket.lock().unwrap().parts, 4);
+    bucket.lock().unwrap().fail_part = None;
+    let mut target = S3Target::new(endpoint.as_str(), "bucket", "test-region", "key", "secret");
+    target.prefix("cam1/").part_size(1000).resume_dir(&resume_dir);
+    target.upload_file("0001.avi", &path).unwrap();
+    assert_eq!(bucket.lock().unwrap().parts, parts + 1);
+    assert_eq!(bucket.lock().unwrap().objects["cam1/0001.avi"], data);
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 0);
+    assert_eq!(MjpegReader::new(target.open_read("0001.avi").unwrap()).unwrap().frame_count(), 40);
+
+    // An aborted upload is dropped by the store
+    bucket.lock().unwrap().fail_part = Some(2);
+    assert!(target.upload_file("0001.avi", &path).is_err());
+    target.abort_upload("0001.avi").unwrap();
+    assert!(bucket.lock().unwrap().uploads.is_empty());
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 0);
+
+    // An upload the store expired starts over
+    assert!(target.upload_file("0001.avi", &path).is_err());
+    bucket.lock().unwrap().uploads.clear();
+    bucket.lock().unwrap().fail_part = None;
+    let sent = bucket.lock().unwrap().parts;
+    target.upload_file("0001.avi", &path).unwrap();
+    assert_eq!(bucket.lock().unwrap().parts, sent + parts);
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 0);
+
+    // An upload failing to complete keeps its progress and is completed without sending parts
+    bucket.lock().unwrap().fail_complete = true;
+    assert!(target.upload_file("0001.avi", &path).is_err());
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 1);
+    bucket.lock().unwrap().fail_complete = false;
+    let sent = bucket.lock().unwrap().parts;
+    target.upload_file("0001.avi", &path).unwrap();
+    assert_eq!(bucket.lock().unwrap().parts, sent);
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 0);
+
+    // Aborting an upload the store already dropped only removes its progress
+    bucket.lock().unwrap().fail_part = Some(2);
+    assert!(target.upload_file("0001.avi", &path).is_err());
+    bucket.lock().unwrap().uploads.clear();
+    target.abort_upload("0001.avi").unwrap();
+    assert_eq!(std::fs::read_dir(&resume_dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
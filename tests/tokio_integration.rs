@@ -191,4 +191,29 @@ mod tokio_tests {
         
         // Keep file for manual inspection - don't delete it
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_pull_at_fps() {
+        use mjpeg_avi_rs::{pull_at_fps_async, MissedTickPolicy};
+        use tokio::fs::File;
+
+        let temp_path = std::path::Path::new("target/test_output/tokio_pull_at_fps_test.avi");
+        std::fs::create_dir_all(temp_path.parent().unwrap()).unwrap();
+
+        let jpeg_data = create_test_jpeg(160, 120, 50);
+        let file = File::create(temp_path).await.unwrap();
+        let mut writer = MjpegAsyncWriter::new(file, 160, 120, 100).await.unwrap();
+
+        let mut remaining = 5;
+        let stats = pull_at_fps_async(&mut writer, 100, MissedTickPolicy::Burst, || {
+            let frame = (remaining > 0).then(|| jpeg_data.clone());
+            remaining -= 1;
+            async move { Ok(frame) }
+        }).await.unwrap();
+        writer.finish().await.unwrap();
+
+        assert_eq!(stats.frames, 5);
+        assert_eq!(stats.missed_ticks, 0);
+    }
+}